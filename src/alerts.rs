@@ -0,0 +1,227 @@
+//! Local evaluation of threshold alerts against the collected samples, for
+//! machines without a Prometheus server and Alertmanager.
+
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::sync::Mutex;
+use std::time::{Instant, SystemTime, UNIX_EPOCH};
+
+use prometheus::core::{Collector, Desc};
+use prometheus::proto::{Metric, MetricFamily, MetricType};
+use prometheus::{IntGaugeVec, Opts};
+use serde::Serialize;
+use tokio::sync::mpsc::{self, UnboundedReceiver, UnboundedSender};
+
+use crate::config::AlertRule;
+use crate::error::Result;
+use crate::NAMESPACE;
+
+/// Whether the condition of an alert did not yet hold for long enough.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum AlertState {
+    Pending,
+    Firing,
+}
+
+/// A series for which the condition of an alert rule holds.
+#[derive(Clone, Debug, Serialize)]
+pub struct Alert {
+    pub name: String,
+    pub state: AlertState,
+    /// Labels of the offending series.
+    pub labels: BTreeMap<String, String>,
+    /// Last evaluated value of the series.
+    pub value: f64,
+    /// Unix time in seconds since which the condition holds.
+    pub active_at: u64,
+}
+
+/// An alert that started or stopped firing.
+#[derive(Clone, Debug)]
+pub struct Notification {
+    pub alert: Alert,
+    /// Whether the alert stopped firing.
+    pub resolved: bool,
+}
+
+struct Active {
+    alert: Alert,
+    since: Instant,
+}
+
+/// Alert rules together with the series they are active for.
+pub(crate) struct Alerts {
+    rules: Vec<AlertRule>,
+    /// Identity labels of the devices.
+    labels: Vec<&'static str>,
+    active: Mutex<HashMap<(usize, BTreeMap<String, String>), Active>>,
+    subscribers: Mutex<Vec<UnboundedSender<Notification>>>,
+}
+
+fn alert_firing_gauge(identity: &[&str]) -> Result<IntGaugeVec> {
+    let alert_firing_opts = Opts::new(
+        "alert_firing",
+        "Whether a locally evaluated alert is firing for the GPU device",
+    )
+    .namespace(NAMESPACE);
+
+    let mut labels = vec!["alert"];
+    labels.extend(identity);
+    Ok(IntGaugeVec::new(alert_firing_opts, &labels)?)
+}
+
+/// Value of a gauge, counter or untyped sample.
+fn sample_value(kind: MetricType, metric: &Metric) -> Option<f64> {
+    match kind {
+        MetricType::GAUGE => Some(metric.get_gauge().get_value()),
+        MetricType::COUNTER => Some(metric.get_counter().get_value()),
+        MetricType::UNTYPED => Some(metric.get_untyped().get_value()),
+        _ => None,
+    }
+}
+
+fn unix_now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |d| d.as_secs())
+}
+
+impl Alerts {
+    pub fn new(rules: Vec<AlertRule>, labels: Vec<&'static str>) -> Alerts {
+        Alerts {
+            rules,
+            labels,
+            active: Mutex::new(HashMap::new()),
+            subscribers: Mutex::new(Vec::new()),
+        }
+    }
+
+    /// Returns a channel receiving a [`Notification`] whenever an alert starts
+    /// or stops firing.
+    pub fn subscribe(&self) -> UnboundedReceiver<Notification> {
+        let (sender, receiver) = mpsc::unbounded_channel();
+        self.subscribers
+            .lock()
+            .expect("Alert subscribers poisoned")
+            .push(sender);
+        receiver
+    }
+
+    fn notify(&self, notifications: Vec<Notification>) {
+        if notifications.is_empty() {
+            return;
+        }
+
+        let mut subscribers = self.subscribers.lock().expect("Alert subscribers poisoned");
+        subscribers.retain(|subscriber| {
+            notifications
+                .iter()
+                .all(|notification| subscriber.send(notification.clone()).is_ok())
+        });
+    }
+
+    pub fn descs(&self) -> Result<Vec<Desc>> {
+        Ok(alert_firing_gauge(&self.labels)?
+            .desc()
+            .into_iter()
+            .cloned()
+            .collect())
+    }
+
+    /// Evaluates all rules against the samples in `families`. Alerts whose
+    /// condition no longer holds are resolved. Subscribers are notified about
+    /// alerts that started or stopped firing.
+    pub fn evaluate(&self, families: &[MetricFamily]) {
+        let now = Instant::now();
+        let mut active = self.active.lock().expect("Alert state poisoned");
+        let mut seen = HashSet::new();
+        let mut notifications = Vec::new();
+
+        for (i, rule) in self.rules.iter().enumerate() {
+            for family in families.iter().filter(|f| f.get_name() == rule.metric) {
+                for metric in family.get_metric() {
+                    let value = match sample_value(family.get_field_type(), metric) {
+                        Some(value) if rule.op.holds(value, rule.threshold) => value,
+                        _ => continue,
+                    };
+
+                    let labels: BTreeMap<String, String> = metric
+                        .get_label()
+                        .iter()
+                        .map(|l| (l.get_name().to_string(), l.get_value().to_string()))
+                        .collect();
+                    let key = (i, labels);
+
+                    let entry = active.entry(key.clone()).or_insert_with(|| Active {
+                        alert: Alert {
+                            name: rule.name.clone(),
+                            state: AlertState::Pending,
+                            labels: key.1.clone(),
+                            value,
+                            active_at: unix_now(),
+                        },
+                        since: now,
+                    });
+                    entry.alert.value = value;
+                    if entry.alert.state == AlertState::Pending
+                        && now.duration_since(entry.since) >= rule.duration.unwrap_or_default()
+                    {
+                        entry.alert.state = AlertState::Firing;
+                        notifications.push(Notification {
+                            alert: entry.alert.clone(),
+                            resolved: false,
+                        });
+                    }
+
+                    seen.insert(key);
+                }
+            }
+        }
+
+        active.retain(|key, a| {
+            let keep = seen.contains(key);
+            if !keep && a.alert.state == AlertState::Firing {
+                notifications.push(Notification {
+                    alert: a.alert.clone(),
+                    resolved: true,
+                });
+            }
+            keep
+        });
+        drop(active);
+
+        self.notify(notifications);
+    }
+
+    /// All pending and firing alerts, ordered by name.
+    pub fn alerts(&self) -> Vec<Alert> {
+        let active = self.active.lock().expect("Alert state poisoned");
+
+        let mut alerts: Vec<Alert> = active.values().map(|a| a.alert.clone()).collect();
+        alerts.sort_by(|a, b| (&a.name, &a.labels).cmp(&(&b.name, &b.labels)));
+        alerts
+    }
+
+    /// `alert_firing` series of all firing alerts.
+    pub fn families(&self) -> Result<Vec<MetricFamily>> {
+        let alert_firing_gauge = alert_firing_gauge(&self.labels)?;
+
+        for alert in self.alerts() {
+            if alert.state != AlertState::Firing {
+                continue;
+            }
+
+            let mut values = vec![alert.name.as_str()];
+            values.extend(
+                self.labels
+                    .iter()
+                    .map(|l| alert.labels.get(*l).map_or("", String::as_str)),
+            );
+            alert_firing_gauge
+                .get_metric_with_label_values(&values)?
+                .set(1);
+        }
+
+        Ok(alert_firing_gauge.collect())
+    }
+}
@@ -0,0 +1,271 @@
+//! Collector for AMD GPUs, read from the sysfs interface of the amdgpu driver.
+//!
+//! Only built with the `amd` feature. The metrics mirror the NVIDIA ones under
+//! the `amd_gpu` namespace.
+
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use prometheus::core::{Collector, Desc};
+use prometheus::proto::MetricFamily;
+use prometheus::{IntGauge, IntGaugeVec, Opts};
+
+use crate::collectors::MetricSet;
+use crate::error::Result;
+
+/// Namespace prefixed to all AMD GPU metric names.
+pub const AMD_NAMESPACE: &str = "amd_gpu";
+
+const LABELS: [&str; 2] = ["card", "pci_bus_id"];
+
+const AMD_VENDOR_ID: &str = "0x1002";
+
+/// A DRM card driven by amdgpu.
+struct Card {
+    name: String,
+    pci_bus_id: String,
+    device: PathBuf,
+}
+
+impl Card {
+    fn read_u64(&self, file: &str) -> Option<u64> {
+        read_u64(&self.device.join(file))
+    }
+
+    /// Reads a file of the card's first hardware monitor.
+    fn read_hwmon(&self, file: &str) -> Option<u64> {
+        let hwmon = fs::read_dir(self.device.join("hwmon")).ok()?.next()?.ok()?;
+        read_u64(&hwmon.path().join(file))
+    }
+}
+
+fn read_u64(path: &Path) -> Option<u64> {
+    fs::read_to_string(path).ok()?.trim().parse().ok()
+}
+
+struct Metrics {
+    num_devices_gauge: IntGauge,
+    gpu_utilization_gauge: IntGaugeVec,
+    memory_utilization_gauge: IntGaugeVec,
+    total_memory_gauge: IntGaugeVec,
+    used_memory_gauge: IntGaugeVec,
+    temperature_gauge: IntGaugeVec,
+    power_usage_gauge: IntGaugeVec,
+}
+
+impl Metrics {
+    fn new() -> Result<Metrics> {
+        // Num devices
+        let num_devices_opts =
+            Opts::new("num_devices", "Number of AMD GPU devices").namespace(AMD_NAMESPACE);
+        let num_devices_gauge = IntGauge::with_opts(num_devices_opts)?;
+
+        // GPU utilization
+        let gpu_utilization_opts =
+            Opts::new("gpu_utilization", "Percent of time the GPU device was busy")
+                .namespace(AMD_NAMESPACE);
+        let gpu_utilization_gauge = IntGaugeVec::new(gpu_utilization_opts, &LABELS)?;
+
+        // Memory utilization
+        let memory_utilization_opts = Opts::new(
+            "memory_utilization",
+            "Percent of time the memory of the GPU device was busy",
+        )
+        .namespace(AMD_NAMESPACE);
+        let memory_utilization_gauge = IntGaugeVec::new(memory_utilization_opts, &LABELS)?;
+
+        // Total memory
+        let total_memory_opts = Opts::new(
+            "memory_total_bytes",
+            "Total VRAM of the GPU device in bytes",
+        )
+        .namespace(AMD_NAMESPACE);
+        let total_memory_gauge = IntGaugeVec::new(total_memory_opts, &LABELS)?;
+
+        // Used memory
+        let used_memory_opts =
+            Opts::new("memory_used_bytes", "VRAM used on the GPU device in bytes")
+                .namespace(AMD_NAMESPACE);
+        let used_memory_gauge = IntGaugeVec::new(used_memory_opts, &LABELS)?;
+
+        // Temperature
+        let temperature_opts = Opts::new(
+            "temperature_celsius",
+            "Edge temperature of the GPU device in celsius",
+        )
+        .namespace(AMD_NAMESPACE);
+        let temperature_gauge = IntGaugeVec::new(temperature_opts, &LABELS)?;
+
+        // Power usage
+        let power_usage_opts = Opts::new(
+            "power_usage_milliwatts",
+            "Average power usage of the GPU device in milliwatts",
+        )
+        .namespace(AMD_NAMESPACE);
+        let power_usage_gauge = IntGaugeVec::new(power_usage_opts, &LABELS)?;
+
+        Ok(Metrics {
+            num_devices_gauge,
+            gpu_utilization_gauge,
+            memory_utilization_gauge,
+            total_memory_gauge,
+            used_memory_gauge,
+            temperature_gauge,
+            power_usage_gauge,
+        })
+    }
+}
+
+impl MetricSet for Metrics {
+    fn collectors(&self) -> Vec<&dyn Collector> {
+        vec![
+            &self.num_devices_gauge,
+            &self.gpu_utilization_gauge,
+            &self.memory_utilization_gauge,
+            &self.total_memory_gauge,
+            &self.used_memory_gauge,
+            &self.temperature_gauge,
+            &self.power_usage_gauge,
+        ]
+    }
+}
+
+/// Collects metrics of all AMD GPUs driven by amdgpu.
+pub struct AmdGpuCollector {
+    root: PathBuf,
+    descs: Vec<Desc>,
+}
+
+impl AmdGpuCollector {
+    pub fn new() -> Result<AmdGpuCollector> {
+        AmdGpuCollector::with_root("/sys/class/drm")
+    }
+
+    /// Creates a collector reading the DRM class directory at `root`.
+    pub fn with_root<P: Into<PathBuf>>(root: P) -> Result<AmdGpuCollector> {
+        Ok(AmdGpuCollector {
+            root: root.into(),
+            descs: Metrics::new()?.descs(),
+        })
+    }
+
+    /// Whether there is at least one AMD GPU.
+    pub fn has_devices(&self) -> bool {
+        !self.cards().is_empty()
+    }
+
+    fn cards(&self) -> Vec<Card> {
+        let entries = match fs::read_dir(&self.root) {
+            Ok(entries) => entries,
+            Err(_) => return Vec::new(),
+        };
+
+        let mut cards: Vec<Card> = entries
+            .filter_map(|entry| entry.ok())
+            .filter_map(|entry| {
+                let name = entry.file_name().to_string_lossy().into_owned();
+                // Connectors like card0-DP-1 are listed next to the cards
+                if !name.starts_with("card") || name.contains('-') {
+                    return None;
+                }
+
+                let device = entry.path().join("device");
+                let vendor = fs::read_to_string(device.join("vendor")).ok()?;
+                if vendor.trim() != AMD_VENDOR_ID {
+                    return None;
+                }
+
+                let pci_bus_id = fs::canonicalize(&device)
+                    .ok()?
+                    .file_name()?
+                    .to_string_lossy()
+                    .into_owned();
+
+                Some(Card {
+                    name,
+                    pci_bus_id,
+                    device,
+                })
+            })
+            .collect();
+        cards.sort_by(|a, b| a.name.cmp(&b.name));
+        cards
+    }
+
+    fn update(&self, metrics: &Metrics) -> Result<()> {
+        let cards = self.cards();
+        metrics.num_devices_gauge.set(cards.len() as i64);
+
+        for card in &cards {
+            let labels = [card.name.as_str(), card.pci_bus_id.as_str()];
+
+            if let Some(busy) = card.read_u64("gpu_busy_percent") {
+                metrics
+                    .gpu_utilization_gauge
+                    .get_metric_with_label_values(&labels)?
+                    .set(busy as i64);
+            }
+
+            if let Some(busy) = card.read_u64("mem_busy_percent") {
+                metrics
+                    .memory_utilization_gauge
+                    .get_metric_with_label_values(&labels)?
+                    .set(busy as i64);
+            }
+
+            if let Some(total) = card.read_u64("mem_info_vram_total") {
+                metrics
+                    .total_memory_gauge
+                    .get_metric_with_label_values(&labels)?
+                    .set(total as i64);
+            }
+
+            if let Some(used) = card.read_u64("mem_info_vram_used") {
+                metrics
+                    .used_memory_gauge
+                    .get_metric_with_label_values(&labels)?
+                    .set(used as i64);
+            }
+
+            // hwmon reports millidegrees celsius
+            if let Some(temperature) = card.read_hwmon("temp1_input") {
+                metrics
+                    .temperature_gauge
+                    .get_metric_with_label_values(&labels)?
+                    .set((temperature / 1000) as i64);
+            }
+
+            // hwmon reports microwatts
+            if let Some(power) = card.read_hwmon("power1_average") {
+                metrics
+                    .power_usage_gauge
+                    .get_metric_with_label_values(&labels)?
+                    .set((power / 1000) as i64);
+            }
+        }
+
+        Ok(())
+    }
+}
+
+impl Collector for AmdGpuCollector {
+    fn desc(&self) -> Vec<&Desc> {
+        self.descs.iter().collect()
+    }
+
+    fn collect(&self) -> Vec<MetricFamily> {
+        let metrics = match Metrics::new() {
+            Ok(metrics) => metrics,
+            Err(e) => {
+                eprintln!("Error creating AMD metrics: {}", e);
+                return Vec::new();
+            }
+        };
+
+        if let Err(e) = self.update(&metrics) {
+            eprintln!("Error collecting AMD metrics: {}", e);
+        }
+
+        metrics.families()
+    }
+}
@@ -0,0 +1,195 @@
+use std::sync::Mutex;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::backend::{
+    ClockType, DeviceInfo, GpuBackend, MemoryInfo, OperationMode, PcieErrors, ProcessInfo,
+    ProcessType, Utilization,
+};
+use crate::error::{CollectingError, Result};
+
+const MIB: u64 = 1024 * 1024;
+
+/// Memory of every simulated device.
+const MEMORY_TOTAL: u64 = 16 * 1024 * MIB;
+/// Memory used by the driver, without any processes.
+const MEMORY_RESERVED: u64 = 300 * MIB;
+/// Power usage of an idle device in milliwatts.
+const IDLE_POWER: u32 = 40_000;
+const POWER_LIMIT: u32 = 300_000;
+/// Range of the graphics and SM clocks in MHz.
+const MIN_CLOCK: u32 = 135;
+const MAX_CLOCK: u32 = 1530;
+
+/// State of a simulated device.
+struct FakeDevice {
+    /// GPU utilization in percent, which drives all other readings.
+    utilization: f64,
+    processes: Vec<ProcessInfo>,
+}
+
+/// Backend simulating devices with plausible readings, for developing
+/// dashboards and testing scrape configurations without GPUs.
+///
+/// The utilization of each device takes a random walk, a step per reading,
+/// and power usage, clocks, temperature and fan speed follow it. Memory totals
+/// and processes are fixed.
+pub struct FakeBackend {
+    devices: Mutex<Vec<FakeDevice>>,
+    /// State of the xorshift generator behind the random walks.
+    random: Mutex<u64>,
+}
+
+impl FakeBackend {
+    /// Simulates `count` devices.
+    pub fn new(count: u32) -> FakeBackend {
+        let devices = (0..count)
+            .map(|index| FakeDevice {
+                utilization: f64::from(index * 37 % 100),
+                // Above the largest possible pid, so they are never resolved to real
+                // processes
+                processes: (0..index % 3)
+                    .map(|i| ProcessInfo {
+                        pid: 4_200_000 + index * 10 + i,
+                        used_memory: Some(u64::from(1024 + 512 * (index + i)) * MIB),
+                        process_type: ProcessType::Compute,
+                    })
+                    .collect(),
+            })
+            .collect();
+        let seed = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(1, |since| since.as_nanos() as u64 | 1);
+
+        FakeBackend {
+            devices: Mutex::new(devices),
+            random: Mutex::new(seed),
+        }
+    }
+
+    /// A uniformly distributed number in `[-1, 1)`.
+    fn random(&self) -> f64 {
+        let mut state = self.random.lock().expect("Random state poisoned");
+        *state ^= *state << 13;
+        *state ^= *state >> 7;
+        *state ^= *state << 17;
+        (*state >> 11) as f64 / (1u64 << 52) as f64 - 1.0
+    }
+
+    /// Current utilization of the device `index` as a fraction.
+    fn load(&self, index: u32) -> Result<f64> {
+        let devices = self.devices.lock().expect("Fake devices poisoned");
+        let device = devices
+            .get(index as usize)
+            .ok_or(CollectingError::NotFound)?;
+        Ok(device.utilization / 100.0)
+    }
+
+    /// Scales `load` to the range from `min` to `max`.
+    fn scaled(&self, index: u32, min: u32, max: u32) -> Result<u32> {
+        let load = self.load(index)?;
+        Ok(min + (f64::from(max - min) * load).round() as u32)
+    }
+}
+
+impl GpuBackend for FakeBackend {
+    fn device_count(&self) -> Result<u32> {
+        Ok(self.devices.lock().expect("Fake devices poisoned").len() as u32)
+    }
+
+    fn device_info(&self, index: u32) -> Result<DeviceInfo> {
+        self.load(index)?;
+
+        Ok(DeviceInfo {
+            index,
+            minor_number: Some(index),
+            uuid: format!("GPU-fa4e0000-0000-0000-0000-{:012x}", index),
+            name: "Fake GPU".to_string(),
+            pci_bus_id: Some(format!("00000000:{:02X}:00.0", index + 1)),
+            serial: Some(format!("{:013}", 1_320_000_000_000u64 + u64::from(index))),
+        })
+    }
+
+    /// Takes a step of the random walk.
+    fn utilization(&self, index: u32) -> Result<Utilization> {
+        let step = self.random() * 10.0;
+        let mut devices = self.devices.lock().expect("Fake devices poisoned");
+        let device = devices
+            .get_mut(index as usize)
+            .ok_or(CollectingError::NotFound)?;
+        device.utilization = (device.utilization + step).max(0.0).min(100.0);
+
+        Ok(Utilization {
+            gpu: device.utilization.round() as u32,
+            memory: Some((device.utilization * 0.6).round() as u32),
+        })
+    }
+
+    fn memory_info(&self, index: u32) -> Result<MemoryInfo> {
+        let processes = self.processes(index)?;
+        let used = MEMORY_RESERVED
+            + processes
+                .iter()
+                .filter_map(|process| process.used_memory)
+                .sum::<u64>();
+
+        Ok(MemoryInfo {
+            total: MEMORY_TOTAL,
+            free: MEMORY_TOTAL - used,
+            used,
+        })
+    }
+
+    fn processes(&self, index: u32) -> Result<Vec<ProcessInfo>> {
+        let devices = self.devices.lock().expect("Fake devices poisoned");
+        let device = devices
+            .get(index as usize)
+            .ok_or(CollectingError::NotFound)?;
+        Ok(device.processes.clone())
+    }
+
+    fn power_usage(&self, index: u32) -> Result<u32> {
+        self.scaled(index, IDLE_POWER, POWER_LIMIT)
+    }
+
+    fn power_limit(&self, index: u32) -> Result<u32> {
+        self.load(index)?;
+        Ok(POWER_LIMIT)
+    }
+
+    fn clock(&self, index: u32, _clock: ClockType) -> Result<u32> {
+        self.scaled(index, MIN_CLOCK, MAX_CLOCK)
+    }
+
+    fn temperature(&self, index: u32) -> Result<u32> {
+        self.scaled(index, 32, 78)
+    }
+
+    fn fan_speed(&self, index: u32) -> Result<u32> {
+        self.scaled(index, 30, 85)
+    }
+
+    fn pcie_errors(&self, index: u32) -> Result<PcieErrors> {
+        self.load(index)?;
+        Ok(PcieErrors {
+            replays: Some(0),
+            correctable: Some(0),
+            non_fatal: Some(0),
+            fatal: Some(0),
+        })
+    }
+
+    fn compute_capability(&self, index: u32) -> Result<(u32, u32)> {
+        self.load(index)?;
+        Ok((7, 0))
+    }
+
+    fn operation_mode(&self, index: u32) -> Result<OperationMode> {
+        self.load(index)?;
+        Ok(OperationMode::AllOn)
+    }
+
+    fn persistence_mode(&self, index: u32) -> Result<bool> {
+        self.load(index)?;
+        Ok(true)
+    }
+}
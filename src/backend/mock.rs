@@ -0,0 +1,346 @@
+use std::collections::VecDeque;
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+
+use crate::backend::{
+    probe_feature, ClockType, DeviceInfo, Feature, GpmMetrics, GpuBackend, GridLicense, MemoryInfo,
+    MigDevice, OperationMode, PcieErrors, ProcessInfo, ProcessUtilization, ThrottleReason,
+    Utilization, XidError,
+};
+use crate::error::{CollectingError, Result};
+
+/// Readings of a single device served by [`MockBackend`].
+///
+/// Readings left at `None` are reported as not supported.
+#[derive(Clone, Debug)]
+pub struct MockDevice {
+    pub info: DeviceInfo,
+    pub utilization: Option<Utilization>,
+    pub memory_info: Option<MemoryInfo>,
+    pub processes: Vec<ProcessInfo>,
+    pub process_utilization: Option<Vec<ProcessUtilization>>,
+    /// Clients of the MPS server, if MPS is supported.
+    pub mps_processes: Option<Vec<ProcessInfo>>,
+    pub power_usage: Option<u32>,
+    pub module_power_usage: Option<u32>,
+    pub memory_power_usage: Option<u32>,
+    pub power_limit: Option<u32>,
+    /// Energy consumed in millijoules.
+    pub total_energy: Option<u64>,
+    pub graphics_clock: Option<u32>,
+    pub sm_clock: Option<u32>,
+    pub temperature: Option<u32>,
+    pub memory_temperature: Option<u32>,
+    pub throttle_reasons: Option<Vec<ThrottleReason>>,
+    pub fan_speed: Option<u32>,
+    pub fan_speed_rpm: Option<u32>,
+    pub target_fan_speed: Option<u32>,
+    pub grid_licenses: Option<Vec<GridLicense>>,
+    pub pcie_errors: Option<PcieErrors>,
+    pub gpm_metrics: Option<GpmMetrics>,
+    /// MIG devices, if MIG is supported.
+    pub mig_devices: Option<Vec<MigDevice>>,
+    pub compute_capability: Option<(u32, u32)>,
+    pub vbios_version: Option<String>,
+    pub numa_node: Option<u32>,
+    pub cpu_affinity: Option<Vec<u32>>,
+    pub physical_slot: Option<String>,
+    pub performance_state: Option<u32>,
+    pub operation_mode: Option<OperationMode>,
+    pub persistence_mode: Option<bool>,
+    /// ECC, NVLink and MIG support. Fans and power readings are supported if
+    /// their readings are set.
+    pub features: Vec<Feature>,
+    /// Graphics clock range in MHz set through
+    /// [`GpuBackend::set_locked_clocks`].
+    pub locked_clocks: Option<(u32, u32)>,
+}
+
+impl MockDevice {
+    /// Creates a device without any readings, with a UUID derived from its index.
+    pub fn new(index: u32, name: &str) -> MockDevice {
+        MockDevice {
+            info: DeviceInfo {
+                index,
+                minor_number: Some(index),
+                uuid: format!("GPU-00000000-0000-0000-0000-{:012x}", index),
+                name: name.to_string(),
+                pci_bus_id: Some(format!("00000000:{:02X}:00.0", index + 1)),
+                serial: None,
+            },
+            utilization: None,
+            memory_info: None,
+            processes: Vec::new(),
+            process_utilization: None,
+            mps_processes: None,
+            power_usage: None,
+            module_power_usage: None,
+            memory_power_usage: None,
+            power_limit: None,
+            total_energy: None,
+            graphics_clock: None,
+            sm_clock: None,
+            temperature: None,
+            memory_temperature: None,
+            throttle_reasons: None,
+            fan_speed: None,
+            fan_speed_rpm: None,
+            target_fan_speed: None,
+            grid_licenses: None,
+            pcie_errors: None,
+            gpm_metrics: None,
+            mig_devices: None,
+            compute_capability: None,
+            vbios_version: None,
+            numa_node: None,
+            cpu_affinity: None,
+            physical_slot: None,
+            performance_state: None,
+            operation_mode: None,
+            persistence_mode: None,
+            features: Vec::new(),
+            locked_clocks: None,
+        }
+    }
+}
+
+/// In-memory backend with fixed readings, for testing without GPUs.
+///
+/// Clones share their devices, so settings changed through one clone are
+/// visible through all others.
+#[derive(Clone, Debug, Default)]
+pub struct MockBackend {
+    devices: Arc<Mutex<Vec<MockDevice>>>,
+    /// XID errors not waited for yet.
+    xid_errors: Arc<Mutex<VecDeque<XidError>>>,
+}
+
+impl MockBackend {
+    pub fn new(devices: Vec<MockDevice>) -> MockBackend {
+        MockBackend {
+            devices: Arc::new(Mutex::new(devices)),
+            xid_errors: Arc::default(),
+        }
+    }
+
+    fn device(&self, index: u32) -> Result<MockDevice> {
+        self.devices
+            .lock()
+            .expect("Mock devices poisoned")
+            .get(index as usize)
+            .cloned()
+            .ok_or(CollectingError::NotFound)
+    }
+
+    /// Changes the readings of the device at `index`, e.g. to simulate
+    /// counters advancing between collections.
+    pub fn update(&self, index: u32, f: impl FnOnce(&mut MockDevice)) {
+        let mut devices = self.devices.lock().expect("Mock devices poisoned");
+        f(&mut devices[index as usize]);
+    }
+
+    /// Reports the critical XID error `xid` of the device at `index` to the
+    /// next wait for XID errors.
+    pub fn report_xid_error(&self, index: u32, xid: u64) {
+        self.xid_errors
+            .lock()
+            .expect("Mock XID errors poisoned")
+            .push_back(XidError { index, xid });
+    }
+
+    /// Replaces a setting of the device at `index`, if the device supports it.
+    fn set<T>(&self, index: u32, value: T, f: fn(&mut MockDevice) -> &mut Option<T>) -> Result<()> {
+        let mut devices = self.devices.lock().expect("Mock devices poisoned");
+        let device = devices
+            .get_mut(index as usize)
+            .ok_or(CollectingError::NotFound)?;
+
+        match f(device) {
+            Some(setting) => {
+                *setting = value;
+                Ok(())
+            }
+            None => Err(CollectingError::NotSupported),
+        }
+    }
+}
+
+fn supported<T: Clone>(value: &Option<T>) -> Result<T> {
+    value.clone().ok_or(CollectingError::NotSupported)
+}
+
+impl GpuBackend for MockBackend {
+    fn device_count(&self) -> Result<u32> {
+        Ok(self.devices.lock().expect("Mock devices poisoned").len() as u32)
+    }
+
+    fn device_info(&self, index: u32) -> Result<DeviceInfo> {
+        Ok(self.device(index)?.info)
+    }
+
+    fn utilization(&self, index: u32) -> Result<Utilization> {
+        supported(&self.device(index)?.utilization)
+    }
+
+    fn memory_info(&self, index: u32) -> Result<MemoryInfo> {
+        supported(&self.device(index)?.memory_info)
+    }
+
+    fn processes(&self, index: u32) -> Result<Vec<ProcessInfo>> {
+        Ok(self.device(index)?.processes)
+    }
+
+    fn process_utilization(&self, index: u32) -> Result<Vec<ProcessUtilization>> {
+        supported(&self.device(index)?.process_utilization)
+    }
+
+    fn mps_processes(&self, index: u32) -> Result<Vec<ProcessInfo>> {
+        supported(&self.device(index)?.mps_processes)
+    }
+
+    fn power_usage(&self, index: u32) -> Result<u32> {
+        supported(&self.device(index)?.power_usage)
+    }
+
+    fn module_power_usage(&self, index: u32) -> Result<u32> {
+        supported(&self.device(index)?.module_power_usage)
+    }
+
+    fn memory_power_usage(&self, index: u32) -> Result<u32> {
+        supported(&self.device(index)?.memory_power_usage)
+    }
+
+    fn power_limit(&self, index: u32) -> Result<u32> {
+        supported(&self.device(index)?.power_limit)
+    }
+
+    fn total_energy(&self, index: u32) -> Result<u64> {
+        supported(&self.device(index)?.total_energy)
+    }
+
+    fn clock(&self, index: u32, clock: ClockType) -> Result<u32> {
+        let device = self.device(index)?;
+        match clock {
+            ClockType::Graphics => supported(&device.graphics_clock),
+            ClockType::Sm => supported(&device.sm_clock),
+        }
+    }
+
+    fn temperature(&self, index: u32) -> Result<u32> {
+        supported(&self.device(index)?.temperature)
+    }
+
+    fn memory_temperature(&self, index: u32) -> Result<u32> {
+        supported(&self.device(index)?.memory_temperature)
+    }
+
+    fn throttle_reasons(&self, index: u32) -> Result<Vec<ThrottleReason>> {
+        supported(&self.device(index)?.throttle_reasons)
+    }
+
+    fn fan_speed(&self, index: u32) -> Result<u32> {
+        supported(&self.device(index)?.fan_speed)
+    }
+
+    fn fan_speed_rpm(&self, index: u32) -> Result<u32> {
+        supported(&self.device(index)?.fan_speed_rpm)
+    }
+
+    fn target_fan_speed(&self, index: u32) -> Result<u32> {
+        supported(&self.device(index)?.target_fan_speed)
+    }
+
+    fn grid_licenses(&self, index: u32) -> Result<Vec<GridLicense>> {
+        supported(&self.device(index)?.grid_licenses)
+    }
+
+    fn pcie_errors(&self, index: u32) -> Result<PcieErrors> {
+        supported(&self.device(index)?.pcie_errors)
+    }
+
+    fn gpm_metrics(&self, index: u32) -> Result<GpmMetrics> {
+        supported(&self.device(index)?.gpm_metrics)
+    }
+
+    fn probe_feature(&self, index: u32, feature: Feature) -> Result<()> {
+        match feature {
+            Feature::Fan | Feature::PowerReadings => probe_feature(self, index, feature),
+            _ if self.device(index)?.features.contains(&feature) => Ok(()),
+            _ => Err(CollectingError::NotSupported),
+        }
+    }
+
+    fn mig_devices(&self, index: u32) -> Result<Vec<MigDevice>> {
+        supported(&self.device(index)?.mig_devices)
+    }
+
+    fn compute_capability(&self, index: u32) -> Result<(u32, u32)> {
+        supported(&self.device(index)?.compute_capability)
+    }
+
+    fn vbios_version(&self, index: u32) -> Result<String> {
+        supported(&self.device(index)?.vbios_version)
+    }
+
+    fn numa_node(&self, index: u32) -> Result<u32> {
+        supported(&self.device(index)?.numa_node)
+    }
+
+    fn cpu_affinity(&self, index: u32) -> Result<Vec<u32>> {
+        supported(&self.device(index)?.cpu_affinity)
+    }
+
+    fn physical_slot(&self, index: u32) -> Result<String> {
+        supported(&self.device(index)?.physical_slot)
+    }
+
+    fn performance_state(&self, index: u32) -> Result<u32> {
+        supported(&self.device(index)?.performance_state)
+    }
+
+    fn operation_mode(&self, index: u32) -> Result<OperationMode> {
+        supported(&self.device(index)?.operation_mode)
+    }
+
+    fn persistence_mode(&self, index: u32) -> Result<bool> {
+        supported(&self.device(index)?.persistence_mode)
+    }
+
+    fn set_power_limit(&self, index: u32, milliwatts: u32) -> Result<()> {
+        self.set(index, milliwatts, |device| &mut device.power_limit)
+    }
+
+    /// Pins the graphics clock to `max`, if the device reports its clock.
+    fn set_locked_clocks(&self, index: u32, min: u32, max: u32) -> Result<()> {
+        self.set(index, max, |device| &mut device.graphics_clock)?;
+        let mut devices = self.devices.lock().expect("Mock devices poisoned");
+        devices[index as usize].locked_clocks = Some((min, max));
+        Ok(())
+    }
+
+    fn reset_locked_clocks(&self, index: u32) -> Result<()> {
+        let mut devices = self.devices.lock().expect("Mock devices poisoned");
+        let device = devices
+            .get_mut(index as usize)
+            .ok_or(CollectingError::NotFound)?;
+        device.locked_clocks = None;
+        Ok(())
+    }
+
+    fn set_persistence_mode(&self, index: u32, enabled: bool) -> Result<()> {
+        self.set(index, enabled, |device| &mut device.persistence_mode)
+    }
+
+    fn wait_xid_error(&self, timeout: Duration) -> Result<Option<XidError>> {
+        let error = self
+            .xid_errors
+            .lock()
+            .expect("Mock XID errors poisoned")
+            .pop_front();
+        if error.is_none() {
+            thread::sleep(timeout);
+        }
+        Ok(error)
+    }
+}
@@ -0,0 +1,674 @@
+//! Abstraction over the source of GPU readings.
+//!
+//! The collector only talks to a [`GpuBackend`], which is implemented on top of
+//! NVML for real hardware, on top of sysfs for Jetson boards without NVML, by
+//! [`FakeBackend`] to simulate GPUs and by [`MockBackend`] for tests.
+
+use std::time::Duration;
+
+use serde::Serialize;
+
+use crate::error::{CollectingError, Result};
+
+mod fake;
+mod mock;
+mod nvml;
+mod nvml_ext;
+mod tegra;
+
+pub use self::fake::FakeBackend;
+pub use self::mock::{MockBackend, MockDevice};
+pub use self::nvml::NvmlBackend;
+pub use self::tegra::TegraBackend;
+
+/// Identity of a device.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct DeviceInfo {
+    pub index: u32,
+    /// Minor number of the device node, which only exists on Linux.
+    pub minor_number: Option<u32>,
+    pub uuid: String,
+    pub name: String,
+    /// PCI bus ID, e.g. `00000000:3B:00.0`.
+    pub pci_bus_id: Option<String>,
+    /// Board serial number, which not all devices report.
+    pub serial: Option<String>,
+}
+
+/// Percent of time over the past sample period during which the GPU
+/// respectively its memory was busy.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize)]
+pub struct Utilization {
+    pub gpu: u32,
+    /// Not available on devices sharing their memory with the host.
+    pub memory: Option<u32>,
+}
+
+/// Memory of a device in bytes.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize)]
+pub struct MemoryInfo {
+    pub total: u64,
+    pub free: u64,
+    pub used: u64,
+}
+
+/// Clock domains that are exported.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum ClockType {
+    Graphics,
+    Sm,
+}
+
+/// PCIe link counters since the driver was loaded. Counters the device does
+/// not report are `None`.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize)]
+pub struct PcieErrors {
+    pub replays: Option<u64>,
+    pub correctable: Option<u64>,
+    pub non_fatal: Option<u64>,
+    pub fatal: Option<u64>,
+}
+
+/// A critical XID error reported by the driver, see
+/// [`GpuBackend::wait_xid_error`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct XidError {
+    /// Index of the device.
+    pub index: u32,
+    pub xid: u64,
+}
+
+/// A reading that backends may read together with others in one call, see
+/// [`GpuBackend::field_values`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Field {
+    /// Power usage in milliwatts.
+    PowerUsage,
+    /// Power management limit in milliwatts.
+    PowerLimit,
+    /// Energy consumed since the driver was loaded in millijoules.
+    TotalEnergy,
+    PcieReplays,
+    PcieCorrectableErrors,
+    PcieNonFatalErrors,
+    PcieFatalErrors,
+    /// Memory temperature in degrees celsius.
+    MemoryTemperature,
+    /// Power usage of the whole module in milliwatts.
+    ModulePowerUsage,
+    /// Power usage of the memory in milliwatts.
+    MemoryPowerUsage,
+}
+
+impl Field {
+    /// All fields, which are read together for each device.
+    pub const ALL: [Field; 10] = [
+        Field::PowerUsage,
+        Field::PowerLimit,
+        Field::TotalEnergy,
+        Field::PcieReplays,
+        Field::PcieCorrectableErrors,
+        Field::PcieNonFatalErrors,
+        Field::PcieFatalErrors,
+        Field::MemoryTemperature,
+        Field::ModulePowerUsage,
+        Field::MemoryPowerUsage,
+    ];
+}
+
+/// Reads `field` of the device `index` through the dedicated method of
+/// `backend`.
+pub fn read_field<B: GpuBackend + ?Sized>(backend: &B, index: u32, field: Field) -> Result<u64> {
+    let pcie_error = |count: fn(PcieErrors) -> Option<u64>| {
+        count(backend.pcie_errors(index)?).ok_or(CollectingError::NotSupported)
+    };
+
+    match field {
+        Field::PowerUsage => backend.power_usage(index).map(u64::from),
+        Field::PowerLimit => backend.power_limit(index).map(u64::from),
+        Field::TotalEnergy => backend.total_energy(index),
+        Field::PcieReplays => pcie_error(|errors| errors.replays),
+        Field::PcieCorrectableErrors => pcie_error(|errors| errors.correctable),
+        Field::PcieNonFatalErrors => pcie_error(|errors| errors.non_fatal),
+        Field::PcieFatalErrors => pcie_error(|errors| errors.fatal),
+        Field::MemoryTemperature => backend.memory_temperature(index).map(u64::from),
+        Field::ModulePowerUsage => backend.module_power_usage(index).map(u64::from),
+        Field::MemoryPowerUsage => backend.memory_power_usage(index).map(u64::from),
+    }
+}
+
+/// An optional capability of a device, see [`GpuBackend::probe_feature`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Feature {
+    Ecc,
+    Nvlink,
+    Mig,
+    Fan,
+    PowerReadings,
+}
+
+impl Feature {
+    pub const ALL: [Feature; 5] = [
+        Feature::Ecc,
+        Feature::Nvlink,
+        Feature::Mig,
+        Feature::Fan,
+        Feature::PowerReadings,
+    ];
+
+    /// Name used in the `feature` label.
+    pub fn name(self) -> &'static str {
+        match self {
+            Feature::Ecc => "ecc",
+            Feature::Nvlink => "nvlink",
+            Feature::Mig => "mig",
+            Feature::Fan => "fan",
+            Feature::PowerReadings => "power_readings",
+        }
+    }
+}
+
+/// Probes `feature` of the device `index` through the readings of `backend`
+/// that depend on it. Features without such a reading are not supported.
+pub fn probe_feature<B: GpuBackend + ?Sized>(
+    backend: &B,
+    index: u32,
+    feature: Feature,
+) -> Result<()> {
+    match feature {
+        Feature::Fan => backend.fan_speed(index).map(drop),
+        Feature::PowerReadings => backend.power_usage(index).map(drop),
+        Feature::Ecc | Feature::Nvlink | Feature::Mig => Err(CollectingError::NotSupported),
+    }
+}
+
+/// A reason for the driver to hold the clocks of a device below their
+/// maximum, see [`GpuBackend::throttle_reasons`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ThrottleReason {
+    GpuIdle,
+    ApplicationsClocksSetting,
+    SwPowerCap,
+    HwSlowdown,
+    SyncBoost,
+    SwThermalSlowdown,
+    HwThermalSlowdown,
+    HwPowerBrakeSlowdown,
+    DisplayClockSetting,
+}
+
+impl ThrottleReason {
+    pub const ALL: [ThrottleReason; 9] = [
+        ThrottleReason::GpuIdle,
+        ThrottleReason::ApplicationsClocksSetting,
+        ThrottleReason::SwPowerCap,
+        ThrottleReason::HwSlowdown,
+        ThrottleReason::SyncBoost,
+        ThrottleReason::SwThermalSlowdown,
+        ThrottleReason::HwThermalSlowdown,
+        ThrottleReason::HwPowerBrakeSlowdown,
+        ThrottleReason::DisplayClockSetting,
+    ];
+
+    /// Name used in the `reason` label.
+    pub fn name(self) -> &'static str {
+        match self {
+            ThrottleReason::GpuIdle => "gpu_idle",
+            ThrottleReason::ApplicationsClocksSetting => "applications_clocks_setting",
+            ThrottleReason::SwPowerCap => "sw_power_cap",
+            ThrottleReason::HwSlowdown => "hw_slowdown",
+            ThrottleReason::SyncBoost => "sync_boost",
+            ThrottleReason::SwThermalSlowdown => "sw_thermal_slowdown",
+            ThrottleReason::HwThermalSlowdown => "hw_thermal_slowdown",
+            ThrottleReason::HwPowerBrakeSlowdown => "hw_power_brake_slowdown",
+            ThrottleReason::DisplayClockSetting => "display_clock_setting",
+        }
+    }
+}
+
+/// Profiling metrics of the GPU performance monitoring (GPM) of Hopper and
+/// newer devices, in percent. Metrics the device does not report are `None`.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Serialize)]
+pub struct GpmMetrics {
+    /// Share of time at least one warp was active on an SM, averaged over
+    /// all SMs.
+    pub sm_activity: Option<f64>,
+    /// Warps resident on the SMs relative to the maximum.
+    pub sm_occupancy: Option<f64>,
+    /// Share of time any tensor pipe was active.
+    pub tensor_activity: Option<f64>,
+    /// Used DRAM bandwidth relative to the maximum.
+    pub dram_bandwidth: Option<f64>,
+}
+
+/// State of a licensable vGPU feature.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct GridLicense {
+    /// Feature code, e.g. `vgpu` or `compute`.
+    pub feature: String,
+    /// Licensed product, e.g. `NVIDIA Virtual Compute Server`.
+    pub product: String,
+    /// Whether a license for the feature was acquired.
+    pub licensed: bool,
+    /// Whether the feature is enabled.
+    pub enabled: bool,
+}
+
+/// GPU operation mode, which disables features to save power on Tesla and
+/// Quadro devices.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum OperationMode {
+    /// Everything is enabled.
+    AllOn,
+    /// Graphics operations are disabled.
+    Compute,
+    /// Graphics operations and double precision floating point are disabled.
+    LowDoublePrecision,
+}
+
+/// Kind of context a process holds on a device.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ProcessType {
+    Compute,
+    Graphics,
+}
+
+/// A process running on a device.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct ProcessInfo {
+    pub pid: u32,
+    /// GPU memory used by the process in bytes, if the driver reports it.
+    pub used_memory: Option<u64>,
+    /// Processes with both a compute and a graphics context are reported as
+    /// compute processes.
+    pub process_type: ProcessType,
+}
+
+/// A MIG device, an instance of a GPU partitioned with MIG mode.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct MigDevice {
+    /// UUID of the instance, e.g. `MIG-8c1d2f3e-6a1b-7c2d-8e3f-4a5b6c7d8e9f`.
+    pub uuid: String,
+    /// Memory of the instance, which is part of the memory of its GPU.
+    pub memory_info: MemoryInfo,
+}
+
+/// Utilization of a device by a single process, in percent.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct ProcessUtilization {
+    pub pid: u32,
+    /// Share of time a kernel of the process was running.
+    pub gpu: u32,
+    /// Share of time device memory was read or written by the process.
+    pub memory: u32,
+}
+
+/// Source of device readings, addressed by device index.
+///
+/// Readings that a backend cannot provide default to
+/// [`CollectingError::NotSupported`], which the collector treats as a missing
+/// metric rather than a failed collection.
+pub trait GpuBackend: Send + Sync {
+    fn device_count(&self) -> Result<u32>;
+
+    fn device_info(&self, index: u32) -> Result<DeviceInfo>;
+
+    fn utilization(&self, index: u32) -> Result<Utilization>;
+
+    fn memory_info(&self, index: u32) -> Result<MemoryInfo>;
+
+    fn processes(&self, index: u32) -> Result<Vec<ProcessInfo>>;
+
+    /// Utilization of the device by each process since the previous call for
+    /// the device. Processes that were idle in between are left out.
+    fn process_utilization(&self, _index: u32) -> Result<Vec<ProcessUtilization>> {
+        Err(CollectingError::NotSupported)
+    }
+
+    /// Clients of the MPS server running on the device, with the memory each
+    /// of them uses. Under MPS, [`GpuBackend::processes`] only lists the
+    /// server.
+    fn mps_processes(&self, _index: u32) -> Result<Vec<ProcessInfo>> {
+        Err(CollectingError::NotSupported)
+    }
+
+    /// Power usage in milliwatts.
+    fn power_usage(&self, _index: u32) -> Result<u32> {
+        Err(CollectingError::NotSupported)
+    }
+
+    /// Power usage of the whole module in milliwatts, on boards reporting the
+    /// power of their parts separately, e.g. Grace Hopper superchips.
+    fn module_power_usage(&self, _index: u32) -> Result<u32> {
+        Err(CollectingError::NotSupported)
+    }
+
+    /// Power usage of the memory in milliwatts, on boards reporting the power
+    /// of their parts separately.
+    fn memory_power_usage(&self, _index: u32) -> Result<u32> {
+        Err(CollectingError::NotSupported)
+    }
+
+    /// Power management limit in milliwatts.
+    fn power_limit(&self, _index: u32) -> Result<u32> {
+        Err(CollectingError::NotSupported)
+    }
+
+    /// Energy consumed since the driver was loaded in millijoules.
+    fn total_energy(&self, _index: u32) -> Result<u64> {
+        Err(CollectingError::NotSupported)
+    }
+
+    /// Current clock speed in MHz.
+    fn clock(&self, _index: u32, _clock: ClockType) -> Result<u32> {
+        Err(CollectingError::NotSupported)
+    }
+
+    /// GPU temperature in degrees celsius.
+    fn temperature(&self, _index: u32) -> Result<u32> {
+        Err(CollectingError::NotSupported)
+    }
+
+    /// Junction temperature of the memory in degrees celsius, which HBM
+    /// devices report separately from the GPU temperature.
+    fn memory_temperature(&self, _index: u32) -> Result<u32> {
+        Err(CollectingError::NotSupported)
+    }
+
+    /// Reasons the clocks are currently held below their maximum.
+    fn throttle_reasons(&self, _index: u32) -> Result<Vec<ThrottleReason>> {
+        Err(CollectingError::NotSupported)
+    }
+
+    /// Fan speed as a percent of its maximum.
+    fn fan_speed(&self, _index: u32) -> Result<u32> {
+        Err(CollectingError::NotSupported)
+    }
+
+    /// Fan speed in revolutions per minute.
+    fn fan_speed_rpm(&self, _index: u32) -> Result<u32> {
+        Err(CollectingError::NotSupported)
+    }
+
+    /// Fan speed the driver is driving the fan towards, as a percent of its
+    /// maximum.
+    fn target_fan_speed(&self, _index: u32) -> Result<u32> {
+        Err(CollectingError::NotSupported)
+    }
+
+    /// Licensable vGPU features.
+    fn grid_licenses(&self, _index: u32) -> Result<Vec<GridLicense>> {
+        Err(CollectingError::NotSupported)
+    }
+
+    /// PCIe replays and errors.
+    fn pcie_errors(&self, _index: u32) -> Result<PcieErrors> {
+        Err(CollectingError::NotSupported)
+    }
+
+    /// GPM metrics averaged since the previous call for the device. The first
+    /// call only takes the initial sample and reports no metrics.
+    fn gpm_metrics(&self, _index: u32) -> Result<GpmMetrics> {
+        Err(CollectingError::NotSupported)
+    }
+
+    /// Values of `fields` in their order. By default, every field is read
+    /// through its dedicated method; backends that can read several readings
+    /// in one call override this.
+    fn field_values(&self, index: u32, fields: &[Field]) -> Result<Vec<Result<u64>>> {
+        Ok(fields
+            .iter()
+            .map(|&field| read_field(self, index, field))
+            .collect())
+    }
+
+    /// Succeeds if the device supports `feature`, whether or not it is
+    /// enabled, and fails with [`CollectingError::NotSupported`] otherwise.
+    fn probe_feature(&self, index: u32, feature: Feature) -> Result<()> {
+        probe_feature(self, index, feature)
+    }
+
+    /// MIG devices the device is partitioned into, empty unless MIG mode is
+    /// enabled.
+    fn mig_devices(&self, _index: u32) -> Result<Vec<MigDevice>> {
+        Err(CollectingError::NotSupported)
+    }
+
+    /// CUDA compute capability as major and minor version.
+    fn compute_capability(&self, _index: u32) -> Result<(u32, u32)> {
+        Err(CollectingError::NotSupported)
+    }
+
+    /// Version of the VBIOS, e.g. `90.04.96.00.01`.
+    fn vbios_version(&self, _index: u32) -> Result<String> {
+        Err(CollectingError::NotSupported)
+    }
+
+    /// NUMA node closest to the device.
+    fn numa_node(&self, _index: u32) -> Result<u32> {
+        Err(CollectingError::NotSupported)
+    }
+
+    /// CPUs ideally running the processes using the device, in ascending
+    /// order.
+    fn cpu_affinity(&self, _index: u32) -> Result<Vec<u32>> {
+        Err(CollectingError::NotSupported)
+    }
+
+    /// Name of the physical slot the device is plugged into, as the firmware
+    /// labels it, e.g. `PCIe Slot 3`.
+    fn physical_slot(&self, _index: u32) -> Result<String> {
+        Err(CollectingError::NotSupported)
+    }
+
+    /// Current performance state, from 0 for the maximum performance to 15
+    /// for the minimum. The deepest states the device supports, usually 8 or
+    /// 12, are the idle states in which clocks and unused units are gated.
+    fn performance_state(&self, _index: u32) -> Result<u32> {
+        Err(CollectingError::NotSupported)
+    }
+
+    /// Current GPU operation mode.
+    fn operation_mode(&self, _index: u32) -> Result<OperationMode> {
+        Err(CollectingError::NotSupported)
+    }
+
+    /// Whether persistence mode is enabled.
+    fn persistence_mode(&self, _index: u32) -> Result<bool> {
+        Err(CollectingError::NotSupported)
+    }
+
+    /// Sets the power management limit in milliwatts.
+    fn set_power_limit(&self, _index: u32, _milliwatts: u32) -> Result<()> {
+        Err(CollectingError::NotSupported)
+    }
+
+    /// Locks the graphics clock to the range from `min` to `max` MHz.
+    fn set_locked_clocks(&self, _index: u32, _min: u32, _max: u32) -> Result<()> {
+        Err(CollectingError::NotSupported)
+    }
+
+    /// Removes a graphics clock lock.
+    fn reset_locked_clocks(&self, _index: u32) -> Result<()> {
+        Err(CollectingError::NotSupported)
+    }
+
+    /// Enables or disables persistence mode.
+    fn set_persistence_mode(&self, _index: u32, _enabled: bool) -> Result<()> {
+        Err(CollectingError::NotSupported)
+    }
+
+    /// Waits up to `timeout` for the next critical XID error of any device,
+    /// returning `None` if there was none in time.
+    fn wait_xid_error(&self, _timeout: Duration) -> Result<Option<XidError>> {
+        Err(CollectingError::NotSupported)
+    }
+
+    /// Shuts down and reinitializes the underlying library, e.g. after the
+    /// driver crashed.
+    fn reinit(&self) -> Result<()> {
+        Err(CollectingError::NotSupported)
+    }
+}
+
+/// Allows choosing the backend at runtime.
+impl GpuBackend for Box<dyn GpuBackend> {
+    fn device_count(&self) -> Result<u32> {
+        (**self).device_count()
+    }
+
+    fn device_info(&self, index: u32) -> Result<DeviceInfo> {
+        (**self).device_info(index)
+    }
+
+    fn utilization(&self, index: u32) -> Result<Utilization> {
+        (**self).utilization(index)
+    }
+
+    fn memory_info(&self, index: u32) -> Result<MemoryInfo> {
+        (**self).memory_info(index)
+    }
+
+    fn processes(&self, index: u32) -> Result<Vec<ProcessInfo>> {
+        (**self).processes(index)
+    }
+
+    fn process_utilization(&self, index: u32) -> Result<Vec<ProcessUtilization>> {
+        (**self).process_utilization(index)
+    }
+
+    fn mps_processes(&self, index: u32) -> Result<Vec<ProcessInfo>> {
+        (**self).mps_processes(index)
+    }
+
+    fn power_usage(&self, index: u32) -> Result<u32> {
+        (**self).power_usage(index)
+    }
+
+    fn module_power_usage(&self, index: u32) -> Result<u32> {
+        (**self).module_power_usage(index)
+    }
+
+    fn memory_power_usage(&self, index: u32) -> Result<u32> {
+        (**self).memory_power_usage(index)
+    }
+
+    fn power_limit(&self, index: u32) -> Result<u32> {
+        (**self).power_limit(index)
+    }
+
+    fn total_energy(&self, index: u32) -> Result<u64> {
+        (**self).total_energy(index)
+    }
+
+    fn clock(&self, index: u32, clock: ClockType) -> Result<u32> {
+        (**self).clock(index, clock)
+    }
+
+    fn temperature(&self, index: u32) -> Result<u32> {
+        (**self).temperature(index)
+    }
+
+    fn memory_temperature(&self, index: u32) -> Result<u32> {
+        (**self).memory_temperature(index)
+    }
+
+    fn throttle_reasons(&self, index: u32) -> Result<Vec<ThrottleReason>> {
+        (**self).throttle_reasons(index)
+    }
+
+    fn fan_speed(&self, index: u32) -> Result<u32> {
+        (**self).fan_speed(index)
+    }
+
+    fn fan_speed_rpm(&self, index: u32) -> Result<u32> {
+        (**self).fan_speed_rpm(index)
+    }
+
+    fn target_fan_speed(&self, index: u32) -> Result<u32> {
+        (**self).target_fan_speed(index)
+    }
+
+    fn grid_licenses(&self, index: u32) -> Result<Vec<GridLicense>> {
+        (**self).grid_licenses(index)
+    }
+
+    fn pcie_errors(&self, index: u32) -> Result<PcieErrors> {
+        (**self).pcie_errors(index)
+    }
+
+    fn gpm_metrics(&self, index: u32) -> Result<GpmMetrics> {
+        (**self).gpm_metrics(index)
+    }
+
+    fn field_values(&self, index: u32, fields: &[Field]) -> Result<Vec<Result<u64>>> {
+        (**self).field_values(index, fields)
+    }
+
+    fn probe_feature(&self, index: u32, feature: Feature) -> Result<()> {
+        (**self).probe_feature(index, feature)
+    }
+
+    fn mig_devices(&self, index: u32) -> Result<Vec<MigDevice>> {
+        (**self).mig_devices(index)
+    }
+
+    fn compute_capability(&self, index: u32) -> Result<(u32, u32)> {
+        (**self).compute_capability(index)
+    }
+
+    fn vbios_version(&self, index: u32) -> Result<String> {
+        (**self).vbios_version(index)
+    }
+
+    fn numa_node(&self, index: u32) -> Result<u32> {
+        (**self).numa_node(index)
+    }
+
+    fn cpu_affinity(&self, index: u32) -> Result<Vec<u32>> {
+        (**self).cpu_affinity(index)
+    }
+
+    fn physical_slot(&self, index: u32) -> Result<String> {
+        (**self).physical_slot(index)
+    }
+
+    fn performance_state(&self, index: u32) -> Result<u32> {
+        (**self).performance_state(index)
+    }
+
+    fn operation_mode(&self, index: u32) -> Result<OperationMode> {
+        (**self).operation_mode(index)
+    }
+
+    fn persistence_mode(&self, index: u32) -> Result<bool> {
+        (**self).persistence_mode(index)
+    }
+
+    fn set_power_limit(&self, index: u32, milliwatts: u32) -> Result<()> {
+        (**self).set_power_limit(index, milliwatts)
+    }
+
+    fn set_locked_clocks(&self, index: u32, min: u32, max: u32) -> Result<()> {
+        (**self).set_locked_clocks(index, min, max)
+    }
+
+    fn reset_locked_clocks(&self, index: u32) -> Result<()> {
+        (**self).reset_locked_clocks(index)
+    }
+
+    fn set_persistence_mode(&self, index: u32, enabled: bool) -> Result<()> {
+        (**self).set_persistence_mode(index, enabled)
+    }
+
+    fn wait_xid_error(&self, timeout: Duration) -> Result<Option<XidError>> {
+        (**self).wait_xid_error(timeout)
+    }
+
+    fn reinit(&self) -> Result<()> {
+        (**self).reinit()
+    }
+}
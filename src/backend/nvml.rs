@@ -0,0 +1,644 @@
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::os::raw::c_ulong;
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use nvml_wrapper::bitmasks::device::ThrottleReasons;
+use nvml_wrapper::enum_wrappers::device::{self, Clock, TemperatureSensor};
+use nvml_wrapper::enums::device::UsedGpuMemory;
+use nvml_wrapper::error::NvmlError;
+use nvml_wrapper::{Device, NVML};
+
+use crate::backend::nvml_ext::{self, GpmSample};
+use crate::backend::{
+    probe_feature, read_field, ClockType, DeviceInfo, Feature, Field, GpmMetrics, GpuBackend,
+    GridLicense, MemoryInfo, MigDevice, OperationMode, PcieErrors, ProcessInfo, ProcessType,
+    ProcessUtilization, ThrottleReason, Utilization, XidError,
+};
+use crate::error::{CollectingError, Result};
+
+/// Backend reading devices through NVML.
+pub struct NvmlBackend {
+    /// `None` after a failed reinitialization. Calls hold on to the handle
+    /// they started with, so a hanging call does not block reinitialization.
+    nvml: Mutex<Option<Arc<NVML>>>,
+    /// Previous GPM sample by device index.
+    gpm_samples: Mutex<HashMap<u32, GpmSample>>,
+    /// Timestamp of the latest process utilization sample by device index.
+    process_samples_seen: Mutex<HashMap<u32, u64>>,
+    /// Event set with the XID errors of all devices, created on first wait.
+    xid_events: Mutex<Option<nvml_ext::EventSet>>,
+}
+
+impl NvmlBackend {
+    /// Initializes NVML.
+    pub fn new() -> Result<NvmlBackend> {
+        Ok(NvmlBackend::with_nvml(NVML::init()?))
+    }
+
+    /// Initializes NVML, looking up the functions that are newer than the
+    /// bindings in the library at `path`. The bindings themselves are linked
+    /// against the library found by the dynamic loader.
+    pub fn with_library_path(path: &Path) -> Result<NvmlBackend> {
+        nvml_ext::set_library_path(path)?;
+        NvmlBackend::new()
+    }
+
+    /// NVML field ID and scope of `field`.
+    pub fn field_id(field: Field) -> (u32, u32) {
+        match field {
+            Field::PowerUsage => (nvml_ext::FI_POWER_INSTANT, nvml_ext::POWER_SCOPE_GPU),
+            Field::PowerLimit => (nvml_ext::FI_POWER_REQUESTED_LIMIT, 0),
+            Field::TotalEnergy => (nvml_ext::FI_TOTAL_ENERGY_CONSUMPTION, 0),
+            Field::PcieReplays => (nvml_ext::FI_PCIE_REPLAY_COUNTER, 0),
+            Field::PcieCorrectableErrors => (nvml_ext::FI_PCIE_CORRECTABLE_ERRORS, 0),
+            Field::PcieNonFatalErrors => (nvml_ext::FI_PCIE_NON_FATAL_ERRORS, 0),
+            Field::PcieFatalErrors => (nvml_ext::FI_PCIE_FATAL_ERRORS, 0),
+            Field::MemoryTemperature => (nvml_ext::FI_MEMORY_TEMP, 0),
+            Field::ModulePowerUsage => (nvml_ext::FI_POWER_INSTANT, nvml_ext::POWER_SCOPE_MODULE),
+            Field::MemoryPowerUsage => (nvml_ext::FI_POWER_INSTANT, nvml_ext::POWER_SCOPE_MEMORY),
+        }
+    }
+
+    /// Uses an already initialized NVML handle.
+    pub fn with_nvml(nvml: NVML) -> NvmlBackend {
+        NvmlBackend {
+            nvml: Mutex::new(Some(Arc::new(nvml))),
+            gpm_samples: Mutex::new(HashMap::new()),
+            process_samples_seen: Mutex::new(HashMap::new()),
+            xid_events: Mutex::new(None),
+        }
+    }
+
+    fn nvml(&self) -> Result<Arc<NVML>> {
+        self.nvml
+            .lock()
+            .expect("NVML handle poisoned")
+            .clone()
+            .ok_or_else(|| NvmlError::Uninitialized.into())
+    }
+}
+
+#[cfg(target_os = "linux")]
+fn minor_number(device: &Device) -> Result<Option<u32>> {
+    Ok(Some(device.minor_number()?))
+}
+
+#[cfg(not(target_os = "linux"))]
+fn minor_number(_device: &Device) -> Result<Option<u32>> {
+    Ok(None)
+}
+
+/// Number of CPUs the affinity of devices is read for.
+const MAX_CPUS: usize = 1024;
+
+/// NUMA node of the PCI device `bus_id`, e.g. `00000000:3B:00.0`, which NVML
+/// only reports in newer versions than the bindings.
+#[cfg(target_os = "linux")]
+fn numa_node(bus_id: &str) -> Result<u32> {
+    // sysfs has a 16 bit domain and lowercase digits, e.g. 0000:3b:00.0
+    let bus_id = bus_id.to_lowercase();
+    let address = &bus_id[bus_id.len().saturating_sub(12)..];
+    let node = std::fs::read_to_string(format!("/sys/bus/pci/devices/{}/numa_node", address))
+        .map_err(|_| CollectingError::NotSupported)?;
+    // -1 on machines without NUMA
+    node.trim()
+        .parse()
+        .map_err(|_| CollectingError::NotSupported)
+}
+
+#[cfg(not(target_os = "linux"))]
+fn numa_node(_bus_id: &str) -> Result<u32> {
+    Err(CollectingError::NotSupported)
+}
+
+/// Physical slot of the PCI device `bus_id`, which the kernel lists in
+/// `/sys/bus/pci/slots` with the names from the ACPI tables or SMBIOS.
+#[cfg(target_os = "linux")]
+fn physical_slot(bus_id: &str) -> Result<String> {
+    let bus_id = bus_id.to_lowercase();
+    let address = &bus_id[bus_id.len().saturating_sub(12)..];
+    let device = std::fs::canonicalize(format!("/sys/bus/pci/devices/{}", address))
+        .map_err(|_| CollectingError::NotSupported)?;
+
+    // Slots are identified by domain, bus and device, e.g. 0000:3b:00
+    let mut slots = Vec::new();
+    for entry in
+        std::fs::read_dir("/sys/bus/pci/slots").map_err(|_| CollectingError::NotSupported)?
+    {
+        let entry = entry.map_err(|_| CollectingError::NotSupported)?;
+        if let Ok(slot_address) = std::fs::read_to_string(entry.path().join("address")) {
+            let name = entry.file_name().to_string_lossy().into_owned();
+            slots.push((slot_address.trim().to_lowercase(), name));
+        }
+    }
+
+    // Behind a PCIe switch, the slot holds one of the bridges above the GPU
+    device
+        .ancestors()
+        .filter_map(|path| path.file_name()?.to_str())
+        .find_map(|function| {
+            let (address, _) = function.split_at(function.rfind('.')?);
+            slots
+                .iter()
+                .find(|(slot_address, _)| slot_address == address)
+                .map(|(_, name)| name.clone())
+        })
+        .ok_or(CollectingError::NotSupported)
+}
+
+#[cfg(not(target_os = "linux"))]
+fn physical_slot(_bus_id: &str) -> Result<String> {
+    Err(CollectingError::NotSupported)
+}
+
+impl GpuBackend for NvmlBackend {
+    fn device_count(&self) -> Result<u32> {
+        Ok(self.nvml()?.device_count()?)
+    }
+
+    fn device_info(&self, index: u32) -> Result<DeviceInfo> {
+        let nvml = self.nvml()?;
+        let device = nvml.device_by_index(index)?;
+
+        Ok(DeviceInfo {
+            index,
+            minor_number: minor_number(&device)?,
+            uuid: device.uuid()?,
+            name: device.name()?,
+            pci_bus_id: device.pci_info().ok().map(|pci| pci.bus_id),
+            serial: device.serial().ok(),
+        })
+    }
+
+    fn utilization(&self, index: u32) -> Result<Utilization> {
+        let utilization = self.nvml()?.device_by_index(index)?.utilization_rates()?;
+
+        Ok(Utilization {
+            gpu: utilization.gpu,
+            memory: Some(utilization.memory),
+        })
+    }
+
+    fn memory_info(&self, index: u32) -> Result<MemoryInfo> {
+        let memory_info = self.nvml()?.device_by_index(index)?.memory_info()?;
+
+        Ok(MemoryInfo {
+            total: memory_info.total,
+            free: memory_info.free,
+            used: memory_info.used,
+        })
+    }
+
+    fn processes(&self, index: u32) -> Result<Vec<ProcessInfo>> {
+        let nvml = self.nvml()?;
+        let device = nvml.device_by_index(index)?;
+        let compute = device.running_compute_processes()?;
+        // Display servers are only listed here
+        let graphics = match device.running_graphics_processes() {
+            Ok(graphics) => graphics,
+            Err(NvmlError::NotSupported) => Vec::new(),
+            Err(e) => return Err(e.into()),
+        };
+
+        let mut processes: Vec<ProcessInfo> = compute
+            .into_iter()
+            .map(|process| (process, ProcessType::Compute))
+            .chain(
+                graphics
+                    .into_iter()
+                    .map(|process| (process, ProcessType::Graphics)),
+            )
+            .map(|(process, process_type)| ProcessInfo {
+                pid: process.pid,
+                used_memory: match process.used_gpu_memory {
+                    UsedGpuMemory::Used(x) => Some(x),
+                    UsedGpuMemory::Unavailable => None,
+                },
+                process_type,
+            })
+            .collect();
+        // Keeps the compute entry of processes listed twice
+        let mut seen = HashSet::new();
+        processes.retain(|process| seen.insert(process.pid));
+
+        Ok(processes)
+    }
+
+    fn power_usage(&self, index: u32) -> Result<u32> {
+        Ok(self.nvml()?.device_by_index(index)?.power_usage()?)
+    }
+
+    fn module_power_usage(&self, index: u32) -> Result<u32> {
+        let nvml = self.nvml()?;
+        let device = nvml.device_by_index(index)?;
+        let field = NvmlBackend::field_id(Field::ModulePowerUsage);
+        nvml_ext::scoped_field_values(&device, &[field])?
+            .remove(0)
+            .map(|value| value as u32)
+    }
+
+    fn memory_power_usage(&self, index: u32) -> Result<u32> {
+        let nvml = self.nvml()?;
+        let device = nvml.device_by_index(index)?;
+        let field = NvmlBackend::field_id(Field::MemoryPowerUsage);
+        nvml_ext::scoped_field_values(&device, &[field])?
+            .remove(0)
+            .map(|value| value as u32)
+    }
+
+    fn power_limit(&self, index: u32) -> Result<u32> {
+        Ok(self
+            .nvml()?
+            .device_by_index(index)?
+            .power_management_limit()?)
+    }
+
+    fn total_energy(&self, index: u32) -> Result<u64> {
+        Ok(self
+            .nvml()?
+            .device_by_index(index)?
+            .total_energy_consumption()?)
+    }
+
+    fn clock(&self, index: u32, clock: ClockType) -> Result<u32> {
+        let clock = match clock {
+            ClockType::Graphics => Clock::Graphics,
+            ClockType::Sm => Clock::SM,
+        };
+
+        Ok(self.nvml()?.device_by_index(index)?.clock_info(clock)?)
+    }
+
+    fn temperature(&self, index: u32) -> Result<u32> {
+        Ok(self
+            .nvml()?
+            .device_by_index(index)?
+            .temperature(TemperatureSensor::Gpu)?)
+    }
+
+    fn memory_temperature(&self, index: u32) -> Result<u32> {
+        let nvml = self.nvml()?;
+        let device = nvml.device_by_index(index)?;
+        // Only available as a field value
+        nvml_ext::field_values(&device, &[nvml_ext::FI_MEMORY_TEMP])?
+            .remove(0)
+            .map(|value| value as u32)
+    }
+
+    fn throttle_reasons(&self, index: u32) -> Result<Vec<ThrottleReason>> {
+        let reasons = self
+            .nvml()?
+            .device_by_index(index)?
+            .current_throttle_reasons()?;
+
+        Ok([
+            (ThrottleReasons::GPU_IDLE, ThrottleReason::GpuIdle),
+            (
+                ThrottleReasons::APPLICATIONS_CLOCKS_SETTING,
+                ThrottleReason::ApplicationsClocksSetting,
+            ),
+            (ThrottleReasons::SW_POWER_CAP, ThrottleReason::SwPowerCap),
+            (ThrottleReasons::HW_SLOWDOWN, ThrottleReason::HwSlowdown),
+            (ThrottleReasons::SYNC_BOOST, ThrottleReason::SyncBoost),
+            (
+                ThrottleReasons::SW_THERMAL_SLOWDOWN,
+                ThrottleReason::SwThermalSlowdown,
+            ),
+            (
+                ThrottleReasons::HW_THERMAL_SLOWDOWN,
+                ThrottleReason::HwThermalSlowdown,
+            ),
+            (
+                ThrottleReasons::HW_POWER_BRAKE_SLOWDOWN,
+                ThrottleReason::HwPowerBrakeSlowdown,
+            ),
+            (
+                ThrottleReasons::DISPLAY_CLOCK_SETTING,
+                ThrottleReason::DisplayClockSetting,
+            ),
+        ]
+        .iter()
+        .filter(|(flag, _)| reasons.contains(*flag))
+        .map(|(_, reason)| *reason)
+        .collect())
+    }
+
+    fn fan_speed(&self, index: u32) -> Result<u32> {
+        Ok(self.nvml()?.device_by_index(index)?.fan_speed(0)?)
+    }
+
+    fn fan_speed_rpm(&self, index: u32) -> Result<u32> {
+        nvml_ext::fan_speed_rpm(&self.nvml()?.device_by_index(index)?)
+    }
+
+    fn target_fan_speed(&self, index: u32) -> Result<u32> {
+        nvml_ext::target_fan_speed(&self.nvml()?.device_by_index(index)?)
+    }
+
+    fn process_utilization(&self, index: u32) -> Result<Vec<ProcessUtilization>> {
+        let nvml = self.nvml()?;
+        let device = nvml.device_by_index(index)?;
+
+        let mut seen = self
+            .process_samples_seen
+            .lock()
+            .expect("Process samples poisoned");
+        let last_seen = seen.get(&index).copied().unwrap_or(0);
+        let samples = match device.process_utilization_stats(last_seen) {
+            Ok(samples) => samples,
+            // No process ran since the last sample
+            Err(NvmlError::NotFound) => return Ok(Vec::new()),
+            Err(e) => return Err(e.into()),
+        };
+        if let Some(latest) = samples.iter().map(|sample| sample.timestamp).max() {
+            seen.insert(index, latest);
+        }
+        drop(seen);
+
+        // The driver keeps several samples per process, which are averaged
+        let mut sums: BTreeMap<u32, (u32, u32, u32)> = BTreeMap::new();
+        for sample in samples {
+            let sum = sums.entry(sample.pid).or_default();
+            sum.0 += sample.sm_util;
+            sum.1 += sample.mem_util;
+            sum.2 += 1;
+        }
+
+        Ok(sums
+            .into_iter()
+            .map(|(pid, (gpu, memory, count))| ProcessUtilization {
+                pid,
+                gpu: gpu / count,
+                memory: memory / count,
+            })
+            .collect())
+    }
+
+    fn mps_processes(&self, index: u32) -> Result<Vec<ProcessInfo>> {
+        nvml_ext::mps_processes(&self.nvml()?.device_by_index(index)?)
+    }
+
+    fn grid_licenses(&self, index: u32) -> Result<Vec<GridLicense>> {
+        nvml_ext::grid_licenses(&self.nvml()?.device_by_index(index)?)
+    }
+
+    fn pcie_errors(&self, index: u32) -> Result<PcieErrors> {
+        let nvml = self.nvml()?;
+        let device = nvml.device_by_index(index)?;
+        let ids: Vec<(u32, u32)> = [
+            Field::PcieReplays,
+            Field::PcieCorrectableErrors,
+            Field::PcieNonFatalErrors,
+            Field::PcieFatalErrors,
+        ]
+        .iter()
+        .map(|&field| NvmlBackend::field_id(field))
+        .collect();
+        let values: Vec<Option<u64>> = nvml_ext::scoped_field_values(&device, &ids)?
+            .into_iter()
+            .map(Result::ok)
+            .collect();
+
+        Ok(PcieErrors {
+            replays: values[0],
+            correctable: values[1],
+            non_fatal: values[2],
+            fatal: values[3],
+        })
+    }
+
+    fn field_values(&self, index: u32, fields: &[Field]) -> Result<Vec<Result<u64>>> {
+        let nvml = self.nvml()?;
+        let device = nvml.device_by_index(index)?;
+        let ids: Vec<(u32, u32)> = fields
+            .iter()
+            .map(|&field| NvmlBackend::field_id(field))
+            .collect();
+        let values = match nvml_ext::scoped_field_values(&device, &ids) {
+            Ok(values) => values,
+            Err(e) if e.is_not_supported() => fields
+                .iter()
+                .map(|_| Err(CollectingError::NotSupported))
+                .collect(),
+            Err(e) => return Err(e),
+        };
+
+        // Older drivers only provide the power readings through their getters
+        Ok(fields
+            .iter()
+            .zip(values)
+            .map(|(&field, value)| match (field, value) {
+                (Field::PowerUsage, Err(e))
+                | (Field::PowerLimit, Err(e))
+                | (Field::TotalEnergy, Err(e))
+                    if e.is_not_supported() =>
+                {
+                    read_field(self, index, field)
+                }
+                (_, value) => value,
+            })
+            .collect())
+    }
+
+    fn gpm_metrics(&self, index: u32) -> Result<GpmMetrics> {
+        let nvml = self.nvml()?;
+        let sample = nvml_ext::gpm_sample(&nvml.device_by_index(index)?)?;
+
+        let mut samples = self.gpm_samples.lock().expect("GPM samples poisoned");
+        let previous = match samples.insert(index, sample) {
+            Some(previous) => previous,
+            None => return Ok(GpmMetrics::default()),
+        };
+        let values: Vec<Option<f64>> = nvml_ext::gpm_metrics(
+            &previous,
+            &samples[&index],
+            &[
+                nvml_ext::GPM_SM_UTIL,
+                nvml_ext::GPM_SM_OCCUPANCY,
+                nvml_ext::GPM_ANY_TENSOR_UTIL,
+                nvml_ext::GPM_DRAM_BW_UTIL,
+            ],
+        )?
+        .into_iter()
+        .map(Result::ok)
+        .collect();
+
+        Ok(GpmMetrics {
+            sm_activity: values[0],
+            sm_occupancy: values[1],
+            tensor_activity: values[2],
+            dram_bandwidth: values[3],
+        })
+    }
+
+    fn probe_feature(&self, index: u32, feature: Feature) -> Result<()> {
+        let nvml = self.nvml()?;
+        let device = nvml.device_by_index(index)?;
+
+        match feature {
+            Feature::Ecc => device.is_ecc_enabled().map(drop)?,
+            // Devices without NVLink fail for every link
+            Feature::Nvlink => device.link_wrapper_for(0).is_active().map(drop)?,
+            Feature::Mig => nvml_ext::mig_mode(&device).map(drop)?,
+            Feature::Fan | Feature::PowerReadings => probe_feature(self, index, feature)?,
+        }
+        Ok(())
+    }
+
+    fn mig_devices(&self, index: u32) -> Result<Vec<MigDevice>> {
+        nvml_ext::mig_devices(&self.nvml()?.device_by_index(index)?)
+    }
+
+    fn compute_capability(&self, index: u32) -> Result<(u32, u32)> {
+        let capability = self
+            .nvml()?
+            .device_by_index(index)?
+            .cuda_compute_capability()?;
+
+        Ok((capability.major as u32, capability.minor as u32))
+    }
+
+    fn vbios_version(&self, index: u32) -> Result<String> {
+        Ok(self.nvml()?.device_by_index(index)?.vbios_version()?)
+    }
+
+    fn numa_node(&self, index: u32) -> Result<u32> {
+        let bus_id = self.nvml()?.device_by_index(index)?.pci_info()?.bus_id;
+        numa_node(&bus_id)
+    }
+
+    fn cpu_affinity(&self, index: u32) -> Result<Vec<u32>> {
+        let word_bits = 8 * std::mem::size_of::<c_ulong>();
+        let mask = self
+            .nvml()?
+            .device_by_index(index)?
+            .cpu_affinity(MAX_CPUS / word_bits)?;
+
+        Ok((0..mask.len() * word_bits)
+            .filter(|cpu| mask[cpu / word_bits] & (1 << (cpu % word_bits)) != 0)
+            .map(|cpu| cpu as u32)
+            .collect())
+    }
+
+    fn physical_slot(&self, index: u32) -> Result<String> {
+        let bus_id = self.nvml()?.device_by_index(index)?.pci_info()?.bus_id;
+        physical_slot(&bus_id)
+    }
+
+    fn performance_state(&self, index: u32) -> Result<u32> {
+        use device::PerformanceState::*;
+
+        let state = self.nvml()?.device_by_index(index)?.performance_state()?;
+        Ok(match state {
+            Zero => 0,
+            One => 1,
+            Two => 2,
+            Three => 3,
+            Four => 4,
+            Five => 5,
+            Six => 6,
+            Seven => 7,
+            Eight => 8,
+            Nine => 9,
+            Ten => 10,
+            Eleven => 11,
+            Twelve => 12,
+            Thirteen => 13,
+            Fourteen => 14,
+            Fifteen => 15,
+            Unknown => return Err(CollectingError::NotSupported),
+        })
+    }
+
+    fn operation_mode(&self, index: u32) -> Result<OperationMode> {
+        let modes = self.nvml()?.device_by_index(index)?.gpu_operation_mode()?;
+
+        Ok(match modes.current {
+            device::OperationMode::AllOn => OperationMode::AllOn,
+            device::OperationMode::Compute => OperationMode::Compute,
+            device::OperationMode::LowDP => OperationMode::LowDoublePrecision,
+        })
+    }
+
+    // Persistence mode only exists on Linux
+    #[cfg(target_os = "linux")]
+    fn persistence_mode(&self, index: u32) -> Result<bool> {
+        Ok(self
+            .nvml()?
+            .device_by_index(index)?
+            .is_in_persistent_mode()?)
+    }
+
+    fn set_power_limit(&self, index: u32, milliwatts: u32) -> Result<()> {
+        let nvml = self.nvml()?;
+        let mut device = nvml.device_by_index(index)?;
+        Ok(device.set_power_management_limit(milliwatts)?)
+    }
+
+    fn set_locked_clocks(&self, index: u32, min: u32, max: u32) -> Result<()> {
+        let nvml = self.nvml()?;
+        let mut device = nvml.device_by_index(index)?;
+        Ok(device.set_gpu_locked_clocks(min, max)?)
+    }
+
+    fn reset_locked_clocks(&self, index: u32) -> Result<()> {
+        let nvml = self.nvml()?;
+        let mut device = nvml.device_by_index(index)?;
+        Ok(device.reset_gpu_locked_clocks()?)
+    }
+
+    #[cfg(target_os = "linux")]
+    fn set_persistence_mode(&self, index: u32, enabled: bool) -> Result<()> {
+        let nvml = self.nvml()?;
+        let mut device = nvml.device_by_index(index)?;
+        Ok(device.set_persistent(enabled)?)
+    }
+
+    fn wait_xid_error(&self, timeout: Duration) -> Result<Option<XidError>> {
+        let mut events = self.xid_events.lock().expect("Event set poisoned");
+        if events.is_none() {
+            let nvml = self.nvml()?;
+            let set = nvml_ext::event_set()?;
+            let mut registered = false;
+            for index in 0..nvml.device_count()? {
+                match nvml_ext::register_xid_errors(&nvml.device_by_index(index)?, &set) {
+                    Ok(()) => registered = true,
+                    Err(e) if e.is_not_supported() => {}
+                    Err(e) => return Err(e),
+                }
+            }
+            if !registered {
+                return Err(CollectingError::NotSupported);
+            }
+            *events = Some(set);
+        }
+
+        let set = events.as_ref().expect("Event set missing");
+        let timeout_ms = timeout.as_millis().min(u128::from(u32::MAX)) as u32;
+        Ok(nvml_ext::wait_xid_error(set, timeout_ms)?.map(|(index, xid)| XidError { index, xid }))
+    }
+
+    fn reinit(&self) -> Result<()> {
+        // Event sets have to be freed before NVML is shut down. Waits hold
+        // on to the event set while taking the handle, so it is released
+        // before taking the handle here.
+        self.xid_events.lock().expect("Event set poisoned").take();
+        let mut nvml = self.nvml.lock().expect("NVML handle poisoned");
+        // Samples do not survive a shutdown
+        self.gpm_samples
+            .lock()
+            .expect("GPM samples poisoned")
+            .clear();
+        self.process_samples_seen
+            .lock()
+            .expect("Process samples poisoned")
+            .clear();
+        // Release the old handle first, so that NVML is actually shut down
+        // unless a hanging call still holds on to it
+        *nvml = None;
+        *nvml = Some(Arc::new(NVML::init()?));
+        Ok(())
+    }
+}
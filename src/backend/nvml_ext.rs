@@ -0,0 +1,672 @@
+//! NVML functions and field values that are newer than the bindings of
+//! `nvml-wrapper`.
+//!
+//! Functions are looked up at runtime, so that the exporter still starts with
+//! drivers that do not provide them. Devices of such drivers report the
+//! readings as not supported.
+
+use std::ffi::CStr;
+use std::io;
+use std::os::raw::{c_char, c_int, c_uint, c_void};
+use std::path::{Path, PathBuf};
+use std::ptr;
+use std::sync::Mutex;
+
+use libloading::{Library, Symbol};
+use nvml_wrapper::error::nvml_try;
+use nvml_wrapper::Device;
+
+use crate::backend::{GridLicense, MemoryInfo, MigDevice, ProcessInfo, ProcessType};
+
+use crate::error::{CollectingError, Result};
+
+#[cfg(not(windows))]
+const LIBRARY_NAME: &str = "libnvidia-ml.so.1";
+
+#[cfg(windows)]
+const LIBRARY_NAME: &str = "nvml.dll";
+
+lazy_static! {
+    /// Path of the library instead of [`LIBRARY_NAME`], which has to be set
+    /// before the first function is looked up.
+    static ref LIBRARY_PATH: Mutex<Option<PathBuf>> = Mutex::new(None);
+    static ref LIBRARY: Option<Library> = {
+        let path = LIBRARY_PATH.lock().expect("Library path poisoned").clone();
+        match path {
+            Some(path) => Library::new(path),
+            None => Library::new(LIBRARY_NAME),
+        }
+        .ok()
+    };
+}
+
+/// Looks up functions in the library at `path` instead of the one found by
+/// the dynamic loader.
+pub fn set_library_path(path: &Path) -> Result<()> {
+    Library::new(path).map_err(|e| {
+        io::Error::new(
+            io::ErrorKind::NotFound,
+            format!("Could not load {}: {}", path.display(), e),
+        )
+    })?;
+    *LIBRARY_PATH.lock().expect("Library path poisoned") = Some(path.to_path_buf());
+    Ok(())
+}
+
+/// `nvmlFanSpeedInfo_v1_t`.
+#[repr(C)]
+struct FanSpeedInfo {
+    version: c_uint,
+    fan: c_uint,
+    speed: c_uint,
+}
+
+const FAN_SPEED_INFO_VERSION: c_uint = std::mem::size_of::<FanSpeedInfo>() as c_uint | 1 << 24;
+
+/// `nvmlFieldValue_t`.
+#[repr(C)]
+struct FieldValue {
+    field_id: c_uint,
+    scope_id: c_uint,
+    timestamp: i64,
+    latency_usec: i64,
+    value_type: c_uint,
+    nvml_return: c_uint,
+    /// `nvmlValue_t`, interpreted according to `value_type`.
+    value: u64,
+}
+
+/// `nvmlValueType_t` of a `double`.
+const VALUE_TYPE_DOUBLE: c_uint = 0;
+/// `nvmlValueType_t` of a `signed long long`.
+const VALUE_TYPE_SIGNED_LONG_LONG: c_uint = 4;
+/// `nvmlValueType_t` of a `signed int`.
+const VALUE_TYPE_SIGNED_INT: c_uint = 5;
+
+/// `NVML_FI_DEV_MEMORY_TEMP`.
+pub const FI_MEMORY_TEMP: u32 = 82;
+/// `NVML_FI_DEV_TOTAL_ENERGY_CONSUMPTION`.
+pub const FI_TOTAL_ENERGY_CONSUMPTION: u32 = 83;
+/// `NVML_FI_DEV_PCIE_REPLAY_COUNTER`.
+pub const FI_PCIE_REPLAY_COUNTER: u32 = 94;
+/// `NVML_FI_DEV_PCIE_COUNT_CORRECTABLE_ERRORS`.
+pub const FI_PCIE_CORRECTABLE_ERRORS: u32 = 173;
+/// `NVML_FI_DEV_PCIE_COUNT_NON_FATAL_ERROR`.
+pub const FI_PCIE_NON_FATAL_ERRORS: u32 = 179;
+/// `NVML_FI_DEV_PCIE_COUNT_FATAL_ERROR`.
+pub const FI_PCIE_FATAL_ERRORS: u32 = 180;
+
+/// `NVML_FI_DEV_POWER_INSTANT`.
+pub const FI_POWER_INSTANT: u32 = 186;
+/// `NVML_POWER_SCOPE_GPU`, `NVML_POWER_SCOPE_MODULE` and
+/// `NVML_POWER_SCOPE_MEMORY`, the scopes of power fields.
+pub const POWER_SCOPE_GPU: u32 = 0;
+pub const POWER_SCOPE_MODULE: u32 = 1;
+pub const POWER_SCOPE_MEMORY: u32 = 2;
+/// `NVML_FI_DEV_POWER_REQUESTED_LIMIT`.
+pub const FI_POWER_REQUESTED_LIMIT: u32 = 192;
+
+/// `NVML_GPM_METRIC_SM_UTIL`.
+pub const GPM_SM_UTIL: u32 = 2;
+/// `NVML_GPM_METRIC_SM_OCCUPANCY`.
+pub const GPM_SM_OCCUPANCY: u32 = 3;
+/// `NVML_GPM_METRIC_ANY_TENSOR_UTIL`.
+pub const GPM_ANY_TENSOR_UTIL: u32 = 5;
+/// `NVML_GPM_METRIC_DRAM_BW_UTIL`.
+pub const GPM_DRAM_BW_UTIL: u32 = 10;
+
+/// `NVML_GPM_SUPPORT_VERSION`.
+const GPM_SUPPORT_VERSION: c_uint = 1;
+/// `NVML_GPM_METRICS_GET_VERSION`.
+const GPM_METRICS_GET_VERSION: c_uint = 1;
+/// `NVML_GPM_METRIC_MAX` of the first drivers with GPM. Newer drivers define
+/// more metrics, but only the requested ones are written.
+const GPM_METRIC_MAX: usize = 98;
+
+/// `nvmlGpmSupport_t`.
+#[repr(C)]
+struct GpmSupport {
+    version: c_uint,
+    is_supported_device: c_uint,
+}
+
+/// `metricInfo` of `nvmlGpmMetric_t`.
+#[repr(C)]
+#[derive(Clone, Copy)]
+struct GpmMetricInfo {
+    short_name: *mut c_char,
+    long_name: *mut c_char,
+    unit: *mut c_char,
+}
+
+/// `nvmlGpmMetric_t`.
+#[repr(C)]
+#[derive(Clone, Copy)]
+struct GpmMetric {
+    metric_id: c_uint,
+    nvml_return: c_uint,
+    value: f64,
+    metric_info: GpmMetricInfo,
+}
+
+/// `nvmlGpmMetricsGet_t`.
+#[repr(C)]
+struct GpmMetricsGet {
+    version: c_uint,
+    num_metrics: c_uint,
+    sample1: *mut c_void,
+    sample2: *mut c_void,
+    metrics: [GpmMetric; GPM_METRIC_MAX],
+}
+
+/// A GPM sample of a device, freed when dropped.
+pub struct GpmSample(*mut c_void);
+
+// Samples are plain buffers that NVML does not tie to a thread
+unsafe impl Send for GpmSample {}
+
+impl Drop for GpmSample {
+    fn drop(&mut self) {
+        if let Ok(free) =
+            function::<unsafe extern "C" fn(*mut c_void) -> c_uint>(b"nvmlGpmSampleFree\0")
+        {
+            unsafe {
+                free(self.0);
+            }
+        }
+    }
+}
+
+/// `nvmlEventTypeXidCriticalError`.
+const EVENT_TYPE_XID_CRITICAL_ERROR: u64 = 0x8;
+/// `NVML_ERROR_TIMEOUT`.
+const ERROR_TIMEOUT: c_uint = 10;
+
+/// `nvmlEventData_t`.
+#[repr(C)]
+struct EventData {
+    device: *mut c_void,
+    event_type: u64,
+    event_data: u64,
+    gpu_instance_id: c_uint,
+    compute_instance_id: c_uint,
+}
+
+/// An NVML event set, freed when dropped.
+pub struct EventSet(*mut c_void);
+
+// Event sets may be waited on from any thread
+unsafe impl Send for EventSet {}
+
+impl Drop for EventSet {
+    fn drop(&mut self) {
+        if let Ok(free) =
+            function::<unsafe extern "C" fn(*mut c_void) -> c_uint>(b"nvmlEventSetFree\0")
+        {
+            unsafe {
+                free(self.0);
+            }
+        }
+    }
+}
+
+/// `nvmlProcessInfo_t` of the `_v3` process listings.
+#[repr(C)]
+#[derive(Clone, Copy, Default)]
+struct RawProcessInfo {
+    pid: c_uint,
+    used_gpu_memory: u64,
+    gpu_instance_id: c_uint,
+    compute_instance_id: c_uint,
+}
+
+/// `NVML_VALUE_NOT_AVAILABLE`, for memory usage that cannot be queried.
+const VALUE_NOT_AVAILABLE: u64 = u64::MAX;
+/// `NVML_ERROR_INSUFFICIENT_SIZE`.
+const ERROR_INSUFFICIENT_SIZE: c_uint = 7;
+
+/// `NVML_GRID_LICENSE_BUFFER_SIZE`.
+const GRID_LICENSE_BUFFER_SIZE: usize = 128;
+/// `NVML_GRID_LICENSE_FEATURE_MAX_COUNT`.
+const GRID_LICENSE_FEATURE_MAX_COUNT: usize = 3;
+
+/// `nvmlGridLicensableFeature_t` of `nvmlDeviceGetGridLicensableFeatures_v3`.
+#[repr(C)]
+#[derive(Clone, Copy)]
+struct GridLicensableFeature {
+    feature_code: c_uint,
+    feature_state: c_uint,
+    license_info: [c_char; GRID_LICENSE_BUFFER_SIZE],
+    product_name: [c_char; GRID_LICENSE_BUFFER_SIZE],
+    feature_enabled: c_uint,
+}
+
+/// `nvmlGridLicensableFeatures_t` of `nvmlDeviceGetGridLicensableFeatures_v3`.
+#[repr(C)]
+struct GridLicensableFeatures {
+    is_grid_license_supported: c_int,
+    licensable_features_count: c_uint,
+    grid_licensable_features: [GridLicensableFeature; GRID_LICENSE_FEATURE_MAX_COUNT],
+}
+
+/// Looks up `name`, which has to be nul-terminated.
+fn function<T>(name: &[u8]) -> Result<Symbol<'static, T>> {
+    let library = LIBRARY.as_ref().ok_or(CollectingError::NotSupported)?;
+    unsafe { library.get(name) }.map_err(|_| CollectingError::NotSupported)
+}
+
+/// Speed of the first fan of `device` in revolutions per minute.
+pub fn fan_speed_rpm(device: &Device) -> Result<u32> {
+    let get_fan_speed_rpm = function::<
+        unsafe extern "C" fn(*mut c_void, *mut FanSpeedInfo) -> c_uint,
+    >(b"nvmlDeviceGetFanSpeedRPM\0")?;
+
+    let mut info = FanSpeedInfo {
+        version: FAN_SPEED_INFO_VERSION,
+        fan: 0,
+        speed: 0,
+    };
+    unsafe {
+        nvml_try(get_fan_speed_rpm(device.handle() as *mut c_void, &mut info))?;
+    }
+    Ok(info.speed)
+}
+
+/// Speed the driver drives the first fan of `device` towards, as a percent of
+/// its maximum.
+pub fn target_fan_speed(device: &Device) -> Result<u32> {
+    let get_target_fan_speed = function::<
+        unsafe extern "C" fn(*mut c_void, c_uint, *mut c_uint) -> c_uint,
+    >(b"nvmlDeviceGetTargetFanSpeed\0")?;
+
+    let mut speed = 0;
+    unsafe {
+        nvml_try(get_target_fan_speed(
+            device.handle() as *mut c_void,
+            0,
+            &mut speed,
+        ))?;
+    }
+    Ok(speed)
+}
+
+/// Values of the fields `ids` of `device` in one call, in the order of `ids`.
+/// Negative values are reported as not supported.
+pub fn field_values(device: &Device, ids: &[u32]) -> Result<Vec<Result<u64>>> {
+    let fields: Vec<(u32, u32)> = ids.iter().map(|&id| (id, 0)).collect();
+    scoped_field_values(device, &fields)
+}
+
+/// Like [`field_values`], but for fields given by ID and scope, e.g.
+/// [`FI_POWER_INSTANT`] of [`POWER_SCOPE_MODULE`].
+pub fn scoped_field_values(device: &Device, fields: &[(u32, u32)]) -> Result<Vec<Result<u64>>> {
+    let get_field_values = function::<
+        unsafe extern "C" fn(*mut c_void, c_int, *mut FieldValue) -> c_uint,
+    >(b"nvmlDeviceGetFieldValues\0")?;
+
+    let mut values: Vec<FieldValue> = fields
+        .iter()
+        .map(|&(field_id, scope_id)| FieldValue {
+            field_id,
+            scope_id,
+            timestamp: 0,
+            latency_usec: 0,
+            value_type: 0,
+            nvml_return: 0,
+            value: 0,
+        })
+        .collect();
+    unsafe {
+        nvml_try(get_field_values(
+            device.handle() as *mut c_void,
+            values.len() as c_int,
+            values.as_mut_ptr(),
+        ))?;
+    }
+
+    Ok(values
+        .iter()
+        .map(|field| {
+            nvml_try(field.nvml_return)?;
+            match field.value_type {
+                VALUE_TYPE_DOUBLE => Ok(f64::from_bits(field.value) as u64),
+                VALUE_TYPE_SIGNED_LONG_LONG if (field.value as i64) < 0 => {
+                    Err(CollectingError::NotSupported)
+                }
+                VALUE_TYPE_SIGNED_INT if (field.value as u32 as i32) < 0 => {
+                    Err(CollectingError::NotSupported)
+                }
+                _ => Ok(field.value),
+            }
+        })
+        .collect())
+}
+
+/// Licensable vGPU features of `device`, or not supported if the device does
+/// not support GRID licensing.
+pub fn grid_licenses(device: &Device) -> Result<Vec<GridLicense>> {
+    let get_grid_licensable_features = function::<
+        unsafe extern "C" fn(*mut c_void, *mut GridLicensableFeatures) -> c_uint,
+    >(b"nvmlDeviceGetGridLicensableFeatures_v3\0")?;
+
+    let feature = GridLicensableFeature {
+        feature_code: 0,
+        feature_state: 0,
+        license_info: [0; GRID_LICENSE_BUFFER_SIZE],
+        product_name: [0; GRID_LICENSE_BUFFER_SIZE],
+        feature_enabled: 0,
+    };
+    let mut features = GridLicensableFeatures {
+        is_grid_license_supported: 0,
+        licensable_features_count: 0,
+        grid_licensable_features: [feature; GRID_LICENSE_FEATURE_MAX_COUNT],
+    };
+    unsafe {
+        nvml_try(get_grid_licensable_features(
+            device.handle() as *mut c_void,
+            &mut features,
+        ))?;
+    }
+    if features.is_grid_license_supported == 0 {
+        return Err(CollectingError::NotSupported);
+    }
+
+    let count = (features.licensable_features_count as usize).min(GRID_LICENSE_FEATURE_MAX_COUNT);
+    Ok(features.grid_licensable_features[..count]
+        .iter()
+        .map(|feature| {
+            // nvmlGridLicenseFeatureCode_t
+            let name = match feature.feature_code {
+                1 => "vgpu",
+                // NVIDIA_RTX, also known as VWORKSTATION
+                2 => "nvidia_rtx",
+                3 => "gaming",
+                4 => "compute",
+                _ => "unknown",
+            };
+            let product = unsafe { CStr::from_ptr(feature.product_name.as_ptr()) };
+
+            GridLicense {
+                feature: name.to_string(),
+                product: product.to_string_lossy().into_owned(),
+                licensed: feature.feature_state != 0,
+                enabled: feature.feature_enabled != 0,
+            }
+        })
+        .collect())
+}
+
+/// Clients of the MPS server running on `device`. Empty if MPS is not used,
+/// or not supported with drivers before R470.
+pub fn mps_processes(device: &Device) -> Result<Vec<ProcessInfo>> {
+    let get_mps_processes = function::<
+        unsafe extern "C" fn(*mut c_void, *mut c_uint, *mut RawProcessInfo) -> c_uint,
+    >(b"nvmlDeviceGetMPSComputeRunningProcesses_v3\0")?;
+
+    // Clients may start between asking for the count and listing them
+    let mut capacity: c_uint = 0;
+    let processes = loop {
+        let mut processes = vec![RawProcessInfo::default(); capacity as usize];
+        let mut count = capacity;
+        let ret = unsafe {
+            get_mps_processes(
+                device.handle() as *mut c_void,
+                &mut count,
+                processes.as_mut_ptr(),
+            )
+        };
+        if ret == ERROR_INSUFFICIENT_SIZE {
+            capacity = count + 4;
+            continue;
+        }
+        nvml_try(ret)?;
+        processes.truncate(count as usize);
+        break processes;
+    };
+
+    Ok(processes
+        .into_iter()
+        .map(|process| ProcessInfo {
+            pid: process.pid,
+            used_memory: match process.used_gpu_memory {
+                VALUE_NOT_AVAILABLE => None,
+                used => Some(used),
+            },
+            process_type: ProcessType::Compute,
+        })
+        .collect())
+}
+
+/// Creates an event set without any registered events.
+pub fn event_set() -> Result<EventSet> {
+    let create =
+        function::<unsafe extern "C" fn(*mut *mut c_void) -> c_uint>(b"nvmlEventSetCreate\0")?;
+
+    let mut set = ptr::null_mut();
+    unsafe {
+        nvml_try(create(&mut set))?;
+    }
+    Ok(EventSet(set))
+}
+
+/// Registers the critical XID errors of `device` with `set`.
+pub fn register_xid_errors(device: &Device, set: &EventSet) -> Result<()> {
+    let register = function::<unsafe extern "C" fn(*mut c_void, u64, *mut c_void) -> c_uint>(
+        b"nvmlDeviceRegisterEvents\0",
+    )?;
+
+    unsafe {
+        nvml_try(register(
+            device.handle() as *mut c_void,
+            EVENT_TYPE_XID_CRITICAL_ERROR,
+            set.0,
+        ))?;
+    }
+    Ok(())
+}
+
+/// Waits up to `timeout_ms` for the next critical XID error registered with
+/// `set`, returning the index of the device and the XID, or `None` if there
+/// was none in time.
+pub fn wait_xid_error(set: &EventSet, timeout_ms: u32) -> Result<Option<(u32, u64)>> {
+    let wait = function::<unsafe extern "C" fn(*mut c_void, *mut EventData, c_uint) -> c_uint>(
+        b"nvmlEventSetWait_v2\0",
+    )?;
+    let get_index = function::<unsafe extern "C" fn(*mut c_void, *mut c_uint) -> c_uint>(
+        b"nvmlDeviceGetIndex\0",
+    )?;
+
+    let mut data = EventData {
+        device: ptr::null_mut(),
+        event_type: 0,
+        event_data: 0,
+        gpu_instance_id: 0,
+        compute_instance_id: 0,
+    };
+    let ret = unsafe { wait(set.0, &mut data, timeout_ms) };
+    if ret == ERROR_TIMEOUT {
+        return Ok(None);
+    }
+    nvml_try(ret)?;
+
+    let mut index = 0;
+    unsafe {
+        nvml_try(get_index(data.device, &mut index))?;
+    }
+    Ok(Some((index, data.event_data)))
+}
+
+/// Whether MIG mode is currently enabled on `device`, or not supported on
+/// devices without MIG.
+pub fn mig_mode(device: &Device) -> Result<bool> {
+    let get_mig_mode = function::<
+        unsafe extern "C" fn(*mut c_void, *mut c_uint, *mut c_uint) -> c_uint,
+    >(b"nvmlDeviceGetMigMode\0")?;
+
+    let mut current = 0;
+    let mut pending = 0;
+    unsafe {
+        nvml_try(get_mig_mode(
+            device.handle() as *mut c_void,
+            &mut current,
+            &mut pending,
+        ))?;
+    }
+    Ok(current == 1)
+}
+
+/// `NVML_ERROR_NOT_FOUND`.
+const ERROR_NOT_FOUND: c_uint = 6;
+/// `NVML_DEVICE_UUID_V2_BUFFER_SIZE`.
+const DEVICE_UUID_BUFFER_SIZE: usize = 96;
+
+/// `nvmlMemory_t`.
+#[repr(C)]
+#[derive(Default)]
+struct Memory {
+    total: u64,
+    free: u64,
+    used: u64,
+}
+
+/// MIG devices of `device` with their memory, empty unless MIG mode is
+/// enabled.
+pub fn mig_devices(device: &Device) -> Result<Vec<MigDevice>> {
+    if !mig_mode(device)? {
+        return Ok(Vec::new());
+    }
+
+    let get_max_count = function::<unsafe extern "C" fn(*mut c_void, *mut c_uint) -> c_uint>(
+        b"nvmlDeviceGetMaxMigDeviceCount\0",
+    )?;
+    let get_handle = function::<
+        unsafe extern "C" fn(*mut c_void, c_uint, *mut *mut c_void) -> c_uint,
+    >(b"nvmlDeviceGetMigDeviceHandleByIndex\0")?;
+    let get_uuid = function::<unsafe extern "C" fn(*mut c_void, *mut c_char, c_uint) -> c_uint>(
+        b"nvmlDeviceGetUUID\0",
+    )?;
+    let get_memory_info = function::<unsafe extern "C" fn(*mut c_void, *mut Memory) -> c_uint>(
+        b"nvmlDeviceGetMemoryInfo\0",
+    )?;
+
+    let mut max_count = 0;
+    unsafe {
+        nvml_try(get_max_count(
+            device.handle() as *mut c_void,
+            &mut max_count,
+        ))?;
+    }
+
+    let mut devices = Vec::new();
+    for index in 0..max_count {
+        let mut handle = ptr::null_mut();
+        // Instances may have been destroyed, leaving gaps
+        let ret = unsafe { get_handle(device.handle() as *mut c_void, index, &mut handle) };
+        if ret == ERROR_NOT_FOUND {
+            continue;
+        }
+        nvml_try(ret)?;
+
+        let mut uuid = [0 as c_char; DEVICE_UUID_BUFFER_SIZE];
+        let mut memory = Memory::default();
+        unsafe {
+            nvml_try(get_uuid(
+                handle,
+                uuid.as_mut_ptr(),
+                DEVICE_UUID_BUFFER_SIZE as c_uint,
+            ))?;
+            nvml_try(get_memory_info(handle, &mut memory))?;
+        }
+        let uuid = unsafe { CStr::from_ptr(uuid.as_ptr()) };
+
+        devices.push(MigDevice {
+            uuid: uuid.to_string_lossy().into_owned(),
+            memory_info: MemoryInfo {
+                total: memory.total,
+                free: memory.free,
+                used: memory.used,
+            },
+        });
+    }
+    Ok(devices)
+}
+
+/// Takes a GPM sample of `device`, or fails with not supported on devices
+/// older than Hopper.
+pub fn gpm_sample(device: &Device) -> Result<GpmSample> {
+    let query_device_support = function::<
+        unsafe extern "C" fn(*mut c_void, *mut GpmSupport) -> c_uint,
+    >(b"nvmlGpmQueryDeviceSupport\0")?;
+    let sample_alloc =
+        function::<unsafe extern "C" fn(*mut *mut c_void) -> c_uint>(b"nvmlGpmSampleAlloc\0")?;
+    let sample_get = function::<unsafe extern "C" fn(*mut c_void, *mut c_void) -> c_uint>(
+        b"nvmlGpmSampleGet\0",
+    )?;
+
+    let mut support = GpmSupport {
+        version: GPM_SUPPORT_VERSION,
+        is_supported_device: 0,
+    };
+    unsafe {
+        nvml_try(query_device_support(
+            device.handle() as *mut c_void,
+            &mut support,
+        ))?;
+    }
+    if support.is_supported_device == 0 {
+        return Err(CollectingError::NotSupported);
+    }
+
+    let mut sample = ptr::null_mut();
+    unsafe {
+        nvml_try(sample_alloc(&mut sample))?;
+    }
+    let sample = GpmSample(sample);
+    unsafe {
+        nvml_try(sample_get(device.handle() as *mut c_void, sample.0))?;
+    }
+    Ok(sample)
+}
+
+/// Values of the GPM metrics `ids` between the samples `previous` and
+/// `current`, in the order of `ids`.
+pub fn gpm_metrics(
+    previous: &GpmSample,
+    current: &GpmSample,
+    ids: &[u32],
+) -> Result<Vec<Result<f64>>> {
+    let metrics_get =
+        function::<unsafe extern "C" fn(*mut GpmMetricsGet) -> c_uint>(b"nvmlGpmMetricsGet\0")?;
+
+    let metric = GpmMetric {
+        metric_id: 0,
+        nvml_return: 0,
+        value: 0.0,
+        metric_info: GpmMetricInfo {
+            short_name: ptr::null_mut(),
+            long_name: ptr::null_mut(),
+            unit: ptr::null_mut(),
+        },
+    };
+    let mut request = GpmMetricsGet {
+        version: GPM_METRICS_GET_VERSION,
+        num_metrics: ids.len() as c_uint,
+        sample1: previous.0,
+        sample2: current.0,
+        metrics: [metric; GPM_METRIC_MAX],
+    };
+    for (metric, &id) in request.metrics.iter_mut().zip(ids) {
+        metric.metric_id = id;
+    }
+    unsafe {
+        nvml_try(metrics_get(&mut request))?;
+    }
+
+    Ok(request.metrics[..ids.len()]
+        .iter()
+        .map(|metric| {
+            nvml_try(metric.nvml_return)?;
+            Ok(metric.value)
+        })
+        .collect())
+}
@@ -0,0 +1,175 @@
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use crate::backend::{ClockType, DeviceInfo, GpuBackend, MemoryInfo, ProcessInfo, Utilization};
+use crate::error::{CollectingError, Result};
+
+/// Directory of the integrated GPU, relative to the filesystem root.
+const GPU_DIR: &str = "sys/devices/gpu.0";
+
+/// Backend reading the integrated GPU of Jetson (Tegra) boards from sysfs,
+/// where NVML is not available.
+///
+/// Tegra GPUs share their memory with the CPU, so memory readings describe the
+/// system memory, and there is no per-process accounting.
+pub struct TegraBackend {
+    root: PathBuf,
+}
+
+impl TegraBackend {
+    /// Creates a backend reading from the root filesystem, if the board has a
+    /// Tegra GPU.
+    pub fn detect() -> Option<TegraBackend> {
+        TegraBackend::with_root("/")
+    }
+
+    /// Creates a backend reading from a filesystem mounted at `root`, if it
+    /// has a Tegra GPU.
+    pub fn with_root<P: Into<PathBuf>>(root: P) -> Option<TegraBackend> {
+        let backend = TegraBackend { root: root.into() };
+        if backend.root.join(GPU_DIR).join("load").exists() {
+            Some(backend)
+        } else {
+            None
+        }
+    }
+
+    fn read(&self, path: &Path) -> Result<String> {
+        let content = fs::read_to_string(self.root.join(path))?;
+        Ok(content
+            .trim_matches(|c: char| c.is_whitespace() || c == '\0')
+            .to_string())
+    }
+
+    fn read_u64(&self, path: &Path) -> Result<u64> {
+        self.read(path)?
+            .parse()
+            .map_err(|_| CollectingError::Io(invalid_data(path)))
+    }
+
+    /// Finds the first entry of `dir` whose file `name` satisfies `predicate`.
+    fn find(&self, dir: &str, name: &str, predicate: impl Fn(&str) -> bool) -> Result<PathBuf> {
+        for entry in fs::read_dir(self.root.join(dir))? {
+            let path = entry?.path();
+            if let Ok(content) = fs::read_to_string(path.join(name)) {
+                if predicate(content.trim()) {
+                    return Ok(path);
+                }
+            }
+        }
+
+        Err(CollectingError::NotSupported)
+    }
+}
+
+fn invalid_data(path: &Path) -> io::Error {
+    io::Error::new(
+        io::ErrorKind::InvalidData,
+        format!("unexpected content in {}", path.display()),
+    )
+}
+
+impl GpuBackend for TegraBackend {
+    fn device_count(&self) -> Result<u32> {
+        Ok(1)
+    }
+
+    fn device_info(&self, index: u32) -> Result<DeviceInfo> {
+        if index != 0 {
+            return Err(CollectingError::NotFound);
+        }
+
+        let name = self
+            .read(Path::new("proc/device-tree/model"))
+            .unwrap_or_else(|_| "NVIDIA Tegra".to_string());
+        let serial = self
+            .read(Path::new("proc/device-tree/serial-number"))
+            .unwrap_or_else(|_| "0".to_string());
+
+        Ok(DeviceInfo {
+            index,
+            minor_number: None,
+            uuid: format!("TEGRA-{}", serial),
+            name,
+            pci_bus_id: None,
+            serial: Some(serial),
+        })
+    }
+
+    fn utilization(&self, _index: u32) -> Result<Utilization> {
+        // The load is reported in per mille
+        let load = self.read_u64(&Path::new(GPU_DIR).join("load"))?;
+
+        Ok(Utilization {
+            gpu: (load / 10) as u32,
+            memory: None,
+        })
+    }
+
+    fn memory_info(&self, _index: u32) -> Result<MemoryInfo> {
+        let meminfo = self.read(Path::new("proc/meminfo"))?;
+        let field = |name: &str| -> Option<u64> {
+            let line = meminfo.lines().find(|line| line.starts_with(name))?;
+            let kib: u64 = line.split_whitespace().nth(1)?.parse().ok()?;
+            Some(kib * 1024)
+        };
+
+        match (field("MemTotal:"), field("MemAvailable:")) {
+            (Some(total), Some(free)) => Ok(MemoryInfo {
+                total,
+                free,
+                used: total.saturating_sub(free),
+            }),
+            _ => Err(CollectingError::Io(invalid_data(Path::new("proc/meminfo")))),
+        }
+    }
+
+    fn processes(&self, _index: u32) -> Result<Vec<ProcessInfo>> {
+        Ok(Vec::new())
+    }
+
+    fn power_usage(&self, _index: u32) -> Result<u32> {
+        // The INA3221 power monitors name their rails, e.g. VDD_GPU or POM_5V_GPU
+        let drivers = "sys/bus/i2c/drivers/ina3221x";
+        for monitor in fs::read_dir(self.root.join(drivers))? {
+            for device in fs::read_dir(monitor?.path())? {
+                let device = device?.path();
+                let is_iio = device.file_name().map_or(false, |name| {
+                    name.to_string_lossy().starts_with("iio:device")
+                });
+                if !is_iio {
+                    continue;
+                }
+
+                for channel in 0..3 {
+                    let rail = fs::read_to_string(device.join(format!("rail_name_{}", channel)));
+                    if rail.map_or(false, |rail| rail.contains("GPU")) {
+                        let power = device.join(format!("in_power{}_input", channel));
+                        return Ok(self.read_u64(&power)? as u32);
+                    }
+                }
+            }
+        }
+
+        Err(CollectingError::NotSupported)
+    }
+
+    fn clock(&self, _index: u32, _clock: ClockType) -> Result<u32> {
+        // The SMs run at the GPU clock, which devfreq reports in Hz
+        let devfreq = Path::new(GPU_DIR).join("devfreq");
+        let dir = fs::read_dir(self.root.join(&devfreq))?
+            .next()
+            .ok_or(CollectingError::NotSupported)??;
+        let hertz = self.read_u64(&dir.path().join("cur_freq"))?;
+
+        Ok((hertz / 1_000_000) as u32)
+    }
+
+    fn temperature(&self, _index: u32) -> Result<u32> {
+        let zone = self.find("sys/devices/virtual/thermal", "type", |t| t == "GPU-therm")?;
+        let millicelsius = self.read_u64(&zone.join("temp"))?;
+
+        Ok((millicelsius / 1000) as u32)
+    }
+}
@@ -0,0 +1,1413 @@
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, AtomicU32, AtomicU8, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use nvml_wrapper::NVML;
+use tokio::sync::mpsc::UnboundedReceiver;
+
+use prometheus::core::{Collector, Desc};
+use prometheus::proto::MetricFamily;
+use prometheus::{
+    exponential_buckets, Gauge, GaugeVec, HistogramOpts, HistogramVec, IntCounter, IntCounterVec,
+    IntGauge, IntGaugeVec, Opts,
+};
+
+use crate::alerts::{Alert, Alerts, Notification};
+use crate::backend::{DeviceInfo, GpuBackend, NvmlBackend, ProcessType, ThrottleReason};
+use crate::collectors::{self, Context, Device, DeviceHealth, UnsupportedCache};
+use crate::config::{
+    AdaptiveConfig, Config, Consistency, DeprecatedMetrics, GpustatConfig, LabelsConfig,
+    UnsupportedReadings, WatchdogConfig,
+};
+use crate::debug;
+use crate::driver;
+use crate::error::{CollectingError, Result};
+use crate::exemplars::Exemplars;
+use crate::gpustat;
+use crate::kubernetes::{Allocation, Allocations};
+use crate::openmetrics;
+use crate::privacy::Privacy;
+use crate::samples::{Reading, Samples};
+use crate::state::State;
+use crate::xids::Xids;
+use crate::NAMESPACE;
+
+// TODO: https://lh3.googleusercontent.com/1GLnuV66rZqTmWQJ1QXW6f8yz1rCLJ9tIzq4RgsEA_qhBOq72KJCBgXeLdc0EXWePx9E-stlEZPShJXeh2WEOtVx-iAOv38cJiApQRn9iA0uqmTnc5vINK2me1vGBxmz-IiCarlN
+
+fn num_devices_gauge() -> Result<IntGauge> {
+    let num_devices_opts = Opts::new("num_devices", "Number of GPU devices").namespace(NAMESPACE);
+    Ok(IntGauge::with_opts(num_devices_opts)?)
+}
+
+fn driver_parameter_gauge() -> Result<IntGaugeVec> {
+    let driver_parameter_opts = Opts::new(
+        "driver_parameter_info",
+        "Parameters the NVIDIA kernel module was loaded with, as labels with a constant value of 1",
+    )
+    .namespace(NAMESPACE);
+    Ok(IntGaugeVec::new(
+        driver_parameter_opts,
+        &["parameter", "value"],
+    )?)
+}
+
+fn persistenced_running_gauge() -> Result<IntGauge> {
+    let persistenced_running_opts = Opts::new(
+        "persistenced_running",
+        "Whether the NVIDIA persistence daemon is running (1) or not (0)",
+    )
+    .namespace(NAMESPACE);
+    Ok(IntGauge::with_opts(persistenced_running_opts)?)
+}
+
+fn devices_missing_gauge() -> Result<IntGauge> {
+    let devices_missing_opts = Opts::new(
+        "devices_missing",
+        "Number of expected GPU devices that are not visible",
+    )
+    .namespace(NAMESPACE);
+    Ok(IntGauge::with_opts(devices_missing_opts)?)
+}
+
+fn device_healthy_gauge(labels: &[&str]) -> Result<IntGaugeVec> {
+    let device_healthy_opts = Opts::new(
+        "device_healthy",
+        "Whether the GPU device is healthy (1) or skipped after repeated failures (0)",
+    )
+    .namespace(NAMESPACE);
+    Ok(IntGaugeVec::new(device_healthy_opts, labels)?)
+}
+
+fn driver_version_mismatch_gauge() -> Result<IntGaugeVec> {
+    let driver_version_mismatch_opts = Opts::new(
+        "driver_version_mismatch",
+        "Whether the version of the loaded NVML library differs from the kernel module (1), which requires a reboot",
+    )
+    .namespace(NAMESPACE);
+    Ok(IntGaugeVec::new(
+        driver_version_mismatch_opts,
+        &["driver_version", "library_version"],
+    )?)
+}
+
+/// Constant 1, with the effective configuration as labels.
+fn config_info_gauge(config: &Config, collectors: &[&str]) -> Result<IntGauge> {
+    let sampling_interval = if config.sampling.enabled {
+        format!("{}s", config.sampling.interval.as_secs_f64())
+    } else {
+        String::new()
+    };
+    let config_info_opts = Opts::new(
+        "config_info",
+        "Effective configuration of the exporter, as labels with a constant value of 1",
+    )
+    .namespace(NAMESPACE)
+    .subsystem("exporter")
+    .const_label("collectors_enabled", collectors.join(","))
+    .const_label("identity_labels", config.labels.identity.join(","))
+    .const_label(
+        "power_unit",
+        format!("{:?}", config.units.power).to_lowercase(),
+    )
+    .const_label(
+        "clock_unit",
+        format!("{:?}", config.units.clocks).to_lowercase(),
+    )
+    .const_label("sampling_interval", sampling_interval)
+    .const_label("watchdog", config.watchdog.enabled.to_string())
+    .const_label("kubernetes", config.kubernetes.enabled.to_string());
+    let config_info_gauge = IntGauge::with_opts(config_info_opts)?;
+    config_info_gauge.set(1);
+    Ok(config_info_gauge)
+}
+
+/// Longest wait for an XID error, after which the watcher waits again.
+const XID_WAIT_TIMEOUT: Duration = Duration::from_secs(1);
+
+/// States of the last collection.
+const NOT_COLLECTED: u8 = 0;
+const SUCCEEDED: u8 = 1;
+const FAILED: u8 = 2;
+
+/// An enabled collector together with its rate limiting state.
+struct Entry<B> {
+    collector: Box<dyn collectors::Collector<B>>,
+    interval: Option<Duration>,
+    /// Whether the collector runs less often while the GPUs are busy.
+    adaptive: bool,
+    /// Descriptors of the per-device readings of the collector.
+    readings: Vec<Desc>,
+    unsupported: UnsupportedReadings,
+    /// Whether renamed metrics are also exported under their old names.
+    deprecated: bool,
+    last: Mutex<Option<(Instant, Vec<MetricFamily>)>>,
+}
+
+impl<B: GpuBackend> Entry<B> {
+    /// Runs the collector and exports the readings devices did not report
+    /// and the renamed metrics as configured.
+    fn collect(&self, ctx: &Context<B>, devices: &[Device]) -> Result<Vec<MetricFamily>> {
+        let mut families = self.collector.collect(ctx, devices)?;
+        collectors::fill_unsupported(
+            &mut families,
+            &self.readings,
+            ctx.labels,
+            devices,
+            self.unsupported,
+        )?;
+        if self.deprecated {
+            collectors::add_deprecated(&mut families);
+        }
+        Ok(families)
+    }
+}
+
+struct Inner<B> {
+    backend: B,
+    /// Names of the identity labels.
+    identity: Vec<&'static str>,
+    collectors: Vec<Entry<B>>,
+    /// Whether the collectors run on background threads, see
+    /// [`GpuCollector::spawn_collectors`].
+    background: AtomicBool,
+    descs: Vec<Desc>,
+    nvml_call_duration_histogram: HistogramVec,
+    collector_duration_gauge: GaugeVec,
+    collector_success_gauge: IntGaugeVec,
+    collector_errors_counter: IntCounterVec,
+    last_collection: AtomicU8,
+    alerts: Alerts,
+    /// Number of failed collections since the last successful one.
+    consecutive_failures: AtomicU32,
+    /// Start of the running collection, if any.
+    collecting_since: Mutex<Option<Instant>>,
+    nvml_reinits_counter: IntCounter,
+    config_info_gauge: IntGauge,
+    start_time_gauge: Gauge,
+    uptime_gauge: Gauge,
+    /// Whether the adaptive collectors are throttled, exported if adaptive
+    /// collection is enabled.
+    throttled_gauge: IntGauge,
+    adaptive: AdaptiveConfig,
+    /// Start of the last collection, exported if readings are taken as a
+    /// snapshot.
+    snapshot_timestamp_gauge: Gauge,
+    consistency: Consistency,
+    /// Creation of the collector, for the uptime.
+    started: Instant,
+    unsupported: UnsupportedCache,
+    health: DeviceHealth,
+    samples: Samples,
+    allocations: Allocations,
+    /// Exemplars of device metrics, if OpenMetrics is served.
+    exemplars: Option<Exemplars>,
+    xids: Xids,
+    /// Pseudonymizes the owners and commands of processes, if enabled.
+    privacy: Option<Privacy>,
+    /// Number of devices expected to be visible, or 0 without expectation.
+    expected_devices: AtomicU32,
+    /// Number of expected devices missing in the last enumeration.
+    devices_missing: AtomicU32,
+    labels: LabelsConfig,
+    /// Devices of the last enumeration, reused while the device count stays
+    /// the same and collections succeed.
+    devices: Mutex<Option<Vec<Device>>>,
+    /// Families of the last full collection and when it finished, which the
+    /// summary is taken from.
+    snapshot: Mutex<Option<(Instant, Arc<Vec<MetricFamily>>)>>,
+    gpustat: GpustatConfig,
+    /// Layout of the summary, if it is not the built-in one.
+    gpustat_template: Option<gpustat::Template>,
+}
+
+/// Collects metrics of all GPUs visible to a [`GpuBackend`], by default NVML.
+///
+/// `GpuCollector` implements [`prometheus::core::Collector`], so it can be
+/// registered into any [`prometheus::Registry`]. Cloning is cheap and clones
+/// share the same backend.
+pub struct GpuCollector<B = NvmlBackend> {
+    inner: Arc<Inner<B>>,
+}
+
+impl<B> Clone for GpuCollector<B> {
+    fn clone(&self) -> Self {
+        GpuCollector {
+            inner: self.inner.clone(),
+        }
+    }
+}
+
+impl GpuCollector<NvmlBackend> {
+    /// Initializes NVML and creates a collector for its devices.
+    pub fn new() -> Result<GpuCollector> {
+        GpuCollector::with_backend(NvmlBackend::new()?)
+    }
+
+    /// Creates a collector from an already initialized NVML handle.
+    pub fn with_nvml(nvml: NVML) -> Result<GpuCollector> {
+        GpuCollector::with_backend(NvmlBackend::with_nvml(nvml))
+    }
+}
+
+impl<B: GpuBackend> GpuCollector<B> {
+    /// Creates a collector reading from the given backend, with all
+    /// collectors enabled.
+    pub fn with_backend(backend: B) -> Result<GpuCollector<B>> {
+        GpuCollector::with_config(backend, &Config::default())
+    }
+
+    /// Creates a collector reading from the given backend, with collectors
+    /// enabled and rate limited as configured.
+    pub fn with_config(backend: B, config: &Config) -> Result<GpuCollector<B>> {
+        // NVML call latency
+        let nvml_call_duration_opts = HistogramOpts::new(
+            "nvml_call_duration_seconds",
+            "Duration of NVML calls in seconds, partitioned by the kind of call",
+        )
+        .namespace(NAMESPACE)
+        .buckets(exponential_buckets(0.0001, 2.5, 12)?);
+        let nvml_call_duration_histogram = HistogramVec::new(nvml_call_duration_opts, &["call"])?;
+
+        // Collector duration
+        let collector_duration_opts = Opts::new(
+            "collector_duration_seconds",
+            "Duration of the last run of a collector in seconds",
+        )
+        .namespace(NAMESPACE)
+        .subsystem("exporter");
+        let collector_duration_gauge = GaugeVec::new(collector_duration_opts, &["collector"])?;
+
+        // Collector success
+        let collector_success_opts = Opts::new(
+            "collector_success",
+            "Whether the last run of a collector succeeded",
+        )
+        .namespace(NAMESPACE)
+        .subsystem("exporter");
+        let collector_success_gauge = IntGaugeVec::new(collector_success_opts, &["collector"])?;
+
+        // Collector errors
+        let collector_errors_opts = Opts::new(
+            "collector_errors_total",
+            "Number of failed runs of a collector",
+        )
+        .namespace(NAMESPACE)
+        .subsystem("exporter");
+        let collector_errors_counter = IntCounterVec::new(collector_errors_opts, &["collector"])?;
+
+        // NVML reinitializations
+        let nvml_reinits_opts = Opts::new(
+            "nvml_reinits_total",
+            "Number of times NVML was reinitialized by the watchdog",
+        )
+        .namespace(NAMESPACE);
+        let nvml_reinits_counter = IntCounter::with_opts(nvml_reinits_opts)?;
+
+        // Start time and uptime, to tell restart loops of the exporter apart
+        // from failed scrapes
+        let start_time_opts = Opts::new(
+            "start_time_seconds",
+            "Start time of the exporter since the unix epoch in seconds",
+        )
+        .namespace(NAMESPACE)
+        .subsystem("exporter");
+        let start_time_gauge = Gauge::with_opts(start_time_opts)?;
+        let start_time = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default();
+        start_time_gauge.set(start_time.as_secs_f64());
+        let uptime_opts = Opts::new(
+            "uptime_seconds",
+            "Time since the exporter started in seconds",
+        )
+        .namespace(NAMESPACE)
+        .subsystem("exporter");
+        let uptime_gauge = Gauge::with_opts(uptime_opts)?;
+
+        // Adaptive collection
+        let throttled_opts = Opts::new(
+            "collectors_throttled",
+            "Whether the expensive collectors run less often as the GPUs are busy (1) or not (0)",
+        )
+        .namespace(NAMESPACE)
+        .subsystem("exporter");
+        let throttled_gauge = IntGauge::with_opts(throttled_opts)?;
+
+        // Snapshot consistency
+        let snapshot_timestamp_opts = Opts::new(
+            "snapshot_timestamp_seconds",
+            "Start of the last collection, whose readings of all devices were taken in parallel, in seconds since the epoch",
+        )
+        .namespace(NAMESPACE)
+        .subsystem("exporter");
+        let snapshot_timestamp_gauge = Gauge::with_opts(snapshot_timestamp_opts)?;
+
+        let identity: Vec<&'static str> = config
+            .labels
+            .identity
+            .iter()
+            .filter_map(|label| {
+                collectors::IDENTITY_LABELS
+                    .iter()
+                    .copied()
+                    .find(|l| l == label)
+            })
+            .collect();
+        let alerts = Alerts::new(config.alerting.rules.clone(), identity.clone());
+
+        let mut entries = Vec::new();
+        let mut descs: Vec<Desc> = num_devices_gauge()?.desc().into_iter().cloned().collect();
+        descs.extend(devices_missing_gauge()?.desc().into_iter().cloned());
+        descs.extend(device_healthy_gauge(&identity)?.desc().into_iter().cloned());
+        descs.extend(driver_version_mismatch_gauge()?.desc().into_iter().cloned());
+        descs.extend(driver_parameter_gauge()?.desc().into_iter().cloned());
+        descs.extend(persistenced_running_gauge()?.desc().into_iter().cloned());
+        for collector in collectors::all(config) {
+            let collector_config = config.collector(collector.name());
+            if !collector_config.enabled {
+                continue;
+            }
+
+            let mut collector_descs = collector.describe(&identity)?;
+            let readings = collector.readings(&identity)?;
+            collector_descs.extend(collectors::unsupported_descs(
+                &readings,
+                &identity,
+                collector_config.unsupported,
+            )?);
+            let deprecated = config.deprecated_metrics == DeprecatedMetrics::Include;
+            if deprecated {
+                collector_descs.extend(collectors::deprecated_descs(&collector_descs)?);
+            }
+            descs.extend(collector_descs);
+            let adaptive = config.adaptive.enabled
+                && config
+                    .adaptive
+                    .collectors
+                    .iter()
+                    .any(|c| c == collector.name());
+            entries.push(Entry {
+                collector,
+                interval: collector_config.interval,
+                adaptive,
+                readings,
+                unsupported: collector_config.unsupported,
+                deprecated,
+                last: Mutex::new(None),
+            });
+        }
+        descs.extend(alerts.descs()?);
+
+        let names: Vec<&str> = entries.iter().map(|e| e.collector.name()).collect();
+        let config_info_gauge = config_info_gauge(config, &names)?;
+
+        for c in &[
+            &nvml_call_duration_histogram as &dyn Collector,
+            &collector_duration_gauge,
+            &collector_success_gauge,
+            &collector_errors_counter,
+            &nvml_reinits_counter,
+            &config_info_gauge,
+            &start_time_gauge,
+            &uptime_gauge,
+        ] {
+            descs.extend(c.desc().into_iter().cloned());
+        }
+        if config.adaptive.enabled {
+            descs.extend(throttled_gauge.desc().into_iter().cloned());
+        }
+        if config.consistency == Consistency::Snapshot {
+            descs.extend(snapshot_timestamp_gauge.desc().into_iter().cloned());
+        }
+
+        let privacy = if config.privacy.enabled {
+            Some(Privacy::new(&config.privacy.salt))
+        } else {
+            None
+        };
+
+        let gpustat_template = match &config.gpustat.template {
+            Some(source) => Some(
+                gpustat::Template::new(source)
+                    .map_err(|e| CollectingError::Template(e.to_string()))?,
+            ),
+            None => None,
+        };
+
+        let inner = Inner {
+            backend,
+            identity,
+            collectors: entries,
+            background: AtomicBool::new(false),
+            descs,
+            nvml_call_duration_histogram,
+            collector_duration_gauge,
+            collector_success_gauge,
+            collector_errors_counter,
+            last_collection: AtomicU8::new(NOT_COLLECTED),
+            alerts,
+            consecutive_failures: AtomicU32::new(0),
+            collecting_since: Mutex::new(None),
+            nvml_reinits_counter,
+            config_info_gauge,
+            start_time_gauge,
+            uptime_gauge,
+            throttled_gauge,
+            adaptive: config.adaptive.clone(),
+            snapshot_timestamp_gauge,
+            consistency: config.consistency,
+            started: Instant::now(),
+            unsupported: UnsupportedCache::new(config.nvml.unsupported_reprobe_interval),
+            health: DeviceHealth::new(&config.nvml),
+            samples: Samples::default(),
+            allocations: Allocations::default(),
+            exemplars: if config.web.openmetrics {
+                Some(Exemplars::new(privacy.clone()))
+            } else {
+                None
+            },
+            xids: Xids::default(),
+            privacy,
+            expected_devices: AtomicU32::new(0),
+            devices_missing: AtomicU32::new(0),
+            labels: config.labels.clone(),
+            devices: Mutex::new(None),
+            snapshot: Mutex::new(None),
+            gpustat: config.gpustat.clone(),
+            gpustat_template,
+        };
+
+        Ok(GpuCollector {
+            inner: Arc::new(inner),
+        })
+    }
+
+    fn context(&self) -> Context<B> {
+        Context::new(
+            &self.inner.backend,
+            &self.inner.identity,
+            &self.inner.nvml_call_duration_histogram,
+            &self.inner.unsupported,
+            &self.inner.health,
+            &self.inner.samples,
+            &self.inner.allocations,
+            self.inner.exemplars.as_ref(),
+            &self.inner.xids,
+            self.inner.privacy.as_ref(),
+        )
+    }
+
+    /// Enumerates all devices of the backend. The identity and static
+    /// attributes of the devices are only read again once the device count
+    /// changed, e.g. after a GPU was hot-plugged, or the cache was
+    /// invalidated.
+    fn devices(&self, ctx: &Context<B>) -> Result<Vec<Device>> {
+        let num_devices = ctx.timed("device_count", || ctx.backend.device_count())?;
+
+        let mut cache = self.inner.devices.lock().expect("Device cache poisoned");
+        if let Some(devices) = &*cache {
+            if devices.len() == num_devices as usize {
+                return Ok(devices.clone());
+            }
+        }
+
+        let mut devices = (0..num_devices)
+            .map(|index| {
+                let info = ctx.timed("identity", || ctx.backend.device_info(index))?;
+                let mut device = Device::new(info, &self.inner.labels);
+                device.statics.vbios_version = ctx
+                    .query(&device, "vbios_version", || {
+                        ctx.backend.vbios_version(index)
+                    })
+                    .ok();
+                device.statics.compute_capability = ctx
+                    .query(&device, "compute_capability", || {
+                        ctx.backend.compute_capability(index)
+                    })
+                    .ok();
+                device.statics.numa_node = ctx
+                    .query(&device, "numa_node", || ctx.backend.numa_node(index))
+                    .ok();
+                device.statics.cpu_affinity = ctx
+                    .query(&device, "cpu_affinity", || ctx.backend.cpu_affinity(index))
+                    .ok();
+                device.statics.physical_slot = ctx
+                    .query(&device, "physical_slot", || {
+                        ctx.backend.physical_slot(index)
+                    })
+                    .ok();
+                Ok(device)
+            })
+            .collect::<Result<Vec<_>>>()?;
+        collectors::index_by_busid(&mut devices);
+        *cache = Some(devices.clone());
+        Ok(devices)
+    }
+
+    /// Enumerates the devices from scratch on the next collection, as indices
+    /// may have changed after an error.
+    fn invalidate_devices(&self) {
+        *self.inner.devices.lock().expect("Device cache poisoned") = None;
+    }
+
+    /// Enumerates the devices and reads their static attributes again on the
+    /// next collection, e.g. after a VBIOS update, as served at
+    /// `/-/refresh-devices`.
+    pub fn refresh_devices(&self) {
+        self.invalidate_devices();
+    }
+
+    /// Expects at least `count` devices to be visible, e.g. to notice a GPU
+    /// that fell off the bus. The devices missing from the expected ones are
+    /// exported as `nvidia_gpu_devices_missing`.
+    pub fn expect_devices(&self, count: u32) {
+        self.inner.expected_devices.store(count, Ordering::SeqCst);
+    }
+
+    /// Number of expected devices the last collection did not find, or `None`
+    /// without expectation, see [`expect_devices`](GpuCollector::expect_devices).
+    pub fn devices_missing(&self) -> Option<u32> {
+        if self.inner.expected_devices.load(Ordering::SeqCst) == 0 {
+            return None;
+        }
+        Some(self.inner.devices_missing.load(Ordering::SeqCst))
+    }
+
+    /// Whether the last collection enumerated all devices and all collectors
+    /// succeeded, or `None` if nothing was collected yet.
+    pub fn last_collection_succeeded(&self) -> Option<bool> {
+        match self.inner.last_collection.load(Ordering::SeqCst) {
+            NOT_COLLECTED => None,
+            state => Some(state == SUCCEEDED),
+        }
+    }
+
+    /// Reinitializes the backend if the last `config.max_failures`
+    /// collections failed or the running collection takes longer than
+    /// `config.stall_timeout`. Returns whether the backend was reinitialized.
+    ///
+    /// A hanging call cannot be aborted, but subsequent calls use the new
+    /// handle.
+    pub fn check_watchdog(&self, config: &WatchdogConfig) -> bool {
+        let failures = self.inner.consecutive_failures.load(Ordering::SeqCst);
+        let stalled = self
+            .inner
+            .collecting_since
+            .lock()
+            .expect("Collection start poisoned")
+            .map_or(false, |since| since.elapsed() > config.stall_timeout);
+
+        if failures < config.max_failures && !stalled {
+            return false;
+        }
+
+        eprintln!(
+            "Reinitializing NVML after {} failed collections{}",
+            failures,
+            if stalled { " and a stalled one" } else { "" }
+        );
+        let ctx = self.context();
+        match ctx.timed("reinit", || ctx.backend.reinit()) {
+            Ok(()) => {
+                self.invalidate_devices();
+                self.inner.nvml_reinits_counter.inc();
+                self.inner.consecutive_failures.store(0, Ordering::SeqCst);
+                true
+            }
+            Err(e) => {
+                eprintln!("Could not reinitialize NVML: {}", e);
+                false
+            }
+        }
+    }
+
+    /// Alerts whose condition held during the last collection.
+    pub fn alerts(&self) -> Vec<Alert> {
+        self.inner.alerts.alerts()
+    }
+
+    /// Returns a channel receiving a notification whenever an alert starts or
+    /// stops firing.
+    pub fn subscribe_alerts(&self) -> UnboundedReceiver<Notification> {
+        self.inner.alerts.subscribe()
+    }
+
+    /// Sets the power management limit of the device at `index` in
+    /// milliwatts.
+    pub fn set_power_limit(&self, index: u32, milliwatts: u32) -> Result<()> {
+        let ctx = self.context();
+        ctx.timed("set_power_limit", || {
+            ctx.backend.set_power_limit(index, milliwatts)
+        })
+    }
+
+    /// Locks the graphics clock of the device at `index` to the range from
+    /// `min` to `max` MHz.
+    pub fn set_locked_clocks(&self, index: u32, min: u32, max: u32) -> Result<()> {
+        let ctx = self.context();
+        ctx.timed("set_locked_clocks", || {
+            ctx.backend.set_locked_clocks(index, min, max)
+        })
+    }
+
+    /// Removes the graphics clock lock of the device at `index`.
+    pub fn reset_locked_clocks(&self, index: u32) -> Result<()> {
+        let ctx = self.context();
+        ctx.timed("reset_locked_clocks", || {
+            ctx.backend.reset_locked_clocks(index)
+        })
+    }
+
+    /// Enables persistence mode on all devices whose index or UUID is listed
+    /// in `devices`, or on all devices if `devices` is empty. Returns the
+    /// outcome per device.
+    pub fn enable_persistence_mode(
+        &self,
+        devices: &[String],
+    ) -> Result<Vec<(DeviceInfo, Result<()>)>> {
+        let ctx = self.context();
+
+        Ok(self
+            .devices(&ctx)?
+            .into_iter()
+            .map(|device| device.info)
+            .filter(|info| {
+                devices.is_empty()
+                    || devices
+                        .iter()
+                        .any(|d| *d == info.index.to_string() || *d == info.uuid)
+            })
+            .map(|info| {
+                let result = ctx.timed("set_persistence_mode", || {
+                    ctx.backend.set_persistence_mode(info.index, true)
+                });
+                (info, result)
+            })
+            .collect())
+    }
+
+    /// Whether the mean GPU utilization of any of `devices` is above the
+    /// threshold of adaptive collection, if enabled.
+    fn busy(&self, devices: &[Device]) -> bool {
+        let adaptive = &self.inner.adaptive;
+        adaptive.enabled
+            && devices.iter().any(|device| {
+                self.inner
+                    .samples
+                    .average(&device.info.uuid, adaptive.window)
+                    .map_or(false, |average| {
+                        average > f64::from(adaptive.utilization_threshold)
+                    })
+            })
+    }
+
+    /// Minimum time between two runs of the collector of `entry`, which is
+    /// longer for adaptive collectors while the GPUs are `busy`.
+    fn interval(&self, entry: &Entry<B>, busy: bool) -> Option<Duration> {
+        if busy && entry.adaptive {
+            let throttled = self.inner.adaptive.interval;
+            Some(entry.interval.map_or(throttled, |i| i.max(throttled)))
+        } else {
+            entry.interval
+        }
+    }
+
+    /// Runs a single collector, unless its last result is still fresh or
+    /// it runs in the background. Returns `None` if the collector failed.
+    fn run(
+        &self,
+        entry: &Entry<B>,
+        ctx: &Context<B>,
+        devices: &[Device],
+        busy: bool,
+    ) -> Option<Vec<MetricFamily>> {
+        let mut last = entry.last.lock().expect("Collector cache poisoned");
+        // Readings of another time would not be part of the snapshot
+        let snapshot = self.inner.consistency == Consistency::Snapshot;
+        if let Some((at, families)) = &*last {
+            let fresh = !snapshot
+                && (self.inner.background.load(Ordering::SeqCst)
+                    || self
+                        .interval(entry, busy)
+                        .map_or(false, |interval| at.elapsed() < interval));
+            if fresh {
+                return Some(families.clone());
+            }
+        }
+
+        self.refresh(entry, ctx, devices, &mut last)
+    }
+
+    /// Runs the collector of `entry`, for each device in its own thread if
+    /// readings are taken as a snapshot.
+    fn collect_entry(
+        &self,
+        entry: &Entry<B>,
+        ctx: &Context<B>,
+        devices: &[Device],
+    ) -> Result<Vec<MetricFamily>> {
+        if self.inner.consistency == Consistency::Sequential || devices.len() < 2 {
+            return entry.collect(ctx, devices);
+        }
+
+        let results = crossbeam_utils::thread::scope(|scope| {
+            let handles: Vec<_> = devices
+                .iter()
+                .map(|device| {
+                    scope.spawn(move |_| entry.collect(ctx, std::slice::from_ref(device)))
+                })
+                .collect();
+            handles
+                .into_iter()
+                .map(|handle| handle.join().expect("Collector panicked"))
+                .collect::<Vec<_>>()
+        })
+        .expect("Collector panicked");
+
+        let mut families = Vec::new();
+        for result in results {
+            families.extend(result?);
+        }
+        Ok(collectors::merge(families))
+    }
+
+    /// Runs a single collector and stores its result in `last`. Returns
+    /// `None` if the collector failed.
+    fn refresh(
+        &self,
+        entry: &Entry<B>,
+        ctx: &Context<B>,
+        devices: &[Device],
+        last: &mut Option<(Instant, Vec<MetricFamily>)>,
+    ) -> Option<Vec<MetricFamily>> {
+        let name = entry.collector.name();
+        let span = tracing::info_span!("collector", collector = name);
+        let start = Instant::now();
+        let result = span.in_scope(|| self.collect_entry(entry, ctx, devices));
+        let elapsed = start.elapsed();
+
+        self.inner
+            .collector_duration_gauge
+            .with_label_values(&[name])
+            .set(elapsed.as_secs_f64());
+
+        match result {
+            Ok(families) => {
+                self.inner
+                    .collector_success_gauge
+                    .with_label_values(&[name])
+                    .set(1);
+                *last = Some((Instant::now(), families.clone()));
+                Some(families)
+            }
+            Err(e) => {
+                eprintln!("Error in collector {}: {}", name, e);
+                self.inner
+                    .collector_success_gauge
+                    .with_label_values(&[name])
+                    .set(0);
+                self.inner
+                    .collector_errors_counter
+                    .with_label_values(&[name])
+                    .inc();
+                None
+            }
+        }
+    }
+}
+
+impl<B: GpuBackend + 'static> GpuCollector<B> {
+    /// Renders a human readable, `gpustat`-like summary of all devices and
+    /// their running processes.
+    pub fn process(&self) -> Result<String> {
+        self.summary(None)
+    }
+
+    /// Like [`process`](GpuCollector::process), but only lists the processes
+    /// owned by `user`.
+    pub fn process_of(&self, user: &str) -> Result<String> {
+        self.summary(Some(user))
+    }
+
+    /// Summary of the last full collection, or of a new one if it is too
+    /// old, so that polling it does not add to the NVML calls of scrapes.
+    fn summary(&self, user: Option<&str>) -> Result<String> {
+        let families = self.snapshot();
+        let ctx = self.context();
+        let devices = self.devices(&ctx)?;
+        let user = user.map(|user| self.user_label(user));
+
+        let stats = gpustat::stats(
+            &devices,
+            &families,
+            &self.inner.identity,
+            user.as_deref(),
+            self.inner.privacy.as_ref(),
+        );
+        match &self.inner.gpustat_template {
+            Some(template) => template.render(&stats),
+            None => Ok(gpustat::render(&stats)),
+        }
+    }
+
+    /// Samples the GPU utilization, power usage, temperature, throttle
+    /// reasons and performance state of all devices, for the rolling
+    /// averages, the extremes between scrapes and the time spent throttled
+    /// and in each performance state.
+    pub fn sample(&self) -> Result<()> {
+        let ctx = self.context();
+        let devices = self.devices(&ctx)?;
+        let samples = &self.inner.samples;
+
+        for device in &devices {
+            let index = device.info.index;
+            let uuid = &device.info.uuid;
+
+            if let Ok(utilization) =
+                ctx.query(device, "utilization", || ctx.backend.utilization(index))
+            {
+                samples.record(uuid, Reading::GpuUtilization, utilization.gpu);
+
+                if let Ok(processes) =
+                    ctx.query(device, "processes", || ctx.backend.processes(index))
+                {
+                    let computing = processes
+                        .iter()
+                        .any(|p| p.process_type == ProcessType::Compute);
+                    samples.record_idle(uuid, utilization.gpu == 0 && !computing);
+                    // Peaks between scrapes; MPS clients are only listed at scrapes
+                    for process in &processes {
+                        if let Some(used_memory) = process.used_memory {
+                            samples.record_process_memory(uuid, process.pid, used_memory);
+                        }
+                    }
+                }
+            }
+            if let Ok(power_usage) =
+                ctx.query(device, "power_usage", || ctx.backend.power_usage(index))
+            {
+                samples.record(uuid, Reading::PowerUsage, power_usage);
+            }
+            if let Ok(temperature) =
+                ctx.query(device, "temperature", || ctx.backend.temperature(index))
+            {
+                samples.record(uuid, Reading::Temperature, temperature);
+            }
+            if let Ok(reasons) = ctx.query(device, "throttle_reasons", || {
+                ctx.backend.throttle_reasons(index)
+            }) {
+                samples.record_throttle_reasons(uuid, reasons);
+            }
+            if let Ok(state) = ctx.query(device, "performance_state", || {
+                ctx.backend.performance_state(index)
+            }) {
+                samples.record_performance_state(uuid, state);
+            }
+        }
+
+        let uuids: Vec<&str> = devices.iter().map(|d| d.info.uuid.as_str()).collect();
+        self.inner.samples.retain(&uuids);
+        Ok(())
+    }
+
+    /// Adds the XID errors and throttled time saved by
+    /// [`save_state`](GpuCollector::save_state) to the counters, unless
+    /// there is no state file at `path` yet.
+    pub fn restore_state(&self, path: &Path) -> io::Result<()> {
+        let state = match State::read(path)? {
+            Some(state) => state,
+            None => return Ok(()),
+        };
+
+        self.inner.xids.restore(state.xid_errors);
+        for (uuid, reasons) in &state.throttled_seconds {
+            for (name, seconds) in reasons {
+                // Reasons of other versions are dropped
+                if let Some(reason) = ThrottleReason::ALL.iter().find(|r| r.name() == name) {
+                    let duration = Duration::from_secs_f64(seconds.max(0.0));
+                    self.inner
+                        .samples
+                        .restore_throttled(uuid, *reason, duration);
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Writes the XID errors and throttled time counted so far to the state
+    /// file at `path`.
+    pub fn save_state(&self, path: &Path) -> io::Result<()> {
+        let throttled_seconds = self
+            .inner
+            .samples
+            .all_throttled()
+            .into_iter()
+            .map(|(uuid, throttled)| {
+                let reasons = throttled
+                    .into_iter()
+                    .map(|(reason, duration)| (reason.name().to_string(), duration.as_secs_f64()))
+                    .collect();
+                (uuid, reasons)
+            })
+            .collect();
+        let state = State {
+            xid_errors: self.inner.xids.all_errors(),
+            throttled_seconds,
+        };
+        state.write(path)
+    }
+
+    /// Replaces the allocations of devices to Kubernetes pods exported by
+    /// the `kubernetes` collector.
+    pub fn set_allocations(&self, allocations: Vec<Allocation>) {
+        self.inner.allocations.set(allocations);
+    }
+
+    /// Collects the metrics of the single device `device`, given by index or
+    /// UUID, e.g. for a tenant that may only see its own GPU. Collectors run
+    /// for this device only and regardless of their rate limits, without
+    /// affecting the state of regular collections.
+    pub fn collect_device(&self, device: &str) -> Result<Vec<MetricFamily>> {
+        self.collect_devices(&[device.to_string()])
+    }
+
+    /// UUID of the device `device`, given by index or UUID, or `None` if
+    /// there is no such device.
+    pub fn device_uuid(&self, device: &str) -> Option<String> {
+        let ctx = self.context();
+        self.devices(&ctx)
+            .ok()?
+            .into_iter()
+            .find(|d| d.info.index.to_string() == device || d.info.uuid == device)
+            .map(|d| d.info.uuid)
+    }
+
+    /// Like [`collect_device`](GpuCollector::collect_device), but for all of
+    /// `devices` at once, e.g. for a team sharing a machine with others.
+    /// Fails with `NotFound` if none of them exists.
+    pub fn collect_devices(&self, devices: &[String]) -> Result<Vec<MetricFamily>> {
+        let ctx = self.context();
+        let devices: Vec<Device> = self
+            .devices(&ctx)?
+            .into_iter()
+            .filter(|d| {
+                devices
+                    .iter()
+                    .any(|device| d.info.index.to_string() == *device || d.info.uuid == *device)
+            })
+            .collect();
+        if devices.is_empty() {
+            return Err(CollectingError::NotFound);
+        }
+
+        let mut families = Vec::new();
+        for entry in &self.inner.collectors {
+            match entry.collect(&ctx, &devices) {
+                Ok(collected) => families.extend(collected),
+                Err(e) => eprintln!("Error in collector {}: {}", entry.collector.name(), e),
+            }
+        }
+        Ok(families)
+    }
+
+    /// Prometheus HTTP service discovery document with one target per device,
+    /// scraping `/metrics/gpu/<uuid>` of the exporter at `address` with the
+    /// device's identity labels.
+    pub fn service_discovery(&self, address: &str) -> Result<serde_json::Value> {
+        let ctx = self.context();
+        let targets: Vec<serde_json::Value> = self
+            .devices(&ctx)?
+            .iter()
+            .map(|device| {
+                let mut labels = serde_json::Map::new();
+                for (name, value) in self.inner.identity.iter().zip(device.labels()) {
+                    labels.insert(name.to_string(), value.into());
+                }
+                let path = format!("/metrics/gpu/{}", device.info.uuid);
+                labels.insert("__metrics_path__".to_string(), path.into());
+
+                serde_json::json!({ "targets": [address], "labels": labels })
+            })
+            .collect();
+        Ok(targets.into())
+    }
+
+    /// Name and value of the label with the hostname to attach to every
+    /// series, if configured.
+    pub fn host_label(&self) -> Option<(String, String)> {
+        let name = self.inner.labels.host_label.clone()?;
+        Some((
+            name,
+            gethostname::gethostname().to_string_lossy().into_owned(),
+        ))
+    }
+
+    /// Value of the `user` label of processes owned by `user`, which is a
+    /// pseudonym if privacy is enabled.
+    pub fn user_label(&self, user: &str) -> String {
+        match &self.inner.privacy {
+            Some(privacy) => privacy.user(user),
+            None => user.to_string(),
+        }
+    }
+
+    /// Whether OpenMetrics with exemplars is served, see
+    /// [`encode_openmetrics`](GpuCollector::encode_openmetrics).
+    pub fn serves_openmetrics(&self) -> bool {
+        self.inner.exemplars.is_some()
+    }
+
+    /// Encodes `families` in the OpenMetrics text format, with the process
+    /// using each device the most attached as exemplar to its utilization and
+    /// used memory, or `None` if OpenMetrics is not served.
+    pub fn encode_openmetrics(&self, families: &[MetricFamily]) -> Option<String> {
+        let exemplars = self.inner.exemplars.as_ref()?;
+        let identity = &self.inner.identity;
+
+        Some(openmetrics::encode(families, |family, metric| {
+            let labels = identity
+                .iter()
+                .map(|name| {
+                    metric
+                        .get_label()
+                        .iter()
+                        .find(|label| label.get_name() == *name)
+                        .map(|label| label.get_value())
+                })
+                .collect::<Option<Vec<&str>>>()?;
+            exemplars.get(family.get_name(), &labels)
+        }))
+    }
+
+    /// Everything the backend reports about each device, including the
+    /// errors of failed readings, as served at `/debug/devices`.
+    pub fn debug_devices(&self) -> Result<serde_json::Value> {
+        debug::devices(&self.inner.backend)
+    }
+
+    /// Runs the collectors named in `selected`, or all of them, and adds the
+    /// exporter's own metrics. Only a `scrape` of all metrics starts the
+    /// extremes of the samples over.
+    fn collect_selected(&self, selected: Option<&[String]>, scrape: bool) -> Vec<MetricFamily> {
+        let span = tracing::info_span!("collection");
+        let _enter = span.enter();
+        *self
+            .inner
+            .collecting_since
+            .lock()
+            .expect("Collection start poisoned") = Some(Instant::now());
+
+        let mut ctx = self.context();
+        ctx.scrape = scrape;
+        let mut families = Vec::new();
+        let mut succeeded = false;
+
+        match self.devices(&ctx) {
+            Ok(devices) => {
+                if let Ok(gauge) = num_devices_gauge() {
+                    gauge.set(devices.len() as i64);
+                    families.extend(gauge.collect());
+                }
+
+                let expected = self.inner.expected_devices.load(Ordering::SeqCst);
+                let missing = expected.saturating_sub(devices.len() as u32);
+                self.inner.devices_missing.store(missing, Ordering::SeqCst);
+                if expected > 0 {
+                    if let Ok(gauge) = devices_missing_gauge() {
+                        gauge.set(missing as i64);
+                        families.extend(gauge.collect());
+                    }
+                }
+
+                if self.inner.consistency == Consistency::Snapshot {
+                    let now = SystemTime::now()
+                        .duration_since(UNIX_EPOCH)
+                        .unwrap_or_default();
+                    self.inner.snapshot_timestamp_gauge.set(now.as_secs_f64());
+                    families.extend(self.inner.snapshot_timestamp_gauge.collect());
+                }
+
+                succeeded = true;
+                let busy = self.busy(&devices);
+                for entry in &self.inner.collectors {
+                    let name = entry.collector.name();
+                    if !selected.map_or(true, |names| names.iter().any(|n| n == name)) {
+                        continue;
+                    }
+
+                    match self.run(entry, &ctx, &devices, busy) {
+                        Some(collected) => families.extend(collected),
+                        None => succeeded = false,
+                    }
+                }
+
+                if let Ok(gauge) = device_healthy_gauge(&self.inner.identity) {
+                    for device in &devices {
+                        let healthy = self.inner.health.is_healthy(&device.info.uuid);
+                        if let Ok(metric) = gauge.get_metric_with_label_values(&device.labels()) {
+                            metric.set(healthy as i64);
+                        }
+                    }
+                    families.extend(gauge.collect());
+                }
+
+                if self.inner.adaptive.enabled {
+                    self.inner.throttled_gauge.set(busy as i64);
+                    families.extend(self.inner.throttled_gauge.collect());
+                }
+            }
+            Err(e) => eprintln!("Error enumerating devices: {}", e),
+        }
+
+        // Also exported when the devices cannot be enumerated, which is the
+        // usual symptom of a mismatch
+        if let Some((driver, library)) = driver::versions() {
+            if let Ok(gauge) = driver_version_mismatch_gauge() {
+                if let Ok(metric) =
+                    gauge.get_metric_with_label_values(&[driver.as_str(), library.as_str()])
+                {
+                    metric.set((driver != library) as i64);
+                }
+                families.extend(gauge.collect());
+            }
+        }
+
+        if let Some(parameters) = driver::parameters() {
+            if let Ok(gauge) = driver_parameter_gauge() {
+                for (name, value) in &parameters {
+                    if let Ok(metric) =
+                        gauge.get_metric_with_label_values(&[name.as_str(), value.as_str()])
+                    {
+                        metric.set(1);
+                    }
+                }
+                families.extend(gauge.collect());
+            }
+            if let Ok(gauge) = persistenced_running_gauge() {
+                gauge.set(driver::persistenced_running() as i64);
+                families.extend(gauge.collect());
+            }
+        }
+
+        let state = if succeeded { SUCCEEDED } else { FAILED };
+        self.inner.last_collection.store(state, Ordering::SeqCst);
+        if succeeded {
+            self.inner.consecutive_failures.store(0, Ordering::SeqCst);
+        } else {
+            self.invalidate_devices();
+            self.inner
+                .consecutive_failures
+                .fetch_add(1, Ordering::SeqCst);
+        }
+        *self
+            .inner
+            .collecting_since
+            .lock()
+            .expect("Collection start poisoned") = None;
+
+        // Alert rules may refer to metrics of collectors that did not run
+        if selected.is_none() {
+            self.inner.alerts.evaluate(&families);
+        }
+        match self.inner.alerts.families() {
+            Ok(alert_families) => families.extend(alert_families),
+            Err(e) => eprintln!("Error exporting alerts: {}", e),
+        }
+
+        families.extend(self.inner.nvml_call_duration_histogram.collect());
+        families.extend(self.inner.collector_duration_gauge.collect());
+        families.extend(self.inner.collector_success_gauge.collect());
+        families.extend(self.inner.collector_errors_counter.collect());
+        families.extend(self.inner.nvml_reinits_counter.collect());
+        families.extend(self.inner.config_info_gauge.collect());
+        families.extend(self.inner.start_time_gauge.collect());
+        self.inner
+            .uptime_gauge
+            .set(self.inner.started.elapsed().as_secs_f64());
+        families.extend(self.inner.uptime_gauge.collect());
+
+        if selected.is_none() {
+            *self.inner.snapshot.lock().expect("Snapshot poisoned") =
+                Some((Instant::now(), Arc::new(families.clone())));
+        }
+        families
+    }
+
+    /// Families of the last full collection if it is at most as old as
+    /// configured for the summary, or of a new one otherwise.
+    fn snapshot(&self) -> Arc<Vec<MetricFamily>> {
+        if let Some((at, families)) = &*self.inner.snapshot.lock().expect("Snapshot poisoned") {
+            if at.elapsed() <= self.inner.gpustat.max_age {
+                return families.clone();
+            }
+        }
+
+        Arc::new(self.collect_selected(None, false))
+    }
+
+    /// Like a regular collection, but only runs the collectors `names`, e.g.
+    /// for scrapes with `collect[]` parameters. Fails with
+    /// [`CollectingError::NotFound`] if a collector is unknown or disabled.
+    pub fn collect_only(&self, names: &[String]) -> Result<Vec<MetricFamily>> {
+        let enabled = |name: &String| {
+            self.inner
+                .collectors
+                .iter()
+                .any(|entry| entry.collector.name() == name)
+        };
+        if !names.iter().all(enabled) {
+            return Err(CollectingError::NotFound);
+        }
+
+        Ok(self.collect_selected(Some(names), false))
+    }
+
+    /// Runs the watchdog every `config.check_interval` on a background thread.
+    pub fn spawn_watchdog(&self, config: WatchdogConfig) -> thread::JoinHandle<()> {
+        let collector = self.clone();
+        thread::spawn(move || loop {
+            thread::sleep(config.check_interval);
+            collector.check_watchdog(&config);
+        })
+    }
+
+    /// Saves the state to `path` every `interval` on a background thread.
+    pub fn spawn_state_saver(&self, path: PathBuf, interval: Duration) -> thread::JoinHandle<()> {
+        let collector = self.clone();
+        thread::spawn(move || loop {
+            thread::sleep(interval);
+            if let Err(e) = collector.save_state(&path) {
+                eprintln!("Error saving state to {}: {}", path.display(), e);
+            }
+        })
+    }
+
+    /// Samples every `interval` on a background thread.
+    pub fn spawn_sampler(&self, interval: Duration) -> thread::JoinHandle<()> {
+        let collector = self.clone();
+        thread::spawn(move || loop {
+            if let Err(e) = collector.sample() {
+                eprintln!("Error sampling: {}", e);
+            }
+            thread::sleep(interval);
+        })
+    }
+
+    /// Counts the critical XID errors reported by the driver on a background
+    /// thread, for the `xid` collector. The thread ends if the backend does
+    /// not report XID errors.
+    pub fn spawn_xid_watcher(&self) -> thread::JoinHandle<()> {
+        let collector = self.clone();
+        self.inner.xids.record_wait();
+        thread::spawn(move || loop {
+            let xids = &collector.inner.xids;
+            let result = collector.inner.backend.wait_xid_error(XID_WAIT_TIMEOUT);
+            xids.record_wait();
+            match result {
+                Ok(error) => {
+                    xids.set_watching(true);
+                    if let Some(error) = error {
+                        match collector.inner.backend.device_info(error.index) {
+                            Ok(info) => {
+                                xids.record(&info.uuid, error.xid);
+                                xids.record_event(false);
+                            }
+                            Err(e) => {
+                                eprintln!("Error identifying device of XID error: {}", e);
+                                xids.record_event(true);
+                            }
+                        }
+                    }
+                }
+                Err(e) if e.is_not_supported() => {
+                    eprintln!("XID errors are not reported, not counting them");
+                    xids.set_watching(false);
+                    xids.record_stop();
+                    return;
+                }
+                Err(e) => {
+                    // Errors that occur in between are missed
+                    eprintln!("Error waiting for XID errors: {}", e);
+                    xids.set_watching(false);
+                    thread::sleep(XID_WAIT_TIMEOUT);
+                }
+            }
+        })
+    }
+
+    /// Runs every collector on its own background thread, every `interval`
+    /// of its configuration or `default_interval`. Scrapes are served the
+    /// latest results from then on, so slow collectors like `processes` can
+    /// run less often than cheap ones without delaying scrapes. Adaptive
+    /// collectors wait longer while the GPUs are busy.
+    pub fn spawn_collectors(&self, default_interval: Duration) -> Vec<thread::JoinHandle<()>> {
+        self.inner.background.store(true, Ordering::SeqCst);
+
+        (0..self.inner.collectors.len())
+            .map(|i| {
+                let collector = self.clone();
+                thread::spawn(move || {
+                    let entry = &collector.inner.collectors[i];
+                    loop {
+                        // Scrapes are served these results instead
+                        let mut ctx = collector.context();
+                        ctx.scrape = true;
+                        let mut busy = false;
+                        match collector.devices(&ctx) {
+                            Ok(devices) => {
+                                let mut last = entry.last.lock().expect("Collector cache poisoned");
+                                collector.refresh(entry, &ctx, &devices, &mut last);
+                                busy = collector.busy(&devices);
+                            }
+                            Err(e) => eprintln!("Error enumerating devices: {}", e),
+                        }
+                        let mut interval = entry.interval.unwrap_or(default_interval);
+                        if let Some(throttled) = collector.interval(entry, busy) {
+                            interval = interval.max(throttled);
+                        }
+                        thread::sleep(interval);
+                    }
+                })
+            })
+            .collect()
+    }
+
+    /// Evaluates the alerts every `interval` on a background thread, so that
+    /// they are also evaluated if nobody scrapes. Only collects if there was
+    /// no full collection within `interval`, as each collection changes what
+    /// the next one computes its readings since, and leaves the extremes to
+    /// scrapes.
+    pub fn spawn_alert_evaluation(&self, interval: Duration) -> thread::JoinHandle<()> {
+        let collector = self.clone();
+        thread::spawn(move || loop {
+            let snapshot = collector
+                .inner
+                .snapshot
+                .lock()
+                .expect("Snapshot poisoned")
+                .clone();
+            match snapshot {
+                Some((at, families)) if at.elapsed() < interval => {
+                    collector.inner.alerts.evaluate(&families)
+                }
+                _ => {
+                    collector.collect_selected(None, false);
+                }
+            }
+            thread::sleep(interval);
+        })
+    }
+}
+
+impl<B: GpuBackend + 'static> Collector for GpuCollector<B> {
+    fn desc(&self) -> Vec<&Desc> {
+        self.inner.descs.iter().collect()
+    }
+
+    fn collect(&self) -> Vec<MetricFamily> {
+        self.collect_selected(None, true)
+    }
+}
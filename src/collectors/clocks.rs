@@ -0,0 +1,148 @@
+use prometheus::core::Desc;
+use prometheus::proto::MetricFamily;
+use prometheus::{CounterVec, IntGaugeVec, Opts};
+
+use crate::backend::{ClockType, GpuBackend};
+use crate::collectors::{Collector, Context, Device, MetricSet};
+use crate::config::ClockUnit;
+use crate::error::Result;
+use crate::NAMESPACE;
+
+/// Graphics and streaming multiprocessor clock speeds, and the time the
+/// clocks were throttled for each reason while the sampler is running.
+pub struct ClocksCollector {
+    pub unit: ClockUnit,
+}
+
+struct Metrics {
+    unit: ClockUnit,
+    clock_speed_graphics_gauge: IntGaugeVec,
+    clock_speed_sm_gauge: IntGaugeVec,
+    throttle_reason_seconds_counter: CounterVec,
+}
+
+impl Metrics {
+    fn new(labels: &[&str], unit: ClockUnit) -> Result<Metrics> {
+        let (suffix, symbol) = match unit {
+            ClockUnit::Hertz => ("hertz", "Hz"),
+            ClockUnit::Megahertz => ("megahertz", "MHz"),
+        };
+
+        // Clock speed graphics
+        let clock_speed_graphics_opts = Opts::new(
+            format!("clock_speed_graphics_{}", suffix),
+            format!("Clock speed of the GPU in {}", symbol),
+        )
+        .namespace(NAMESPACE);
+        let clock_speed_graphics_gauge = IntGaugeVec::new(clock_speed_graphics_opts, labels)?;
+
+        // Clock speed streaming multiprocessor
+        let clock_speed_sm_opts = Opts::new(
+            format!("clock_speed_sm_{}", suffix),
+            format!(
+                "Clock speed of the GPU streaming multiprocessor in {}",
+                symbol
+            ),
+        )
+        .namespace(NAMESPACE);
+        let clock_speed_sm_gauge = IntGaugeVec::new(clock_speed_sm_opts, labels)?;
+
+        // Throttle reasons
+        let throttle_reason_seconds_opts = Opts::new(
+            "throttle_reason_seconds_total",
+            "Time the clocks of the GPU were throttled for the reason in seconds, as sampled while the exporter was running",
+        )
+        .namespace(NAMESPACE);
+        let mut throttle_reason_labels = labels.to_vec();
+        throttle_reason_labels.push("reason");
+        let throttle_reason_seconds_counter =
+            CounterVec::new(throttle_reason_seconds_opts, &throttle_reason_labels)?;
+
+        Ok(Metrics {
+            unit,
+            clock_speed_graphics_gauge,
+            clock_speed_sm_gauge,
+            throttle_reason_seconds_counter,
+        })
+    }
+
+    /// Converts a reading in MHz to the configured unit.
+    fn value(&self, megahertz: u32) -> i64 {
+        match self.unit {
+            ClockUnit::Hertz => i64::from(megahertz) * 1_000_000,
+            ClockUnit::Megahertz => i64::from(megahertz),
+        }
+    }
+}
+
+impl MetricSet for Metrics {
+    fn collectors(&self) -> Vec<&dyn prometheus::core::Collector> {
+        vec![
+            &self.clock_speed_graphics_gauge,
+            &self.clock_speed_sm_gauge,
+            &self.throttle_reason_seconds_counter,
+        ]
+    }
+
+    fn readings(&self) -> Vec<&dyn prometheus::core::Collector> {
+        vec![&self.clock_speed_graphics_gauge, &self.clock_speed_sm_gauge]
+    }
+}
+
+impl<B: GpuBackend + ?Sized> Collector<B> for ClocksCollector {
+    fn name(&self) -> &'static str {
+        "clocks"
+    }
+
+    fn describe(&self, labels: &[&str]) -> Result<Vec<Desc>> {
+        Ok(Metrics::new(labels, self.unit)?.descs())
+    }
+
+    fn readings(&self, labels: &[&str]) -> Result<Vec<Desc>> {
+        Ok(Metrics::new(labels, self.unit)?.reading_descs())
+    }
+
+    fn collect(&self, ctx: &Context<B>, devices: &[Device]) -> Result<Vec<MetricFamily>> {
+        let metrics = Metrics::new(ctx.labels, self.unit)?;
+
+        for device in devices {
+            let labels = device.labels();
+            let index = device.info.index;
+
+            // Clock speed graphics
+            if let Ok(clock_speed_graphics) = ctx.query(device, "clock_graphics", || {
+                ctx.backend.clock(index, ClockType::Graphics)
+            }) {
+                metrics
+                    .clock_speed_graphics_gauge
+                    .get_metric_with_label_values(&labels)?
+                    .set(metrics.value(clock_speed_graphics));
+            }
+
+            // Clock speed streaming multiprocessor
+            if let Ok(clock_speed_sm) = ctx.query(device, "clock_sm", || {
+                ctx.backend.clock(index, ClockType::Sm)
+            }) {
+                metrics
+                    .clock_speed_sm_gauge
+                    .get_metric_with_label_values(&labels)?
+                    .set(metrics.value(clock_speed_sm));
+            }
+
+            // Throttle reasons, only available while the sampler is running
+            if let Some(throttled) = ctx.samples.throttled(&device.info.uuid) {
+                for (reason, duration) in throttled {
+                    let mut labels = labels.clone();
+                    labels.push(reason.name());
+
+                    metrics
+                        .throttle_reason_seconds_counter
+                        .get_metric_with_label_values(&labels)?
+                        .inc_by(duration.as_secs_f64());
+                }
+            }
+        }
+
+        Ok(metrics.families())
+    }
+}
@@ -0,0 +1,118 @@
+use prometheus::core::Desc;
+use prometheus::proto::MetricFamily;
+use prometheus::{IntGaugeVec, Opts};
+
+use crate::backend::GpuBackend;
+use crate::collectors::{Collector, Context, Device, MetricSet};
+use crate::error::Result;
+use crate::NAMESPACE;
+
+/// Fan speed.
+pub struct FanCollector;
+
+struct Metrics {
+    fan_speed_gauge: IntGaugeVec,
+    fan_speed_rpm_gauge: IntGaugeVec,
+    fan_target_speed_gauge: IntGaugeVec,
+}
+
+impl Metrics {
+    fn new(labels: &[&str]) -> Result<Metrics> {
+        let fan_speed_opts = Opts::new(
+            "fan_speed_percent",
+            "Fan speed of the GPU device as a percent of its maximum",
+        )
+        .namespace(NAMESPACE);
+        let fan_speed_gauge = IntGaugeVec::new(fan_speed_opts, labels)?;
+
+        // Absolute fan speed, which newer drivers report
+        let fan_speed_rpm_opts = Opts::new(
+            "fan_speed_rpm",
+            "Fan speed of the GPU device in revolutions per minute",
+        )
+        .namespace(NAMESPACE);
+        let fan_speed_rpm_gauge = IntGaugeVec::new(fan_speed_rpm_opts, labels)?;
+
+        // Target fan speed
+        let fan_target_speed_opts = Opts::new(
+            "fan_speed_target_percent",
+            "Fan speed the driver drives the fan of the GPU device towards as a percent of its maximum",
+        )
+        .namespace(NAMESPACE);
+        let fan_target_speed_gauge = IntGaugeVec::new(fan_target_speed_opts, labels)?;
+
+        Ok(Metrics {
+            fan_speed_gauge,
+            fan_speed_rpm_gauge,
+            fan_target_speed_gauge,
+        })
+    }
+}
+
+impl MetricSet for Metrics {
+    fn collectors(&self) -> Vec<&dyn prometheus::core::Collector> {
+        vec![
+            &self.fan_speed_gauge,
+            &self.fan_speed_rpm_gauge,
+            &self.fan_target_speed_gauge,
+        ]
+    }
+
+    fn readings(&self) -> Vec<&dyn prometheus::core::Collector> {
+        vec![
+            &self.fan_speed_gauge,
+            &self.fan_speed_rpm_gauge,
+            &self.fan_target_speed_gauge,
+        ]
+    }
+}
+
+impl<B: GpuBackend + ?Sized> Collector<B> for FanCollector {
+    fn name(&self) -> &'static str {
+        "fan"
+    }
+
+    fn describe(&self, labels: &[&str]) -> Result<Vec<Desc>> {
+        Ok(Metrics::new(labels)?.descs())
+    }
+
+    fn readings(&self, labels: &[&str]) -> Result<Vec<Desc>> {
+        Ok(Metrics::new(labels)?.reading_descs())
+    }
+
+    fn collect(&self, ctx: &Context<B>, devices: &[Device]) -> Result<Vec<MetricFamily>> {
+        let metrics = Metrics::new(ctx.labels)?;
+
+        for device in devices {
+            let labels = device.labels();
+            let index = device.info.index;
+
+            if let Ok(fan_speed) = ctx.query(device, "fan_speed", || ctx.backend.fan_speed(index)) {
+                metrics
+                    .fan_speed_gauge
+                    .get_metric_with_label_values(&labels)?
+                    .set(fan_speed as i64);
+            }
+
+            if let Ok(fan_speed_rpm) =
+                ctx.query(device, "fan_speed_rpm", || ctx.backend.fan_speed_rpm(index))
+            {
+                metrics
+                    .fan_speed_rpm_gauge
+                    .get_metric_with_label_values(&labels)?
+                    .set(fan_speed_rpm as i64);
+            }
+
+            if let Ok(target_fan_speed) = ctx.query(device, "fan_target_speed", || {
+                ctx.backend.target_fan_speed(index)
+            }) {
+                metrics
+                    .fan_target_speed_gauge
+                    .get_metric_with_label_values(&labels)?
+                    .set(target_fan_speed as i64);
+            }
+        }
+
+        Ok(metrics.families())
+    }
+}
@@ -0,0 +1,89 @@
+use prometheus::core::Desc;
+use prometheus::proto::MetricFamily;
+use prometheus::{IntGaugeVec, Opts};
+
+use crate::backend::{Feature, GpuBackend};
+use crate::collectors::{Collector, Context, Device, MetricSet};
+use crate::error::Result;
+use crate::NAMESPACE;
+
+/// Which optional features each device supports, so that dashboards can hide
+/// panels that do not apply to a card.
+pub struct FeaturesCollector;
+
+struct Metrics {
+    feature_supported_gauge: IntGaugeVec,
+}
+
+impl Metrics {
+    fn new(labels: &[&str]) -> Result<Metrics> {
+        let feature_supported_opts = Opts::new(
+            "feature_supported",
+            "Whether the GPU device supports the feature (1) or not (0)",
+        )
+        .namespace(NAMESPACE);
+        let mut feature_labels = labels.to_vec();
+        feature_labels.push("feature");
+        let feature_supported_gauge = IntGaugeVec::new(feature_supported_opts, &feature_labels)?;
+
+        Ok(Metrics {
+            feature_supported_gauge,
+        })
+    }
+}
+
+impl MetricSet for Metrics {
+    fn collectors(&self) -> Vec<&dyn prometheus::core::Collector> {
+        vec![&self.feature_supported_gauge]
+    }
+}
+
+/// Names of the probes in the NVML call metrics, by feature.
+fn call(feature: Feature) -> &'static str {
+    match feature {
+        Feature::Ecc => "probe_ecc",
+        Feature::Nvlink => "probe_nvlink",
+        Feature::Mig => "probe_mig",
+        Feature::Fan => "probe_fan",
+        Feature::PowerReadings => "probe_power_readings",
+    }
+}
+
+impl<B: GpuBackend + ?Sized> Collector<B> for FeaturesCollector {
+    fn name(&self) -> &'static str {
+        "features"
+    }
+
+    fn describe(&self, labels: &[&str]) -> Result<Vec<Desc>> {
+        Ok(Metrics::new(labels)?.descs())
+    }
+
+    fn collect(&self, ctx: &Context<B>, devices: &[Device]) -> Result<Vec<MetricFamily>> {
+        let metrics = Metrics::new(ctx.labels)?;
+
+        for device in devices {
+            let index = device.info.index;
+
+            for &feature in &Feature::ALL {
+                // Unsupported features are remembered, so they are not
+                // probed on every collection
+                let supported = match ctx.query(device, call(feature), || {
+                    ctx.backend.probe_feature(index, feature)
+                }) {
+                    Ok(()) => 1,
+                    Err(e) if e.is_not_supported() => 0,
+                    Err(_) => continue,
+                };
+
+                let mut labels = device.labels();
+                labels.push(feature.name());
+                metrics
+                    .feature_supported_gauge
+                    .get_metric_with_label_values(&labels)?
+                    .set(supported);
+            }
+        }
+
+        Ok(metrics.families())
+    }
+}
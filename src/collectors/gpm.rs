@@ -0,0 +1,124 @@
+use prometheus::core::Desc;
+use prometheus::proto::MetricFamily;
+use prometheus::{GaugeVec, Opts};
+
+use crate::backend::GpuBackend;
+use crate::collectors::{Collector, Context, Device, MetricSet};
+use crate::error::Result;
+use crate::NAMESPACE;
+
+/// Profiling metrics of the GPU performance monitoring of Hopper and newer
+/// devices, averaged since the previous collection.
+pub struct GpmCollector;
+
+struct Metrics {
+    sm_activity_gauge: GaugeVec,
+    sm_occupancy_gauge: GaugeVec,
+    tensor_activity_gauge: GaugeVec,
+    dram_bandwidth_gauge: GaugeVec,
+}
+
+impl Metrics {
+    fn new(labels: &[&str]) -> Result<Metrics> {
+        // SM activity
+        let sm_activity_opts = Opts::new(
+            "sm_activity_percent",
+            "Percent of time at least one warp was active on an SM of the GPU device, averaged over all SMs",
+        )
+        .namespace(NAMESPACE);
+        let sm_activity_gauge = GaugeVec::new(sm_activity_opts, labels)?;
+
+        // SM occupancy
+        let sm_occupancy_opts = Opts::new(
+            "sm_occupancy_percent",
+            "Warps resident on the SMs of the GPU device in percent of the maximum",
+        )
+        .namespace(NAMESPACE);
+        let sm_occupancy_gauge = GaugeVec::new(sm_occupancy_opts, labels)?;
+
+        // Tensor activity
+        let tensor_activity_opts = Opts::new(
+            "tensor_activity_percent",
+            "Percent of time any tensor pipe of the GPU device was active",
+        )
+        .namespace(NAMESPACE);
+        let tensor_activity_gauge = GaugeVec::new(tensor_activity_opts, labels)?;
+
+        // DRAM bandwidth
+        let dram_bandwidth_opts = Opts::new(
+            "dram_bandwidth_utilization_percent",
+            "Used DRAM bandwidth of the GPU device in percent of the maximum",
+        )
+        .namespace(NAMESPACE);
+        let dram_bandwidth_gauge = GaugeVec::new(dram_bandwidth_opts, labels)?;
+
+        Ok(Metrics {
+            sm_activity_gauge,
+            sm_occupancy_gauge,
+            tensor_activity_gauge,
+            dram_bandwidth_gauge,
+        })
+    }
+}
+
+impl MetricSet for Metrics {
+    fn collectors(&self) -> Vec<&dyn prometheus::core::Collector> {
+        vec![
+            &self.sm_activity_gauge,
+            &self.sm_occupancy_gauge,
+            &self.tensor_activity_gauge,
+            &self.dram_bandwidth_gauge,
+        ]
+    }
+
+    fn readings(&self) -> Vec<&dyn prometheus::core::Collector> {
+        vec![
+            &self.sm_activity_gauge,
+            &self.sm_occupancy_gauge,
+            &self.tensor_activity_gauge,
+            &self.dram_bandwidth_gauge,
+        ]
+    }
+}
+
+impl<B: GpuBackend + ?Sized> Collector<B> for GpmCollector {
+    fn name(&self) -> &'static str {
+        "gpm"
+    }
+
+    fn describe(&self, labels: &[&str]) -> Result<Vec<Desc>> {
+        Ok(Metrics::new(labels)?.descs())
+    }
+
+    fn readings(&self, labels: &[&str]) -> Result<Vec<Desc>> {
+        Ok(Metrics::new(labels)?.reading_descs())
+    }
+
+    fn collect(&self, ctx: &Context<B>, devices: &[Device]) -> Result<Vec<MetricFamily>> {
+        let metrics = Metrics::new(ctx.labels)?;
+
+        for device in devices {
+            let labels = device.labels();
+            let index = device.info.index;
+
+            // The first collection only takes the initial sample
+            let gpm = match ctx.query(device, "gpm_metrics", || ctx.backend.gpm_metrics(index)) {
+                Ok(gpm) => gpm,
+                Err(_) => continue,
+            };
+
+            for (gauge, value) in &[
+                (&metrics.sm_activity_gauge, gpm.sm_activity),
+                (&metrics.sm_occupancy_gauge, gpm.sm_occupancy),
+                (&metrics.tensor_activity_gauge, gpm.tensor_activity),
+                (&metrics.dram_bandwidth_gauge, gpm.dram_bandwidth),
+            ] {
+                if let Some(value) = value {
+                    gauge.get_metric_with_label_values(&labels)?.set(*value);
+                }
+            }
+        }
+
+        Ok(metrics.families())
+    }
+}
@@ -0,0 +1,91 @@
+use prometheus::core::Desc;
+use prometheus::proto::MetricFamily;
+use prometheus::{IntGaugeVec, Opts};
+
+use crate::backend::GpuBackend;
+use crate::collectors::{Collector, Context, Device, MetricSet};
+use crate::error::Result;
+use crate::NAMESPACE;
+
+/// Licensing state of vGPU (GRID) features.
+pub struct GridCollector;
+
+struct Metrics {
+    grid_licensed_gauge: IntGaugeVec,
+    grid_feature_enabled_gauge: IntGaugeVec,
+}
+
+impl Metrics {
+    fn new(labels: &[&str]) -> Result<Metrics> {
+        let mut feature_labels = labels.to_vec();
+        feature_labels.extend(&["feature", "product"]);
+
+        // License state
+        let grid_licensed_opts = Opts::new(
+            "grid_licensed",
+            "Whether a license was acquired for the vGPU feature of the GPU device",
+        )
+        .namespace(NAMESPACE);
+        let grid_licensed_gauge = IntGaugeVec::new(grid_licensed_opts, &feature_labels)?;
+
+        // Feature state
+        let grid_feature_enabled_opts = Opts::new(
+            "grid_feature_enabled",
+            "Whether the licensable vGPU feature of the GPU device is enabled",
+        )
+        .namespace(NAMESPACE);
+        let grid_feature_enabled_gauge =
+            IntGaugeVec::new(grid_feature_enabled_opts, &feature_labels)?;
+
+        Ok(Metrics {
+            grid_licensed_gauge,
+            grid_feature_enabled_gauge,
+        })
+    }
+}
+
+impl MetricSet for Metrics {
+    fn collectors(&self) -> Vec<&dyn prometheus::core::Collector> {
+        vec![&self.grid_licensed_gauge, &self.grid_feature_enabled_gauge]
+    }
+}
+
+impl<B: GpuBackend + ?Sized> Collector<B> for GridCollector {
+    fn name(&self) -> &'static str {
+        "grid"
+    }
+
+    fn describe(&self, labels: &[&str]) -> Result<Vec<Desc>> {
+        Ok(Metrics::new(labels)?.descs())
+    }
+
+    fn collect(&self, ctx: &Context<B>, devices: &[Device]) -> Result<Vec<MetricFamily>> {
+        let metrics = Metrics::new(ctx.labels)?;
+
+        for device in devices {
+            let index = device.info.index;
+
+            let licenses =
+                match ctx.query(device, "grid_licenses", || ctx.backend.grid_licenses(index)) {
+                    Ok(licenses) => licenses,
+                    Err(_) => continue,
+                };
+
+            for license in &licenses {
+                let mut labels = device.labels();
+                labels.extend(&[license.feature.as_str(), license.product.as_str()]);
+
+                metrics
+                    .grid_licensed_gauge
+                    .get_metric_with_label_values(&labels)?
+                    .set(license.licensed as i64);
+                metrics
+                    .grid_feature_enabled_gauge
+                    .get_metric_with_label_values(&labels)?
+                    .set(license.enabled as i64);
+            }
+        }
+
+        Ok(metrics.families())
+    }
+}
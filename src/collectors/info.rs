@@ -0,0 +1,179 @@
+use prometheus::core::Desc;
+use prometheus::proto::MetricFamily;
+use prometheus::{IntGaugeVec, Opts};
+
+use crate::backend::GpuBackend;
+use crate::collectors::{Collector, Context, Device, MetricSet};
+use crate::error::Result;
+use crate::NAMESPACE;
+
+/// Static properties of the devices, exported as labels of constant series.
+/// They are read once when the devices are enumerated, not on every
+/// collection.
+pub struct InfoCollector;
+
+struct Metrics {
+    compute_capability_gauge: IntGaugeVec,
+    vbios_gauge: IntGaugeVec,
+    affinity_gauge: IntGaugeVec,
+    slot_gauge: IntGaugeVec,
+}
+
+impl Metrics {
+    fn new(labels: &[&str]) -> Result<Metrics> {
+        // Compute capability
+        let compute_capability_opts = Opts::new(
+            "compute_capability",
+            "CUDA compute capability of the GPU device, given by the major and minor labels",
+        )
+        .namespace(NAMESPACE);
+        let mut compute_capability_labels = labels.to_vec();
+        compute_capability_labels.extend(&["major", "minor"]);
+        let compute_capability_gauge =
+            IntGaugeVec::new(compute_capability_opts, &compute_capability_labels)?;
+
+        // VBIOS version
+        let vbios_opts = Opts::new(
+            "vbios_info",
+            "VBIOS of the GPU device, given by the version label",
+        )
+        .namespace(NAMESPACE);
+        let mut vbios_labels = labels.to_vec();
+        vbios_labels.push("version");
+        let vbios_gauge = IntGaugeVec::new(vbios_opts, &vbios_labels)?;
+
+        // NUMA node and CPU affinity
+        let affinity_opts = Opts::new(
+            "affinity_info",
+            "NUMA node closest to the GPU device and CPUs ideally running its processes, given by the numa_node and cpus labels",
+        )
+        .namespace(NAMESPACE);
+        let mut affinity_labels = labels.to_vec();
+        affinity_labels.extend(&["numa_node", "cpus"]);
+        let affinity_gauge = IntGaugeVec::new(affinity_opts, &affinity_labels)?;
+
+        // Physical slot
+        let slot_opts = Opts::new(
+            "slot_info",
+            "Physical slot the GPU device is plugged into, given by the slot label",
+        )
+        .namespace(NAMESPACE);
+        let mut slot_labels = labels.to_vec();
+        slot_labels.push("slot");
+        let slot_gauge = IntGaugeVec::new(slot_opts, &slot_labels)?;
+
+        Ok(Metrics {
+            compute_capability_gauge,
+            vbios_gauge,
+            affinity_gauge,
+            slot_gauge,
+        })
+    }
+}
+
+impl MetricSet for Metrics {
+    fn collectors(&self) -> Vec<&dyn prometheus::core::Collector> {
+        vec![
+            &self.compute_capability_gauge,
+            &self.vbios_gauge,
+            &self.affinity_gauge,
+            &self.slot_gauge,
+        ]
+    }
+}
+
+impl<B: GpuBackend + ?Sized> Collector<B> for InfoCollector {
+    fn name(&self) -> &'static str {
+        "info"
+    }
+
+    fn describe(&self, labels: &[&str]) -> Result<Vec<Desc>> {
+        Ok(Metrics::new(labels)?.descs())
+    }
+
+    fn collect(&self, ctx: &Context<B>, devices: &[Device]) -> Result<Vec<MetricFamily>> {
+        let metrics = Metrics::new(ctx.labels)?;
+
+        for device in devices {
+            // Compute capability
+            if let Some((major, minor)) = device.statics.compute_capability {
+                let major = major.to_string();
+                let minor = minor.to_string();
+                let mut labels = device.labels();
+                labels.extend(&[major.as_str(), minor.as_str()]);
+
+                metrics
+                    .compute_capability_gauge
+                    .get_metric_with_label_values(&labels)?
+                    .set(1);
+            }
+
+            // VBIOS version
+            if let Some(version) = &device.statics.vbios_version {
+                let mut labels = device.labels();
+                labels.push(version.as_str());
+
+                metrics
+                    .vbios_gauge
+                    .get_metric_with_label_values(&labels)?
+                    .set(1);
+            }
+
+            // NUMA node and CPU affinity, empty if unknown
+            let statics = &device.statics;
+            if statics.numa_node.is_some() || statics.cpu_affinity.is_some() {
+                let numa_node = statics
+                    .numa_node
+                    .map_or_else(String::new, |node| node.to_string());
+                let cpus = statics
+                    .cpu_affinity
+                    .as_deref()
+                    .map_or_else(String::new, cpu_list);
+                let mut labels = device.labels();
+                labels.extend(&[numa_node.as_str(), cpus.as_str()]);
+
+                metrics
+                    .affinity_gauge
+                    .get_metric_with_label_values(&labels)?
+                    .set(1);
+            }
+
+            // Physical slot
+            if let Some(slot) = &statics.physical_slot {
+                let mut labels = device.labels();
+                labels.push(slot.as_str());
+
+                metrics
+                    .slot_gauge
+                    .get_metric_with_label_values(&labels)?
+                    .set(1);
+            }
+        }
+
+        Ok(metrics.families())
+    }
+}
+
+/// Formats ascending CPU numbers as a list of ranges like in `taskset` and
+/// sysfs, e.g. `0-15,32-47`.
+fn cpu_list(cpus: &[u32]) -> String {
+    let mut ranges: Vec<(u32, u32)> = Vec::new();
+    for &cpu in cpus {
+        match ranges.last_mut() {
+            Some((_, last)) if *last + 1 == cpu => *last = cpu,
+            _ => ranges.push((cpu, cpu)),
+        }
+    }
+
+    ranges
+        .iter()
+        .map(|&(first, last)| {
+            if first == last {
+                first.to_string()
+            } else {
+                format!("{}-{}", first, last)
+            }
+        })
+        .collect::<Vec<_>>()
+        .join(",")
+}
@@ -0,0 +1,140 @@
+use std::collections::BTreeMap;
+
+use prometheus::core::Desc;
+use prometheus::proto::MetricFamily;
+use prometheus::{IntGaugeVec, Opts};
+
+use crate::backend::GpuBackend;
+use crate::collectors::{Collector, Context, Device, MetricSet};
+use crate::error::Result;
+use crate::procinfo;
+use crate::NAMESPACE;
+
+/// Allocation of devices to Kubernetes pods, as last read from the kubelet,
+/// and the utilization of each device by the pods it is allocated to.
+pub struct KubernetesCollector;
+
+struct Metrics {
+    gpu_allocated_gauge: IntGaugeVec,
+    pod_gpu_utilization_gauge: IntGaugeVec,
+}
+
+impl Metrics {
+    fn new(labels: &[&str]) -> Result<Metrics> {
+        let mut pod_labels = labels.to_vec();
+        pod_labels.extend(&["pod", "namespace"]);
+
+        // Allocation
+        let gpu_allocated_opts = Opts::new(
+            "gpu_allocated",
+            "Whether the GPU device is allocated to a Kubernetes pod",
+        )
+        .namespace(NAMESPACE);
+        let gpu_allocated_gauge = IntGaugeVec::new(gpu_allocated_opts, &pod_labels)?;
+
+        // Utilization by pod
+        let pod_gpu_utilization_opts = Opts::new(
+            "pod_gpu_utilization",
+            "GPU utilization of the GPU device by the processes of the Kubernetes pod in percent",
+        )
+        .namespace(NAMESPACE);
+        let pod_gpu_utilization_gauge = IntGaugeVec::new(pod_gpu_utilization_opts, &pod_labels)?;
+
+        Ok(Metrics {
+            gpu_allocated_gauge,
+            pod_gpu_utilization_gauge,
+        })
+    }
+}
+
+impl MetricSet for Metrics {
+    fn collectors(&self) -> Vec<&dyn prometheus::core::Collector> {
+        vec![&self.gpu_allocated_gauge, &self.pod_gpu_utilization_gauge]
+    }
+}
+
+impl<B: GpuBackend + ?Sized> Collector<B> for KubernetesCollector {
+    fn name(&self) -> &'static str {
+        "kubernetes"
+    }
+
+    fn describe(&self, labels: &[&str]) -> Result<Vec<Desc>> {
+        Ok(Metrics::new(labels)?.descs())
+    }
+
+    fn collect(&self, ctx: &Context<B>, devices: &[Device]) -> Result<Vec<MetricFamily>> {
+        let metrics = Metrics::new(ctx.labels)?;
+
+        for device in devices {
+            let allocations = match ctx.allocations.of(&device.info.uuid) {
+                Some(allocations) => allocations,
+                None => continue,
+            };
+
+            if allocations.is_empty() {
+                let mut labels = device.labels();
+                labels.extend(&["", ""]);
+
+                metrics
+                    .gpu_allocated_gauge
+                    .get_metric_with_label_values(&labels)?
+                    .set(0);
+            }
+
+            for allocation in &allocations {
+                let pod = ctx.pod(&allocation.pod);
+                let mut labels = device.labels();
+                labels.extend(&[pod.as_str(), allocation.namespace.as_str()]);
+
+                metrics
+                    .gpu_allocated_gauge
+                    .get_metric_with_label_values(&labels)?
+                    .set(1);
+            }
+
+            // Several pods share a time-sliced device
+            let mut pods: Vec<(&str, &str)> = allocations
+                .iter()
+                .map(|a| (a.pod.as_str(), a.namespace.as_str()))
+                .collect();
+            pods.sort();
+            pods.dedup();
+            if pods.is_empty() {
+                continue;
+            }
+
+            let processes = match ctx.process_utilization(device) {
+                Ok(processes) => processes,
+                Err(_) => continue,
+            };
+
+            let mut utilization: BTreeMap<(&str, &str), u32> =
+                pods.iter().map(|&pod| (pod, 0)).collect();
+            for process in &processes {
+                let pod = if pods.len() == 1 {
+                    Some(pods[0])
+                } else {
+                    procinfo::pod_name(process.pid)
+                        .and_then(|name| pods.iter().find(|(pod, _)| *pod == name).copied())
+                };
+                // Processes that cannot be attributed to a pod keep the sum
+                // of all pods equal to the utilization of the device
+                *utilization.entry(pod.unwrap_or(("", ""))).or_insert(0) += process.gpu;
+            }
+
+            for ((pod, namespace), gpu) in utilization {
+                // Matched against the processes by their real name above
+                let pod = ctx.pod(pod);
+                let mut labels = device.labels();
+                labels.extend(&[pod.as_str(), namespace]);
+
+                metrics
+                    .pod_gpu_utilization_gauge
+                    .get_metric_with_label_values(&labels)?
+                    .set(i64::from(gpu));
+            }
+        }
+
+        Ok(metrics.families())
+    }
+}
@@ -0,0 +1,160 @@
+use prometheus::core::Desc;
+use prometheus::proto::MetricFamily;
+use prometheus::{IntGaugeVec, Opts};
+
+use crate::backend::{GpuBackend, MemoryInfo};
+use crate::collectors::{Collector, Context, Device, MetricSet};
+use crate::config::MigIdentity;
+use crate::error::Result;
+use crate::NAMESPACE;
+
+/// Total, free and used device memory, of GPUs in MIG mode or their MIG
+/// instances.
+pub struct MemoryCollector {
+    pub mig: MigIdentity,
+}
+
+struct Metrics {
+    total_memory_gauge: IntGaugeVec,
+    free_memory_gauge: IntGaugeVec,
+    used_memory_gauge: IntGaugeVec,
+}
+
+impl Metrics {
+    fn new(labels: &[&str], mig: MigIdentity) -> Result<Metrics> {
+        let mut labels = labels.to_vec();
+        if mig == MigIdentity::Both {
+            labels.push("parent_uuid");
+        }
+        let labels = &labels;
+
+        // Total memory
+        let total_memory_opts = Opts::new(
+            "memory_total_bytes",
+            "Total memory available by the GPU device in bytes",
+        )
+        .namespace(NAMESPACE);
+        let total_memory_gauge = IntGaugeVec::new(total_memory_opts, labels)?;
+
+        // Free memory
+        let free_memory_opts = Opts::new(
+            "memory_free_bytes",
+            "Free memory of the GPU device in bytes",
+        )
+        .namespace(NAMESPACE);
+        let free_memory_gauge = IntGaugeVec::new(free_memory_opts, labels)?;
+
+        // Used memory
+        let used_memory_opts = Opts::new(
+            "memory_used_bytes",
+            "Memory used by the GPU device in bytes",
+        )
+        .namespace(NAMESPACE);
+        let used_memory_gauge = IntGaugeVec::new(used_memory_opts, labels)?;
+
+        Ok(Metrics {
+            total_memory_gauge,
+            free_memory_gauge,
+            used_memory_gauge,
+        })
+    }
+
+    /// Sets the memory of the device or MIG instance `labels`.
+    fn set(&self, labels: &[String], memory_info: &MemoryInfo) -> Result<()> {
+        let labels: Vec<&str> = labels.iter().map(String::as_str).collect();
+        self.total_memory_gauge
+            .get_metric_with_label_values(&labels)?
+            .set(memory_info.total as i64);
+        self.free_memory_gauge
+            .get_metric_with_label_values(&labels)?
+            .set(memory_info.free as i64);
+        self.used_memory_gauge
+            .get_metric_with_label_values(&labels)?
+            .set(memory_info.used as i64);
+        Ok(())
+    }
+}
+
+impl MetricSet for Metrics {
+    fn collectors(&self) -> Vec<&dyn prometheus::core::Collector> {
+        vec![
+            &self.total_memory_gauge,
+            &self.free_memory_gauge,
+            &self.used_memory_gauge,
+        ]
+    }
+
+    fn readings(&self) -> Vec<&dyn prometheus::core::Collector> {
+        vec![
+            &self.total_memory_gauge,
+            &self.free_memory_gauge,
+            &self.used_memory_gauge,
+        ]
+    }
+}
+
+impl<B: GpuBackend + ?Sized> Collector<B> for MemoryCollector {
+    fn name(&self) -> &'static str {
+        "memory"
+    }
+
+    fn describe(&self, labels: &[&str]) -> Result<Vec<Desc>> {
+        Ok(Metrics::new(labels, self.mig)?.descs())
+    }
+
+    fn readings(&self, labels: &[&str]) -> Result<Vec<Desc>> {
+        Ok(Metrics::new(labels, self.mig)?.reading_descs())
+    }
+
+    fn collect(&self, ctx: &Context<B>, devices: &[Device]) -> Result<Vec<MetricFamily>> {
+        let metrics = Metrics::new(ctx.labels, self.mig)?;
+
+        for device in devices {
+            let labels = device.labels();
+            let index = device.info.index;
+
+            // Without MIG mode, the list is empty
+            let instances = match self.mig {
+                MigIdentity::Parent => Vec::new(),
+                MigIdentity::Instances | MigIdentity::Both => ctx
+                    .query(device, "mig_devices", || ctx.backend.mig_devices(index))
+                    .unwrap_or_default(),
+            };
+            let parent_uuid = device.uuid_label();
+            for instance in &instances {
+                let mut instance_labels = device.mig_labels(&instance.uuid);
+                if self.mig == MigIdentity::Both {
+                    instance_labels.push(parent_uuid.clone());
+                }
+                metrics.set(&instance_labels, &instance.memory_info)?;
+            }
+            if self.mig == MigIdentity::Instances && !instances.is_empty() {
+                continue;
+            }
+
+            if let Ok(memory_info) = ctx.query(device, "memory", || ctx.backend.memory_info(index))
+            {
+                let mut parent_labels: Vec<String> = labels.iter().map(|l| l.to_string()).collect();
+                if self.mig == MigIdentity::Both {
+                    parent_labels.push(String::new());
+                }
+                metrics.set(&parent_labels, &memory_info)?;
+                if let Some(exemplars) = ctx.exemplars {
+                    let top = ctx
+                        .query(device, "processes", || ctx.backend.processes(index))
+                        .ok()
+                        .and_then(|processes| {
+                            processes
+                                .into_iter()
+                                .filter_map(|p| Some((p.pid, p.used_memory?)))
+                                .max_by_key(|&(_, used_memory)| used_memory)
+                        })
+                        .map(|(pid, used_memory)| (pid, used_memory as f64));
+                    exemplars.set(&format!("{}_memory_used_bytes", NAMESPACE), &labels, top);
+                }
+            }
+        }
+
+        Ok(metrics.families())
+    }
+}
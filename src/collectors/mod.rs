@@ -0,0 +1,799 @@
+//! Independent collectors, each responsible for one group of device metrics.
+//!
+//! Collectors are listed in [`all`] and can be enabled, disabled and
+//! rate-limited individually through the `[collectors.<name>]` sections of the
+//! configuration.
+
+use std::collections::{HashMap, HashSet};
+use std::iter;
+use std::sync::Mutex;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use prometheus::core::{Collector as _, Desc};
+use prometheus::proto::MetricFamily;
+use prometheus::{GaugeVec, HistogramVec, IntGaugeVec, Opts};
+
+use crate::backend::{DeviceInfo, Field, GpuBackend, ProcessUtilization};
+use crate::config::{Config, LabelsConfig, NvmlConfig, UnsupportedReadings};
+use crate::error::{CollectingError, Result};
+use crate::exemplars::Exemplars;
+use crate::kubernetes::Allocations;
+use crate::privacy::Privacy;
+use crate::procinfo::{self, ProcessDetails};
+use crate::samples::{Reading, Samples};
+use crate::xids::Xids;
+use crate::NAMESPACE;
+
+mod clocks;
+mod fan;
+mod features;
+mod gpm;
+mod grid;
+mod info;
+mod kubernetes;
+mod memory;
+mod operation_mode;
+mod pcie;
+mod persistence;
+mod power;
+mod processes;
+mod temperature;
+mod utilization;
+mod xid;
+
+/// Identity labels attached to every device metric by default.
+#[cfg(target_os = "linux")]
+pub const LABELS: [&str; 3] = ["minor_number", "uuid", "name"];
+
+/// Identity labels attached to every device metric by default. Device minor
+/// numbers only exist on Linux, so the NVML index is used instead.
+#[cfg(not(target_os = "linux"))]
+pub const LABELS: [&str; 3] = ["index", "uuid", "name"];
+
+/// Identity labels that can be chosen in the `[labels]` section of the
+/// configuration.
+pub const IDENTITY_LABELS: [&str; 8] = [
+    "index",
+    "index_by_busid",
+    "minor_number",
+    "uuid",
+    "name",
+    "pci_bus_id",
+    "serial",
+    "hostname",
+];
+
+lazy_static! {
+    static ref HOSTNAME: String = gethostname::gethostname().to_string_lossy().into_owned();
+}
+
+/// Attributes of a device that do not change while it is attached, read once
+/// when the device is enumerated. Attributes the device does not report are
+/// `None`.
+#[derive(Clone, Debug, Default)]
+pub struct StaticInfo {
+    pub vbios_version: Option<String>,
+    pub compute_capability: Option<(u32, u32)>,
+    pub numa_node: Option<u32>,
+    pub cpu_affinity: Option<Vec<u32>>,
+    pub physical_slot: Option<String>,
+}
+
+/// A device enumerated for the current collection.
+#[derive(Clone, Debug)]
+pub struct Device {
+    pub info: DeviceInfo,
+    pub statics: StaticInfo,
+    /// Position of the device among all devices sorted by PCI bus ID, which
+    /// unlike the NVML index matches the order `nvidia-smi` shows.
+    pub index_by_busid: u32,
+    labels: Vec<String>,
+    config: LabelsConfig,
+}
+
+impl Device {
+    pub fn new(info: DeviceInfo, config: &LabelsConfig) -> Device {
+        let labels = identity_labels(&info, info.index, config);
+
+        Device {
+            index_by_busid: info.index,
+            info,
+            statics: StaticInfo::default(),
+            labels,
+            config: config.clone(),
+        }
+    }
+
+    /// Sets the position of the device among all devices sorted by PCI bus
+    /// ID, as it is only known once all devices are enumerated.
+    pub(crate) fn set_index_by_busid(&mut self, index_by_busid: u32) {
+        self.index_by_busid = index_by_busid;
+        self.labels = identity_labels(&self.info, index_by_busid, &self.config);
+    }
+
+    /// Values of the identity labels, in the configured order.
+    pub fn labels(&self) -> Vec<&str> {
+        self.labels.iter().map(String::as_str).collect()
+    }
+
+    /// Value of the `uuid` label of the device, whether or not it is an
+    /// identity label.
+    pub fn uuid_label(&self) -> String {
+        uuid_label(&self.info.uuid, &self.config)
+    }
+
+    /// Values of the identity labels of the MIG instance `uuid` of the
+    /// device, which shares all labels but the UUID with the device.
+    pub fn mig_labels(&self, uuid: &str) -> Vec<String> {
+        let info = DeviceInfo {
+            uuid: uuid.to_string(),
+            ..self.info.clone()
+        };
+        identity_labels(&info, self.index_by_busid, &self.config)
+    }
+}
+
+/// Sets the position of each of `devices` among them sorted by PCI bus ID.
+/// Devices without a bus ID come last, in the order of their NVML index.
+pub(crate) fn index_by_busid(devices: &mut [Device]) {
+    let mut order: Vec<usize> = (0..devices.len()).collect();
+    order.sort_by_key(|&i| {
+        let info = &devices[i].info;
+        let bus_id = info.pci_bus_id.as_ref().map(|id| id.to_lowercase());
+        (bus_id.is_none(), bus_id, info.index)
+    });
+    for (position, i) in order.into_iter().enumerate() {
+        devices[i].set_index_by_busid(position as u32);
+    }
+}
+
+/// Values of the identity labels of the device `info`, whose position among
+/// all devices sorted by PCI bus ID is `index_by_busid`, in the configured
+/// order.
+fn identity_labels(info: &DeviceInfo, index_by_busid: u32, config: &LabelsConfig) -> Vec<String> {
+    config
+        .identity
+        .iter()
+        .map(|label| match label.as_str() {
+            "index" => info.index.to_string(),
+            "index_by_busid" => index_by_busid.to_string(),
+            "minor_number" => info.minor_number.unwrap_or(info.index).to_string(),
+            "uuid" => uuid_label(&info.uuid, config),
+            "name" => info.name.clone(),
+            "pci_bus_id" => info.pci_bus_id.clone().unwrap_or_default(),
+            "serial" => info.serial.clone().unwrap_or_default(),
+            "hostname" => HOSTNAME.clone(),
+            _ => String::new(),
+        })
+        .collect()
+}
+
+/// Formats `uuid` for the `uuid` label, e.g. `8c1d2f3e` instead of
+/// `GPU-8c1d2f3e-6a1b-7c2d-8e3f-4a5b6c7d8e9f` with both options set. The
+/// `MIG-` prefix of MIG instances is treated alike.
+fn uuid_label(uuid: &str, config: &LabelsConfig) -> String {
+    let (prefix, rest) = if uuid.starts_with("GPU-") || uuid.starts_with("MIG-") {
+        uuid.split_at(4)
+    } else {
+        ("", uuid)
+    };
+
+    let rest = if config.short_uuid {
+        rest.get(..8).unwrap_or(rest)
+    } else {
+        rest
+    };
+
+    if config.strip_uuid_prefix {
+        rest.to_string()
+    } else {
+        format!("{}{}", prefix, rest)
+    }
+}
+
+/// Calls that returned [`CollectingError::NotSupported`] for a device. They
+/// are skipped until they are probed again after the re-probe interval.
+pub struct UnsupportedCache {
+    reprobe_interval: Duration,
+    since: Mutex<HashMap<(String, &'static str), Instant>>,
+}
+
+impl UnsupportedCache {
+    pub fn new(reprobe_interval: Duration) -> UnsupportedCache {
+        UnsupportedCache {
+            reprobe_interval,
+            since: Mutex::new(HashMap::new()),
+        }
+    }
+
+    fn is_unsupported(&self, key: &(String, &'static str)) -> bool {
+        self.since
+            .lock()
+            .expect("Unsupported cache poisoned")
+            .get(key)
+            .map_or(false, |since| since.elapsed() < self.reprobe_interval)
+    }
+
+    fn record(&self, key: (String, &'static str), supported: bool) {
+        let mut since = self.since.lock().expect("Unsupported cache poisoned");
+        if supported {
+            since.remove(&key);
+        } else {
+            since.insert(key, Instant::now());
+        }
+    }
+}
+
+/// Retries of readings failing with transient errors, and a circuit breaker
+/// per device that skips a device after repeated failures until a cooldown
+/// passed.
+pub struct DeviceHealth {
+    retries: u32,
+    retry_backoff: Duration,
+    max_failures: u32,
+    cooldown: Duration,
+    breakers: Mutex<HashMap<String, Breaker>>,
+}
+
+/// Circuit breaker of a device, keyed by UUID.
+#[derive(Default)]
+struct Breaker {
+    /// Transient failures in a row.
+    failures: u32,
+    /// When the device was last skipped or failed its probe after being
+    /// skipped.
+    open_since: Option<Instant>,
+}
+
+impl DeviceHealth {
+    pub fn new(config: &NvmlConfig) -> DeviceHealth {
+        DeviceHealth {
+            retries: config.retries,
+            retry_backoff: config.retry_backoff,
+            max_failures: config.circuit_breaker_failures,
+            cooldown: config.circuit_breaker_cooldown,
+            breakers: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Whether readings of the device are currently skipped.
+    fn is_open(&self, uuid: &str) -> bool {
+        self.breakers
+            .lock()
+            .expect("Circuit breakers poisoned")
+            .get(uuid)
+            .and_then(|breaker| breaker.open_since)
+            .map_or(false, |since| since.elapsed() < self.cooldown)
+    }
+
+    /// Whether the device has not been marked unhealthy, or succeeded a
+    /// reading since.
+    pub fn is_healthy(&self, uuid: &str) -> bool {
+        self.breakers
+            .lock()
+            .expect("Circuit breakers poisoned")
+            .get(uuid)
+            .map_or(true, |breaker| breaker.open_since.is_none())
+    }
+
+    fn record(&self, uuid: &str, failed: bool) {
+        let mut breakers = self.breakers.lock().expect("Circuit breakers poisoned");
+        if !failed {
+            breakers.remove(uuid);
+            return;
+        }
+
+        let breaker = breakers.entry(uuid.to_string()).or_default();
+        breaker.failures += 1;
+        if self.max_failures > 0 && breaker.failures >= self.max_failures {
+            breaker.open_since = Some(Instant::now());
+        }
+    }
+}
+
+/// What a collector gets to work with during a collection.
+pub struct Context<'a, B: ?Sized> {
+    pub backend: &'a B,
+    /// Names of the identity labels, in the order of [`Device::labels`].
+    pub labels: &'a [&'static str],
+    call_duration_histogram: &'a HistogramVec,
+    unsupported: &'a UnsupportedCache,
+    health: &'a DeviceHealth,
+    pub(crate) samples: &'a Samples,
+    pub(crate) allocations: &'a Allocations,
+    /// Where exemplars are recorded, if they are served.
+    pub(crate) exemplars: Option<&'a Exemplars>,
+    pub(crate) xids: &'a Xids,
+    /// Pseudonymizes the owners and commands of processes, if enabled.
+    privacy: Option<&'a Privacy>,
+    /// Whether the extremes of the samples start over after this collection,
+    /// which only collections served as all metrics do.
+    pub(crate) scrape: bool,
+    /// Values of [`Field::ALL`] by device index, read once per collection.
+    fields: Mutex<HashMap<u32, Vec<Option<u64>>>>,
+    /// Utilization by process by device index, read once per collection.
+    process_utilization: Mutex<HashMap<u32, Vec<ProcessUtilization>>>,
+}
+
+impl<'a, B: ?Sized> Context<'a, B> {
+    pub(crate) fn new(
+        backend: &'a B,
+        labels: &'a [&'static str],
+        call_duration_histogram: &'a HistogramVec,
+        unsupported: &'a UnsupportedCache,
+        health: &'a DeviceHealth,
+        samples: &'a Samples,
+        allocations: &'a Allocations,
+        exemplars: Option<&'a Exemplars>,
+        xids: &'a Xids,
+        privacy: Option<&'a Privacy>,
+    ) -> Context<'a, B> {
+        Context {
+            backend,
+            labels,
+            call_duration_histogram,
+            unsupported,
+            health,
+            samples,
+            allocations,
+            exemplars,
+            xids,
+            privacy,
+            scrape: false,
+            fields: Mutex::new(HashMap::new()),
+            process_utilization: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Owner and command of the host process `pid`, pseudonymized if
+    /// privacy is enabled, or `None` if it is not visible to the exporter.
+    pub(crate) fn process_details(&self, pid: u32) -> Option<ProcessDetails> {
+        let details = procinfo::lookup(pid)?;
+        Some(match self.privacy {
+            Some(privacy) => privacy.details(details),
+            None => details,
+        })
+    }
+
+    /// Value of the `pod` label of the Kubernetes pod `pod`, which is a
+    /// pseudonym if privacy is enabled.
+    pub(crate) fn pod(&self, pod: &str) -> String {
+        match self.privacy {
+            Some(privacy) => privacy.pod(pod),
+            None => pod.to_string(),
+        }
+    }
+
+    /// Minimum and maximum of `reading` of the device `uuid` since the
+    /// previous scrape of all metrics, or `None` if there were no samples in
+    /// between. Only such scrapes start the extremes over.
+    pub(crate) fn extremes(&self, uuid: &str, reading: Reading) -> Option<(u32, u32)> {
+        if self.scrape {
+            self.samples.take_extremes(uuid, reading)
+        } else {
+            self.samples.extremes(uuid, reading)
+        }
+    }
+
+    /// Cgroup path of the host process `pid`, with the users in it
+    /// pseudonymized if privacy is enabled, or `None` if it is unknown.
+    pub(crate) fn cgroup(&self, pid: u32) -> Option<String> {
+        let cgroup = procinfo::cgroup(pid)?;
+        Some(match self.privacy {
+            Some(privacy) => privacy.cgroup(&cgroup),
+            None => cgroup,
+        })
+    }
+
+    /// Like [`timed`](Context::timed), but for a reading of `device`, which
+    /// is retried after transient errors. If the reading recently turned out
+    /// not to be supported by the device, or the device is skipped after
+    /// repeated failures, `f` is not run at all.
+    pub fn query<T, F: Fn() -> Result<T>>(
+        &self,
+        device: &Device,
+        call: &'static str,
+        f: F,
+    ) -> Result<T> {
+        let uuid = &device.info.uuid;
+        if self.health.is_open(uuid) {
+            return Err(CollectingError::Unhealthy);
+        }
+        let key = (uuid.clone(), call);
+        if self.unsupported.is_unsupported(&key) {
+            return Err(CollectingError::NotSupported);
+        }
+
+        let span = tracing::info_span!("nvml", call, device = device.info.index);
+        let result = span.in_scope(|| self.retried(call, f));
+        let supported = match &result {
+            Err(e) => !e.is_not_supported(),
+            Ok(_) => true,
+        };
+        self.unsupported.record(key, supported);
+        match &result {
+            Err(e) if e.is_transient() => self.health.record(uuid, true),
+            Err(_) => {}
+            Ok(_) => self.health.record(uuid, false),
+        }
+        result
+    }
+
+    /// Runs `f` until it does not fail with a transient error, or the
+    /// retries are used up.
+    fn retried<T, F: Fn() -> Result<T>>(&self, call: &str, f: F) -> Result<T> {
+        let mut backoff = self.health.retry_backoff;
+        for _ in 0..self.health.retries {
+            match self.observed(call, &f) {
+                Err(e) if e.is_transient() => {
+                    thread::sleep(backoff);
+                    backoff *= 2;
+                }
+                result => return result,
+            }
+        }
+        self.observed(call, f)
+    }
+
+    /// Runs `f`, recording how long it took under the given NVML call category.
+    pub fn timed<T, F: FnOnce() -> T>(&self, call: &str, f: F) -> T {
+        tracing::info_span!("nvml", call).in_scope(|| self.observed(call, f))
+    }
+
+    fn observed<T, F: FnOnce() -> T>(&self, call: &str, f: F) -> T {
+        let timer = self
+            .call_duration_histogram
+            .with_label_values(&[call])
+            .start_timer();
+        let result = f();
+        timer.observe_duration();
+        result
+    }
+}
+
+impl<'a, B: GpuBackend + ?Sized> Context<'a, B> {
+    /// Value of `field` of `device`. All fields of a device are read in one
+    /// backend call on first use and shared by the collectors of this
+    /// collection.
+    pub fn field(&self, device: &Device, field: Field) -> Result<u64> {
+        let index = device.info.index;
+        let cached = self
+            .fields
+            .lock()
+            .expect("Field values poisoned")
+            .contains_key(&index);
+        // Not locked while querying, so that devices can be read in parallel
+        if !cached {
+            let values = self.query(device, "field_values", || {
+                self.backend.field_values(index, &Field::ALL)
+            })?;
+            self.fields
+                .lock()
+                .expect("Field values poisoned")
+                .insert(index, values.into_iter().map(Result::ok).collect());
+        }
+        let fields = self.fields.lock().expect("Field values poisoned");
+
+        let position = Field::ALL
+            .iter()
+            .position(|&f| f == field)
+            .expect("Field missing from Field::ALL");
+        fields[&index][position].ok_or(CollectingError::NotSupported)
+    }
+
+    /// Utilization of `device` by each process. As reading it restarts the
+    /// averaging of the backend, it is read once on first use and shared by
+    /// the collectors of this collection.
+    pub fn process_utilization(&self, device: &Device) -> Result<Vec<ProcessUtilization>> {
+        let index = device.info.index;
+        let cached = self
+            .process_utilization
+            .lock()
+            .expect("Process utilization poisoned")
+            .get(&index)
+            .cloned();
+        if let Some(processes) = cached {
+            return Ok(processes);
+        }
+
+        let processes = self.query(device, "process_utilization", || {
+            self.backend.process_utilization(index)
+        })?;
+        self.process_utilization
+            .lock()
+            .expect("Process utilization poisoned")
+            .insert(index, processes.clone());
+        Ok(processes)
+    }
+}
+
+/// A group of metrics that is collected together.
+pub trait Collector<B: ?Sized>: Send + Sync {
+    /// Name used in the configuration and in the exporter's own metrics.
+    fn name(&self) -> &'static str;
+
+    /// Descriptors of all metrics this collector can produce, with the given
+    /// identity labels.
+    fn describe(&self, labels: &[&str]) -> Result<Vec<Desc>>;
+
+    /// Collects the metrics of all given devices.
+    fn collect(&self, ctx: &Context<B>, devices: &[Device]) -> Result<Vec<MetricFamily>>;
+
+    /// Descriptors of the gauges holding one reading per device, which
+    /// devices not reporting the reading are exported for as configured, see
+    /// [`fill_unsupported`].
+    fn readings(&self, _labels: &[&str]) -> Result<Vec<Desc>> {
+        Ok(Vec::new())
+    }
+}
+
+/// Names of all available collectors.
+pub const NAMES: [&str; 16] = [
+    "utilization",
+    "memory",
+    "power",
+    "clocks",
+    "temperature",
+    "fan",
+    "operation_mode",
+    "persistence",
+    "info",
+    "pcie",
+    "grid",
+    "processes",
+    "kubernetes",
+    "gpm",
+    "features",
+    "xid",
+];
+
+/// All available collectors, in the order of [`NAMES`], exporting in the
+/// configured units.
+pub fn all<B: GpuBackend + ?Sized>(config: &Config) -> Vec<Box<dyn Collector<B>>> {
+    vec![
+        Box::new(utilization::UtilizationCollector),
+        Box::new(memory::MemoryCollector {
+            mig: config.labels.mig,
+        }),
+        Box::new(power::PowerCollector::new(config.units.power)),
+        Box::new(clocks::ClocksCollector {
+            unit: config.units.clocks,
+        }),
+        Box::new(temperature::TemperatureCollector),
+        Box::new(fan::FanCollector),
+        Box::new(operation_mode::OperationModeCollector),
+        Box::new(persistence::PersistenceCollector),
+        Box::new(info::InfoCollector),
+        Box::new(pcie::PcieCollector),
+        Box::new(grid::GridCollector),
+        Box::new(processes::ProcessesCollector),
+        Box::new(kubernetes::KubernetesCollector),
+        Box::new(gpm::GpmCollector),
+        Box::new(features::FeaturesCollector),
+        Box::new(xid::XidCollector),
+    ]
+}
+
+/// Metric vectors of a collector, created from scratch for every collection
+/// so that series of devices or processes that went away are dropped.
+pub(crate) trait MetricSet {
+    fn collectors(&self) -> Vec<&dyn prometheus::core::Collector>;
+
+    fn descs(&self) -> Vec<Desc> {
+        self.collectors()
+            .iter()
+            .flat_map(|c| c.desc())
+            .cloned()
+            .collect()
+    }
+
+    fn families(&self) -> Vec<MetricFamily> {
+        self.collectors().iter().flat_map(|c| c.collect()).collect()
+    }
+
+    /// Gauges holding one reading per device, see [`Collector::readings`].
+    fn readings(&self) -> Vec<&dyn prometheus::core::Collector> {
+        Vec::new()
+    }
+
+    fn reading_descs(&self) -> Vec<Desc> {
+        self.readings()
+            .iter()
+            .flat_map(|c| c.desc())
+            .cloned()
+            .collect()
+    }
+}
+
+/// Companion of the reading `desc`, telling whether a device reported it.
+fn supported_gauge(desc: &Desc, labels: &[&str]) -> Result<IntGaugeVec> {
+    let supported_opts = Opts::new(
+        format!("{}_supported", desc.fq_name),
+        format!(
+            "Whether the GPU device reports {} (1) or not (0)",
+            desc.fq_name
+        ),
+    );
+    Ok(IntGaugeVec::new(supported_opts, labels)?)
+}
+
+/// Descriptors of the metrics [`fill_unsupported`] adds for `readings`.
+pub(crate) fn unsupported_descs(
+    readings: &[Desc],
+    labels: &[&str],
+    handling: UnsupportedReadings,
+) -> Result<Vec<Desc>> {
+    if handling != UnsupportedReadings::SupportedGauge {
+        return Ok(Vec::new());
+    }
+
+    let mut descs = Vec::new();
+    for desc in readings {
+        descs.extend(supported_gauge(desc, labels)?.desc().into_iter().cloned());
+    }
+    Ok(descs)
+}
+
+/// Exports the `readings` of a collector for the devices without a series in
+/// `families` as `handling` asks for. Labels of a reading besides the
+/// identity labels are empty in its NaN series.
+pub(crate) fn fill_unsupported(
+    families: &mut Vec<MetricFamily>,
+    readings: &[Desc],
+    labels: &[&str],
+    devices: &[Device],
+    handling: UnsupportedReadings,
+) -> Result<()> {
+    if handling == UnsupportedReadings::Omit {
+        return Ok(());
+    }
+
+    for desc in readings {
+        // Identity label values of the devices reporting the reading
+        let reported: HashSet<Vec<String>> = families
+            .iter()
+            .filter(|family| family.get_name() == desc.fq_name)
+            .flat_map(|family| family.get_metric())
+            .map(|metric| {
+                labels
+                    .iter()
+                    .map(|name| {
+                        metric
+                            .get_label()
+                            .iter()
+                            .find(|label| label.get_name() == *name)
+                            .map_or_else(String::new, |label| label.get_value().to_string())
+                    })
+                    .collect()
+            })
+            .collect();
+        let is_reported = |device: &Device| {
+            let values: Vec<String> = device.labels().iter().map(|v| v.to_string()).collect();
+            reported.contains(&values)
+        };
+
+        match handling {
+            UnsupportedReadings::Omit => {}
+            UnsupportedReadings::Nan => {
+                let variable_labels: Vec<&str> =
+                    desc.variable_labels.iter().map(String::as_str).collect();
+                let nan_gauge = GaugeVec::new(
+                    Opts::new(desc.fq_name.clone(), desc.help.clone()),
+                    &variable_labels,
+                )?;
+                let extra = variable_labels.len().saturating_sub(labels.len());
+                for device in devices.iter().filter(|device| !is_reported(device)) {
+                    let mut values = device.labels();
+                    values.extend(iter::repeat("").take(extra));
+                    nan_gauge
+                        .get_metric_with_label_values(&values)?
+                        .set(f64::NAN);
+                }
+
+                for nan_family in nan_gauge.collect() {
+                    if nan_family.get_metric().is_empty() {
+                        continue;
+                    }
+                    match families
+                        .iter_mut()
+                        .find(|family| family.get_name() == desc.fq_name)
+                    {
+                        Some(family) => {
+                            for metric in nan_family.get_metric() {
+                                family.mut_metric().push(metric.clone());
+                            }
+                        }
+                        None => families.push(nan_family),
+                    }
+                }
+            }
+            UnsupportedReadings::SupportedGauge => {
+                let supported_gauge = supported_gauge(desc, labels)?;
+                for device in devices {
+                    supported_gauge
+                        .get_metric_with_label_values(&device.labels())?
+                        .set(is_reported(device) as i64);
+                }
+                families.extend(supported_gauge.collect());
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Metrics renamed since their first release, by their new name without the
+/// namespace, with the name they are also exported under while deprecated.
+const RENAMED: [(&str, &str); 1] = [("fan_speed_percent", "fanspeed_percent")];
+
+/// Deprecated name of the metric `name`, if it was renamed. The companions
+/// of [`fill_unsupported`] are renamed along with their readings.
+fn deprecated_name(name: &str) -> Option<String> {
+    let name = name.strip_prefix(NAMESPACE)?.strip_prefix('_')?;
+    RENAMED.iter().find_map(|(new, old)| {
+        let suffix = name.strip_prefix(new)?;
+        if suffix.is_empty() || suffix == "_supported" {
+            Some(format!("{}_{}{}", NAMESPACE, old, suffix))
+        } else {
+            None
+        }
+    })
+}
+
+/// Help text of the deprecated alias of the metric `name`.
+fn deprecated_help(name: &str, help: &str) -> String {
+    format!("{} (deprecated, renamed to {})", help, name)
+}
+
+/// Descriptors of the deprecated aliases [`add_deprecated`] adds for the
+/// metrics of `descs`.
+pub(crate) fn deprecated_descs(descs: &[Desc]) -> Result<Vec<Desc>> {
+    let mut aliases = Vec::new();
+    for desc in descs {
+        if let Some(old) = deprecated_name(&desc.fq_name) {
+            aliases.push(Desc::new(
+                old,
+                deprecated_help(&desc.fq_name, &desc.help),
+                desc.variable_labels.clone(),
+                HashMap::new(),
+            )?);
+        }
+    }
+    Ok(aliases)
+}
+
+/// Exports the renamed metrics of `families` under their deprecated names as
+/// well.
+pub(crate) fn add_deprecated(families: &mut Vec<MetricFamily>) {
+    let aliases: Vec<MetricFamily> = families
+        .iter()
+        .filter_map(|family| {
+            let old = deprecated_name(family.get_name())?;
+            let mut alias = family.clone();
+            alias.set_help(deprecated_help(family.get_name(), family.get_help()));
+            alias.set_name(old);
+            Some(alias)
+        })
+        .collect();
+    families.extend(aliases);
+}
+
+/// Merges the families of the same name in `families`, e.g. collected for
+/// different devices, keeping the order in which they first appear.
+pub(crate) fn merge(families: Vec<MetricFamily>) -> Vec<MetricFamily> {
+    let mut merged: Vec<MetricFamily> = Vec::new();
+    for mut family in families {
+        match merged
+            .iter_mut()
+            .find(|m| m.get_name() == family.get_name())
+        {
+            Some(existing) => {
+                for metric in family.take_metric().into_iter() {
+                    existing.mut_metric().push(metric);
+                }
+            }
+            None => merged.push(family),
+        }
+    }
+    merged
+}
@@ -0,0 +1,80 @@
+use prometheus::core::Desc;
+use prometheus::proto::MetricFamily;
+use prometheus::{IntGaugeVec, Opts};
+
+use crate::backend::{GpuBackend, OperationMode};
+use crate::collectors::{Collector, Context, Device, MetricSet};
+use crate::error::Result;
+use crate::NAMESPACE;
+
+/// GPU operation mode of Tesla and Quadro devices.
+pub struct OperationModeCollector;
+
+/// Label values of all operation modes.
+const MODES: [(OperationMode, &str); 3] = [
+    (OperationMode::AllOn, "all_on"),
+    (OperationMode::Compute, "compute"),
+    (OperationMode::LowDoublePrecision, "low_dp"),
+];
+
+struct Metrics {
+    operation_mode_gauge: IntGaugeVec,
+}
+
+impl Metrics {
+    fn new(labels: &[&str]) -> Result<Metrics> {
+        let operation_mode_opts = Opts::new(
+            "operation_mode",
+            "Whether the GPU device runs in the operation mode given by the mode label",
+        )
+        .namespace(NAMESPACE);
+        let mut operation_mode_labels = labels.to_vec();
+        operation_mode_labels.push("mode");
+        let operation_mode_gauge = IntGaugeVec::new(operation_mode_opts, &operation_mode_labels)?;
+
+        Ok(Metrics {
+            operation_mode_gauge,
+        })
+    }
+}
+
+impl MetricSet for Metrics {
+    fn collectors(&self) -> Vec<&dyn prometheus::core::Collector> {
+        vec![&self.operation_mode_gauge]
+    }
+}
+
+impl<B: GpuBackend + ?Sized> Collector<B> for OperationModeCollector {
+    fn name(&self) -> &'static str {
+        "operation_mode"
+    }
+
+    fn describe(&self, labels: &[&str]) -> Result<Vec<Desc>> {
+        Ok(Metrics::new(labels)?.descs())
+    }
+
+    fn collect(&self, ctx: &Context<B>, devices: &[Device]) -> Result<Vec<MetricFamily>> {
+        let metrics = Metrics::new(ctx.labels)?;
+
+        for device in devices {
+            let index = device.info.index;
+
+            // One series per mode, so that a misconfigured mode can be
+            // alerted on without knowing the current one
+            if let Ok(current) = ctx.query(device, "operation_mode", || {
+                ctx.backend.operation_mode(index)
+            }) {
+                for &(mode, name) in &MODES {
+                    let mut labels = device.labels();
+                    labels.push(name);
+                    metrics
+                        .operation_mode_gauge
+                        .get_metric_with_label_values(&labels)?
+                        .set((mode == current) as i64);
+                }
+            }
+        }
+
+        Ok(metrics.families())
+    }
+}
@@ -0,0 +1,91 @@
+use prometheus::core::Desc;
+use prometheus::proto::MetricFamily;
+use prometheus::{IntCounterVec, Opts};
+
+use crate::backend::{Field, GpuBackend};
+use crate::collectors::{Collector, Context, Device, MetricSet};
+use crate::error::Result;
+use crate::NAMESPACE;
+
+/// PCIe replays and errors, which reveal degrading links.
+pub struct PcieCollector;
+
+struct Metrics {
+    pcie_replays_counter: IntCounterVec,
+    pcie_errors_counter: IntCounterVec,
+}
+
+impl Metrics {
+    fn new(labels: &[&str]) -> Result<Metrics> {
+        // PCIe replays
+        let pcie_replays_opts = Opts::new(
+            "pcie_replays_total",
+            "Number of PCIe replays of the GPU device since the driver was loaded",
+        )
+        .namespace(NAMESPACE);
+        let pcie_replays_counter = IntCounterVec::new(pcie_replays_opts, labels)?;
+
+        // PCIe errors
+        let pcie_errors_opts = Opts::new(
+            "pcie_errors_total",
+            "Number of PCIe errors of the GPU device since the driver was loaded, partitioned by severity",
+        )
+        .namespace(NAMESPACE);
+        let mut pcie_errors_labels = labels.to_vec();
+        pcie_errors_labels.push("severity");
+        let pcie_errors_counter = IntCounterVec::new(pcie_errors_opts, &pcie_errors_labels)?;
+
+        Ok(Metrics {
+            pcie_replays_counter,
+            pcie_errors_counter,
+        })
+    }
+}
+
+impl MetricSet for Metrics {
+    fn collectors(&self) -> Vec<&dyn prometheus::core::Collector> {
+        vec![&self.pcie_replays_counter, &self.pcie_errors_counter]
+    }
+}
+
+impl<B: GpuBackend + ?Sized> Collector<B> for PcieCollector {
+    fn name(&self) -> &'static str {
+        "pcie"
+    }
+
+    fn describe(&self, labels: &[&str]) -> Result<Vec<Desc>> {
+        Ok(Metrics::new(labels)?.descs())
+    }
+
+    fn collect(&self, ctx: &Context<B>, devices: &[Device]) -> Result<Vec<MetricFamily>> {
+        let metrics = Metrics::new(ctx.labels)?;
+
+        for device in devices {
+            // PCIe replays
+            if let Ok(replays) = ctx.field(device, Field::PcieReplays) {
+                metrics
+                    .pcie_replays_counter
+                    .get_metric_with_label_values(&device.labels())?
+                    .inc_by(replays);
+            }
+
+            // PCIe errors
+            for &(severity, field) in &[
+                ("correctable", Field::PcieCorrectableErrors),
+                ("non_fatal", Field::PcieNonFatalErrors),
+                ("fatal", Field::PcieFatalErrors),
+            ] {
+                if let Ok(count) = ctx.field(device, field) {
+                    let mut labels = device.labels();
+                    labels.push(severity);
+                    metrics
+                        .pcie_errors_counter
+                        .get_metric_with_label_values(&labels)?
+                        .inc_by(count);
+                }
+            }
+        }
+
+        Ok(metrics.families())
+    }
+}
@@ -0,0 +1,74 @@
+use prometheus::core::Desc;
+use prometheus::proto::MetricFamily;
+use prometheus::{IntGaugeVec, Opts};
+
+use crate::backend::GpuBackend;
+use crate::collectors::{Collector, Context, Device, MetricSet};
+use crate::error::Result;
+use crate::NAMESPACE;
+
+/// Persistence mode.
+pub struct PersistenceCollector;
+
+struct Metrics {
+    persistence_mode_gauge: IntGaugeVec,
+}
+
+impl Metrics {
+    fn new(labels: &[&str]) -> Result<Metrics> {
+        let persistence_mode_opts = Opts::new(
+            "persistence_mode",
+            "Whether persistence mode is enabled on the GPU device",
+        )
+        .namespace(NAMESPACE);
+        let persistence_mode_gauge = IntGaugeVec::new(persistence_mode_opts, labels)?;
+
+        Ok(Metrics {
+            persistence_mode_gauge,
+        })
+    }
+}
+
+impl MetricSet for Metrics {
+    fn collectors(&self) -> Vec<&dyn prometheus::core::Collector> {
+        vec![&self.persistence_mode_gauge]
+    }
+
+    fn readings(&self) -> Vec<&dyn prometheus::core::Collector> {
+        vec![&self.persistence_mode_gauge]
+    }
+}
+
+impl<B: GpuBackend + ?Sized> Collector<B> for PersistenceCollector {
+    fn name(&self) -> &'static str {
+        "persistence"
+    }
+
+    fn describe(&self, labels: &[&str]) -> Result<Vec<Desc>> {
+        Ok(Metrics::new(labels)?.descs())
+    }
+
+    fn readings(&self, labels: &[&str]) -> Result<Vec<Desc>> {
+        Ok(Metrics::new(labels)?.reading_descs())
+    }
+
+    fn collect(&self, ctx: &Context<B>, devices: &[Device]) -> Result<Vec<MetricFamily>> {
+        let metrics = Metrics::new(ctx.labels)?;
+
+        for device in devices {
+            let labels = device.labels();
+            let index = device.info.index;
+
+            if let Ok(enabled) = ctx.query(device, "persistence_mode", || {
+                ctx.backend.persistence_mode(index)
+            }) {
+                metrics
+                    .persistence_mode_gauge
+                    .get_metric_with_label_values(&labels)?
+                    .set(enabled as i64);
+            }
+        }
+
+        Ok(metrics.families())
+    }
+}
@@ -0,0 +1,263 @@
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::Instant;
+
+use prometheus::core::Desc;
+use prometheus::proto::MetricFamily;
+use prometheus::{CounterVec, GaugeVec, Opts};
+
+use crate::backend::{Field, GpuBackend};
+use crate::collectors::{Collector, Context, Device, MetricSet};
+use crate::config::PowerUnit;
+use crate::error::Result;
+use crate::samples::Reading;
+use crate::NAMESPACE;
+
+/// Power usage and power management limit.
+pub struct PowerCollector {
+    unit: PowerUnit,
+    /// Total energy in millijoules at the previous collection, by device UUID.
+    energy: Mutex<HashMap<String, (u64, Instant)>>,
+}
+
+impl PowerCollector {
+    pub fn new(unit: PowerUnit) -> PowerCollector {
+        PowerCollector {
+            unit,
+            energy: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Mean power usage of `device` in milliwatts since the previous call,
+    /// from its total energy of `millijoules`.
+    fn average(&self, device: &Device, millijoules: u64) -> Option<f64> {
+        let mut energy = self.energy.lock().expect("Energy readings poisoned");
+        let now = Instant::now();
+        let (previous, at) = energy.insert(device.info.uuid.clone(), (millijoules, now))?;
+
+        let elapsed = now.duration_since(at).as_secs_f64();
+        // The counter restarts when the driver is reloaded
+        if millijoules < previous || elapsed <= 0.0 {
+            return None;
+        }
+        Some((millijoules - previous) as f64 / elapsed)
+    }
+}
+
+struct Metrics {
+    unit: PowerUnit,
+    power_usage_gauge: GaugeVec,
+    power_usage_avg_gauge: GaugeVec,
+    power_limit_gauge: GaugeVec,
+    power_usage_min_gauge: GaugeVec,
+    power_usage_max_gauge: GaugeVec,
+    performance_state_seconds_counter: CounterVec,
+}
+
+impl Metrics {
+    fn new(labels: &[&str], unit: PowerUnit) -> Result<Metrics> {
+        let (suffix, name) = match unit {
+            PowerUnit::Milliwatts => ("milliwatts", "milliwatts"),
+            PowerUnit::Watts => ("watts", "watts"),
+        };
+
+        // Power usage
+        let power_usage_opts = Opts::new(
+            format!("power_usage_{}", suffix),
+            format!(
+                "Power usage of the GPU device in {}, by scope (gpu, module or memory) on boards reporting the power of their parts separately",
+                name
+            ),
+        )
+        .namespace(NAMESPACE);
+        let mut power_usage_labels = labels.to_vec();
+        power_usage_labels.push("scope");
+        let power_usage_gauge = GaugeVec::new(power_usage_opts, &power_usage_labels)?;
+
+        // Average power usage
+        let power_usage_avg_opts = Opts::new(
+            format!("power_usage_avg_{}", suffix),
+            format!(
+                "Mean power usage of the GPU device in {} since the previous collection, from its energy consumption",
+                name
+            ),
+        )
+        .namespace(NAMESPACE);
+        let power_usage_avg_gauge = GaugeVec::new(power_usage_avg_opts, labels)?;
+
+        // Power limit
+        let power_limit_opts = Opts::new(
+            format!("power_limit_{}", suffix),
+            format!("Power limit of the GPU device in {}", name),
+        )
+        .namespace(NAMESPACE);
+        let power_limit_gauge = GaugeVec::new(power_limit_opts, labels)?;
+
+        // Power usage extremes
+        let power_usage_min_opts = Opts::new(
+            format!("power_usage_{}_min", suffix),
+            format!(
+                "Minimum power usage of the GPU device in {} sampled since the previous scrape",
+                name
+            ),
+        )
+        .namespace(NAMESPACE);
+        let power_usage_min_gauge = GaugeVec::new(power_usage_min_opts, labels)?;
+
+        let power_usage_max_opts = Opts::new(
+            format!("power_usage_{}_max", suffix),
+            format!(
+                "Maximum power usage of the GPU device in {} sampled since the previous scrape",
+                name
+            ),
+        )
+        .namespace(NAMESPACE);
+        let power_usage_max_gauge = GaugeVec::new(power_usage_max_opts, labels)?;
+
+        // Performance state residency
+        let performance_state_seconds_opts = Opts::new(
+            "performance_state_seconds_total",
+            "Time the GPU device spent in the performance state, given by the pstate label, in seconds, as sampled while the exporter was running",
+        )
+        .namespace(NAMESPACE);
+        let mut performance_state_labels = labels.to_vec();
+        performance_state_labels.push("pstate");
+        let performance_state_seconds_counter =
+            CounterVec::new(performance_state_seconds_opts, &performance_state_labels)?;
+
+        Ok(Metrics {
+            unit,
+            power_usage_gauge,
+            power_usage_avg_gauge,
+            power_limit_gauge,
+            power_usage_min_gauge,
+            power_usage_max_gauge,
+            performance_state_seconds_counter,
+        })
+    }
+
+    /// Converts a reading in milliwatts to the configured unit.
+    fn value(&self, milliwatts: u32) -> f64 {
+        self.value_f64(f64::from(milliwatts))
+    }
+
+    fn value_f64(&self, milliwatts: f64) -> f64 {
+        match self.unit {
+            PowerUnit::Milliwatts => milliwatts,
+            PowerUnit::Watts => milliwatts / 1000.0,
+        }
+    }
+}
+
+impl MetricSet for Metrics {
+    fn collectors(&self) -> Vec<&dyn prometheus::core::Collector> {
+        vec![
+            &self.power_usage_gauge,
+            &self.power_usage_avg_gauge,
+            &self.power_limit_gauge,
+            &self.power_usage_min_gauge,
+            &self.power_usage_max_gauge,
+            &self.performance_state_seconds_counter,
+        ]
+    }
+
+    fn readings(&self) -> Vec<&dyn prometheus::core::Collector> {
+        vec![&self.power_usage_gauge, &self.power_limit_gauge]
+    }
+}
+
+impl<B: GpuBackend + ?Sized> Collector<B> for PowerCollector {
+    fn name(&self) -> &'static str {
+        "power"
+    }
+
+    fn describe(&self, labels: &[&str]) -> Result<Vec<Desc>> {
+        Ok(Metrics::new(labels, self.unit)?.descs())
+    }
+
+    fn readings(&self, labels: &[&str]) -> Result<Vec<Desc>> {
+        Ok(Metrics::new(labels, self.unit)?.reading_descs())
+    }
+
+    fn collect(&self, ctx: &Context<B>, devices: &[Device]) -> Result<Vec<MetricFamily>> {
+        let metrics = Metrics::new(ctx.labels, self.unit)?;
+
+        for device in devices {
+            let labels = device.labels();
+
+            // Power usage, by scope if the module or memory power is known.
+            // The scope is empty otherwise, which Prometheus treats like a
+            // missing label, so series of other boards stay the same.
+            let scopes = [
+                ("module", ctx.field(device, Field::ModulePowerUsage).ok()),
+                ("memory", ctx.field(device, Field::MemoryPowerUsage).ok()),
+            ];
+            let scoped = scopes.iter().any(|(_, power_usage)| power_usage.is_some());
+            if let Ok(power_usage) = ctx.field(device, Field::PowerUsage) {
+                let mut labels = labels.clone();
+                labels.push(if scoped { "gpu" } else { "" });
+                metrics
+                    .power_usage_gauge
+                    .get_metric_with_label_values(&labels)?
+                    .set(metrics.value(power_usage as u32));
+            }
+            for (scope, power_usage) in &scopes {
+                if let Some(power_usage) = power_usage {
+                    let mut labels = labels.clone();
+                    labels.push(scope);
+                    metrics
+                        .power_usage_gauge
+                        .get_metric_with_label_values(&labels)?
+                        .set(metrics.value(*power_usage as u32));
+                }
+            }
+
+            // Average power usage, from the second collection on
+            if let Ok(energy) = ctx.field(device, Field::TotalEnergy) {
+                if let Some(average) = self.average(device, energy) {
+                    metrics
+                        .power_usage_avg_gauge
+                        .get_metric_with_label_values(&labels)?
+                        .set(metrics.value_f64(average));
+                }
+            }
+
+            // Power limit
+            if let Ok(power_limit) = ctx.field(device, Field::PowerLimit) {
+                metrics
+                    .power_limit_gauge
+                    .get_metric_with_label_values(&labels)?
+                    .set(metrics.value(power_limit as u32));
+            }
+
+            // Power usage extremes, only available while the sampler is running
+            if let Some((min, max)) = ctx.extremes(&device.info.uuid, Reading::PowerUsage) {
+                metrics
+                    .power_usage_min_gauge
+                    .get_metric_with_label_values(&labels)?
+                    .set(metrics.value(min));
+                metrics
+                    .power_usage_max_gauge
+                    .get_metric_with_label_values(&labels)?
+                    .set(metrics.value(max));
+            }
+
+            // Performance state residency, only available while the sampler
+            // is running
+            if let Some(states) = ctx.samples.performance_states(&device.info.uuid) {
+                for (state, duration) in states {
+                    let pstate = format!("P{}", state);
+                    let mut labels = labels.clone();
+                    labels.push(pstate.as_str());
+
+                    metrics
+                        .performance_state_seconds_counter
+                        .get_metric_with_label_values(&labels)?
+                        .inc_by(duration.as_secs_f64());
+                }
+            }
+        }
+
+        Ok(metrics.families())
+    }
+}
@@ -0,0 +1,239 @@
+use std::collections::{BTreeMap, HashSet};
+use std::ffi::OsStr;
+use std::path::Path;
+
+use prometheus::core::Desc;
+use prometheus::proto::MetricFamily;
+use prometheus::{GaugeVec, HistogramOpts, HistogramVec, IntGaugeVec, Opts};
+
+use crate::backend::{GpuBackend, ProcessInfo, ProcessType};
+use crate::collectors::{Collector, Context, Device, MetricSet};
+use crate::error::Result;
+use crate::procinfo::ProcessDetails;
+use crate::NAMESPACE;
+
+/// GPU memory used by each running process, and summed up per cgroup.
+///
+/// Under MPS, the memory of the MPS server is attributed to its clients as
+/// far as the driver lists them, and only the remainder is left to the server.
+pub struct ProcessesCollector;
+
+const MIB: f64 = 1024.0 * 1024.0;
+const GIB: f64 = 1024.0 * MIB;
+
+/// Buckets of the process memory histogram, sized after common job and GPU
+/// memory sizes.
+const PROCESS_MEMORY_BUCKETS: [f64; 7] = [
+    256.0 * MIB,
+    GIB,
+    4.0 * GIB,
+    8.0 * GIB,
+    16.0 * GIB,
+    40.0 * GIB,
+    80.0 * GIB,
+];
+
+/// Command of the MPS server, which holds the contexts of all MPS clients.
+const MPS_SERVER: &str = "nvidia-cuda-mps-server";
+
+/// Value of the `type` label of `process`.
+fn process_type(process: &ProcessInfo, details: &ProcessDetails) -> &'static str {
+    if Path::new(&details.command).file_name() == Some(OsStr::new(MPS_SERVER)) {
+        return "mps";
+    }
+
+    match process.process_type {
+        ProcessType::Compute => "compute",
+        ProcessType::Graphics => "graphics",
+    }
+}
+
+struct Metrics {
+    mps_enabled_gauge: IntGaugeVec,
+    process_memory_used_gauge: IntGaugeVec,
+    process_memory_used_ratio_gauge: GaugeVec,
+    process_memory_max_gauge: IntGaugeVec,
+    process_memory_histogram: HistogramVec,
+    cgroup_memory_used_gauge: IntGaugeVec,
+}
+
+impl Metrics {
+    fn new(labels: &[&str]) -> Result<Metrics> {
+        let mps_enabled_opts = Opts::new(
+            "mps_enabled",
+            "Whether an MPS server is running on the GPU device (1 if it is)",
+        )
+        .namespace(NAMESPACE);
+        let mps_enabled_gauge = IntGaugeVec::new(mps_enabled_opts, labels)?;
+
+        let process_memory_used_opts = Opts::new(
+            "process_memory_used_bytes",
+            "Memory used by the process in bytes",
+        )
+        .namespace(NAMESPACE);
+        let mut process_labels = labels.to_vec();
+        process_labels.extend(&["pid", "user", "command", "type"]);
+        let process_memory_used_gauge =
+            IntGaugeVec::new(process_memory_used_opts, &process_labels)?;
+
+        let process_memory_used_ratio_opts = Opts::new(
+            "process_memory_used_ratio",
+            "Memory used by the process as a fraction of the total memory of the GPU device",
+        )
+        .namespace(NAMESPACE);
+        let process_memory_used_ratio_gauge =
+            GaugeVec::new(process_memory_used_ratio_opts, &process_labels)?;
+
+        let process_memory_max_opts = Opts::new(
+            "process_memory_max_bytes",
+            "Highest memory used by the process in bytes since it appeared",
+        )
+        .namespace(NAMESPACE);
+        let process_memory_max_gauge = IntGaugeVec::new(process_memory_max_opts, &process_labels)?;
+
+        let process_memory_opts = HistogramOpts::new(
+            "process_memory_bytes",
+            "Memory used by the processes running on the GPU device in bytes",
+        )
+        .namespace(NAMESPACE)
+        .buckets(PROCESS_MEMORY_BUCKETS.to_vec());
+        let process_memory_histogram = HistogramVec::new(process_memory_opts, labels)?;
+
+        let cgroup_memory_used_opts = Opts::new(
+            "cgroup_memory_used_bytes",
+            "Memory used by the processes of the cgroup in bytes",
+        )
+        .namespace(NAMESPACE);
+        let mut cgroup_labels = labels.to_vec();
+        cgroup_labels.push("cgroup");
+        let cgroup_memory_used_gauge = IntGaugeVec::new(cgroup_memory_used_opts, &cgroup_labels)?;
+
+        Ok(Metrics {
+            mps_enabled_gauge,
+            process_memory_used_gauge,
+            process_memory_used_ratio_gauge,
+            process_memory_max_gauge,
+            process_memory_histogram,
+            cgroup_memory_used_gauge,
+        })
+    }
+}
+
+impl MetricSet for Metrics {
+    fn collectors(&self) -> Vec<&dyn prometheus::core::Collector> {
+        vec![
+            &self.mps_enabled_gauge,
+            &self.process_memory_used_gauge,
+            &self.process_memory_used_ratio_gauge,
+            &self.process_memory_max_gauge,
+            &self.process_memory_histogram,
+            &self.cgroup_memory_used_gauge,
+        ]
+    }
+}
+
+impl<B: GpuBackend + ?Sized> Collector<B> for ProcessesCollector {
+    fn name(&self) -> &'static str {
+        "processes"
+    }
+
+    fn describe(&self, labels: &[&str]) -> Result<Vec<Desc>> {
+        Ok(Metrics::new(labels)?.descs())
+    }
+
+    fn collect(&self, ctx: &Context<B>, devices: &[Device]) -> Result<Vec<MetricFamily>> {
+        let metrics = Metrics::new(ctx.labels)?;
+
+        for device in devices {
+            let device_labels = device.labels();
+            let index = device.info.index;
+
+            let mut processes = ctx.query(device, "processes", || ctx.backend.processes(index))?;
+            let clients = ctx
+                .query(device, "mps_processes", || ctx.backend.mps_processes(index))
+                .unwrap_or_default();
+            let mut mps_enabled = !clients.is_empty();
+            let attributed: u64 = clients.iter().filter_map(|c| c.used_memory).sum();
+            let listed: HashSet<u32> = processes.iter().map(|p| p.pid).collect();
+            processes.extend(clients.into_iter().filter(|c| !listed.contains(&c.pid)));
+            let total_memory = ctx
+                .query(device, "memory", || ctx.backend.memory_info(index))
+                .ok()
+                .map(|memory_info| memory_info.total)
+                .filter(|&total| total > 0);
+
+            // Also exported for idle devices, with a count of 0
+            let process_memory = metrics
+                .process_memory_histogram
+                .get_metric_with_label_values(&device_labels)?;
+            let mut cgroups = BTreeMap::new();
+            let pids: Vec<u32> = processes.iter().map(|p| p.pid).collect();
+            for process in processes {
+                let mut used_memory = match process.used_memory {
+                    Some(used_memory) => used_memory,
+                    None => continue,
+                };
+
+                // Processes in other PID namespaces cannot be resolved
+                let details = ctx.process_details(process.pid).unwrap_or_default();
+                let process_type = process_type(&process, &details);
+                if process_type == "mps" {
+                    mps_enabled = true;
+                    // What is left after the clients is the server's own
+                    used_memory = used_memory.saturating_sub(attributed);
+                }
+
+                let pid = process.pid.to_string();
+                let mut labels = device_labels.clone();
+                labels.extend(&[
+                    pid.as_str(),
+                    details.user.as_str(),
+                    details.command.as_str(),
+                    process_type,
+                ]);
+
+                metrics
+                    .process_memory_used_gauge
+                    .get_metric_with_label_values(&labels)?
+                    .set(used_memory as i64);
+                if let Some(total_memory) = total_memory {
+                    metrics
+                        .process_memory_used_ratio_gauge
+                        .get_metric_with_label_values(&labels)?
+                        .set(used_memory as f64 / total_memory as f64);
+                }
+                // Includes the peaks the sampler saw between scrapes
+                let max_memory =
+                    ctx.samples
+                        .record_process_memory(&device.info.uuid, process.pid, used_memory);
+                metrics
+                    .process_memory_max_gauge
+                    .get_metric_with_label_values(&labels)?
+                    .set(max_memory as i64);
+                process_memory.observe(used_memory as f64);
+
+                let cgroup = ctx.cgroup(process.pid).unwrap_or_default();
+                *cgroups.entry(cgroup).or_insert(0) += used_memory;
+            }
+
+            ctx.samples.retain_processes(&device.info.uuid, &pids);
+
+            metrics
+                .mps_enabled_gauge
+                .get_metric_with_label_values(&device_labels)?
+                .set(mps_enabled as i64);
+
+            for (cgroup, used_memory) in &cgroups {
+                let mut labels = device_labels.clone();
+                labels.push(cgroup.as_str());
+
+                metrics
+                    .cgroup_memory_used_gauge
+                    .get_metric_with_label_values(&labels)?
+                    .set(*used_memory as i64);
+            }
+        }
+
+        Ok(metrics.families())
+    }
+}
@@ -0,0 +1,128 @@
+use prometheus::core::Desc;
+use prometheus::proto::MetricFamily;
+use prometheus::{IntGaugeVec, Opts};
+
+use crate::backend::{Field, GpuBackend};
+use crate::collectors::{Collector, Context, Device, MetricSet};
+use crate::error::Result;
+use crate::samples::Reading;
+use crate::NAMESPACE;
+
+/// GPU temperature, and memory temperature on devices with HBM.
+pub struct TemperatureCollector;
+
+struct Metrics {
+    temperature_gauge: IntGaugeVec,
+    memory_temperature_gauge: IntGaugeVec,
+    temperature_min_gauge: IntGaugeVec,
+    temperature_max_gauge: IntGaugeVec,
+}
+
+impl Metrics {
+    fn new(labels: &[&str]) -> Result<Metrics> {
+        let temperature_opts = Opts::new(
+            "temperature_celsius",
+            "Temperature of the GPU device in celsius",
+        )
+        .namespace(NAMESPACE);
+        let temperature_gauge = IntGaugeVec::new(temperature_opts, labels)?;
+
+        // Memory temperature
+        let memory_temperature_opts = Opts::new(
+            "memory_temperature_celsius",
+            "Junction temperature of the memory of the GPU device in celsius",
+        )
+        .namespace(NAMESPACE);
+        let memory_temperature_gauge = IntGaugeVec::new(memory_temperature_opts, labels)?;
+
+        // Temperature extremes
+        let temperature_min_opts = Opts::new(
+            "temperature_celsius_min",
+            "Minimum temperature of the GPU device in celsius sampled since the previous scrape",
+        )
+        .namespace(NAMESPACE);
+        let temperature_min_gauge = IntGaugeVec::new(temperature_min_opts, labels)?;
+
+        let temperature_max_opts = Opts::new(
+            "temperature_celsius_max",
+            "Maximum temperature of the GPU device in celsius sampled since the previous scrape",
+        )
+        .namespace(NAMESPACE);
+        let temperature_max_gauge = IntGaugeVec::new(temperature_max_opts, labels)?;
+
+        Ok(Metrics {
+            temperature_gauge,
+            memory_temperature_gauge,
+            temperature_min_gauge,
+            temperature_max_gauge,
+        })
+    }
+}
+
+impl MetricSet for Metrics {
+    fn collectors(&self) -> Vec<&dyn prometheus::core::Collector> {
+        vec![
+            &self.temperature_gauge,
+            &self.memory_temperature_gauge,
+            &self.temperature_min_gauge,
+            &self.temperature_max_gauge,
+        ]
+    }
+
+    fn readings(&self) -> Vec<&dyn prometheus::core::Collector> {
+        vec![&self.temperature_gauge, &self.memory_temperature_gauge]
+    }
+}
+
+impl<B: GpuBackend + ?Sized> Collector<B> for TemperatureCollector {
+    fn name(&self) -> &'static str {
+        "temperature"
+    }
+
+    fn describe(&self, labels: &[&str]) -> Result<Vec<Desc>> {
+        Ok(Metrics::new(labels)?.descs())
+    }
+
+    fn readings(&self, labels: &[&str]) -> Result<Vec<Desc>> {
+        Ok(Metrics::new(labels)?.reading_descs())
+    }
+
+    fn collect(&self, ctx: &Context<B>, devices: &[Device]) -> Result<Vec<MetricFamily>> {
+        let metrics = Metrics::new(ctx.labels)?;
+
+        for device in devices {
+            let labels = device.labels();
+            let index = device.info.index;
+
+            if let Ok(temperature) =
+                ctx.query(device, "temperature", || ctx.backend.temperature(index))
+            {
+                metrics
+                    .temperature_gauge
+                    .get_metric_with_label_values(&labels)?
+                    .set(temperature as i64);
+            }
+
+            if let Ok(memory_temperature) = ctx.field(device, Field::MemoryTemperature) {
+                metrics
+                    .memory_temperature_gauge
+                    .get_metric_with_label_values(&labels)?
+                    .set(memory_temperature as i64);
+            }
+
+            // Only available while the sampler is running
+            if let Some((min, max)) = ctx.extremes(&device.info.uuid, Reading::Temperature) {
+                metrics
+                    .temperature_min_gauge
+                    .get_metric_with_label_values(&labels)?
+                    .set(min as i64);
+                metrics
+                    .temperature_max_gauge
+                    .get_metric_with_label_values(&labels)?
+                    .set(max as i64);
+            }
+        }
+
+        Ok(metrics.families())
+    }
+}
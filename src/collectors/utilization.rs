@@ -0,0 +1,189 @@
+use std::time::Duration;
+
+use prometheus::core::Desc;
+use prometheus::proto::MetricFamily;
+use prometheus::{GaugeVec, IntGaugeVec, Opts};
+
+use crate::backend::GpuBackend;
+use crate::collectors::{Collector, Context, Device, MetricSet};
+use crate::error::Result;
+use crate::samples::Reading;
+use crate::NAMESPACE;
+
+/// GPU and memory utilization.
+pub struct UtilizationCollector;
+
+struct Metrics {
+    gpu_utilization_gauge: IntGaugeVec,
+    memory_utilization_gauge: IntGaugeVec,
+    gpu_utilization_avg_1m_gauge: GaugeVec,
+    gpu_utilization_avg_5m_gauge: GaugeVec,
+    gpu_utilization_min_gauge: IntGaugeVec,
+    gpu_utilization_max_gauge: IntGaugeVec,
+    gpu_idle_gauge: GaugeVec,
+}
+
+impl Metrics {
+    fn new(labels: &[&str]) -> Result<Metrics> {
+        // GPU utilization
+        let gpu_utilization_opts = Opts::new(
+            "gpu_utilization",
+            "Percent of time over the past sample period during which one or more kernels were executing on the GPU device",
+        )
+        .namespace(NAMESPACE);
+        let gpu_utilization_gauge = IntGaugeVec::new(gpu_utilization_opts, labels)?;
+
+        // Memory utilization
+        let memory_utilization_opts = Opts::new(
+            "memory_utilization",
+            "Percent of time over the past sample period during which global (device) memory was being read or written to.",
+        )
+        .namespace(NAMESPACE);
+        let memory_utilization_gauge = IntGaugeVec::new(memory_utilization_opts, labels)?;
+
+        // GPU utilization averages
+        let gpu_utilization_avg_1m_opts = Opts::new(
+            "gpu_utilization_avg_1m",
+            "Mean GPU utilization in percent over the past minute, sampled between scrapes",
+        )
+        .namespace(NAMESPACE);
+        let gpu_utilization_avg_1m_gauge = GaugeVec::new(gpu_utilization_avg_1m_opts, labels)?;
+
+        let gpu_utilization_avg_5m_opts = Opts::new(
+            "gpu_utilization_avg_5m",
+            "Mean GPU utilization in percent over the past five minutes, sampled between scrapes",
+        )
+        .namespace(NAMESPACE);
+        let gpu_utilization_avg_5m_gauge = GaugeVec::new(gpu_utilization_avg_5m_opts, labels)?;
+
+        // GPU utilization extremes
+        let gpu_utilization_min_opts = Opts::new(
+            "gpu_utilization_min",
+            "Minimum GPU utilization in percent sampled since the previous scrape",
+        )
+        .namespace(NAMESPACE);
+        let gpu_utilization_min_gauge = IntGaugeVec::new(gpu_utilization_min_opts, labels)?;
+
+        let gpu_utilization_max_opts = Opts::new(
+            "gpu_utilization_max",
+            "Maximum GPU utilization in percent sampled since the previous scrape",
+        )
+        .namespace(NAMESPACE);
+        let gpu_utilization_max_gauge = IntGaugeVec::new(gpu_utilization_max_opts, labels)?;
+
+        // Idle time
+        let gpu_idle_opts = Opts::new(
+            "gpu_idle_seconds",
+            "Time in seconds the device has had no GPU utilization and no compute processes, sampled between scrapes",
+        )
+        .namespace(NAMESPACE);
+        let gpu_idle_gauge = GaugeVec::new(gpu_idle_opts, labels)?;
+
+        Ok(Metrics {
+            gpu_utilization_gauge,
+            memory_utilization_gauge,
+            gpu_utilization_avg_1m_gauge,
+            gpu_utilization_avg_5m_gauge,
+            gpu_utilization_min_gauge,
+            gpu_utilization_max_gauge,
+            gpu_idle_gauge,
+        })
+    }
+}
+
+impl MetricSet for Metrics {
+    fn collectors(&self) -> Vec<&dyn prometheus::core::Collector> {
+        vec![
+            &self.gpu_utilization_gauge,
+            &self.memory_utilization_gauge,
+            &self.gpu_utilization_avg_1m_gauge,
+            &self.gpu_utilization_avg_5m_gauge,
+            &self.gpu_utilization_min_gauge,
+            &self.gpu_utilization_max_gauge,
+            &self.gpu_idle_gauge,
+        ]
+    }
+
+    fn readings(&self) -> Vec<&dyn prometheus::core::Collector> {
+        vec![&self.gpu_utilization_gauge, &self.memory_utilization_gauge]
+    }
+}
+
+impl<B: GpuBackend + ?Sized> Collector<B> for UtilizationCollector {
+    fn name(&self) -> &'static str {
+        "utilization"
+    }
+
+    fn describe(&self, labels: &[&str]) -> Result<Vec<Desc>> {
+        Ok(Metrics::new(labels)?.descs())
+    }
+
+    fn readings(&self, labels: &[&str]) -> Result<Vec<Desc>> {
+        Ok(Metrics::new(labels)?.reading_descs())
+    }
+
+    fn collect(&self, ctx: &Context<B>, devices: &[Device]) -> Result<Vec<MetricFamily>> {
+        let metrics = Metrics::new(ctx.labels)?;
+
+        for device in devices {
+            let labels = device.labels();
+            let index = device.info.index;
+
+            if let Ok(utilization) =
+                ctx.query(device, "utilization", || ctx.backend.utilization(index))
+            {
+                metrics
+                    .gpu_utilization_gauge
+                    .get_metric_with_label_values(&labels)?
+                    .set(utilization.gpu as i64);
+                if let Some(exemplars) = ctx.exemplars {
+                    let top = ctx
+                        .process_utilization(device)
+                        .ok()
+                        .and_then(|processes| processes.into_iter().max_by_key(|p| p.gpu))
+                        .map(|process| (process.pid, f64::from(process.gpu)));
+                    exemplars.set(&format!("{}_gpu_utilization", NAMESPACE), &labels, top);
+                }
+                if let Some(memory) = utilization.memory {
+                    metrics
+                        .memory_utilization_gauge
+                        .get_metric_with_label_values(&labels)?
+                        .set(memory as i64);
+                }
+            }
+
+            // Only available while the sampler is running
+            let uuid = &device.info.uuid;
+            if let Some(average) = ctx.samples.average(uuid, Duration::from_secs(60)) {
+                metrics
+                    .gpu_utilization_avg_1m_gauge
+                    .get_metric_with_label_values(&labels)?
+                    .set(average);
+            }
+            if let Some(average) = ctx.samples.average(uuid, Duration::from_secs(5 * 60)) {
+                metrics
+                    .gpu_utilization_avg_5m_gauge
+                    .get_metric_with_label_values(&labels)?
+                    .set(average);
+            }
+            if let Some((min, max)) = ctx.extremes(uuid, Reading::GpuUtilization) {
+                metrics
+                    .gpu_utilization_min_gauge
+                    .get_metric_with_label_values(&labels)?
+                    .set(min as i64);
+                metrics
+                    .gpu_utilization_max_gauge
+                    .get_metric_with_label_values(&labels)?
+                    .set(max as i64);
+            }
+            if let Some(idle) = ctx.samples.idle(uuid) {
+                metrics
+                    .gpu_idle_gauge
+                    .get_metric_with_label_values(&labels)?
+                    .set(idle.as_secs_f64());
+            }
+        }
+
+        Ok(metrics.families())
+    }
+}
@@ -0,0 +1,161 @@
+use prometheus::core::Desc;
+use prometheus::proto::MetricFamily;
+use prometheus::{GaugeVec, IntCounterVec, Opts};
+
+use crate::backend::GpuBackend;
+use crate::collectors::{Collector, Context, Device, MetricSet};
+use crate::error::Result;
+use crate::NAMESPACE;
+
+/// XIDs after which the driver resets the device, or which require a reset,
+/// by the reason exported for them.
+const RESET_XIDS: [(u64, &str); 6] = [
+    (48, "double_bit_ecc"),
+    (79, "fallen_off_bus"),
+    (95, "uncontained_ecc"),
+    (119, "gsp_timeout"),
+    (120, "gsp_error"),
+    (154, "recovery_action"),
+];
+
+/// Critical XID errors and the resets they imply, as counted by the XID
+/// watcher while the exporter was running.
+pub struct XidCollector;
+
+struct Metrics {
+    xid_errors_counter: IntCounterVec,
+    resets_counter: IntCounterVec,
+    events_received_counter: IntCounterVec,
+    events_dropped_counter: IntCounterVec,
+    watcher_lag_gauge: GaugeVec,
+}
+
+impl Metrics {
+    fn new(labels: &[&str]) -> Result<Metrics> {
+        // XID errors
+        let xid_errors_opts = Opts::new(
+            "xid_errors_total",
+            "Number of critical XID errors of the GPU device reported by the driver while the exporter was running, by XID",
+        )
+        .namespace(NAMESPACE);
+        let mut xid_errors_labels = labels.to_vec();
+        xid_errors_labels.push("xid");
+        let xid_errors_counter = IntCounterVec::new(xid_errors_opts, &xid_errors_labels)?;
+
+        // Resets
+        let resets_opts = Opts::new(
+            "gpu_resets_total",
+            "Number of XID errors while the exporter was running after which the GPU device was reset or required a reset, by reason",
+        )
+        .namespace(NAMESPACE);
+        let mut resets_labels = labels.to_vec();
+        resets_labels.push("reason");
+        let resets_counter = IntCounterVec::new(resets_opts, &resets_labels)?;
+
+        // Health of the watcher, without labels, only exported while it runs
+        let events_received_opts = Opts::new(
+            "xid_events_received_total",
+            "Number of XID events the XID watcher received",
+        )
+        .namespace(NAMESPACE)
+        .subsystem("exporter");
+        let events_received_counter = IntCounterVec::new(events_received_opts, &[])?;
+
+        let events_dropped_opts = Opts::new(
+            "xid_events_dropped_total",
+            "Number of XID events the XID watcher received but could not attribute to a GPU device",
+        )
+        .namespace(NAMESPACE)
+        .subsystem("exporter");
+        let events_dropped_counter = IntCounterVec::new(events_dropped_opts, &[])?;
+
+        let watcher_lag_opts = Opts::new(
+            "xid_watcher_lag_seconds",
+            "Time since the XID watcher last returned from waiting for events in seconds, which grows beyond a second if it is stuck",
+        )
+        .namespace(NAMESPACE)
+        .subsystem("exporter");
+        let watcher_lag_gauge = GaugeVec::new(watcher_lag_opts, &[])?;
+
+        Ok(Metrics {
+            xid_errors_counter,
+            resets_counter,
+            events_received_counter,
+            events_dropped_counter,
+            watcher_lag_gauge,
+        })
+    }
+}
+
+impl MetricSet for Metrics {
+    fn collectors(&self) -> Vec<&dyn prometheus::core::Collector> {
+        vec![
+            &self.xid_errors_counter,
+            &self.resets_counter,
+            &self.events_received_counter,
+            &self.events_dropped_counter,
+            &self.watcher_lag_gauge,
+        ]
+    }
+}
+
+impl<B: GpuBackend + ?Sized> Collector<B> for XidCollector {
+    fn name(&self) -> &'static str {
+        "xid"
+    }
+
+    fn describe(&self, labels: &[&str]) -> Result<Vec<Desc>> {
+        Ok(Metrics::new(labels)?.descs())
+    }
+
+    fn collect(&self, ctx: &Context<B>, devices: &[Device]) -> Result<Vec<MetricFamily>> {
+        let metrics = Metrics::new(ctx.labels)?;
+        if let Some(lag) = ctx.xids.wait_lag() {
+            let (received, dropped) = ctx.xids.events();
+            metrics
+                .events_received_counter
+                .get_metric_with_label_values(&[])?
+                .inc_by(received);
+            metrics
+                .events_dropped_counter
+                .get_metric_with_label_values(&[])?
+                .inc_by(dropped);
+            metrics
+                .watcher_lag_gauge
+                .get_metric_with_label_values(&[])?
+                .set(lag.as_secs_f64());
+        }
+
+        // Zero errors only mean something while the watcher is running
+        if !ctx.xids.is_watching() {
+            return Ok(metrics.families());
+        }
+
+        for device in devices {
+            let errors = ctx.xids.errors(&device.info.uuid);
+
+            for (xid, count) in &errors {
+                let xid = xid.to_string();
+                let mut labels = device.labels();
+                labels.push(&xid);
+                metrics
+                    .xid_errors_counter
+                    .get_metric_with_label_values(&labels)?
+                    .inc_by(*count);
+            }
+
+            // Every reason is exported from the start, so that increase()
+            // sees the first reset
+            for (xid, reason) in &RESET_XIDS {
+                let mut labels = device.labels();
+                labels.push(reason);
+                metrics
+                    .resets_counter
+                    .get_metric_with_label_values(&labels)?
+                    .inc_by(errors.get(xid).copied().unwrap_or(0));
+            }
+        }
+
+        Ok(metrics.families())
+    }
+}
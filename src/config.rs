@@ -0,0 +1,982 @@
+//! Configuration file of the exporter.
+//!
+//! The configuration is read from a TOML file. Every section and key is
+//! optional, so an empty file yields the defaults:
+//!
+//! ```toml
+//! # Export renamed metrics under their new names only
+//! deprecated_metrics = "exclude"
+//! # Read all devices in parallel at every collection, so that readings of
+//! # different devices are taken at about the same time
+//! consistency = "snapshot"
+//!
+//! [collectors.processes]
+//! enabled = true
+//! # Collect at most every 30 seconds, serving the previous result in between
+//! interval = "30s"
+//!
+//! [collectors.fan]
+//! enabled = false
+//!
+//! [collectors.temperature]
+//! # Export devices without a reading as NaN instead of leaving them out
+//! unsupported = "nan"
+//!
+//! [web]
+//! # Allow shutting down the exporter with a POST to /-/quit
+//! enable_lifecycle = true
+//! # Allow changing device settings through /admin/*, authenticated with
+//! # "Authorization: Bearer <admin_token>"
+//! enable_admin_api = true
+//! admin_token = "secret"
+//! # Serve OpenMetrics to scrapers asking for it, with the top process of
+//! # each device as exemplar of its utilization and used memory
+//! openmetrics = true
+//!
+//! # Require one of these bearer tokens for the metrics, and only show each
+//! # user the processes they own
+//! [[web.users]]
+//! name = "alice"
+//! token = "alice-secret"
+//!
+//! [[web.users]]
+//! name = "prometheus"
+//! token = "prometheus-secret"
+//! all_processes = true
+//!
+//! # Serve the metrics of the devices 0-3 only at /metrics/team-a, to
+//! # scrapers with "Authorization: Bearer team-a-secret"
+//! [[web.shards]]
+//! path = "/metrics/team-a"
+//! devices = ["0", "1", "2", "3"]
+//! token = "team-a-secret"
+//!
+//! # Serve the metrics of other exporters at /proxy/metrics, with a node label
+//! [[web.proxy_targets]]
+//! url = "http://lab-1:9898/metrics"
+//! # Defaults to the host of the url
+//! node = "lab-1"
+//!
+//! [labels]
+//! # Identity labels of every device metric, out of index, index_by_busid,
+//! # minor_number, uuid, name, pci_bus_id, serial and hostname
+//! identity = ["index", "uuid", "pci_bus_id"]
+//! # Export UUIDs as e.g. "8c1d2f3e" instead of
+//! # "GPU-8c1d2f3e-6a1b-7c2d-8e3f-4a5b6c7d8e9f"
+//! strip_uuid_prefix = true
+//! short_uuid = true
+//! # Attach node="<hostname>" to every series, e.g. when scraped through a
+//! # gateway
+//! host_label = "node"
+//! # Export the memory of the MIG instances of GPUs in MIG mode instead of the
+//! # memory of the GPUs, or "both" with a parent_uuid label
+//! mig = "instances"
+//!
+//! [units]
+//! # Export nvidia_gpu_power_usage_watts instead of
+//! # nvidia_gpu_power_usage_milliwatts
+//! power = "watts"
+//! # Export nvidia_gpu_clock_speed_graphics_megahertz instead of
+//! # nvidia_gpu_clock_speed_graphics_hertz
+//! clocks = "megahertz"
+//!
+//! [sampling]
+//! # Sample utilization, power, temperature and processes this often for the
+//! # rolling averages and the extremes between scrapes, which is off by
+//! # default as it adds NVML calls every interval
+//! enabled = true
+//! interval = "1s"
+//! # Run every collector in the background, at the interval of its
+//! # [collectors.<name>] section or the sampling interval, and serve scrapes
+//! # the latest results
+//! background_collection = true
+//!
+//! [adaptive]
+//! # While the mean GPU utilization of a device over the last 10 seconds is
+//! # above 80%, run the processes and gpm collectors at most once a minute
+//! enabled = true
+//! utilization_threshold = 80
+//! window = "10s"
+//! interval = "1m"
+//! collectors = ["processes", "gpm"]
+//!
+//! [state]
+//! # Keep the XID error counts and throttled time across restarts, saving
+//! # them every minute and on shutdown
+//! path = "/var/lib/prometheus-nvidia-gpu/state.json"
+//! save_interval = "1m"
+//!
+//! [privacy]
+//! # Export the owners of processes as e.g. user="u-3f9a0c1b2d4e5f60" and
+//! # only the executable name as their command
+//! enabled = true
+//! salt = "a long random secret"
+//!
+//! [gpustat]
+//! # Serve /gpustat from the previous collection, e.g. of a scrape, if it is
+//! # at most 15 seconds old
+//! max_age = "15s"
+//! # Layout of /gpustat as Handlebars template instead of the built-in one
+//! template = """
+//! {{#each devices}}[{{index}}] {{name}} {{temperature}}°C
+//! {{#each processes}}  {{user}} {{pod}} {{cgroup}} {{used_memory_mib}} MiB
+//! {{/each}}{{/each}}"""
+//!
+//! [nvml]
+//! # Skip readings a device does not support for this long before probing again
+//! unsupported_reprobe_interval = "10m"
+//! # Retry readings failing with Unknown, GpuLost or Timeout twice, after
+//! # 100ms and 200ms
+//! retries = 2
+//! retry_backoff = "100ms"
+//! # Skip a device for a minute after 5 such failures in a row, exporting
+//! # nvidia_gpu_device_healthy 0
+//! circuit_breaker_failures = 5
+//! circuit_breaker_cooldown = "1m"
+//!
+//! [watchdog]
+//! # Reinitialize NVML after this many failed collections in a row, or once a
+//! # collection ran longer than stall_timeout
+//! max_failures = 3
+//! stall_timeout = "30s"
+//!
+//! [kubernetes]
+//! # Export which GPUs are allocated to pods, read from the kubelet
+//! enabled = true
+//! pod_resources_socket = "/var/lib/kubelet/pod-resources/kubelet.sock"
+//! refresh_interval = "10s"
+//!
+//! [alerting]
+//! # Evaluate alerts at least this often, collecting if there was no scrape
+//! # in between
+//! evaluation_interval = "15s"
+//!
+//! [[alerting.rules]]
+//! name = "GpuTooHot"
+//! metric = "nvidia_gpu_temperature_celsius"
+//! op = ">"
+//! threshold = 85.0
+//! # Only fire once the condition held this long
+//! for = "2m"
+//!
+//! [[alerting.webhooks]]
+//! url = "https://hooks.slack.com/services/T000/B000/XXXX"
+//! # One of "generic", "slack" or "discord"
+//! format = "slack"
+//! # Placeholders are {alert}, {status}, {value} and the labels of the series
+//! template = "{alert} is {status} on {name} ({value})"
+//! # Send at most one notification per alert, series and status in this period
+//! min_interval = "5m"
+//! ```
+
+use std::collections::BTreeMap;
+use std::fmt;
+use std::fs;
+use std::path::Path;
+use std::time::Duration;
+
+use serde::Deserialize;
+
+use crate::collectors;
+use crate::gpustat;
+
+/// Errors in the configuration file.
+#[derive(Debug)]
+pub enum ConfigError {
+    Io(std::io::Error),
+    Parse(toml::de::Error),
+    Invalid(String),
+}
+
+impl From<std::io::Error> for ConfigError {
+    fn from(err: std::io::Error) -> ConfigError {
+        ConfigError::Io(err)
+    }
+}
+
+impl From<toml::de::Error> for ConfigError {
+    fn from(err: toml::de::Error) -> ConfigError {
+        ConfigError::Parse(err)
+    }
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ConfigError::Io(e) => write!(f, "Could not read config file: {}", e),
+            ConfigError::Parse(e) => write!(f, "Could not parse config file: {}", e),
+            ConfigError::Invalid(e) => write!(f, "Invalid config: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for ConfigError {}
+
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    /// Per-collector settings, keyed by collector name.
+    pub collectors: BTreeMap<String, CollectorConfig>,
+    pub web: WebConfig,
+    pub labels: LabelsConfig,
+    pub units: UnitsConfig,
+    pub sampling: SamplingConfig,
+    pub adaptive: AdaptiveConfig,
+    pub state: StateConfig,
+    pub privacy: PrivacyConfig,
+    pub nvml: NvmlConfig,
+    pub watchdog: WatchdogConfig,
+    pub kubernetes: KubernetesConfig,
+    pub alerting: AlertingConfig,
+    pub deprecated_metrics: DeprecatedMetrics,
+    pub consistency: Consistency,
+    pub gpustat: GpustatConfig,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct CollectorConfig {
+    pub enabled: bool,
+    /// Minimum time between two collections. Scrapes in between are served
+    /// the previous result.
+    #[serde(with = "humantime_serde")]
+    pub interval: Option<Duration>,
+    /// How readings are exported for devices not reporting them.
+    pub unsupported: UnsupportedReadings,
+}
+
+impl Default for CollectorConfig {
+    fn default() -> CollectorConfig {
+        CollectorConfig {
+            enabled: true,
+            interval: None,
+            unsupported: UnsupportedReadings::default(),
+        }
+    }
+}
+
+/// Export of the per-device readings of a collector for devices that do not
+/// support them or failed to report them.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum UnsupportedReadings {
+    /// The series of the device is left out.
+    Omit,
+    /// The series of the device is NaN.
+    Nan,
+    /// The series of the device is left out, and a companion gauge with the
+    /// `_supported` suffix tells for every device whether it reported the
+    /// reading (1) or not (0).
+    SupportedGauge,
+}
+
+impl Default for UnsupportedReadings {
+    fn default() -> UnsupportedReadings {
+        UnsupportedReadings::Omit
+    }
+}
+
+/// Export of renamed metrics under their old names, while alert rules,
+/// recording rules and dashboards are migrated.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DeprecatedMetrics {
+    /// Renamed metrics are exported under their old and new names.
+    Include,
+    /// Renamed metrics are exported under their new names only.
+    Exclude,
+}
+
+impl Default for DeprecatedMetrics {
+    fn default() -> DeprecatedMetrics {
+        DeprecatedMetrics::Include
+    }
+}
+
+/// How close together the readings of different devices are taken.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Consistency {
+    /// Collectors read one device after the other, and collectors with an
+    /// interval serve their previous readings in between.
+    Sequential,
+    /// Collectors read all devices in parallel, and all collectors run at
+    /// every collection, so that readings of different devices are
+    /// comparable.
+    Snapshot,
+}
+
+impl Default for Consistency {
+    fn default() -> Consistency {
+        Consistency::Sequential
+    }
+}
+
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct WebConfig {
+    /// Whether the `/-/quit` endpoint is enabled.
+    pub enable_lifecycle: bool,
+    /// Whether the `/admin/*` endpoints changing device settings are enabled.
+    pub enable_admin_api: bool,
+    /// Bearer token required by the admin endpoints.
+    pub admin_token: Option<String>,
+    /// Exporters whose metrics are served at `/proxy/metrics`.
+    pub proxy_targets: Vec<ProxyTarget>,
+    /// Users allowed to read metrics. If any are configured, all endpoints
+    /// except the health, lifecycle and admin ones require a user's token.
+    pub users: Vec<WebUser>,
+    /// Whether scrapers asking for OpenMetrics are served it, with the
+    /// process using a device the most as exemplar of its utilization and
+    /// used memory.
+    pub openmetrics: bool,
+    /// Paths serving the metrics of a subset of the devices only, each with
+    /// its own token.
+    pub shards: Vec<WebShard>,
+}
+
+/// The metrics of some devices served at their own path, e.g. for one of
+/// several teams sharing a machine.
+#[derive(Clone, Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct WebShard {
+    pub path: String,
+    /// Indices or UUIDs of the devices.
+    pub devices: Vec<String>,
+    /// Bearer token required instead of a user's token.
+    pub token: String,
+}
+
+/// A user authenticated by a bearer token, who only sees the metrics of
+/// processes they own.
+#[derive(Clone, Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct WebUser {
+    /// Name compared to the owners of processes.
+    pub name: String,
+    pub token: String,
+    /// Whether the user sees the processes of all users.
+    #[serde(default)]
+    pub all_processes: bool,
+}
+
+/// Another exporter instance scraped by the aggregation proxy.
+#[derive(Clone, Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ProxyTarget {
+    pub url: String,
+    /// Value of the `node` label of its metrics, by default the host of the
+    /// URL.
+    pub node: Option<String>,
+}
+
+/// Identity labels of device metrics and their formatting.
+#[derive(Clone, Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct LabelsConfig {
+    /// Names of the identity labels, out of
+    /// [`IDENTITY_LABELS`](collectors::IDENTITY_LABELS).
+    pub identity: Vec<String>,
+    /// Whether the `GPU-` prefix is removed from the `uuid` label.
+    pub strip_uuid_prefix: bool,
+    /// Whether the `uuid` label is shortened to the first 8 characters of the
+    /// UUID.
+    pub short_uuid: bool,
+    /// Name of a label with the hostname of the machine attached to every
+    /// series served, e.g. `node` when the exporter is scraped through a
+    /// gateway and the `instance` label is the address of the gateway.
+    pub host_label: Option<String>,
+    /// Whether the memory of GPUs in MIG mode is exported for the GPU, its
+    /// MIG instances or both.
+    pub mig: MigIdentity,
+}
+
+impl Default for LabelsConfig {
+    fn default() -> LabelsConfig {
+        LabelsConfig {
+            identity: collectors::LABELS.iter().map(|l| l.to_string()).collect(),
+            strip_uuid_prefix: false,
+            short_uuid: false,
+            host_label: None,
+            mig: MigIdentity::default(),
+        }
+    }
+}
+
+/// Devices the memory series of a GPU in MIG mode are exported for.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum MigIdentity {
+    /// The GPU only, as without MIG mode.
+    Parent,
+    /// The MIG instances only, each identified by its own UUID.
+    Instances,
+    /// The GPU and its MIG instances, with the UUID of the GPU in the
+    /// `parent_uuid` label of the instances and an empty one of the GPU.
+    Both,
+}
+
+impl Default for MigIdentity {
+    fn default() -> MigIdentity {
+        MigIdentity::Parent
+    }
+}
+
+/// Units of the exported power and clock metrics, which also determine their
+/// names.
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct UnitsConfig {
+    pub power: PowerUnit,
+    pub clocks: ClockUnit,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum PowerUnit {
+    Milliwatts,
+    Watts,
+}
+
+impl Default for PowerUnit {
+    fn default() -> PowerUnit {
+        PowerUnit::Milliwatts
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ClockUnit {
+    Hertz,
+    Megahertz,
+}
+
+impl Default for ClockUnit {
+    fn default() -> ClockUnit {
+        ClockUnit::Hertz
+    }
+}
+
+/// Settings of the background sampler behind the rolling utilization
+/// averages and the extremes between scrapes.
+#[derive(Clone, Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct SamplingConfig {
+    /// Whether the sampler runs, which is opt-in as it reads every device
+    /// every `interval` regardless of scrapes.
+    pub enabled: bool,
+    #[serde(with = "humantime_serde")]
+    pub interval: Duration,
+    /// Whether every collector runs on a background thread at its own
+    /// interval, defaulting to the sampling interval, instead of at scrape
+    /// time.
+    pub background_collection: bool,
+}
+
+impl Default for SamplingConfig {
+    fn default() -> SamplingConfig {
+        SamplingConfig {
+            enabled: false,
+            interval: Duration::from_secs(1),
+            background_collection: false,
+        }
+    }
+}
+
+/// Settings of the `/gpustat` summary.
+#[derive(Clone, Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct GpustatConfig {
+    /// Age up to which the summary is taken from the previous collection,
+    /// e.g. of a scrape, instead of collecting again.
+    #[serde(with = "humantime_serde")]
+    pub max_age: Duration,
+    /// Handlebars template rendered with the `devices` instead of the
+    /// built-in layout.
+    pub template: Option<String>,
+}
+
+impl Default for GpustatConfig {
+    fn default() -> GpustatConfig {
+        GpustatConfig {
+            max_age: Duration::from_secs(15),
+            template: None,
+        }
+    }
+}
+
+/// Settings of running expensive collectors less often while the GPUs are
+/// busy, so that the exporter interferes less with latency-sensitive
+/// workloads. The utilization is taken from the sampler.
+#[derive(Clone, Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct AdaptiveConfig {
+    pub enabled: bool,
+    /// Mean GPU utilization in percent of any device above which the GPUs
+    /// count as busy.
+    pub utilization_threshold: u32,
+    /// Time the mean GPU utilization is taken over.
+    #[serde(with = "humantime_serde")]
+    pub window: Duration,
+    /// Minimum time between two runs of the collectors while busy.
+    #[serde(with = "humantime_serde")]
+    pub interval: Duration,
+    /// Names of the collectors to run less often.
+    pub collectors: Vec<String>,
+}
+
+impl Default for AdaptiveConfig {
+    fn default() -> AdaptiveConfig {
+        AdaptiveConfig {
+            enabled: false,
+            utilization_threshold: 80,
+            window: Duration::from_secs(10),
+            interval: Duration::from_secs(60),
+            collectors: vec![
+                "processes".to_string(),
+                "kubernetes".to_string(),
+                "gpm".to_string(),
+            ],
+        }
+    }
+}
+
+/// Settings of persisting the counters the exporter derives itself, the XID
+/// errors and the time spent throttled, across restarts.
+#[derive(Clone, Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct StateConfig {
+    /// File the state is kept in, not persisted if unset.
+    pub path: Option<String>,
+    /// Time between two saves of the state.
+    #[serde(with = "humantime_serde")]
+    pub save_interval: Duration,
+}
+
+impl Default for StateConfig {
+    fn default() -> StateConfig {
+        StateConfig {
+            path: None,
+            save_interval: Duration::from_secs(60),
+        }
+    }
+}
+
+#[derive(Clone, Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct NvmlConfig {
+    /// Time during which a reading that a device reported as not supported
+    /// is skipped.
+    #[serde(with = "humantime_serde")]
+    pub unsupported_reprobe_interval: Duration,
+    /// Number of times a reading that failed with a transient error is
+    /// retried.
+    pub retries: u32,
+    /// Time before the first retry, doubled for every further retry.
+    #[serde(with = "humantime_serde")]
+    pub retry_backoff: Duration,
+    /// Number of transient failures in a row after which a device is marked
+    /// unhealthy and skipped, or 0 to never skip devices.
+    pub circuit_breaker_failures: u32,
+    /// Time during which an unhealthy device is skipped before it is probed
+    /// again.
+    #[serde(with = "humantime_serde")]
+    pub circuit_breaker_cooldown: Duration,
+}
+
+impl Default for NvmlConfig {
+    fn default() -> NvmlConfig {
+        NvmlConfig {
+            unsupported_reprobe_interval: Duration::from_secs(10 * 60),
+            retries: 1,
+            retry_backoff: Duration::from_millis(100),
+            circuit_breaker_failures: 5,
+            circuit_breaker_cooldown: Duration::from_secs(60),
+        }
+    }
+}
+
+/// Settings of the watchdog reinitializing NVML when collections keep failing
+/// or stall.
+#[derive(Clone, Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct WatchdogConfig {
+    pub enabled: bool,
+    /// Number of failed collections in a row after which NVML is
+    /// reinitialized.
+    pub max_failures: u32,
+    /// Duration after which a running collection is considered hanging.
+    #[serde(with = "humantime_serde")]
+    pub stall_timeout: Duration,
+    /// Time between two checks of the watchdog.
+    #[serde(with = "humantime_serde")]
+    pub check_interval: Duration,
+}
+
+impl Default for WatchdogConfig {
+    fn default() -> WatchdogConfig {
+        WatchdogConfig {
+            enabled: true,
+            max_failures: 3,
+            stall_timeout: Duration::from_secs(30),
+            check_interval: Duration::from_secs(10),
+        }
+    }
+}
+
+/// Settings of reading the allocation of GPUs to pods from the kubelet's
+/// pod-resources API.
+#[derive(Clone, Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct KubernetesConfig {
+    pub enabled: bool,
+    pub pod_resources_socket: String,
+    /// Prefix of the names of the resources whose devices are GPUs.
+    pub resource_prefix: String,
+    /// Time between two reads of the allocations.
+    #[serde(with = "humantime_serde")]
+    pub refresh_interval: Duration,
+}
+
+impl Default for KubernetesConfig {
+    fn default() -> KubernetesConfig {
+        KubernetesConfig {
+            enabled: false,
+            pod_resources_socket: "/var/lib/kubelet/pod-resources/kubelet.sock".to_string(),
+            resource_prefix: "nvidia.com/".to_string(),
+            refresh_interval: Duration::from_secs(10),
+        }
+    }
+}
+
+/// Pseudonymization of the owners and commands of processes, for clusters
+/// where user names must not be stored in the metrics system.
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct PrivacyConfig {
+    /// Whether the `user` label holds a hash of the user name, and the
+    /// `command` label only the name of the executable.
+    pub enabled: bool,
+    /// Secret mixed into the hashes, so that they cannot be reversed by
+    /// hashing known user names. The hash of a user stays the same as long
+    /// as the salt does.
+    pub salt: String,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct AlertingConfig {
+    /// Time between two evaluations of the alerts without scrapes, which
+    /// only collect if there was no scrape in between.
+    #[serde(with = "humantime_serde")]
+    pub evaluation_interval: Duration,
+    pub rules: Vec<AlertRule>,
+    pub webhooks: Vec<WebhookConfig>,
+}
+
+impl Default for AlertingConfig {
+    fn default() -> AlertingConfig {
+        AlertingConfig {
+            evaluation_interval: Duration::from_secs(15),
+            rules: Vec::new(),
+            webhooks: Vec::new(),
+        }
+    }
+}
+
+/// Receiver of notifications about alerts that start or stop firing.
+#[derive(Clone, Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct WebhookConfig {
+    pub url: String,
+    #[serde(default)]
+    pub format: WebhookFormat,
+    /// Message with `{alert}`, `{status}`, `{value}` and label placeholders.
+    pub template: Option<String>,
+    /// Minimum time between two notifications about the same alert, series
+    /// and status.
+    #[serde(default = "default_min_interval", with = "humantime_serde")]
+    pub min_interval: Duration,
+}
+
+fn default_min_interval() -> Duration {
+    Duration::from_secs(5 * 60)
+}
+
+/// Payload format of a webhook.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum WebhookFormat {
+    /// The message together with the alert.
+    Generic,
+    Slack,
+    Discord,
+}
+
+impl Default for WebhookFormat {
+    fn default() -> WebhookFormat {
+        WebhookFormat::Generic
+    }
+}
+
+/// A threshold on the samples of an exported metric.
+#[derive(Clone, Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct AlertRule {
+    pub name: String,
+    /// Full name of the metric, e.g. `nvidia_gpu_temperature_celsius`.
+    pub metric: String,
+    pub op: Comparison,
+    pub threshold: f64,
+    /// How long the condition has to hold before the alert fires.
+    #[serde(default, rename = "for", with = "humantime_serde")]
+    pub duration: Option<Duration>,
+}
+
+/// Comparison of a sample against the threshold of an [`AlertRule`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize)]
+pub enum Comparison {
+    #[serde(rename = ">")]
+    Greater,
+    #[serde(rename = ">=")]
+    GreaterOrEqual,
+    #[serde(rename = "<")]
+    Less,
+    #[serde(rename = "<=")]
+    LessOrEqual,
+    #[serde(rename = "==")]
+    Equal,
+    #[serde(rename = "!=")]
+    NotEqual,
+}
+
+impl Comparison {
+    /// Whether `value` compares to `threshold` as required.
+    pub fn holds(self, value: f64, threshold: f64) -> bool {
+        match self {
+            Comparison::Greater => value > threshold,
+            Comparison::GreaterOrEqual => value >= threshold,
+            Comparison::Less => value < threshold,
+            Comparison::LessOrEqual => value <= threshold,
+            Comparison::Equal => (value - threshold).abs() < std::f64::EPSILON,
+            Comparison::NotEqual => (value - threshold).abs() >= std::f64::EPSILON,
+        }
+    }
+}
+
+/// Whether `name` is a valid Prometheus metric name.
+fn valid_metric_name(name: &str) -> bool {
+    let mut chars = name.chars();
+    match chars.next() {
+        Some(c) if c.is_ascii_alphabetic() || c == '_' || c == ':' => {}
+        _ => return false,
+    }
+    chars.all(|c| c.is_ascii_alphanumeric() || c == '_' || c == ':')
+}
+
+/// Whether `name` is a valid label name, which unlike metric names may not
+/// contain colons, and is not reserved for internal use.
+fn valid_label_name(name: &str) -> bool {
+    !name.contains(':') && !name.starts_with("__") && valid_metric_name(name)
+}
+
+impl Config {
+    /// Reads and validates the configuration file at `path`.
+    pub fn from_file<P: AsRef<Path>>(path: P) -> Result<Config, ConfigError> {
+        let config: Config = toml::from_str(&fs::read_to_string(path)?)?;
+        config.validate()?;
+        Ok(config)
+    }
+
+    /// Checks settings that cannot be expressed through deserialization.
+    pub fn validate(&self) -> Result<(), ConfigError> {
+        for name in self.collectors.keys() {
+            if !collectors::NAMES.contains(&name.as_str()) {
+                return Err(ConfigError::Invalid(format!(
+                    "unknown collector '{}', expected one of: {}",
+                    name,
+                    collectors::NAMES.join(", ")
+                )));
+            }
+        }
+
+        if self.adaptive.enabled {
+            if !self.sampling.enabled {
+                return Err(ConfigError::Invalid(
+                    "adaptive collection requires sampling".to_string(),
+                ));
+            }
+            if self.adaptive.utilization_threshold > 100 {
+                return Err(ConfigError::Invalid(format!(
+                    "adaptive utilization_threshold {} is above 100",
+                    self.adaptive.utilization_threshold
+                )));
+            }
+            for name in &self.adaptive.collectors {
+                if !collectors::NAMES.contains(&name.as_str()) {
+                    return Err(ConfigError::Invalid(format!(
+                        "unknown adaptive collector '{}', expected one of: {}",
+                        name,
+                        collectors::NAMES.join(", ")
+                    )));
+                }
+            }
+        }
+
+        for (i, label) in self.labels.identity.iter().enumerate() {
+            if !collectors::IDENTITY_LABELS.contains(&label.as_str()) {
+                return Err(ConfigError::Invalid(format!(
+                    "unknown identity label '{}', expected one of: {}",
+                    label,
+                    collectors::IDENTITY_LABELS.join(", ")
+                )));
+            }
+            if self.labels.identity[..i].contains(label) {
+                return Err(ConfigError::Invalid(format!(
+                    "duplicate identity label '{}'",
+                    label
+                )));
+            }
+        }
+
+        // MIG instances are told apart from their GPU by their UUID
+        if self.labels.mig != MigIdentity::Parent
+            && !self.labels.identity.iter().any(|label| label == "uuid")
+        {
+            return Err(ConfigError::Invalid(
+                "MIG instance series require the identity label 'uuid'".to_string(),
+            ));
+        }
+
+        if let Some(label) = &self.labels.host_label {
+            if !valid_label_name(label) {
+                return Err(ConfigError::Invalid(format!(
+                    "invalid host label name '{}'",
+                    label
+                )));
+            }
+            if self.labels.identity.contains(label) {
+                return Err(ConfigError::Invalid(format!(
+                    "host label '{}' is also an identity label",
+                    label
+                )));
+            }
+            if label == "parent_uuid" && self.labels.mig == MigIdentity::Both {
+                return Err(ConfigError::Invalid(
+                    "host label 'parent_uuid' conflicts with the label of MIG instances"
+                        .to_string(),
+                ));
+            }
+            // The proxy adds a node label to the local metrics itself
+            if label == "node" && !self.web.proxy_targets.is_empty() {
+                return Err(ConfigError::Invalid(
+                    "host label 'node' conflicts with the node label of the proxy".to_string(),
+                ));
+            }
+        }
+
+        if self.consistency == Consistency::Snapshot && self.sampling.background_collection {
+            return Err(ConfigError::Invalid(
+                "snapshot consistency conflicts with background collection".to_string(),
+            ));
+        }
+
+        if self.privacy.enabled && self.privacy.salt.is_empty() {
+            return Err(ConfigError::Invalid("privacy requires a salt".to_string()));
+        }
+
+        if let Some(template) = &self.gpustat.template {
+            if let Err(e) = gpustat::Template::new(template) {
+                return Err(ConfigError::Invalid(format!(
+                    "invalid gpustat template: {}",
+                    e
+                )));
+            }
+        }
+
+        for rule in &self.alerting.rules {
+            if rule.name.is_empty() {
+                return Err(ConfigError::Invalid("alert rules need a name".to_string()));
+            }
+            if !valid_metric_name(&rule.metric) {
+                return Err(ConfigError::Invalid(format!(
+                    "invalid metric name '{}' in alert rule '{}'",
+                    rule.metric, rule.name
+                )));
+            }
+        }
+
+        for webhook in &self.alerting.webhooks {
+            if webhook.url.parse::<hyper::Uri>().is_err() {
+                return Err(ConfigError::Invalid(format!(
+                    "invalid webhook url '{}'",
+                    webhook.url
+                )));
+            }
+        }
+
+        for target in &self.web.proxy_targets {
+            if target.url.parse::<hyper::Uri>().is_err() {
+                return Err(ConfigError::Invalid(format!(
+                    "invalid proxy target url '{}'",
+                    target.url
+                )));
+            }
+        }
+
+        for (i, user) in self.web.users.iter().enumerate() {
+            if user.token.is_empty() {
+                return Err(ConfigError::Invalid(format!(
+                    "user '{}' needs a token",
+                    user.name
+                )));
+            }
+            if self.web.users[..i].iter().any(|u| u.token == user.token) {
+                return Err(ConfigError::Invalid(format!(
+                    "user '{}' shares its token with another user",
+                    user.name
+                )));
+            }
+        }
+
+        for (i, shard) in self.web.shards.iter().enumerate() {
+            if !shard.path.starts_with('/') {
+                return Err(ConfigError::Invalid(format!(
+                    "shard path '{}' must start with '/'",
+                    shard.path
+                )));
+            }
+            if self.web.shards[..i].iter().any(|s| s.path == shard.path) {
+                return Err(ConfigError::Invalid(format!(
+                    "duplicate shard path '{}'",
+                    shard.path
+                )));
+            }
+            if shard.devices.is_empty() {
+                return Err(ConfigError::Invalid(format!(
+                    "shard '{}' needs devices",
+                    shard.path
+                )));
+            }
+            if shard.token.is_empty() {
+                return Err(ConfigError::Invalid(format!(
+                    "shard '{}' needs a token",
+                    shard.path
+                )));
+            }
+        }
+
+        if self.web.enable_admin_api && self.web.admin_token.is_none() {
+            return Err(ConfigError::Invalid(
+                "enable_admin_api requires an admin_token".to_string(),
+            ));
+        }
+
+        Ok(())
+    }
+
+    /// Settings of the collector `name`, falling back to the defaults.
+    pub fn collector(&self, name: &str) -> CollectorConfig {
+        self.collectors.get(name).cloned().unwrap_or_default()
+    }
+}
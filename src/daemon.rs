@@ -0,0 +1,68 @@
+//! Running as a classic daemon for init systems without process supervision.
+
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Write};
+use std::os::unix::io::AsRawFd;
+use std::path::Path;
+use std::process;
+
+/// Forks, exiting in the parent, and continues in the child.
+fn fork() -> io::Result<()> {
+    match unsafe { libc::fork() } {
+        -1 => Err(io::Error::last_os_error()),
+        0 => Ok(()),
+        _ => process::exit(0),
+    }
+}
+
+/// Points `fd` at `file`.
+fn redirect(file: &File, fd: libc::c_int) -> io::Result<()> {
+    if unsafe { libc::dup2(file.as_raw_fd(), fd) } == -1 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(())
+}
+
+/// Detaches the process from its terminal and session with a double fork,
+/// moves to `/`, writes the PID of the daemon to `pid_file` and redirects
+/// stdout and stderr to `log_file`, or discards them without one.
+///
+/// Has to be called before any threads are started, as only the calling
+/// thread survives a fork. Relative paths are resolved against the working
+/// directory at the time of the call.
+pub fn daemonize(pid_file: Option<&Path>, log_file: Option<&Path>) -> io::Result<()> {
+    // Open everything before leaving the working directory
+    let null = File::open("/dev/null")?;
+    let log = match log_file {
+        Some(path) => OpenOptions::new().create(true).append(true).open(path)?,
+        None => OpenOptions::new().write(true).open("/dev/null")?,
+    };
+    let pid_file = match pid_file {
+        Some(path) => Some((File::create(path)?, path.canonicalize()?)),
+        None => None,
+    };
+
+    fork()?;
+    if unsafe { libc::setsid() } == -1 {
+        return Err(io::Error::last_os_error());
+    }
+    // The session leader exits, so the daemon can never reacquire a terminal
+    fork()?;
+
+    std::env::set_current_dir("/")?;
+    unsafe {
+        libc::umask(0o022);
+    }
+
+    if let Some((mut file, path)) = pid_file {
+        if let Err(e) = writeln!(file, "{}", process::id()) {
+            let _ = fs::remove_file(path);
+            return Err(e);
+        }
+    }
+
+    redirect(&null, libc::STDIN_FILENO)?;
+    redirect(&log, libc::STDOUT_FILENO)?;
+    redirect(&log, libc::STDERR_FILENO)?;
+    Ok(())
+}
@@ -0,0 +1,72 @@
+//! Grafana dashboard generated from the metrics an exporter actually serves.
+
+use prometheus::proto::{MetricFamily, MetricType};
+use serde_json::{json, Value};
+
+/// Legend of device metrics, falling back to the metric name for others.
+const LEGEND: &str = "{{name}} {{uuid}}";
+
+const PANEL_WIDTH: usize = 12;
+const PANEL_HEIGHT: usize = 8;
+
+/// Query plotting the metric family `family`.
+fn expr(family: &MetricFamily) -> Option<String> {
+    let name = family.get_name();
+
+    match family.get_field_type() {
+        MetricType::GAUGE | MetricType::UNTYPED => Some(name.to_string()),
+        MetricType::COUNTER => Some(format!("rate({}[5m])", name)),
+        MetricType::HISTOGRAM => Some(format!(
+            "histogram_quantile(0.99, sum by (le) (rate({}_bucket[5m])))",
+            name
+        )),
+        _ => None,
+    }
+}
+
+/// Generates a dashboard with one graph per metric family in `families`.
+pub fn generate(families: &[MetricFamily]) -> Value {
+    let panels: Vec<Value> = families
+        .iter()
+        .filter_map(|family| Some((family, expr(family)?)))
+        .enumerate()
+        .map(|(i, (family, expr))| {
+            json!({
+                "id": i + 1,
+                "type": "graph",
+                "title": family.get_name(),
+                "description": family.get_help(),
+                "datasource": "$datasource",
+                "gridPos": {
+                    "h": PANEL_HEIGHT,
+                    "w": PANEL_WIDTH,
+                    "x": (i % 2) * PANEL_WIDTH,
+                    "y": (i / 2) * PANEL_HEIGHT,
+                },
+                "targets": [{
+                    "expr": expr,
+                    "legendFormat": LEGEND,
+                    "refId": "A",
+                }],
+            })
+        })
+        .collect();
+
+    json!({
+        "title": "NVIDIA GPUs",
+        "uid": "nvidia-gpu-exporter",
+        "schemaVersion": 22,
+        "editable": true,
+        "time": { "from": "now-6h", "to": "now" },
+        "refresh": "30s",
+        "templating": {
+            "list": [{
+                "name": "datasource",
+                "label": "Data source",
+                "type": "datasource",
+                "query": "prometheus",
+            }],
+        },
+        "panels": panels,
+    })
+}
@@ -0,0 +1,76 @@
+//! Raw device state for `/debug/devices`.
+//!
+//! Every reading of every device is read directly from the backend, bypassing
+//! the cache of unsupported readings, and reported together with the error it
+//! failed with, so that it is visible why a metric is missing for a device.
+
+use serde::Serialize;
+use serde_json::{json, Value};
+
+use crate::backend::{ClockType, Feature, GpuBackend};
+use crate::error::Result;
+
+/// A reading as JSON: its value, or the error it failed with.
+fn reading<T: Serialize>(result: Result<T>) -> Value {
+    match result {
+        Ok(value) => json!({ "value": value }),
+        Err(e) => json!({ "error": e.to_string() }),
+    }
+}
+
+/// All readings of the device `index`.
+fn device<B: GpuBackend + ?Sized>(backend: &B, index: u32) -> Value {
+    let features: serde_json::Map<String, Value> = Feature::ALL
+        .iter()
+        .map(|&feature| {
+            let probed = backend.probe_feature(index, feature).map(|()| true);
+            (feature.name().to_string(), reading(probed))
+        })
+        .collect();
+
+    // GPM metrics and the utilization of processes are left out, as reading
+    // them restarts the averaging of the next collection
+    json!({
+        "index": index,
+        "identity": reading(backend.device_info(index)),
+        "utilization": reading(backend.utilization(index)),
+        "memory": reading(backend.memory_info(index)),
+        "processes": reading(backend.processes(index)),
+        "mps_processes": reading(backend.mps_processes(index)),
+        "power_usage_milliwatts": reading(backend.power_usage(index)),
+        "module_power_usage_milliwatts": reading(backend.module_power_usage(index)),
+        "memory_power_usage_milliwatts": reading(backend.memory_power_usage(index)),
+        "power_limit_milliwatts": reading(backend.power_limit(index)),
+        "graphics_clock_mhz": reading(backend.clock(index, ClockType::Graphics)),
+        "sm_clock_mhz": reading(backend.clock(index, ClockType::Sm)),
+        "temperature_celsius": reading(backend.temperature(index)),
+        "memory_temperature_celsius": reading(backend.memory_temperature(index)),
+        "throttle_reasons": reading(backend.throttle_reasons(index)),
+        "fan_speed_percent": reading(backend.fan_speed(index)),
+        "fan_speed_rpm": reading(backend.fan_speed_rpm(index)),
+        "target_fan_speed_percent": reading(backend.target_fan_speed(index)),
+        "grid_licenses": reading(backend.grid_licenses(index)),
+        "pcie_errors": reading(backend.pcie_errors(index)),
+        "mig_devices": reading(backend.mig_devices(index)),
+        "compute_capability": reading(backend.compute_capability(index)),
+        "vbios_version": reading(backend.vbios_version(index)),
+        "numa_node": reading(backend.numa_node(index)),
+        "cpu_affinity": reading(backend.cpu_affinity(index)),
+        "physical_slot": reading(backend.physical_slot(index)),
+        "performance_state": reading(backend.performance_state(index)),
+        "operation_mode": reading(backend.operation_mode(index)),
+        "persistence_mode": reading(backend.persistence_mode(index)),
+        "features": features,
+    })
+}
+
+/// All readings of all devices of `backend`.
+pub(crate) fn devices<B: GpuBackend + ?Sized>(backend: &B) -> Result<Value> {
+    let count = backend.device_count()?;
+    let devices: Vec<Value> = (0..count).map(|index| device(backend, index)).collect();
+
+    Ok(json!({
+        "device_count": count,
+        "devices": devices,
+    }))
+}
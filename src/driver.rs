@@ -0,0 +1,103 @@
+//! Versions of the NVIDIA kernel module and of the NVML library loaded by the
+//! exporter, which have to match. After a partial upgrade, e.g. of the driver
+//! packages without a reboot, they differ and NVML calls fail in confusing
+//! ways.
+//!
+//! Also the parameters the kernel module was loaded with and whether the
+//! persistence daemon is running, so that drift in the driver configuration
+//! of a fleet shows up in the metrics.
+
+use std::fs;
+use std::path::Path;
+
+/// Kernel module parameters that are exported, by their name in
+/// `/proc/driver/nvidia/params`. They are set as `NVreg_<name>` options.
+pub const PARAMETERS: [&str; 9] = [
+    "EnableGpuFirmware",
+    "DynamicPowerManagement",
+    "EnableResizableBar",
+    "PreserveVideoMemoryAllocations",
+    "EnableStreamMemOPs",
+    "EnableMSI",
+    "NvLinkDisable",
+    "OpenRmEnableUnsupportedGpus",
+    "RegistryDwords",
+];
+
+/// PID file of `nvidia-persistenced`.
+const PERSISTENCED_PID_FILE: &str = "/var/run/nvidia-persistenced/nvidia-persistenced.pid";
+
+/// Whether `token` looks like a driver version, e.g. `535.104.05`.
+fn is_version(token: &str) -> bool {
+    token.contains('.') && token.chars().all(|c| c.is_ascii_digit() || c == '.')
+}
+
+/// Version of the kernel module from the contents of
+/// `/proc/driver/nvidia/version`.
+pub fn parse_kernel_module_version(version_file: &str) -> Option<String> {
+    version_file
+        .lines()
+        .find(|line| line.starts_with("NVRM version:"))?
+        .split_whitespace()
+        .find(|token| is_version(token))
+        .map(str::to_string)
+}
+
+/// Version of `libnvidia-ml` from the memory mappings of a process, the
+/// contents of `/proc/<pid>/maps`, which list the library by its versioned
+/// file name.
+pub fn parse_library_version(maps: &str) -> Option<String> {
+    maps.lines()
+        .filter_map(|line| line.split_whitespace().last())
+        .filter_map(|path| path.rsplit('/').next())
+        .filter_map(|file| file.strip_prefix("libnvidia-ml.so."))
+        .find(|version| is_version(version))
+        .map(str::to_string)
+}
+
+/// Versions of the loaded kernel module and of the NVML library loaded by
+/// this process, or `None` if either is not loaded or not on Linux.
+pub fn versions() -> Option<(String, String)> {
+    let version_file = fs::read_to_string("/proc/driver/nvidia/version").ok()?;
+    let maps = fs::read_to_string("/proc/self/maps").ok()?;
+
+    Some((
+        parse_kernel_module_version(&version_file)?,
+        parse_library_version(&maps)?,
+    ))
+}
+
+/// Values of the [`PARAMETERS`] from the contents of
+/// `/proc/driver/nvidia/params`, by their `NVreg_` option name. Quotes around
+/// string values are removed.
+pub fn parse_parameters(params: &str) -> Vec<(String, String)> {
+    params
+        .lines()
+        .filter_map(|line| {
+            let (name, value) = line.split_at(line.find(':')?);
+            let value = value[1..].trim().trim_matches('"');
+            if PARAMETERS.contains(&name.trim()) {
+                Some((format!("NVreg_{}", name.trim()), value.to_string()))
+            } else {
+                None
+            }
+        })
+        .collect()
+}
+
+/// Exported parameters of the loaded kernel module, or `None` if it is not
+/// loaded or not on Linux.
+pub fn parameters() -> Option<Vec<(String, String)>> {
+    let params = fs::read_to_string("/proc/driver/nvidia/params").ok()?;
+    Some(parse_parameters(&params))
+}
+
+/// Whether `nvidia-persistenced` is running, according to its PID file.
+/// Inside containers, the PID file and process are only visible with the
+/// host's `/var/run` and PID namespace.
+pub fn persistenced_running() -> bool {
+    fs::read_to_string(PERSISTENCED_PID_FILE)
+        .ok()
+        .and_then(|pid| pid.trim().parse::<u32>().ok())
+        .map_or(false, |pid| Path::new(&format!("/proc/{}", pid)).exists())
+}
@@ -0,0 +1,79 @@
+use std::fmt;
+
+use nvml_wrapper::error::NvmlError;
+
+/// Result type used throughout the crate.
+pub type Result<T> = std::result::Result<T, CollectingError>;
+
+/// Errors that can occur while collecting GPU metrics.
+#[derive(Debug)]
+pub enum CollectingError {
+    /// The backend cannot provide the requested reading for this device.
+    NotSupported,
+    /// The requested device does not exist.
+    NotFound,
+    /// The device is skipped after repeated failures, see
+    /// [`DeviceHealth`](crate::collectors::DeviceHealth).
+    Unhealthy,
+    Nvml(NvmlError),
+    Prometheus(prometheus::Error),
+    Io(std::io::Error),
+    /// The `/gpustat` template failed to render.
+    Template(String),
+}
+
+impl CollectingError {
+    /// Whether the error only means that a reading is unavailable.
+    pub fn is_not_supported(&self) -> bool {
+        matches!(self, CollectingError::NotSupported)
+    }
+
+    /// Whether the error may go away when the call is retried, e.g. after a
+    /// GPU briefly fell off the bus.
+    pub fn is_transient(&self) -> bool {
+        matches!(
+            self,
+            CollectingError::Nvml(NvmlError::Unknown)
+                | CollectingError::Nvml(NvmlError::GpuLost)
+                | CollectingError::Nvml(NvmlError::Timeout)
+        )
+    }
+}
+
+impl From<NvmlError> for CollectingError {
+    fn from(err: NvmlError) -> CollectingError {
+        match err {
+            NvmlError::NotSupported => CollectingError::NotSupported,
+            NvmlError::NotFound => CollectingError::NotFound,
+            err => CollectingError::Nvml(err),
+        }
+    }
+}
+
+impl From<prometheus::Error> for CollectingError {
+    fn from(err: prometheus::Error) -> CollectingError {
+        CollectingError::Prometheus(err)
+    }
+}
+
+impl From<std::io::Error> for CollectingError {
+    fn from(err: std::io::Error) -> CollectingError {
+        CollectingError::Io(err)
+    }
+}
+
+impl fmt::Display for CollectingError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            CollectingError::NotSupported => write!(f, "Not supported"),
+            CollectingError::NotFound => write!(f, "Device not found"),
+            CollectingError::Unhealthy => write!(f, "Device unhealthy after repeated failures"),
+            CollectingError::Nvml(e) => write!(f, "NVML error: {}", e),
+            CollectingError::Prometheus(e) => write!(f, "Prometheus error: {}", e),
+            CollectingError::Io(e) => write!(f, "I/O error: {}", e),
+            CollectingError::Template(e) => write!(f, "Template error: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for CollectingError {}
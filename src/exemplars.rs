@@ -0,0 +1,75 @@
+//! Exemplars of device metrics, naming the process that used the device the
+//! most when the metric was collected.
+
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::openmetrics::Exemplar;
+use crate::privacy::Privacy;
+use crate::procinfo;
+
+/// Latest exemplar by metric name and identity label values of the device.
+pub struct Exemplars {
+    exemplars: Mutex<HashMap<(String, Vec<String>), Exemplar>>,
+    /// Pseudonymizes the owners of the processes, if enabled.
+    privacy: Option<Privacy>,
+}
+
+impl Exemplars {
+    pub fn new(privacy: Option<Privacy>) -> Exemplars {
+        Exemplars {
+            exemplars: Mutex::new(HashMap::new()),
+            privacy,
+        }
+    }
+
+    /// Attaches `value` of the process `pid` to the series of `metric` with
+    /// the identity label values `labels`, or detaches the exemplar without a
+    /// process.
+    pub fn set(&self, metric: &str, labels: &[&str], process: Option<(u32, f64)>) {
+        let key = (
+            metric.to_string(),
+            labels.iter().map(|l| l.to_string()).collect(),
+        );
+        let mut exemplars = self.exemplars.lock().expect("Exemplars poisoned");
+        let (pid, value) = match process {
+            Some(process) => process,
+            None => {
+                exemplars.remove(&key);
+                return;
+            }
+        };
+
+        let mut details = procinfo::lookup(pid).unwrap_or_default();
+        if let Some(privacy) = &self.privacy {
+            details = privacy.details(details);
+        }
+        let timestamp = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0.0, |since| since.as_secs_f64());
+        exemplars.insert(
+            key,
+            Exemplar {
+                labels: vec![
+                    ("pid".to_string(), pid.to_string()),
+                    ("user".to_string(), details.user),
+                ],
+                value,
+                timestamp,
+            },
+        );
+    }
+
+    pub fn get(&self, metric: &str, labels: &[&str]) -> Option<Exemplar> {
+        let key = (
+            metric.to_string(),
+            labels.iter().map(|l| l.to_string()).collect(),
+        );
+        self.exemplars
+            .lock()
+            .expect("Exemplars poisoned")
+            .get(&key)
+            .cloned()
+    }
+}
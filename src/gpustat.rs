@@ -0,0 +1,194 @@
+//! Human readable, `gpustat`-like summary of the devices and their processes,
+//! taken from the metrics of a collection rather than from NVML.
+
+use handlebars::{Handlebars, TemplateError};
+use prometheus::proto::{Metric, MetricFamily};
+use serde::Serialize;
+
+use crate::collectors::Device;
+use crate::error::{CollectingError, Result};
+use crate::privacy::Privacy;
+use crate::procinfo;
+use crate::NAMESPACE;
+
+/// Readings of a device in the summary. Readings whose collector is disabled
+/// or that the device did not report are `None`.
+#[derive(Clone, Debug, Serialize)]
+pub struct DeviceStat {
+    pub index: u32,
+    pub name: String,
+    pub uuid: String,
+    pub temperature: Option<u32>,
+    pub utilization: Option<u32>,
+    pub memory_used_mib: Option<u64>,
+    pub memory_total_mib: Option<u64>,
+    pub processes: Vec<ProcessStat>,
+}
+
+/// A process in the summary, with its owner and command as exported.
+#[derive(Clone, Debug, Serialize)]
+pub struct ProcessStat {
+    pub pid: String,
+    pub user: String,
+    pub command: String,
+    pub used_memory_mib: u64,
+    /// Control group of the process, e.g. `/slurm/uid_1000/job_4242/step_0`,
+    /// empty if unknown.
+    pub cgroup: String,
+    /// Kubernetes pod of the process, empty if unknown.
+    pub pod: String,
+}
+
+/// Value of the label `name` of `metric`, empty if it has none.
+fn label<'a>(metric: &'a Metric, name: &str) -> &'a str {
+    metric
+        .get_label()
+        .iter()
+        .find(|label| label.get_name() == name)
+        .map_or("", |label| label.get_value())
+}
+
+/// Series of the family `name` of `device`, identified by the identity labels
+/// `labels`.
+fn series<'a>(
+    families: &'a [MetricFamily],
+    name: &str,
+    labels: &'a [&str],
+    device: &'a Device,
+) -> impl Iterator<Item = &'a Metric> + 'a {
+    let name = format!("{}_{}", NAMESPACE, name);
+    let values = device.labels();
+    families
+        .iter()
+        .filter(move |family| family.get_name() == name)
+        .flat_map(|family| family.get_metric())
+        .filter(move |metric| {
+            labels
+                .iter()
+                .zip(&values)
+                .all(|(name, value)| label(metric, name) == *value)
+        })
+}
+
+/// Value of the gauge `name` of `device`, if it was collected.
+fn gauge(families: &[MetricFamily], name: &str, labels: &[&str], device: &Device) -> Option<f64> {
+    series(families, name, labels, device)
+        .next()
+        .map(|metric| metric.get_gauge().get_value())
+}
+
+/// Summary of `devices` from the collected `families`, whose device metrics
+/// are identified by the identity labels `labels`. Only the processes whose
+/// `user` label is `user` are listed, if given. Cgroups and pods are
+/// pseudonymized with `privacy`, if given, like the owners in the metrics.
+pub fn stats(
+    devices: &[Device],
+    families: &[MetricFamily],
+    labels: &[&str],
+    user: Option<&str>,
+    privacy: Option<&Privacy>,
+) -> Vec<DeviceStat> {
+    let mib = |bytes: f64| bytes as u64 / 1024 / 1024;
+
+    devices
+        .iter()
+        .map(|device| {
+            let processes = series(families, "process_memory_used_bytes", labels, device)
+                // Processes in other PID namespaces cannot be resolved
+                .filter(|metric| !label(metric, "user").is_empty())
+                .filter(|metric| user.map_or(true, |user| label(metric, "user") == user))
+                .map(|metric| {
+                    let pid = label(metric, "pid");
+                    let mut cgroup = pid.parse().ok().and_then(procinfo::cgroup);
+                    let mut pod = pid.parse().ok().and_then(procinfo::pod_name);
+                    if let Some(privacy) = privacy {
+                        cgroup = cgroup.map(|cgroup| privacy.cgroup(&cgroup));
+                        pod = pod.map(|pod| privacy.pod(&pod));
+                    }
+                    ProcessStat {
+                        pid: pid.to_string(),
+                        user: label(metric, "user").to_string(),
+                        command: label(metric, "command").to_string(),
+                        used_memory_mib: mib(metric.get_gauge().get_value()),
+                        cgroup: cgroup.unwrap_or_default(),
+                        pod: pod.unwrap_or_default(),
+                    }
+                })
+                .collect();
+
+            DeviceStat {
+                index: device.info.index,
+                name: device.info.name.clone(),
+                uuid: device.info.uuid.clone(),
+                temperature: gauge(families, "temperature_celsius", labels, device)
+                    .map(|celsius| celsius as u32),
+                utilization: gauge(families, "gpu_utilization", labels, device)
+                    .map(|percent| percent as u32),
+                memory_used_mib: gauge(families, "memory_used_bytes", labels, device).map(mib),
+                memory_total_mib: gauge(families, "memory_total_bytes", labels, device).map(mib),
+                processes,
+            }
+        })
+        .collect()
+}
+
+/// Formats `value`, or `?` if it is unknown.
+fn or_unknown<T: ToString>(value: Option<T>) -> String {
+    value.map_or_else(|| "?".to_string(), |value| value.to_string())
+}
+
+/// Renders `stats` with one line per device.
+pub fn render(stats: &[DeviceStat]) -> String {
+    let lines: Vec<String> = stats
+        .iter()
+        .map(|stat| {
+            let processes: Vec<String> = stat
+                .processes
+                .iter()
+                .map(|process| {
+                    format!(
+                        "{}:{}/{}({} MiB)",
+                        process.user, process.command, process.pid, process.used_memory_mib
+                    )
+                })
+                .collect();
+
+            format!(
+                "[{}] {}|{}|{:>3}°C {:>3}%| {:>6} / {:<6} MiB | {}",
+                stat.index,
+                stat.name,
+                stat.uuid,
+                or_unknown(stat.temperature),
+                or_unknown(stat.utilization),
+                or_unknown(stat.memory_used_mib),
+                or_unknown(stat.memory_total_mib),
+                processes.join(" ")
+            )
+        })
+        .collect();
+
+    lines.join("\n") + "\n"
+}
+
+/// Layout of the summary given by the user instead of the built-in one, as a
+/// Handlebars template rendered with the `devices`.
+pub struct Template {
+    registry: Handlebars<'static>,
+}
+
+impl Template {
+    pub fn new(source: &str) -> std::result::Result<Template, TemplateError> {
+        let mut registry = Handlebars::new();
+        // The summary is plain text rather than HTML
+        registry.register_escape_fn(handlebars::no_escape);
+        registry.register_template_string("gpustat", source)?;
+        Ok(Template { registry })
+    }
+
+    /// Renders `stats` with the template.
+    pub fn render(&self, stats: &[DeviceStat]) -> Result<String> {
+        self.registry
+            .render("gpustat", &serde_json::json!({ "devices": stats }))
+            .map_err(|e| CollectingError::Template(e.to_string()))
+    }
+}
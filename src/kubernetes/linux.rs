@@ -0,0 +1,82 @@
+use std::io;
+use std::path::{Path, PathBuf};
+
+use hyper::body::{self, Buf};
+use hyper::header::CONTENT_TYPE;
+use hyper::{Body, Client, Request};
+use hyperlocal::UnixConnector;
+
+use super::{decode, invalid, Allocation};
+use crate::config::KubernetesConfig;
+use crate::error::Result;
+
+/// Calls `List` of the pod-resources API version `version` on `socket`.
+/// Returns `None` if the kubelet does not implement that version.
+async fn list(socket: &Path, version: &str) -> Result<Option<Vec<u8>>> {
+    let client = Client::builder()
+        .http2_only(true)
+        .build::<_, Body>(UnixConnector);
+    let uri: hyper::Uri =
+        hyperlocal::Uri::new(socket, &format!("/{}.PodResourcesLister/List", version)).into();
+
+    // An empty ListPodResourcesRequest, uncompressed
+    let request = Request::post(uri)
+        .header(CONTENT_TYPE, "application/grpc")
+        .header("te", "trailers")
+        .body(Body::from(vec![0u8; 5]))
+        .expect("Failed to build pod-resources request");
+
+    let response = client
+        .request(request)
+        .await
+        .map_err(|e| io::Error::new(io::ErrorKind::Other, e))?;
+    // Errors without a message are sent in the headers
+    match response.headers().get("grpc-status").map(|s| s.as_bytes()) {
+        None | Some(b"0") => {}
+        Some(b"12") => return Ok(None),
+        Some(status) => {
+            return Err(invalid(&format!(
+                "pod-resources API failed with gRPC status {}",
+                String::from_utf8_lossy(status)
+            ))
+            .into())
+        }
+    }
+
+    let mut body = body::aggregate(response.into_body())
+        .await
+        .map_err(|e| io::Error::new(io::ErrorKind::Other, e))?;
+    if body.remaining() < 5 {
+        return Err(invalid("empty pod-resources response").into());
+    }
+    let _compressed = body.get_u8();
+    let len = body.get_u32() as usize;
+    if body.remaining() < len {
+        return Err(invalid("truncated pod-resources response").into());
+    }
+    Ok(Some(body.to_bytes()[..len].to_vec()))
+}
+
+/// Reads the current allocations of devices whose resource name starts with
+/// `prefix` from the kubelet socket at `socket`.
+pub async fn allocations(socket: &Path, prefix: &str) -> Result<Vec<Allocation>> {
+    for version in &["v1", "v1alpha1"] {
+        if let Some(message) = list(socket, version).await? {
+            return Ok(decode(&message, prefix)?);
+        }
+    }
+    Err(invalid("the kubelet implements no known pod-resources API version").into())
+}
+
+/// Refreshes `target` with the allocations every `config.refresh_interval`.
+/// Never completes.
+pub async fn run<F: Fn(Vec<Allocation>)>(config: KubernetesConfig, target: F) {
+    let socket = PathBuf::from(&config.pod_resources_socket);
+    loop {
+        match allocations(&socket, &config.resource_prefix).await {
+            Ok(allocations) => target(allocations),
+            Err(e) => eprintln!("Could not read pod resources: {}", e),
+        }
+        tokio::time::delay_for(config.refresh_interval).await;
+    }
+}
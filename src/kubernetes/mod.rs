@@ -0,0 +1,153 @@
+//! Allocation of GPUs to Kubernetes pods, read from the kubelet's
+//! pod-resources API.
+//!
+//! The API is served over gRPC on a Unix socket, so reading it is only
+//! supported on Linux. Its messages are small, so they are decoded by hand
+//! instead of pulling in a protobuf toolchain.
+
+use std::io;
+use std::sync::Mutex;
+
+#[cfg(target_os = "linux")]
+mod linux;
+
+#[cfg(target_os = "linux")]
+pub use self::linux::{allocations, run};
+
+/// A device allocated to a container of a pod.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Allocation {
+    /// Device ID reported by the device plugin, the UUID for NVIDIA GPUs.
+    pub device_id: String,
+    pub pod: String,
+    pub namespace: String,
+    pub container: String,
+}
+
+/// Latest allocations, `None` until they were read for the first time.
+#[derive(Default)]
+pub struct Allocations(Mutex<Option<Vec<Allocation>>>);
+
+impl Allocations {
+    pub fn set(&self, allocations: Vec<Allocation>) {
+        *self.0.lock().expect("Allocations poisoned") = Some(allocations);
+    }
+
+    /// Allocations of the device `device_id`, or `None` if allocations were
+    /// not read yet. With time-slicing, the device plugin allocates replicas
+    /// of a device, e.g. `GPU-8c1d2f3e-...::3`, which count for the device.
+    pub fn of(&self, device_id: &str) -> Option<Vec<Allocation>> {
+        let replica = format!("{}::", device_id);
+        self.0
+            .lock()
+            .expect("Allocations poisoned")
+            .as_ref()
+            .map(|allocations| {
+                allocations
+                    .iter()
+                    .filter(|a| a.device_id == device_id || a.device_id.starts_with(&replica))
+                    .cloned()
+                    .collect()
+            })
+    }
+}
+
+pub(crate) fn invalid(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+/// Reads a base 128 varint from the start of `buf`.
+fn varint(buf: &mut &[u8]) -> io::Result<u64> {
+    let mut value = 0;
+    for shift in (0..64).step_by(7) {
+        let (&byte, rest) = buf
+            .split_first()
+            .ok_or_else(|| invalid("truncated varint"))?;
+        *buf = rest;
+        value |= u64::from(byte & 0x7f) << shift;
+        if byte & 0x80 == 0 {
+            return Ok(value);
+        }
+    }
+    Err(invalid("varint too long"))
+}
+
+/// Fields of the protobuf message `buf` as field number and value. Only
+/// length-delimited values are returned, all others are skipped.
+fn fields(mut buf: &[u8]) -> io::Result<Vec<(u64, &[u8])>> {
+    let mut fields = Vec::new();
+    while !buf.is_empty() {
+        let key = varint(&mut buf)?;
+        match key & 0x7 {
+            0 => {
+                varint(&mut buf)?;
+            }
+            1 | 5 => {
+                let len = if key & 0x7 == 1 { 8 } else { 4 };
+                buf = buf.get(len..).ok_or_else(|| invalid("truncated field"))?;
+            }
+            2 => {
+                let len = varint(&mut buf)? as usize;
+                let value = buf.get(..len).ok_or_else(|| invalid("truncated field"))?;
+                fields.push((key >> 3, value));
+                buf = &buf[len..];
+            }
+            _ => return Err(invalid("unsupported wire type")),
+        }
+    }
+    Ok(fields)
+}
+
+fn string(value: &[u8]) -> String {
+    String::from_utf8_lossy(value).into_owned()
+}
+
+/// Decodes a `ListPodResourcesResponse`, keeping devices of resources whose
+/// name starts with `prefix`.
+pub fn decode(message: &[u8], prefix: &str) -> io::Result<Vec<Allocation>> {
+    let mut allocations = Vec::new();
+
+    // ListPodResourcesResponse.pod_resources
+    for (_, pod) in fields(message)?.into_iter().filter(|(n, _)| *n == 1) {
+        let pod = fields(pod)?;
+        let field = |number| {
+            pod.iter()
+                .find(|(n, _)| *n == number)
+                .map(|(_, v)| string(v))
+        };
+        let (name, namespace) = (field(1).unwrap_or_default(), field(2).unwrap_or_default());
+
+        // PodResources.containers
+        for (_, container) in pod.iter().filter(|(n, _)| *n == 3) {
+            let container = fields(container)?;
+            let container_name = container
+                .iter()
+                .find(|(n, _)| *n == 1)
+                .map_or_else(String::new, |(_, v)| string(v));
+
+            // ContainerResources.devices
+            for (_, devices) in container.iter().filter(|(n, _)| *n == 2) {
+                let devices = fields(devices)?;
+                let resource_name = devices
+                    .iter()
+                    .find(|(n, _)| *n == 1)
+                    .map_or_else(String::new, |(_, v)| string(v));
+                if !resource_name.starts_with(prefix) {
+                    continue;
+                }
+
+                // ContainerDevices.device_ids
+                for (_, device_id) in devices.iter().filter(|(n, _)| *n == 2) {
+                    allocations.push(Allocation {
+                        device_id: string(device_id),
+                        pod: name.clone(),
+                        namespace: namespace.clone(),
+                        container: container_name.clone(),
+                    });
+                }
+            }
+        }
+    }
+
+    Ok(allocations)
+}
@@ -0,0 +1,61 @@
+//! Prometheus metrics for NVIDIA GPUs, collected through NVML.
+//!
+//! The [`GpuCollector`] can be registered into any [`prometheus::Registry`],
+//! which allows embedding GPU metrics into an existing application:
+//!
+//! ```no_run
+//! use prometheus::Registry;
+//! use prometheus_nvidia_gpu::GpuCollector;
+//!
+//! let registry = Registry::new();
+//! let collector = GpuCollector::new().expect("Could not initialize NVML");
+//! registry.register(Box::new(collector)).unwrap();
+//! ```
+
+extern crate prometheus;
+
+#[macro_use]
+extern crate lazy_static;
+
+extern crate nvml_wrapper;
+
+#[cfg(target_os = "linux")]
+extern crate procfs;
+
+#[cfg(target_os = "linux")]
+extern crate users;
+
+pub mod alerts;
+#[cfg(feature = "amd")]
+pub mod amd;
+pub mod backend;
+mod collector;
+pub mod collectors;
+pub mod config;
+#[cfg(target_os = "linux")]
+pub mod daemon;
+pub mod dashboard;
+mod debug;
+pub mod driver;
+mod error;
+mod exemplars;
+mod gpustat;
+pub mod kubernetes;
+pub mod openmetrics;
+mod privacy;
+#[cfg(target_os = "linux")]
+pub mod privileges;
+mod procinfo;
+pub mod proxy;
+mod samples;
+pub mod server;
+mod state;
+pub mod webhooks;
+mod xids;
+
+pub use crate::collector::GpuCollector;
+pub use crate::config::Config;
+pub use crate::error::{CollectingError, Result};
+
+/// Namespace prefixed to all GPU metric names.
+pub const NAMESPACE: &str = "nvidia_gpu";
@@ -1,390 +1,595 @@
 #![feature(async_closure)]
 
-extern crate prometheus;
-
-extern crate nvml_wrapper;
-
-extern crate procfs;
+extern crate prometheus_nvidia_gpu;
+
+use std::io::{self, Write};
+use std::net::{Ipv4Addr, Ipv6Addr, SocketAddr};
+use std::path::{Path, PathBuf};
+use std::process;
+use std::time::Duration;
+#[cfg(target_os = "linux")]
+use std::{env, fs};
+
+use hyper::{Client, Uri};
+use structopt::StructOpt;
+
+use prometheus_nvidia_gpu::backend::{FakeBackend, GpuBackend, NvmlBackend, TegraBackend};
+use prometheus_nvidia_gpu::config::DeprecatedMetrics;
+use prometheus_nvidia_gpu::server::{self, Exporter};
+#[cfg(target_os = "linux")]
+use prometheus_nvidia_gpu::{daemon, kubernetes, privileges};
+use prometheus_nvidia_gpu::{driver, webhooks};
+use prometheus_nvidia_gpu::{CollectingError, Config, GpuCollector, Result};
+
+/// Prometheus exporter for NVIDIA GPU metrics.
+#[derive(Debug, StructOpt)]
+struct Opt {
+    /// Address to listen on for HTTP requests
+    #[structopt(long, default_value = "0.0.0.0:9898")]
+    listen_address: SocketAddr,
+
+    /// Address to serve health, readiness and lifecycle endpoints on instead
+    /// of the listen address, e.g. to keep them on localhost
+    #[structopt(long)]
+    admin_address: Option<SocketAddr>,
+
+    /// Path to the TOML configuration file
+    #[structopt(long, parse(from_os_str))]
+    config: Option<PathBuf>,
+
+    /// Source of GPU readings; `auto` falls back to sysfs on Jetson boards
+    /// without NVML
+    #[structopt(long, default_value = "auto", possible_values = &["auto", "nvml", "tegra"])]
+    backend: String,
+
+    /// Serve synthetic metrics of this many simulated GPUs instead of reading
+    /// real ones, e.g. to develop dashboards
+    #[structopt(long)]
+    fake_gpus: Option<u32>,
+
+    /// Path of the NVML library, e.g.
+    /// /usr/lib/x86_64-linux-gnu/libnvidia-ml.so.1
+    #[structopt(long, env = "NVIDIA_GPU_EXPORTER_NVML_PATH", parse(from_os_str))]
+    nvml_path: Option<PathBuf>,
+
+    /// Number of GPUs expected to be visible; the exporter is not ready while
+    /// fewer are, e.g. after a GPU fell off the bus
+    #[structopt(long)]
+    expect_devices: Option<u32>,
+
+    /// Whether renamed metrics are also exported under their old names,
+    /// overriding deprecated_metrics of the configuration file
+    #[structopt(long, possible_values = &["include", "exclude"])]
+    deprecated_metrics: Option<String>,
+
+    /// Enable persistence mode on the GPUs at startup, which requires root
+    #[structopt(long)]
+    set_persistence_mode: bool,
+
+    /// Indices or UUIDs of the GPUs to enable persistence mode on, instead of
+    /// all GPUs
+    #[structopt(long, use_delimiter = true)]
+    persistence_mode_devices: Vec<String>,
+
+    /// User, or user:group, to switch to after initializing NVML and binding
+    /// the listeners when started as root
+    #[cfg(target_os = "linux")]
+    #[structopt(long)]
+    run_as: Option<String>,
+
+    /// Detach from the terminal and run in the background, for init systems
+    /// without process supervision
+    #[cfg(target_os = "linux")]
+    #[structopt(long)]
+    daemonize: bool,
+
+    /// File to write the PID of the daemon to
+    #[cfg(target_os = "linux")]
+    #[structopt(long, parse(from_os_str), requires = "daemonize")]
+    pid_file: Option<PathBuf>,
+
+    /// File to append the output of the daemon to, instead of discarding it
+    #[cfg(target_os = "linux")]
+    #[structopt(long, parse(from_os_str))]
+    log_file: Option<PathBuf>,
+
+    /// Working directory before daemonizing, to resolve relative paths of the
+    /// configuration file against
+    #[cfg(target_os = "linux")]
+    #[structopt(skip)]
+    working_dir: Option<PathBuf>,
+
+    /// OTLP endpoint to export spans of requests, collections and NVML
+    /// calls to, e.g. http://localhost:4317
+    #[cfg(feature = "otlp")]
+    #[structopt(long)]
+    otlp_endpoint: Option<String>,
+
+    #[structopt(subcommand)]
+    command: Option<Command>,
+}
 
-extern crate users;
+#[derive(Debug, StructOpt)]
+enum Command {
+    /// Validate a configuration file and exit
+    CheckConfig {
+        #[structopt(parse(from_os_str))]
+        file: PathBuf,
+    },
+    /// Check whether the exporter on the listen address (or the admin
+    /// address, if given) is ready, exiting with a non-zero code otherwise
+    Healthcheck {
+        /// Probe NVML directly instead of asking the running exporter
+        #[structopt(long)]
+        nvml: bool,
+    },
+    /// Show a continuously refreshing view of the GPUs and their processes,
+    /// taken from the exporter on the listen address
+    Top {
+        /// Collect from the GPUs directly instead of asking the running exporter
+        #[structopt(long)]
+        local: bool,
+        /// URL of the exporter to ask instead, e.g. http://node1:9898
+        #[structopt(long, conflicts_with = "local")]
+        url: Option<String>,
+        /// Seconds between refreshes
+        #[structopt(long, default_value = "2")]
+        interval: u64,
+    },
+}
 
-use nvml_wrapper::enum_wrappers::device::{Clock, TemperatureSensor};
-use nvml_wrapper::enums::device::UsedGpuMemory::Used;
-use nvml_wrapper::NVML;
+/// Validates the configuration file at `file`, exiting with a non-zero code if
+/// it is invalid.
+fn check_config(file: &Path) -> ! {
+    match Config::from_file(file) {
+        Ok(_) => {
+            println!("{}: OK", file.display());
+            process::exit(0);
+        }
+        Err(e) => {
+            eprintln!("{}: {}", file.display(), e);
+            process::exit(1);
+        }
+    }
+}
 
-use hyper::header::CONTENT_TYPE;
-use hyper::service::{make_service_fn, service_fn};
-use hyper::{Body, Error, Method, Response, Server, StatusCode};
+/// Address to reach a listener bound to `addr` at from the same host.
+fn local_address(addr: &SocketAddr) -> SocketAddr {
+    let mut addr = *addr;
+    if addr.ip().is_unspecified() {
+        addr.set_ip(match addr {
+            SocketAddr::V4(_) => Ipv4Addr::LOCALHOST.into(),
+            SocketAddr::V6(_) => Ipv6Addr::LOCALHOST.into(),
+        });
+    }
+    addr
+}
 
-use prometheus::{Encoder, IntGauge, IntGaugeVec, Opts, Registry, TextEncoder};
+/// Checks the readiness of the running exporter, or of the devices if `nvml`
+/// is set, exiting with a non-zero code if it is not ready.
+async fn healthcheck(opt: &Opt, nvml: bool) -> ! {
+    if nvml {
+        match backend(&opt.backend, opt.nvml_path.as_deref()).and_then(|b| b.device_count()) {
+            Ok(count) if count < opt.expect_devices.unwrap_or(0) => {
+                eprintln!(
+                    "Not ready: {} of {} expected devices",
+                    count,
+                    opt.expect_devices.unwrap_or(0)
+                );
+                process::exit(1);
+            }
+            Ok(count) => {
+                println!("OK: {} devices", count);
+                process::exit(0);
+            }
+            Err(e) => {
+                eprintln!("{}", e);
+                process::exit(1);
+            }
+        }
+    }
 
-const NAMESPACE: &str = "nvidia_gpu";
-const LABELS: [&'static str; 3] = ["minor_number", "uuid", "name"];
-const PROCESS_LABELS: [&'static str; 6] =
-    ["minor_number", "uuid", "name", "pid", "user", "command"];
+    let addr = local_address(opt.admin_address.as_ref().unwrap_or(&opt.listen_address));
+    let uri: Uri = format!("http://{}/readyz", addr)
+        .parse()
+        .expect("Invalid readiness URL");
+    let response = tokio::time::timeout(Duration::from_secs(5), Client::new().get(uri)).await;
+    match response {
+        Ok(Ok(response)) if response.status().is_success() => {
+            println!("OK");
+            process::exit(0);
+        }
+        Ok(Ok(response)) => eprintln!("Not ready: {}", response.status()),
+        Ok(Err(e)) => eprintln!("Could not reach http://{}: {}", addr, e),
+        Err(_) => eprintln!("Timed out waiting for http://{}", addr),
+    }
+    process::exit(1);
+}
 
-// TODO: https://lh3.googleusercontent.com/1GLnuV66rZqTmWQJ1QXW6f8yz1rCLJ9tIzq4RgsEA_qhBOq72KJCBgXeLdc0EXWePx9E-stlEZPShJXeh2WEOtVx-iAOv38cJiApQRn9iA0uqmTnc5vINK2me1vGBxmz-IiCarlN
+/// Clears the terminal and moves the cursor to the top left.
+const CLEAR_SCREEN: &str = "\x1b[2J\x1b[H";
+
+/// Fetches the gpustat summary from the exporter at `url`.
+async fn fetch_gpustat(url: &str) -> std::result::Result<String, String> {
+    let uri: Uri = url
+        .parse()
+        .map_err(|e| format!("Invalid URL {}: {}", url, e))?;
+    let response = match tokio::time::timeout(Duration::from_secs(5), Client::new().get(uri)).await
+    {
+        Ok(Ok(response)) => response,
+        Ok(Err(e)) => return Err(format!("Could not reach {}: {}", url, e)),
+        Err(_) => return Err(format!("Timed out waiting for {}", url)),
+    };
+    let status = response.status();
+    let body = hyper::body::to_bytes(response.into_body())
+        .await
+        .map_err(|e| format!("Could not read the response of {}: {}", url, e))?;
+    let body = String::from_utf8_lossy(&body).into_owned();
+    if status.is_success() {
+        Ok(body)
+    } else {
+        Err(format!("{} answered with {}: {}", url, status, body))
+    }
+}
 
-// Error types
+/// Shows the gpustat summary of the running exporter, or of a local
+/// collection if `local` is set, every `interval` seconds until interrupted.
+async fn top(opt: &Opt, local: bool, url: Option<&str>, interval: u64) -> ! {
+    let collector = if local {
+        let mut config = config(opt);
+        // Every refresh shows current readings rather than those of a scrape
+        config.gpustat.max_age = Duration::from_secs(0);
+        match gpus(opt).and_then(|backend| GpuCollector::with_config(backend, &config)) {
+            Ok(collector) => Some(collector),
+            Err(e) => {
+                eprintln!("Could not access the GPUs: {}", e);
+                process::exit(1);
+            }
+        }
+    } else {
+        None
+    };
+    let url = match url {
+        Some(url) => format!("{}/gpustat", url.trim_end_matches('/')),
+        None => format!("http://{}/gpustat", local_address(&opt.listen_address)),
+    };
+
+    loop {
+        let (source, summary) = match &collector {
+            Some(collector) => ("local GPUs", collector.process().map_err(|e| e.to_string())),
+            None => (url.as_str(), fetch_gpustat(&url).await),
+        };
 
-type Result<T> = std::result::Result<T, CollectingError>;
+        let mut stdout = io::stdout();
+        let _ = write!(
+            stdout,
+            "{}Every {}s: {}\n\n",
+            CLEAR_SCREEN, interval, source
+        );
+        let _ = match summary {
+            Ok(summary) => write!(stdout, "{}", summary),
+            Err(e) => writeln!(stdout, "{}", e),
+        };
+        let _ = stdout.flush();
 
-#[derive(Debug)]
-enum CollectingError {
-    Nvml(nvml_wrapper::error::NvmlError),
-    Prometheus(prometheus::Error),
+        tokio::time::delay_for(Duration::from_secs(interval)).await;
+    }
 }
 
-impl From<nvml_wrapper::error::NvmlError> for CollectingError {
-    fn from(err: nvml_wrapper::error::NvmlError) -> CollectingError {
-        CollectingError::Nvml(err)
+/// The configuration file given with `--config`, exiting if it is invalid,
+/// with the overrides of the command line applied.
+fn config(opt: &Opt) -> Config {
+    let mut config = match &opt.config {
+        Some(path) => Config::from_file(path).unwrap_or_else(|e| {
+            eprintln!("{}", e);
+            process::exit(1);
+        }),
+        None => Config::default(),
+    };
+    #[cfg(target_os = "linux")]
+    if let (Some(dir), Some(path)) = (&opt.working_dir, &mut config.state.path) {
+        *path = dir.join(&*path).to_string_lossy().into_owned();
     }
+    match opt.deprecated_metrics.as_deref() {
+        Some("include") => config.deprecated_metrics = DeprecatedMetrics::Include,
+        Some("exclude") => config.deprecated_metrics = DeprecatedMetrics::Exclude,
+        _ => {}
+    }
+    config
 }
 
-impl From<prometheus::Error> for CollectingError {
-    fn from(err: prometheus::Error) -> CollectingError {
-        CollectingError::Prometheus(err)
+/// The backend to read the GPUs through, simulated ones with `--fake-gpus`.
+fn gpus(opt: &Opt) -> Result<Box<dyn GpuBackend>> {
+    match opt.fake_gpus {
+        Some(count) => Ok(Box::new(FakeBackend::new(count))),
+        None => backend(&opt.backend, opt.nvml_path.as_deref()),
     }
 }
 
-struct Collector {
-    nvml: NVML,
-    registry: Registry,
-    num_devices_gauge: IntGauge,
-    gpu_utilization_gauge: IntGaugeVec,
-    memory_utilization_gauge: IntGaugeVec,
-    power_usage_gauge: IntGaugeVec,
-    power_limit_gauge: IntGaugeVec,
-    clock_speed_graphics_gauge: IntGaugeVec,
-    clock_speed_sm_gauge: IntGaugeVec,
-    temperature_gauge: IntGaugeVec,
-    fan_speed_gauge: IntGaugeVec,
-    total_memory_gauge: IntGaugeVec,
-    free_memory_gauge: IntGaugeVec,
-    used_memory_gauge: IntGaugeVec,
+fn tegra() -> Option<Box<dyn GpuBackend>> {
+    TegraBackend::detect().map(|backend| Box::new(backend) as Box<dyn GpuBackend>)
 }
 
-impl Collector {
-    fn new() -> Result<Collector> {
-        let nvml = NVML::init()?;
+fn nvml(path: Option<&Path>) -> Result<NvmlBackend> {
+    match path {
+        Some(path) => NvmlBackend::with_library_path(path),
+        None => NvmlBackend::new(),
+    }
+}
 
-        let registry = Registry::new_custom(Some(NAMESPACE.to_string()), None)?;
+fn backend(kind: &str, nvml_path: Option<&Path>) -> Result<Box<dyn GpuBackend>> {
+    match kind {
+        "nvml" => Ok(Box::new(nvml(nvml_path)?)),
+        "tegra" => tegra().ok_or(CollectingError::NotFound),
+        _ => match nvml(nvml_path) {
+            Ok(backend) => Ok(Box::new(backend)),
+            Err(e) => tegra().ok_or(e),
+        },
+    }
+}
 
-        // Num devices
-        let num_devices_opts = Opts::new("num_devices", "Number of GPU devices");
-        let num_devices_gauge = IntGauge::with_opts(num_devices_opts)?;
-        registry.register(Box::new(num_devices_gauge.clone()))?;
+fn enable_persistence_mode<B: GpuBackend>(collector: &GpuCollector<B>, devices: &[String]) {
+    match collector.enable_persistence_mode(devices) {
+        Ok(results) => {
+            for (info, result) in results {
+                match result {
+                    Ok(()) => println!("Enabled persistence mode on GPU {}", info.uuid),
+                    Err(e) => eprintln!(
+                        "Could not enable persistence mode on GPU {}: {}",
+                        info.uuid, e
+                    ),
+                }
+            }
+        }
+        Err(e) => eprintln!("Could not enable persistence mode: {}", e),
+    }
+}
 
-        // CPU utilization
-        let gpu_utilization_opts = Opts::new("gpu_utilization", "Percent of time over the past sample period during which one or more kernels were executing on the GPU device");
-        let gpu_utilization_gauge = IntGaugeVec::new(gpu_utilization_opts, &LABELS)?;
-        registry.register(Box::new(gpu_utilization_gauge.clone()))?;
+/// Switches to the `--run-as` user, exiting if that fails, as serving as root
+/// was not intended.
+#[cfg(target_os = "linux")]
+fn drop_privileges(opt: &Opt) {
+    if let Some(run_as) = &opt.run_as {
+        if let Err(e) = privileges::drop_to(run_as) {
+            eprintln!("Could not switch to {}: {}", run_as, e);
+            process::exit(1);
+        }
+    }
+}
 
-        // Memory utilization
-        let memory_utilization_opts = Opts::new("memory_utilization", "Percent of time over the past sample period during which global (device) memory was being read or written to.");
-        let memory_utilization_gauge = IntGaugeVec::new(memory_utilization_opts, &LABELS)?;
-        registry.register(Box::new(memory_utilization_gauge.clone()))?;
+#[cfg(not(target_os = "linux"))]
+fn drop_privileges(_opt: &Opt) {}
+
+/// Runs the rest of the process as a daemon, exiting if that fails. Paths
+/// given relative to the working directory are made absolute first, as the
+/// daemon runs in `/`, and the working directory is kept for those of the
+/// configuration file.
+#[cfg(target_os = "linux")]
+fn daemonize(opt: &mut Opt) {
+    let cwd = env::current_dir().unwrap_or_else(|_| PathBuf::from("/"));
+    for path in vec![&mut opt.config, &mut opt.nvml_path, &mut opt.pid_file] {
+        if let Some(path) = path {
+            *path = cwd.join(&*path);
+        }
+    }
+    opt.working_dir = Some(cwd);
 
-        // Power usage
-        let power_usage_opts = Opts::new(
-            "power_usage_milliwatts",
-            "Power usage of the GPU device in milliwatts",
-        );
-        let power_usage_gauge = IntGaugeVec::new(power_usage_opts, &LABELS)?;
-        registry.register(Box::new(power_usage_gauge.clone()))?;
+    if let Err(e) = daemon::daemonize(opt.pid_file.as_deref(), opt.log_file.as_deref()) {
+        eprintln!("Could not daemonize: {}", e);
+        process::exit(1);
+    }
+}
 
-        // Power limit
-        let power_limit_opts = Opts::new(
-            "power_limit_milliwatts",
-            "Power limit of the GPU device in milliwatts",
-        );
-        let power_limit_gauge = IntGaugeVec::new(power_limit_opts, &LABELS)?;
-        registry.register(Box::new(power_limit_gauge.clone()))?;
+/// Exports spans to the `--otlp-endpoint`, if given. Spans are only
+/// exported while the returned guard is alive.
+#[cfg(feature = "otlp")]
+fn init_tracing(opt: &Opt) -> Option<opentelemetry_otlp::Uninstall> {
+    use opentelemetry::sdk::{trace, Resource};
+    use opentelemetry::KeyValue;
+    use tracing_subscriber::layer::SubscriberExt;
+    use tracing_subscriber::util::SubscriberInitExt;
+
+    let endpoint = opt.otlp_endpoint.as_ref()?;
+    let resource = Resource::new(vec![KeyValue::new("service.name", "nvidia-gpu-exporter")]);
+    let installed = opentelemetry_otlp::new_pipeline()
+        .with_endpoint(endpoint)
+        .with_trace_config(trace::config().with_resource(resource))
+        .install();
+    let (tracer, uninstall) = match installed {
+        Ok(installed) => installed,
+        Err(e) => {
+            eprintln!("Could not export spans to {}: {}", endpoint, e);
+            return None;
+        }
+    };
 
-        // Clock speed graphics
-        let clock_speed_graphics_opts = Opts::new(
-            "clock_speed_graphics_hertz",
-            "Clock speed of the GPU in Hz",
-        );
-        let clock_speed_graphics_gauge = IntGaugeVec::new(clock_speed_graphics_opts, &LABELS)?;
-        registry.register(Box::new(clock_speed_graphics_gauge.clone()))?;
+    tracing_subscriber::registry()
+        .with(tracing_opentelemetry::layer().with_tracer(tracer))
+        .init();
+    Some(uninstall)
+}
 
-        // Clock speed streaming multiprocessor
-        let clock_speed_sm_opts = Opts::new(
-            "clock_speed_sm_hertz",
-            "Clock speed of the GPU streaming multiprocessor in Hz",
-        );
-        let clock_speed_sm_gauge = IntGaugeVec::new(clock_speed_sm_opts, &LABELS)?;
-        registry.register(Box::new(clock_speed_sm_gauge.clone()))?;
+#[cfg(feature = "amd")]
+fn register_amd<B: GpuBackend + 'static>(exporter: &Exporter<B>) {
+    use prometheus_nvidia_gpu::amd::AmdGpuCollector;
 
-        // Temperature
-        let temperature_opts = Opts::new(
-            "temperature_celsius",
-            "Temperature of the GPU device in celsius",
-        );
-        let temperature_gauge = IntGaugeVec::new(temperature_opts, &LABELS)?;
-        registry.register(Box::new(temperature_gauge.clone()))?;
+    match AmdGpuCollector::new() {
+        Ok(collector) if collector.has_devices() => {
+            if let Err(e) = exporter.register(Box::new(collector)) {
+                eprintln!("Could not register AMD collector: {}", e);
+            }
+        }
+        Ok(_) => {}
+        Err(e) => eprintln!("Could not create AMD collector: {}", e),
+    }
+}
 
-        // Fan speed
-        let fan_speed_opts = Opts::new(
-            "fanspeed_percent",
-            "Fan speed of the GPU device as a percent of its maximum",
-        );
-        let fan_speed_gauge = IntGaugeVec::new(fan_speed_opts, &LABELS)?;
-        registry.register(Box::new(fan_speed_gauge.clone()))?;
+fn main() {
+    #[allow(unused_mut)]
+    let mut opt = Opt::from_args();
 
-        // Total memory
-        let total_memory_opts = Opts::new(
-            "memory_total_bytes",
-            "Total memory available by the GPU device in bytes",
-        );
-        let total_memory_gauge = IntGaugeVec::new(total_memory_opts, &LABELS)?;
-        registry.register(Box::new(total_memory_gauge.clone()))?;
+    // Only the calling thread survives the fork, so this has to happen before
+    // the runtime starts its threads
+    #[cfg(target_os = "linux")]
+    if opt.daemonize && opt.command.is_none() {
+        daemonize(&mut opt);
+    }
 
-        // Free memory
-        let free_memory_opts = Opts::new(
-            "memory_free_bytes",
-            "Free memory of the GPU device in bytes",
-        );
-        let free_memory_gauge = IntGaugeVec::new(free_memory_opts, &LABELS)?;
-        registry.register(Box::new(free_memory_gauge.clone()))?;
+    tokio::runtime::Runtime::new()
+        .expect("Could not start the runtime")
+        .block_on(run(opt));
+}
 
-        // Used memory
-        let used_memory_opts = Opts::new(
-            "memory_used_bytes",
-            "Memory used by the GPU device in bytes",
-        );
-        let used_memory_gauge = IntGaugeVec::new(used_memory_opts, &LABELS)?;
-        registry.register(Box::new(used_memory_gauge.clone()))?;
+async fn run(opt: Opt) {
+    match &opt.command {
+        Some(Command::CheckConfig { file }) => check_config(file),
+        Some(Command::Healthcheck { nvml }) => healthcheck(&opt, *nvml).await,
+        Some(Command::Top {
+            local,
+            url,
+            interval,
+        }) => top(&opt, *local, url.as_deref(), *interval).await,
+        None => {}
+    }
 
-        // Running processes
-        let process_memory_used_opts = Opts::new(
-            "process_memory_used_bytes",
-            "Memory used by the process in bytes",
-        );
-        let process_memory_used_gauge =
-            IntGaugeVec::new(process_memory_used_opts, &PROCESS_LABELS)?;
-        registry.register(Box::new(process_memory_used_gauge.clone()))?;
-
-        // Process
-        let collector = Collector {
-            nvml,
-            registry,
-            num_devices_gauge,
-            gpu_utilization_gauge,
-            memory_utilization_gauge,
-            power_usage_gauge,
-            power_limit_gauge,
-            clock_speed_graphics_gauge,
-            clock_speed_sm_gauge,
-            temperature_gauge,
-            fan_speed_gauge,
-            total_memory_gauge,
-            free_memory_gauge,
-            used_memory_gauge,
-        };
+    #[cfg(feature = "otlp")]
+    let _tracing = init_tracing(&opt);
+
+    let config = config(&opt);
+
+    let collector = gpus(&opt).and_then(|backend| GpuCollector::with_config(backend, &config));
+    if let Err(e) = &collector {
+        eprintln!("Could not access the GPUs: {}", e);
+        // NVML fails to initialize after a partial driver upgrade
+        if let Some((driver, library)) = driver::versions() {
+            if driver != library {
+                eprintln!(
+                    "The kernel module {} does not match the NVML library {}, a reboot is required",
+                    driver, library
+                );
+            }
+        }
+    }
 
-        Ok(collector)
+    if let Some(count) = opt.expect_devices {
+        if let Ok(collector) = &collector {
+            collector.expect_devices(count);
+        }
     }
 
-    fn collect(&self) -> Result<()> {
-        let num_devices = self.nvml.device_count()?;
-        self.num_devices_gauge.set(num_devices.into());
+    if opt.set_persistence_mode {
+        if let Ok(collector) = &collector {
+            enable_persistence_mode(collector, &opt.persistence_mode_devices);
+        }
+    }
 
-        for device_num in 0..num_devices {
-            let device = self.nvml.device_by_index(device_num)?;
+    // Restored before anything is counted
+    if let Some(path) = &config.state.path {
+        if let Ok(collector) = &collector {
+            if let Err(e) = collector.restore_state(Path::new(path)) {
+                eprintln!("Could not restore state from {}: {}", path, e);
+            }
+            collector.spawn_state_saver(PathBuf::from(path), config.state.save_interval);
+        }
+    }
 
-            // Create labels
-            // This only exists on Linux, so we cheat for Windows
-            let minor_number = device.minor_number()?.to_string();
+    let sampled = ["utilization", "power", "temperature", "clocks"]
+        .iter()
+        .any(|name| config.collector(name).enabled);
+    if config.sampling.enabled && sampled {
+        if let Ok(collector) = &collector {
+            collector.spawn_sampler(config.sampling.interval);
+        }
+    }
 
-            let uuid = device.uuid()?;
-            let name = device.name()?;
-            let labels: [&str; 3] = [&minor_number, &uuid, &name];
+    if config.sampling.background_collection {
+        if let Ok(collector) = &collector {
+            collector.spawn_collectors(config.sampling.interval);
+        }
+    }
 
-            // Utilization
-            if let Ok(utilization) = device.utilization_rates() {
-                self.gpu_utilization_gauge
-                    .get_metric_with_label_values(&labels)?
-                    .set(utilization.gpu as i64);
-                self.memory_utilization_gauge
-                    .get_metric_with_label_values(&labels)?
-                    .set(utilization.memory as i64);
-            }
+    if config.collector("xid").enabled {
+        if let Ok(collector) = &collector {
+            collector.spawn_xid_watcher();
+        }
+    }
 
-            // Power usage
-            if let Ok(power_usage) = device.power_usage() {
-                self.power_usage_gauge
-                    .get_metric_with_label_values(&labels)?
-                    .set(power_usage as i64);
-            }
+    if config.watchdog.enabled {
+        if let Ok(collector) = &collector {
+            collector.spawn_watchdog(config.watchdog.clone());
+        }
+    }
 
-            // Power limit
-            if let Ok(power_limit) = device.power_management_limit() {
-                self.power_limit_gauge
-                    .get_metric_with_label_values(&labels)?
-                    .set(power_limit as i64);
-            }
+    #[cfg(target_os = "linux")]
+    if config.kubernetes.enabled {
+        if let Ok(collector) = &collector {
+            let collector = collector.clone();
+            tokio::spawn(kubernetes::run(
+                config.kubernetes.clone(),
+                move |allocations| collector.set_allocations(allocations),
+            ));
+        }
+    }
 
-            // Clock speed graphics
-            if let Ok(clock_speed_graphics) = device.clock_info(Clock::Graphics) {
-                self.clock_speed_graphics_gauge
-                    .get_metric_with_label_values(&labels)?
-                    .set(clock_speed_graphics as i64);
+    if !config.alerting.rules.is_empty() {
+        if let Ok(collector) = &collector {
+            if !config.alerting.webhooks.is_empty() {
+                let notifications = collector.subscribe_alerts();
+                tokio::spawn(webhooks::run(
+                    config.alerting.webhooks.clone(),
+                    notifications,
+                ));
             }
+            collector.spawn_alert_evaluation(config.alerting.evaluation_interval);
+        }
+    }
 
-            // Clock speed streaming multiprocessor
-            if let Ok(clock_speed_sm) = device.clock_info(Clock::SM) {
-                self.clock_speed_sm_gauge
-                    .get_metric_with_label_values(&labels)?
-                    .set(clock_speed_sm as i64);
-            }
+    let state = match (&config.state.path, &collector) {
+        (Some(path), Ok(collector)) => Some((path, collector.clone())),
+        _ => None,
+    };
+    let exporter = collector.and_then(Exporter::new);
 
-            // Temperature
-            if let Ok(temperature) = device.temperature(TemperatureSensor::Gpu) {
-                self.temperature_gauge
-                    .get_metric_with_label_values(&labels)?
-                    .set(temperature as i64);
-            }
+    #[cfg(feature = "amd")]
+    if let Ok(exporter) = &exporter {
+        register_amd(exporter);
+    }
 
-            // Fan speed
-            if let Ok(fan_speed) = device.fan_speed(0) {
-                self.fan_speed_gauge
-                    .get_metric_with_label_values(&labels)?
-                    .set(fan_speed as i64);
-            }
+    let result = match &opt.admin_address {
+        Some(admin_address) => {
+            let (addr, admin_addr, server) =
+                server::bind_with_admin(&opt.listen_address, admin_address, exporter, &config.web);
+            drop_privileges(&opt);
 
-            // Memory
-            if let Ok(memory_info) = device.memory_info() {
-                self.total_memory_gauge
-                    .get_metric_with_label_values(&labels)?
-                    .set(memory_info.total as i64);
-                self.free_memory_gauge
-                    .get_metric_with_label_values(&labels)?
-                    .set(memory_info.free as i64);
-                self.used_memory_gauge
-                    .get_metric_with_label_values(&labels)?
-                    .set(memory_info.used as i64);
-            }
+            println!("Listening on http://{}", addr);
+            println!("Admin endpoints on http://{}", admin_addr);
+            server.await
         }
+        None => {
+            let (addr, server) = server::bind(&opt.listen_address, exporter, &config.web);
+            drop_privileges(&opt);
 
-        Ok(())
-    }
-
-    fn process(&self) -> Result<String> {
-        let num_devices = self.nvml.device_count()?;
-
-        let mut lines = Vec::<String>::new();
-
-        for device_num in 0..num_devices {
-            let device = self.nvml.device_by_index(device_num)?;
-            let uuid = device.uuid()?;
-            let name = device.name()?;
-
-            let temperature = device
-                .temperature(TemperatureSensor::Gpu)
-                .expect("Temperature");
-            let gpu_usage = device.utilization_rates().expect("GPU").gpu;
-            let memory_info = device.memory_info().expect("Memory");
-
-            let mut pvec = Vec::<String>::new();
-            for process in device.running_compute_processes()? {
-                let pid = process.pid as i32;
-                if let Ok(proc) = procfs::process::Process::new(pid) {
-                    let cmd = &proc.cmdline().expect("cmd name not found")[0];
-                    let user_id = proc.owner;
-                    let owner = users::get_user_by_uid(user_id).expect("User not found");
-                    let mem = match process.used_gpu_memory {
-                        Used(x) => ((x / 1024 / 1024) as u64).to_string(),
-                        _ => "?".to_string()
-                    };
-
-                    let s = format!(
-                        "{}:{}/{}({} MiB)",
-                        owner.name().to_str().expect("Encoding error"),
-                        cmd,
-                        pid,
-                        mem,
-                    );
-                    pvec.push(s)
-                }
-            }
-
-            let line = format!(
-                "[{}] {}|{}|{:>3}°C {:>3}%| {:>6} / {:<6} MiB | {}",
-                device_num,
-                name,
-                uuid,
-                temperature,
-                gpu_usage,
-                (memory_info.used / 1024 / 1024) as u64,
-                (memory_info.total / 1024 / 1024) as u64,
-                pvec.join(" ")
-            );
-
-            lines.push(line);
+            println!("Listening on http://{}", addr);
+            server.await
         }
+    };
 
-        Ok(lines.join("\n") + "\n")
+    if let Err(e) = result {
+        eprintln!("server error: {}", e);
     }
-}
 
-#[tokio::main]
-async fn main() {
-    let addr = ([0, 0, 0, 0], 9898).into();
-
-    let make_service = make_service_fn(move |_| {
-        let collector = Collector::new();
-        let encoder = TextEncoder::new();
-
-        async move {
-            Ok::<_, Error>(service_fn(move |req| {
-                let response = if let Ok(c) = &collector {
-                    match (req.method(), req.uri().path()) {
-                        (&Method::GET, "/metrics") => {
-                            c.collect().expect("Error collecting");
-
-                            let mut buffer = Vec::<u8>::new();
-                            encoder
-                                .encode(&c.registry.gather(), &mut buffer)
-                                .expect("Encoding error");
-
-                            Response::builder()
-                                .status(200)
-                                .header(CONTENT_TYPE, encoder.format_type())
-                                .body(Body::from(buffer))
-                                .expect("Failed to build metrics response")
-                        }
-                        (&Method::GET, "/gpustat") => {
-                            let s = c.process().expect("Failed process query");
-                            Response::builder()
-                                .status(200)
-                                .header(CONTENT_TYPE, encoder.format_type())
-                                .body(Body::from(s))
-                                .expect("Failed to build gpustat response")
-                        }
-                        _ => Response::builder()
-                            .status(StatusCode::NOT_FOUND)
-                            .body(Body::from("Not found"))
-                            .expect("Failed to build 404 response"),
-                    }
-                } else {
-                    Response::builder()
-                        .status(StatusCode::INTERNAL_SERVER_ERROR)
-                        .body(Body::from("Could not get access to NVML"))
-                        .expect("Failed to build error response")
-                };
-
-                async move { Ok::<_, Error>(response) }
-            }))
+    if let Some((path, collector)) = state {
+        if let Err(e) = collector.save_state(Path::new(path)) {
+            eprintln!("Could not save state to {}: {}", path, e);
         }
-    });
-
-    let server = Server::bind(&addr).serve(make_service);
-
-    println!("Listening on http://{}", addr);
+    }
 
-    if let Err(e) = server.await {
-        eprintln!("server error: {}", e);
+    // Only the daemon wrote it, another instance may own it otherwise
+    #[cfg(target_os = "linux")]
+    if let Some(pid_file) = opt.pid_file.as_ref().filter(|_| opt.daemonize) {
+        // Fails after switching users if the directory is only writable by root
+        if let Err(e) = fs::remove_file(pid_file) {
+            eprintln!("Could not remove {}: {}", pid_file.display(), e);
+        }
     }
 }
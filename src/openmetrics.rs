@@ -0,0 +1,211 @@
+//! Encoding of metric families in the OpenMetrics text format, which unlike
+//! the Prometheus text format can carry exemplars.
+
+use std::fmt::Write;
+
+use prometheus::proto::{LabelPair, Metric, MetricFamily, MetricType};
+
+/// Content type of the OpenMetrics text format.
+pub const FORMAT_TYPE: &str = "application/openmetrics-text; version=1.0.0; charset=utf-8";
+
+/// A sample of a single process behind a series, attached to it as an
+/// exemplar.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Exemplar {
+    pub labels: Vec<(String, String)>,
+    pub value: f64,
+    /// Seconds since the Unix epoch.
+    pub timestamp: f64,
+}
+
+/// Whether a scraper accepting `accept`, the value of its `Accept` header,
+/// prefers OpenMetrics.
+pub fn accepted(accept: &str) -> bool {
+    accept.split(',').any(|media_type| {
+        media_type
+            .trim()
+            .starts_with("application/openmetrics-text")
+    })
+}
+
+fn escape(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
+
+fn float(value: f64) -> String {
+    if value == f64::INFINITY {
+        "+Inf".to_string()
+    } else if value == f64::NEG_INFINITY {
+        "-Inf".to_string()
+    } else if value.is_nan() {
+        "NaN".to_string()
+    } else {
+        value.to_string()
+    }
+}
+
+/// Writes one sample line, with `extra` appended to the labels of the metric.
+fn sample(
+    out: &mut String,
+    name: &str,
+    labels: &[LabelPair],
+    extra: Option<(&str, &str)>,
+    value: f64,
+    exemplar: Option<&Exemplar>,
+) {
+    let mut pairs: Vec<String> = labels
+        .iter()
+        .map(|l| format!("{}=\"{}\"", l.get_name(), escape(l.get_value())))
+        .collect();
+    if let Some((label, label_value)) = extra {
+        pairs.push(format!("{}=\"{}\"", label, escape(label_value)));
+    }
+
+    out.push_str(name);
+    if !pairs.is_empty() {
+        let _ = write!(out, "{{{}}}", pairs.join(","));
+    }
+    let _ = write!(out, " {}", float(value));
+    if let Some(exemplar) = exemplar {
+        let pairs: Vec<String> = exemplar
+            .labels
+            .iter()
+            .map(|(label, label_value)| format!("{}=\"{}\"", label, escape(label_value)))
+            .collect();
+        let _ = write!(
+            out,
+            " # {{{}}} {} {}",
+            pairs.join(","),
+            float(exemplar.value),
+            exemplar.timestamp
+        );
+    }
+    out.push('\n');
+}
+
+/// Encodes `families` in the OpenMetrics text format, attaching the exemplar
+/// `exemplar` returns for a series of a family.
+pub fn encode<F>(families: &[MetricFamily], exemplar: F) -> String
+where
+    F: Fn(&MetricFamily, &Metric) -> Option<Exemplar>,
+{
+    let mut out = String::new();
+    for family in families {
+        let name = family.get_name();
+        let (family_name, kind) = match family.get_field_type() {
+            // Counter samples are suffixed with _total, but their family is not
+            MetricType::COUNTER => (name.trim_end_matches("_total"), "counter"),
+            MetricType::GAUGE => (name, "gauge"),
+            MetricType::HISTOGRAM => (name, "histogram"),
+            MetricType::SUMMARY => (name, "summary"),
+            MetricType::UNTYPED => (name, "unknown"),
+        };
+        let _ = writeln!(out, "# HELP {} {}", family_name, escape(family.get_help()));
+        let _ = writeln!(out, "# TYPE {} {}", family_name, kind);
+
+        for metric in family.get_metric() {
+            let labels = metric.get_label();
+            let exemplar = exemplar(family, metric);
+            match family.get_field_type() {
+                MetricType::COUNTER => sample(
+                    &mut out,
+                    &format!("{}_total", family_name),
+                    labels,
+                    None,
+                    metric.get_counter().get_value(),
+                    exemplar.as_ref(),
+                ),
+                MetricType::GAUGE => sample(
+                    &mut out,
+                    name,
+                    labels,
+                    None,
+                    metric.get_gauge().get_value(),
+                    exemplar.as_ref(),
+                ),
+                MetricType::UNTYPED => sample(
+                    &mut out,
+                    name,
+                    labels,
+                    None,
+                    metric.get_untyped().get_value(),
+                    None,
+                ),
+                MetricType::HISTOGRAM => {
+                    let histogram = metric.get_histogram();
+                    let bucket_name = format!("{}_bucket", name);
+                    for bucket in histogram.get_bucket() {
+                        let bound = float(bucket.get_upper_bound());
+                        sample(
+                            &mut out,
+                            &bucket_name,
+                            labels,
+                            Some(("le", &bound)),
+                            bucket.get_cumulative_count() as f64,
+                            None,
+                        );
+                    }
+                    sample(
+                        &mut out,
+                        &bucket_name,
+                        labels,
+                        Some(("le", "+Inf")),
+                        histogram.get_sample_count() as f64,
+                        None,
+                    );
+                    sample(
+                        &mut out,
+                        &format!("{}_count", name),
+                        labels,
+                        None,
+                        histogram.get_sample_count() as f64,
+                        None,
+                    );
+                    sample(
+                        &mut out,
+                        &format!("{}_sum", name),
+                        labels,
+                        None,
+                        histogram.get_sample_sum(),
+                        None,
+                    );
+                }
+                MetricType::SUMMARY => {
+                    let summary = metric.get_summary();
+                    for quantile in summary.get_quantile() {
+                        let q = float(quantile.get_quantile());
+                        sample(
+                            &mut out,
+                            name,
+                            labels,
+                            Some(("quantile", &q)),
+                            quantile.get_value(),
+                            None,
+                        );
+                    }
+                    sample(
+                        &mut out,
+                        &format!("{}_count", name),
+                        labels,
+                        None,
+                        summary.get_sample_count() as f64,
+                        None,
+                    );
+                    sample(
+                        &mut out,
+                        &format!("{}_sum", name),
+                        labels,
+                        None,
+                        summary.get_sample_sum(),
+                        None,
+                    );
+                }
+            }
+        }
+    }
+    out.push_str("# EOF\n");
+    out
+}
@@ -0,0 +1,105 @@
+//! Pseudonymization of the owners, commands and cgroups of processes, for
+//! clusters where user names must not end up in the metrics system.
+
+use std::path::Path;
+
+use sha2::{Digest, Sha256};
+
+use crate::procinfo::{self, ProcessDetails};
+
+/// Number of bytes of the hash kept in a pseudonym, enough to tell the users
+/// of a cluster apart.
+const PSEUDONYM_BYTES: usize = 8;
+
+/// Prefixes and suffixes of cgroup path components naming a user by UID, e.g.
+/// `user-1000.slice` of systemd or `uid_1000` of Slurm.
+const UID_COMPONENTS: [(&str, &str); 3] =
+    [("user-", ".slice"), ("user@", ".service"), ("uid_", "")];
+
+/// Replaces user names by stable pseudonyms and commands by the name of
+/// their executable.
+#[derive(Clone, Debug)]
+pub struct Privacy {
+    salt: String,
+}
+
+impl Privacy {
+    pub fn new(salt: &str) -> Privacy {
+        Privacy {
+            salt: salt.to_string(),
+        }
+    }
+
+    /// Hex encoded salted hash of `value`.
+    fn hash(&self, value: &str) -> String {
+        let digest = Sha256::new()
+            .chain(self.salt.as_bytes())
+            .chain(&[0u8])
+            .chain(value.as_bytes())
+            .finalize();
+        digest[..PSEUDONYM_BYTES]
+            .iter()
+            .map(|byte| format!("{:02x}", byte))
+            .collect()
+    }
+
+    /// Pseudonym of `user`, e.g. `u-3f9a0c1b2d4e5f60`, which is the same for
+    /// the same user and salt. Unknown users stay empty.
+    pub fn user(&self, user: &str) -> String {
+        if user.is_empty() {
+            return String::new();
+        }
+
+        format!("u-{}", self.hash(user))
+    }
+
+    /// `cgroup` with the UIDs in its components replaced by the pseudonym of
+    /// their user, e.g. `/user.slice/user-u-3f9a0c1b2d4e5f60.slice/session-3.scope`.
+    pub fn cgroup(&self, cgroup: &str) -> String {
+        let components: Vec<String> = cgroup
+            .split('/')
+            .map(|component| {
+                for (prefix, suffix) in &UID_COMPONENTS {
+                    let uid = component
+                        .strip_prefix(prefix)
+                        .and_then(|rest| rest.strip_suffix(suffix));
+                    if let Some(uid) = uid.and_then(|uid| uid.parse::<u32>().ok()) {
+                        // The same pseudonym as in the user label
+                        let user = procinfo::user_name(uid).unwrap_or_else(|| uid.to_string());
+                        return format!("{}{}{}", prefix, self.user(&user), suffix);
+                    }
+                }
+                component.to_string()
+            })
+            .collect();
+        components.join("/")
+    }
+
+    /// Pseudonym of the Kubernetes pod `pod`, e.g. `p-3f9a0c1b2d4e5f60`, as
+    /// pod names often contain the name of their user.
+    pub fn pod(&self, pod: &str) -> String {
+        if pod.is_empty() {
+            return String::new();
+        }
+
+        format!("p-{}", self.hash(pod))
+    }
+
+    /// Name of the executable of `command`, without its directory, which may
+    /// be in the home of the user, and without the arguments some processes
+    /// put into their name.
+    pub fn command(&self, command: &str) -> String {
+        command
+            .split_whitespace()
+            .next()
+            .and_then(|executable| Path::new(executable).file_name())
+            .map_or_else(String::new, |name| name.to_string_lossy().into_owned())
+    }
+
+    pub fn details(&self, details: ProcessDetails) -> ProcessDetails {
+        ProcessDetails {
+            user: self.user(&details.user),
+            command: self.command(&details.command),
+        }
+    }
+}
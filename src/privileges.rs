@@ -0,0 +1,41 @@
+//! Dropping root privileges once everything that needs them is set up.
+
+use std::io;
+
+fn not_found(message: String) -> io::Error {
+    io::Error::new(io::ErrorKind::NotFound, message)
+}
+
+/// Switches the process to `spec`, given as `user` or `user:group`. Without
+/// a group, the primary group of the user is used. Supplementary groups are
+/// dropped.
+pub fn drop_to(spec: &str) -> io::Result<()> {
+    let mut parts = spec.splitn(2, ':');
+    let user_name = parts.next().unwrap_or_default();
+    let user = users::get_user_by_name(user_name)
+        .ok_or_else(|| not_found(format!("unknown user '{}'", user_name)))?;
+    let gid = match parts.next() {
+        Some(group_name) => users::get_group_by_name(group_name)
+            .ok_or_else(|| not_found(format!("unknown group '{}'", group_name)))?
+            .gid(),
+        None => user.primary_group_id(),
+    };
+    let uid = user.uid();
+
+    // The groups have to be changed while the process is still root
+    unsafe {
+        if libc::setgroups(1, &gid) != 0 || libc::setgid(gid) != 0 || libc::setuid(uid) != 0 {
+            return Err(io::Error::last_os_error());
+        }
+    }
+
+    // Make sure the privileges cannot be regained
+    if uid != 0 && unsafe { libc::setuid(0) } == 0 {
+        return Err(io::Error::new(
+            io::ErrorKind::Other,
+            format!("privileges were not dropped to '{}'", spec),
+        ));
+    }
+
+    Ok(())
+}
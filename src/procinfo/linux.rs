@@ -0,0 +1,70 @@
+use std::collections::HashMap;
+use std::path::Path;
+use std::sync::Mutex;
+
+use crate::procinfo::ProcessDetails;
+
+/// Number of cached processes above which exited processes are evicted.
+const CACHE_CAPACITY: usize = 1024;
+
+lazy_static! {
+    /// Details of processes by pid, together with their start time to detect
+    /// reused pids.
+    static ref CACHE: Mutex<HashMap<u32, (u64, ProcessDetails)>> = Mutex::new(HashMap::new());
+}
+
+/// Looks up the owner and command of `pid` through procfs, if it is visible to
+/// the exporter. Details are cached until the pid belongs to another process.
+pub fn lookup(pid: u32) -> Option<ProcessDetails> {
+    let process = procfs::process::Process::new(pid as i32).ok()?;
+    let start_time = process.stat.starttime;
+
+    let mut cache = CACHE.lock().expect("Process cache poisoned");
+    if let Some((cached_start_time, details)) = cache.get(&pid) {
+        if *cached_start_time == start_time {
+            return Some(details.clone());
+        }
+    }
+
+    let command = process.cmdline().ok()?.into_iter().next()?;
+    let user = user_name(process.owner).unwrap_or_else(|| process.owner.to_string());
+    let details = ProcessDetails { user, command };
+
+    if cache.len() >= CACHE_CAPACITY {
+        cache.retain(|pid, _| Path::new("/proc").join(pid.to_string()).exists());
+    }
+    cache.insert(pid, (start_time, details.clone()));
+    Some(details)
+}
+
+/// Name of the user with the ID `uid`, if it is known.
+pub fn user_name(uid: u32) -> Option<String> {
+    users::get_user_by_uid(uid).map(|user| user.name().to_string_lossy().into_owned())
+}
+
+/// Looks up the cgroup path of `pid`, e.g. `/system.slice/jupyter.service`.
+/// The unified hierarchy is preferred over the systemd one of cgroup v1.
+pub fn cgroup(pid: u32) -> Option<String> {
+    let process = procfs::process::Process::new(pid as i32).ok()?;
+    let cgroups = process.cgroups().ok()?;
+    cgroups
+        .iter()
+        .find(|cgroup| cgroup.hierarchy == 0)
+        .or_else(|| {
+            cgroups
+                .iter()
+                .find(|cgroup| cgroup.controllers.iter().any(|c| c == "name=systemd"))
+        })
+        .map(|cgroup| cgroup.pathname.clone())
+}
+
+/// Name of the Kubernetes pod `pid` runs in, from the `HOSTNAME` the kubelet
+/// sets to the pod name. Reading the environment of other users' processes
+/// requires root.
+pub fn pod_name(pid: u32) -> Option<String> {
+    let process = procfs::process::Process::new(pid as i32).ok()?;
+    let environ = process.environ().ok()?;
+    environ
+        .get(std::ffi::OsStr::new("HOSTNAME"))
+        .map(|hostname| hostname.to_string_lossy().into_owned())
+}
@@ -0,0 +1,42 @@
+//! Lookup of host process details for processes running on GPUs.
+
+#[cfg(target_os = "linux")]
+mod linux;
+#[cfg(windows)]
+mod windows;
+
+#[cfg(target_os = "linux")]
+pub use self::linux::{cgroup, lookup, pod_name, user_name};
+#[cfg(windows)]
+pub use self::windows::lookup;
+
+/// Owner and command of a host process.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct ProcessDetails {
+    pub user: String,
+    pub command: String,
+}
+
+/// Process details are not available on this platform.
+#[cfg(not(any(target_os = "linux", windows)))]
+pub fn lookup(_pid: u32) -> Option<ProcessDetails> {
+    None
+}
+
+/// Control groups only exist on Linux.
+#[cfg(not(target_os = "linux"))]
+pub fn cgroup(_pid: u32) -> Option<String> {
+    None
+}
+
+/// User names are only resolved on Linux.
+#[cfg(not(target_os = "linux"))]
+pub fn user_name(_uid: u32) -> Option<String> {
+    None
+}
+
+/// Pods only exist on Linux.
+#[cfg(not(target_os = "linux"))]
+pub fn pod_name(_pid: u32) -> Option<String> {
+    None
+}
@@ -0,0 +1,97 @@
+use std::ffi::OsString;
+use std::os::windows::ffi::OsStringExt;
+use std::ptr;
+
+use winapi::shared::minwindef::{DWORD, FALSE, MAX_PATH};
+use winapi::um::handleapi::CloseHandle;
+use winapi::um::processthreadsapi::{OpenProcess, OpenProcessToken};
+use winapi::um::securitybaseapi::GetTokenInformation;
+use winapi::um::winbase::{LookupAccountSidW, QueryFullProcessImageNameW};
+use winapi::um::winnt::{
+    TokenUser, HANDLE, PROCESS_QUERY_LIMITED_INFORMATION, SID_NAME_USE, TOKEN_QUERY, TOKEN_USER,
+};
+
+use crate::procinfo::ProcessDetails;
+
+/// Handle that is closed when dropped.
+struct Handle(HANDLE);
+
+impl Drop for Handle {
+    fn drop(&mut self) {
+        unsafe {
+            CloseHandle(self.0);
+        }
+    }
+}
+
+/// Looks up the owner and executable of `pid` through the Win32 API, if the
+/// exporter is allowed to query it.
+pub fn lookup(pid: u32) -> Option<ProcessDetails> {
+    unsafe {
+        let process = OpenProcess(PROCESS_QUERY_LIMITED_INFORMATION, FALSE, pid);
+        if process.is_null() {
+            return None;
+        }
+        let process = Handle(process);
+
+        let command = image_name(&process)?;
+        let user = owner(&process).unwrap_or_default();
+
+        Some(ProcessDetails { user, command })
+    }
+}
+
+unsafe fn image_name(process: &Handle) -> Option<String> {
+    let mut buffer = [0u16; MAX_PATH];
+    let mut size = buffer.len() as DWORD;
+    if QueryFullProcessImageNameW(process.0, 0, buffer.as_mut_ptr(), &mut size) == 0 {
+        return None;
+    }
+
+    let path = OsString::from_wide(&buffer[..size as usize]);
+    Some(path.to_string_lossy().into_owned())
+}
+
+unsafe fn owner(process: &Handle) -> Option<String> {
+    let mut token = ptr::null_mut();
+    if OpenProcessToken(process.0, TOKEN_QUERY, &mut token) == 0 {
+        return None;
+    }
+    let token = Handle(token);
+
+    // The first call only determines the required buffer size
+    let mut size: DWORD = 0;
+    GetTokenInformation(token.0, TokenUser, ptr::null_mut(), 0, &mut size);
+    let mut buffer = vec![0u8; size as usize];
+    if GetTokenInformation(
+        token.0,
+        TokenUser,
+        buffer.as_mut_ptr() as *mut _,
+        size,
+        &mut size,
+    ) == 0
+    {
+        return None;
+    }
+    let token_user = &*(buffer.as_ptr() as *const TOKEN_USER);
+
+    let mut name = [0u16; 256];
+    let mut name_len = name.len() as DWORD;
+    let mut domain = [0u16; 256];
+    let mut domain_len = domain.len() as DWORD;
+    let mut sid_type: SID_NAME_USE = 0;
+    if LookupAccountSidW(
+        ptr::null(),
+        token_user.User.Sid,
+        name.as_mut_ptr(),
+        &mut name_len,
+        domain.as_mut_ptr(),
+        &mut domain_len,
+        &mut sid_type,
+    ) == 0
+    {
+        return None;
+    }
+
+    Some(String::from_utf16_lossy(&name[..name_len as usize]))
+}
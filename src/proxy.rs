@@ -0,0 +1,226 @@
+//! Aggregation of the metrics of other exporter instances, for labs without
+//! Prometheus federation that want a single URL showing all machines.
+
+use std::collections::HashMap;
+use std::time::Duration;
+
+use hyper::body::{self, Bytes};
+use hyper::client::HttpConnector;
+use hyper::{Client, Uri};
+use hyper_tls::HttpsConnector;
+
+use crate::config::ProxyTarget;
+use crate::NAMESPACE;
+
+/// Time after which a target that did not answer is considered down.
+const TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Header and samples of one metric family in the text format.
+#[derive(Default)]
+struct Family {
+    header: Vec<String>,
+    samples: Vec<String>,
+}
+
+/// Metric families merged from several expositions, in order of appearance.
+#[derive(Default)]
+pub struct Families {
+    order: Vec<String>,
+    families: HashMap<String, Family>,
+}
+
+impl Families {
+    fn family(&mut self, name: &str) -> &mut Family {
+        if !self.families.contains_key(name) {
+            self.order.push(name.to_string());
+        }
+        self.families.entry(name.to_string()).or_default()
+    }
+
+    /// Adds all samples of the text exposition `text` with the additional
+    /// label `node`. The first `# HELP` and `# TYPE` line of every family is
+    /// kept.
+    pub fn add(&mut self, text: &str, node: &str) {
+        let mut current: Option<String> = None;
+
+        for line in text.lines() {
+            if let Some(comment) = line.strip_prefix("# ") {
+                let mut parts = comment.splitn(3, ' ');
+                let (kind, name) = match (parts.next(), parts.next()) {
+                    (Some(kind @ "HELP"), Some(name)) | (Some(kind @ "TYPE"), Some(name)) => {
+                        (kind, name)
+                    }
+                    _ => continue,
+                };
+
+                let family = self.family(name);
+                if !family
+                    .header
+                    .iter()
+                    .any(|l| l.split(' ').nth(1) == Some(kind))
+                {
+                    family.header.push(line.to_string());
+                }
+                current = Some(name.to_string());
+                continue;
+            }
+            if line.trim().is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            // Samples of histograms and summaries carry suffixes
+            let name_end = line
+                .find(|c: char| c == '{' || c == ' ')
+                .unwrap_or(line.len());
+            let name = &line[..name_end];
+            let family = match &current {
+                Some(family) if name.starts_with(family.as_str()) => family.clone(),
+                _ => name.to_string(),
+            };
+            self.family(&family)
+                .samples
+                .push(relabel(line, name_end, node));
+        }
+    }
+
+    /// Renders all families in the text format.
+    pub fn render(&self) -> String {
+        let mut text = String::new();
+        for name in &self.order {
+            let family = &self.families[name];
+            for line in family.header.iter().chain(&family.samples) {
+                text.push_str(line);
+                text.push('\n');
+            }
+        }
+        text
+    }
+}
+
+/// Escapes `value` for use as a label value.
+fn escape(value: &str) -> String {
+    value
+        .replace('\\', r"\\")
+        .replace('"', "\\\"")
+        .replace('\n', r"\n")
+}
+
+/// Inserts `node="<node>"` as first label of the sample `line`, whose metric
+/// name ends at `name_end`.
+fn relabel(line: &str, name_end: usize, node: &str) -> String {
+    let node = escape(node);
+    let (name, rest) = line.split_at(name_end);
+
+    match rest.strip_prefix('{') {
+        Some(rest) if rest.starts_with('}') => format!("{}{{node=\"{}\"{}", name, node, rest),
+        Some(rest) => format!("{}{{node=\"{}\",{}", name, node, rest),
+        None => format!("{}{{node=\"{}\"}}{}", name, node, rest),
+    }
+}
+
+/// Scrapes the configured exporters and merges their metrics.
+pub struct Proxy {
+    client: Client<HttpsConnector<HttpConnector>>,
+    targets: Vec<ProxyTarget>,
+}
+
+impl Proxy {
+    pub fn new(targets: Vec<ProxyTarget>) -> Proxy {
+        Proxy {
+            client: Client::builder().build(HttpsConnector::new()),
+            targets,
+        }
+    }
+
+    /// Name of the `node` label of `target`, by default the host of its URL.
+    fn node(target: &ProxyTarget) -> String {
+        target.node.clone().unwrap_or_else(|| {
+            target
+                .url
+                .parse::<Uri>()
+                .ok()
+                .and_then(|uri| uri.host().map(str::to_string))
+                .unwrap_or_else(|| target.url.clone())
+        })
+    }
+
+    /// Scrapes all targets concurrently and merges their metrics with `local`,
+    /// an exposition of this instance together with its node name. Whether
+    /// each target could be scraped is exported as
+    /// `nvidia_gpu_exporter_proxy_target_up`.
+    pub async fn metrics(&self, local: Option<(Bytes, String)>) -> String {
+        let scrapes: Vec<_> = self
+            .targets
+            .iter()
+            .map(|target| {
+                let client = self.client.clone();
+                let url = target.url.clone();
+                tokio::spawn(async move { scrape(&client, &url).await })
+            })
+            .collect();
+
+        let mut families = Families::default();
+        if let Some((local, node)) = local {
+            families.add(&String::from_utf8_lossy(&local), &node);
+        }
+
+        let up_name = format!("{}_exporter_proxy_target_up", NAMESPACE);
+        let mut up = Family {
+            header: vec![
+                format!(
+                    "# HELP {} Whether the last scrape of the proxied exporter succeeded",
+                    up_name
+                ),
+                format!("# TYPE {} gauge", up_name),
+            ],
+            samples: Vec::new(),
+        };
+        for (target, scrape) in self.targets.iter().zip(scrapes) {
+            let node = Proxy::node(target);
+            let result = match scrape.await {
+                Ok(result) => result,
+                Err(e) => Err(e.to_string()),
+            };
+
+            let value = match result {
+                Ok(text) => {
+                    families.add(&text, &node);
+                    1
+                }
+                Err(e) => {
+                    eprintln!("Could not scrape {}: {}", target.url, e);
+                    0
+                }
+            };
+            up.samples.push(format!(
+                "{}{{node=\"{}\"}} {}",
+                up_name,
+                escape(&node),
+                value
+            ));
+        }
+        families.order.push(up_name.clone());
+        families.families.insert(up_name, up);
+
+        families.render()
+    }
+}
+
+async fn scrape(
+    client: &Client<HttpsConnector<HttpConnector>>,
+    url: &str,
+) -> Result<String, String> {
+    let uri: Uri = url.parse().map_err(|e| format!("{}", e))?;
+    let response = tokio::time::timeout(TIMEOUT, client.get(uri))
+        .await
+        .map_err(|_| "timed out".to_string())?
+        .map_err(|e| e.to_string())?;
+    if !response.status().is_success() {
+        return Err(format!("answered with {}", response.status()));
+    }
+
+    let body = body::to_bytes(response.into_body())
+        .await
+        .map_err(|e| e.to_string())?;
+    Ok(String::from_utf8_lossy(&body).into_owned())
+}
@@ -0,0 +1,293 @@
+//! Readings sampled between scrapes, from which rolling averages, the
+//! extremes since the previous scrape, the time spent throttled, the time
+//! spent in each performance state, the time spent idle and the peak memory
+//! of processes are computed.
+
+use std::collections::{BTreeMap, HashMap, VecDeque};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use crate::backend::ThrottleReason;
+
+/// Longest window an average can be computed over.
+pub const MAX_WINDOW: Duration = Duration::from_secs(5 * 60);
+
+/// Readings whose extremes between scrapes are tracked.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Reading {
+    GpuUtilization,
+    PowerUsage,
+    Temperature,
+}
+
+/// Samples of all devices, keyed by device UUID.
+#[derive(Default)]
+pub struct Samples {
+    /// GPU utilization samples of the last [`MAX_WINDOW`].
+    utilization: Mutex<HashMap<String, VecDeque<(Instant, u32)>>>,
+    /// Minimum and maximum since the extremes were last taken.
+    extremes: Mutex<HashMap<(String, Reading), (u32, u32)>>,
+    /// Throttle reasons asserted at the last sample.
+    throttle_reasons: Mutex<HashMap<String, (Instant, Vec<ThrottleReason>)>>,
+    /// Time each throttle reason was asserted since the first sample.
+    throttled: Mutex<HashMap<String, HashMap<ThrottleReason, Duration>>>,
+    /// Performance state at the last sample.
+    performance_state: Mutex<HashMap<String, (Instant, u32)>>,
+    /// Time spent in each performance state since the first sample.
+    performance_states: Mutex<HashMap<String, BTreeMap<u32, Duration>>>,
+    /// Start of the current idle period, `None` while busy.
+    idle_since: Mutex<HashMap<String, Option<Instant>>>,
+    /// Highest memory used by each process, keyed by device UUID and PID,
+    /// since it appeared.
+    process_memory_max: Mutex<HashMap<(String, u32), u64>>,
+}
+
+impl Samples {
+    /// Records a sample of `reading` of the device `uuid`.
+    pub fn record(&self, uuid: &str, reading: Reading, value: u32) {
+        let mut extremes = self.extremes.lock().expect("Samples poisoned");
+        let (min, max) = extremes
+            .entry((uuid.to_string(), reading))
+            .or_insert((value, value));
+        *min = (*min).min(value);
+        *max = (*max).max(value);
+        drop(extremes);
+
+        if reading == Reading::GpuUtilization {
+            self.record_utilization(uuid, value);
+        }
+    }
+
+    /// Keeps GPU utilization samples of the last [`MAX_WINDOW`].
+    fn record_utilization(&self, uuid: &str, gpu: u32) {
+        let now = Instant::now();
+        let mut utilization = self.utilization.lock().expect("Samples poisoned");
+
+        let device = utilization.entry(uuid.to_string()).or_default();
+        device.push_back((now, gpu));
+        while let Some((at, _)) = device.front() {
+            if now.duration_since(*at) <= MAX_WINDOW {
+                break;
+            }
+            device.pop_front();
+        }
+    }
+
+    /// Mean GPU utilization of the device `uuid` over the past `window`, or
+    /// `None` if there are no samples.
+    pub fn average(&self, uuid: &str, window: Duration) -> Option<f64> {
+        let utilization = self.utilization.lock().expect("Samples poisoned");
+
+        let values: Vec<u32> = utilization
+            .get(uuid)?
+            .iter()
+            .filter(|(at, _)| at.elapsed() <= window)
+            .map(|(_, gpu)| *gpu)
+            .collect();
+        if values.is_empty() {
+            return None;
+        }
+
+        Some(values.iter().map(|v| f64::from(*v)).sum::<f64>() / values.len() as f64)
+    }
+
+    /// Minimum and maximum of `reading` of the device `uuid` since they were
+    /// last taken, or `None` if there were no samples in between. They are
+    /// kept for the next call of [`take_extremes`](Samples::take_extremes).
+    pub fn extremes(&self, uuid: &str, reading: Reading) -> Option<(u32, u32)> {
+        self.extremes
+            .lock()
+            .expect("Samples poisoned")
+            .get(&(uuid.to_string(), reading))
+            .copied()
+    }
+
+    /// Like [`extremes`](Samples::extremes), but starts over from the next
+    /// sample.
+    pub fn take_extremes(&self, uuid: &str, reading: Reading) -> Option<(u32, u32)> {
+        self.extremes
+            .lock()
+            .expect("Samples poisoned")
+            .remove(&(uuid.to_string(), reading))
+    }
+
+    /// Records the throttle reasons asserted on the device `uuid`. The time
+    /// since the previous sample is counted for the reasons asserted then.
+    pub fn record_throttle_reasons(&self, uuid: &str, reasons: Vec<ThrottleReason>) {
+        let now = Instant::now();
+        let previous = self
+            .throttle_reasons
+            .lock()
+            .expect("Samples poisoned")
+            .insert(uuid.to_string(), (now, reasons));
+
+        let mut throttled = self.throttled.lock().expect("Samples poisoned");
+        let device = throttled.entry(uuid.to_string()).or_insert_with(|| {
+            ThrottleReason::ALL
+                .iter()
+                .map(|&reason| (reason, Duration::default()))
+                .collect()
+        });
+        if let Some((at, reasons)) = previous {
+            for reason in reasons {
+                *device.entry(reason).or_default() += now.duration_since(at);
+            }
+        }
+    }
+
+    /// Time each throttle reason was asserted on the device `uuid` since it
+    /// was first sampled, or `None` if it was never sampled.
+    pub fn throttled(&self, uuid: &str) -> Option<Vec<(ThrottleReason, Duration)>> {
+        let throttled = self.throttled.lock().expect("Samples poisoned");
+
+        let device = throttled.get(uuid)?;
+        Some(
+            ThrottleReason::ALL
+                .iter()
+                .map(|reason| (*reason, device.get(reason).copied().unwrap_or_default()))
+                .collect(),
+        )
+    }
+
+    /// Time each throttle reason was asserted by device UUID, for all
+    /// sampled devices.
+    pub fn all_throttled(&self) -> Vec<(String, Vec<(ThrottleReason, Duration)>)> {
+        let uuids: Vec<String> = self
+            .throttled
+            .lock()
+            .expect("Samples poisoned")
+            .keys()
+            .cloned()
+            .collect();
+        uuids
+            .into_iter()
+            .filter_map(|uuid| {
+                let throttled = self.throttled(&uuid)?;
+                Some((uuid, throttled))
+            })
+            .collect()
+    }
+
+    /// Adds `duration` to the time `reason` was asserted on the device
+    /// `uuid`, e.g. the time counted before a restart.
+    pub fn restore_throttled(&self, uuid: &str, reason: ThrottleReason, duration: Duration) {
+        let mut throttled = self.throttled.lock().expect("Samples poisoned");
+        let device = throttled.entry(uuid.to_string()).or_insert_with(|| {
+            ThrottleReason::ALL
+                .iter()
+                .map(|&reason| (reason, Duration::default()))
+                .collect()
+        });
+        *device.entry(reason).or_default() += duration;
+    }
+
+    /// Records the performance state of the device `uuid`. The time since the
+    /// previous sample is counted for the state it was in then.
+    pub fn record_performance_state(&self, uuid: &str, state: u32) {
+        let now = Instant::now();
+        let previous = self
+            .performance_state
+            .lock()
+            .expect("Samples poisoned")
+            .insert(uuid.to_string(), (now, state));
+
+        let mut states = self.performance_states.lock().expect("Samples poisoned");
+        let device = states.entry(uuid.to_string()).or_default();
+        device.entry(state).or_default();
+        if let Some((at, state)) = previous {
+            *device.entry(state).or_default() += now.duration_since(at);
+        }
+    }
+
+    /// Time spent in each performance state the device `uuid` was sampled
+    /// in, in ascending order of the states, or `None` if it was never
+    /// sampled.
+    pub fn performance_states(&self, uuid: &str) -> Option<Vec<(u32, Duration)>> {
+        let states = self.performance_states.lock().expect("Samples poisoned");
+        Some(
+            states
+                .get(uuid)?
+                .iter()
+                .map(|(state, duration)| (*state, *duration))
+                .collect(),
+        )
+    }
+
+    /// Records whether the device `uuid` was idle, i.e. neither utilized nor
+    /// running compute processes.
+    pub fn record_idle(&self, uuid: &str, idle: bool) {
+        let mut idle_since = self.idle_since.lock().expect("Samples poisoned");
+        let since = idle_since.entry(uuid.to_string()).or_default();
+        if !idle {
+            *since = None;
+        } else if since.is_none() {
+            *since = Some(Instant::now());
+        }
+    }
+
+    /// Time the device `uuid` has been idle since it was first sampled idle,
+    /// zero if it was busy at the last sample, or `None` if it was never
+    /// sampled.
+    pub fn idle(&self, uuid: &str) -> Option<Duration> {
+        let idle_since = self.idle_since.lock().expect("Samples poisoned");
+        Some(
+            idle_since
+                .get(uuid)?
+                .map_or_else(Duration::default, |at| at.elapsed()),
+        )
+    }
+
+    /// Records the memory used by the process `pid` on the device `uuid` and
+    /// returns the highest it used since it appeared.
+    pub fn record_process_memory(&self, uuid: &str, pid: u32, used_memory: u64) -> u64 {
+        let mut maxima = self.process_memory_max.lock().expect("Samples poisoned");
+        let max = maxima.entry((uuid.to_string(), pid)).or_insert(used_memory);
+        *max = (*max).max(used_memory);
+        *max
+    }
+
+    /// Forgets the processes of the device `uuid` except those in `pids`, so
+    /// that a process reusing the PID of an exited one starts over.
+    pub fn retain_processes(&self, uuid: &str, pids: &[u32]) {
+        self.process_memory_max
+            .lock()
+            .expect("Samples poisoned")
+            .retain(|(device, pid), _| device != uuid || pids.contains(pid));
+    }
+
+    /// Forgets all devices except those in `uuids`.
+    pub fn retain(&self, uuids: &[&str]) {
+        self.utilization
+            .lock()
+            .expect("Samples poisoned")
+            .retain(|uuid, _| uuids.contains(&uuid.as_str()));
+        self.extremes
+            .lock()
+            .expect("Samples poisoned")
+            .retain(|(uuid, _), _| uuids.contains(&uuid.as_str()));
+        self.throttle_reasons
+            .lock()
+            .expect("Samples poisoned")
+            .retain(|uuid, _| uuids.contains(&uuid.as_str()));
+        self.throttled
+            .lock()
+            .expect("Samples poisoned")
+            .retain(|uuid, _| uuids.contains(&uuid.as_str()));
+        self.performance_state
+            .lock()
+            .expect("Samples poisoned")
+            .retain(|uuid, _| uuids.contains(&uuid.as_str()));
+        self.performance_states
+            .lock()
+            .expect("Samples poisoned")
+            .retain(|uuid, _| uuids.contains(&uuid.as_str()));
+        self.idle_since
+            .lock()
+            .expect("Samples poisoned")
+            .retain(|uuid, _| uuids.contains(&uuid.as_str()));
+        self.process_memory_max
+            .lock()
+            .expect("Samples poisoned")
+            .retain(|(uuid, _), _| uuids.contains(&uuid.as_str()));
+    }
+}
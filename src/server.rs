@@ -0,0 +1,976 @@
+//! HTTP server exposing the collected metrics.
+
+use std::convert::Infallible;
+use std::future::Future;
+use std::net::SocketAddr;
+use std::sync::{Arc, Mutex};
+
+use hyper::body::Bytes;
+use hyper::header::{ACCEPT, AUTHORIZATION, CONTENT_TYPE, HOST, WWW_AUTHENTICATE};
+use hyper::service::{make_service_fn, service_fn};
+use hyper::{Body, Error, Method, Request, Response, Server, StatusCode};
+use nvml_wrapper::error::NvmlError;
+use serde::Serialize;
+use tokio::sync::{watch, Notify};
+use tracing::Instrument;
+
+use prometheus::core::Collector;
+#[cfg(target_os = "linux")]
+use prometheus::process_collector::ProcessCollector;
+use prometheus::proto::{LabelPair, Metric, MetricFamily};
+use prometheus::{Encoder, Registry, TextEncoder};
+
+use crate::backend::{GpuBackend, NvmlBackend};
+use crate::collector::GpuCollector;
+use crate::config::{WebConfig, WebShard};
+use crate::dashboard;
+use crate::error::{CollectingError, Result};
+use crate::openmetrics;
+use crate::proxy::Proxy;
+use crate::NAMESPACE;
+
+/// Number of series encoded at once into a chunk of a streamed response.
+const CHUNK_SERIES: usize = 500;
+
+/// A gather of the registry that concurrent scrapes wait for. Its result is
+/// sent once the gather finishes; the sender is dropped without sending if it
+/// panicked.
+type Flight = watch::Receiver<Option<Arc<Vec<MetricFamily>>>>;
+
+/// Registry and collector served by the HTTP server.
+pub struct Exporter<B = NvmlBackend> {
+    registry: Registry,
+    collector: GpuCollector<B>,
+    /// Label attached to every series, which the registry adds to the
+    /// metrics it gathers itself.
+    host_label: Option<(String, String)>,
+    /// The running gather, if any, cleared by the task running it.
+    in_flight: Arc<Mutex<Option<Flight>>>,
+}
+
+impl<B: GpuBackend + 'static> Exporter<B> {
+    pub fn new(collector: GpuCollector<B>) -> Result<Exporter<B>> {
+        let host_label = collector.host_label();
+        let const_labels = host_label
+            .clone()
+            .map(|label| vec![label].into_iter().collect());
+        let registry = Registry::new_custom(None, const_labels)?;
+
+        // Exporter process, registered without the namespace so the standard
+        // process_* metric names are kept
+        #[cfg(target_os = "linux")]
+        registry.register(Box::new(ProcessCollector::for_self()))?;
+
+        registry.register(Box::new(collector.clone()))?;
+
+        Ok(Exporter {
+            registry,
+            collector,
+            host_label,
+            in_flight: Arc::new(Mutex::new(None)),
+        })
+    }
+
+    /// Registers an additional collector, e.g. for other GPU vendors, to be
+    /// served next to the NVIDIA metrics.
+    pub fn register(&self, collector: Box<dyn Collector>) -> Result<()> {
+        Ok(self.registry.register(collector)?)
+    }
+
+    /// Whether the exporter can serve meaningful metrics, which requires all
+    /// expected devices to be visible. Before the first scrape, a collection
+    /// is run once to find out.
+    async fn ready(&self) -> bool {
+        if self.collector.last_collection_succeeded().is_none() {
+            self.gather().await;
+        }
+
+        self.collector.last_collection_succeeded() == Some(true)
+            && self.collector.devices_missing().unwrap_or(0) == 0
+    }
+
+    /// Gathers the registry on the blocking thread pool. Scrapes arriving
+    /// while a gather is running wait for it and are answered with its result
+    /// instead of sweeping all devices again. Returns `None` if the gather
+    /// panicked.
+    ///
+    /// The gather runs in its own task, so that it finishes and the next
+    /// scrape starts a new one even if the scrapes waiting for it go away.
+    async fn gather(&self) -> Option<Arc<Vec<MetricFamily>>> {
+        let mut flight = {
+            let mut in_flight = self.in_flight.lock().expect("Scrape lock poisoned");
+            match &*in_flight {
+                Some(flight) => flight.clone(),
+                None => {
+                    let (sender, flight) = watch::channel(None);
+                    *in_flight = Some(flight.clone());
+
+                    let registry = self.registry.clone();
+                    let in_flight = self.in_flight.clone();
+                    tokio::spawn(async move {
+                        let gathered = tokio::task::spawn_blocking(move || registry.gather()).await;
+                        *in_flight.lock().expect("Scrape lock poisoned") = None;
+                        match gathered {
+                            Ok(families) => {
+                                let _ = sender.broadcast(Some(Arc::new(families)));
+                            }
+                            Err(e) => eprintln!("Error gathering metrics: {}", e),
+                        }
+                    });
+                    flight
+                }
+            }
+        };
+
+        loop {
+            match flight.recv().await {
+                Some(Some(families)) => return Some(families),
+                // Not finished yet
+                Some(None) => continue,
+                None => return None,
+            }
+        }
+    }
+
+    /// Gathers and encodes the registry as a whole.
+    async fn metrics(&self) -> Option<Bytes> {
+        let families = self.gather().await?;
+        let mut buffer = Vec::<u8>::new();
+        TextEncoder::new()
+            .encode(&families, &mut buffer)
+            .expect("Encoding error");
+        Some(Bytes::from(buffer))
+    }
+
+    /// Serves all metrics in the OpenMetrics text format with exemplars, or
+    /// in the text format if OpenMetrics is not enabled.
+    async fn openmetrics(&self) -> Response<Body> {
+        let families = match self.gather().await {
+            Some(families) => families,
+            None => return gather_failed(),
+        };
+        match self.collector.encode_openmetrics(&families) {
+            Some(encoded) => Response::builder()
+                .status(200)
+                .header(CONTENT_TYPE, openmetrics::FORMAT_TYPE)
+                .body(Body::from(encoded))
+                .expect("Failed to build metrics response"),
+            None => streamed(families),
+        }
+    }
+
+    /// Attaches the host label, if any, to `families` collected without the
+    /// registry.
+    fn labeled(&self, mut families: Vec<MetricFamily>) -> Vec<MetricFamily> {
+        let (name, value) = match &self.host_label {
+            Some(label) => label,
+            None => return families,
+        };
+
+        for family in &mut families {
+            for metric in family.mut_metric().iter_mut() {
+                let mut label = LabelPair::default();
+                label.set_name(name.clone());
+                label.set_value(value.clone());
+                let labels = metric.mut_label();
+                labels.push(label);
+                labels.sort_by(|a, b| a.get_name().cmp(b.get_name()));
+            }
+        }
+        families
+    }
+
+    /// Value of the `user` label of the processes of `user`, if given.
+    fn user_label(&self, user: Option<&str>) -> Option<String> {
+        user.map(|user| self.collector.user_label(user))
+    }
+
+    /// Serves the metrics of the single device `device`, given by index or
+    /// UUID.
+    fn device_metrics(&self, device: &str, user: Option<&str>) -> Response<Body> {
+        match self.collector.collect_device(device) {
+            Ok(families) => encoded(only_processes_of(
+                self.labeled(families),
+                self.user_label(user).as_deref(),
+            )),
+            Err(e) => failed(&e, self.collector.device_uuid(device)),
+        }
+    }
+
+    /// Serves the metrics of the devices of `shard`, including all their
+    /// processes.
+    fn shard_metrics(&self, shard: &WebShard) -> Response<Body> {
+        match self.collector.collect_devices(&shard.devices) {
+            Ok(families) => encoded(self.labeled(families)),
+            Err(e) => failed(&e, None),
+        }
+    }
+
+    /// Serves an HTTP service discovery document listing the per-device
+    /// endpoints of this exporter, as reached through `req`.
+    fn service_discovery(&self, req: &Request<Body>) -> Response<Body> {
+        let address = match req.headers().get(HOST).and_then(|host| host.to_str().ok()) {
+            Some(address) => address,
+            None => {
+                return error(
+                    StatusCode::BAD_REQUEST,
+                    "bad_request",
+                    "Missing Host header",
+                )
+            }
+        };
+
+        match self.collector.service_discovery(address) {
+            Ok(targets) => Response::builder()
+                .status(200)
+                .header(CONTENT_TYPE, "application/json")
+                .body(Body::from(targets.to_string()))
+                .expect("Failed to build service discovery response"),
+            Err(e) => failed(&e, None),
+        }
+    }
+
+    /// Serves the metrics of only the collectors `names`, as requested by
+    /// `collect[]` query parameters.
+    fn selected_metrics(&self, names: &[String], user: Option<&str>) -> Response<Body> {
+        match self.collector.collect_only(names) {
+            Ok(families) => encoded(only_processes_of(
+                self.labeled(families),
+                self.user_label(user).as_deref(),
+            )),
+            Err(_) => error(
+                StatusCode::BAD_REQUEST,
+                "bad_request",
+                &format!("Unknown or disabled collector in {}", names.join(", ")),
+            ),
+        }
+    }
+
+    /// Applies the device setting requested by an `/admin/*` request, or
+    /// returns the response to a malformed request.
+    fn admin_action(&self, req: &Request<Body>) -> std::result::Result<Result<()>, Response<Body>> {
+        let collector = &self.collector;
+
+        match req.uri().path() {
+            "/admin/power_limit" => Ok(
+                collector.set_power_limit(required(req, "index")?, required(req, "milliwatts")?)
+            ),
+            "/admin/locked_clocks" => Ok(collector.set_locked_clocks(
+                required(req, "index")?,
+                required(req, "min_mhz")?,
+                required(req, "max_mhz")?,
+            )),
+            "/admin/reset_locked_clocks" => {
+                Ok(collector.reset_locked_clocks(required(req, "index")?))
+            }
+            _ => Err(not_found()),
+        }
+    }
+
+    /// Enumerates the devices again on the next collection.
+    fn refresh_devices(&self) -> Response<Body> {
+        self.collector.refresh_devices();
+        plain(StatusCode::OK, "OK")
+    }
+
+    /// Serves the raw state of all devices as JSON.
+    fn debug_devices(&self) -> Response<Body> {
+        match self.collector.debug_devices() {
+            Ok(devices) => Response::builder()
+                .status(200)
+                .header(CONTENT_TYPE, "application/json")
+                .body(Body::from(devices.to_string()))
+                .expect("Failed to build debug response"),
+            Err(e) => failed(&e, None),
+        }
+    }
+
+    /// Handles `/admin/*` requests changing device settings.
+    fn admin(&self, req: &Request<Body>) -> Response<Body> {
+        match self.admin_action(req) {
+            Ok(Ok(())) => plain(StatusCode::OK, "OK"),
+            Ok(Err(e)) => {
+                let device = query_param::<String>(req, "index")
+                    .and_then(|index| self.collector.device_uuid(&index));
+                failed(&e, device)
+            }
+            Err(response) => response,
+        }
+    }
+
+    /// Answers `req` of a user who may only see the processes of `user`, or
+    /// of all users if `None`.
+    async fn handle(&self, req: &Request<Body>, user: Option<&str>) -> Response<Body> {
+        let encoder = TextEncoder::new();
+
+        match (req.method(), req.uri().path()) {
+            (&Method::GET, path) if path.starts_with("/metrics/gpu/") => {
+                self.device_metrics(&path["/metrics/gpu/".len()..], user)
+            }
+            (&Method::GET, "/metrics") => {
+                let collect = query_params(req, "collect[]");
+                match query_param::<String>(req, "device") {
+                    Some(device) => self.device_metrics(&device, user),
+                    None if !collect.is_empty() => self.selected_metrics(&collect, user),
+                    None if user.is_some() => match self.gather().await {
+                        Some(families) => encoded(only_processes_of(
+                            families.as_ref().clone(),
+                            self.user_label(user).as_deref(),
+                        )),
+                        None => gather_failed(),
+                    },
+                    None if accepts_openmetrics(req) && self.collector.serves_openmetrics() => {
+                        self.openmetrics().await
+                    }
+                    None => match self.gather().await {
+                        Some(families) => streamed(families),
+                        None => gather_failed(),
+                    },
+                }
+            }
+            (&Method::GET, "/readyz") => readiness(self.ready().await),
+            (&Method::GET, "/sd") => self.service_discovery(req),
+            (&Method::GET, "/alerts") => {
+                let alerts = serde_json::to_vec(&self.collector.alerts())
+                    .expect("Failed to serialize alerts");
+                Response::builder()
+                    .status(200)
+                    .header(CONTENT_TYPE, "application/json")
+                    .body(Body::from(alerts))
+                    .expect("Failed to build alerts response")
+            }
+            (&Method::GET, "/dashboard.json") => {
+                let families = match self.gather().await {
+                    Some(families) => families,
+                    None => return gather_failed(),
+                };
+                let dashboard = dashboard::generate(&families);
+                Response::builder()
+                    .status(200)
+                    .header(CONTENT_TYPE, "application/json")
+                    .body(Body::from(dashboard.to_string()))
+                    .expect("Failed to build dashboard response")
+            }
+            (&Method::GET, "/gpustat") => {
+                let summary = match user {
+                    Some(user) => self.collector.process_of(user),
+                    None => self.collector.process(),
+                };
+                match summary {
+                    Ok(s) => Response::builder()
+                        .status(200)
+                        .header(CONTENT_TYPE, encoder.format_type())
+                        .body(Body::from(s))
+                        .expect("Failed to build gpustat response"),
+                    Err(e) => failed(&e, None),
+                }
+            }
+            _ => not_found(),
+        }
+    }
+}
+
+/// Removes the process metrics of processes not owned by `user`, if given,
+/// and the metrics summing up the processes of all users, whose cgroups and
+/// memory sizes tell about the other users.
+fn only_processes_of(families: Vec<MetricFamily>, user: Option<&str>) -> Vec<MetricFamily> {
+    let user = match user {
+        Some(user) => user,
+        None => return families,
+    };
+    let process_metrics = [
+        format!("{}_process_memory_used_bytes", NAMESPACE),
+        format!("{}_process_memory_max_bytes", NAMESPACE),
+        format!("{}_process_memory_used_ratio", NAMESPACE),
+    ];
+    let all_processes_metrics = [
+        format!("{}_cgroup_memory_used_bytes", NAMESPACE),
+        format!("{}_process_memory_bytes", NAMESPACE),
+    ];
+
+    families
+        .into_iter()
+        .filter_map(|mut family| {
+            if all_processes_metrics.iter().any(|m| m == family.get_name()) {
+                return None;
+            }
+            if process_metrics.iter().any(|m| m == family.get_name()) {
+                let owned: Vec<Metric> = family
+                    .take_metric()
+                    .into_iter()
+                    .filter(|metric| {
+                        metric
+                            .get_label()
+                            .iter()
+                            .any(|label| label.get_name() == "user" && label.get_value() == user)
+                    })
+                    .collect();
+                // Families without metrics cannot be encoded
+                if owned.is_empty() {
+                    return None;
+                }
+                family.set_metric(owned.into());
+            }
+            Some(family)
+        })
+        .collect()
+}
+
+/// Whether the scraper sending `req` asks for OpenMetrics.
+fn accepts_openmetrics(req: &Request<Body>) -> bool {
+    req.headers()
+        .get(ACCEPT)
+        .and_then(|accept| accept.to_str().ok())
+        .map_or(false, openmetrics::accepted)
+}
+
+/// Encodes `families` into a metrics response.
+fn encoded(families: Vec<MetricFamily>) -> Response<Body> {
+    streamed(Arc::new(families))
+}
+
+/// A metrics response encoding `families` chunk by chunk while the client
+/// reads it, so that the whole exposition is never held in memory at once.
+fn streamed(families: Arc<Vec<MetricFamily>>) -> Response<Body> {
+    let chunks = Chunks {
+        families,
+        family: 0,
+        series: 0,
+    };
+
+    Response::builder()
+        .status(200)
+        .header(CONTENT_TYPE, TextEncoder::new().format_type())
+        .body(Body::wrap_stream(tokio::stream::iter(chunks)))
+        .expect("Failed to build metrics response")
+}
+
+/// Text encoding of metric families in chunks of at most
+/// [`CHUNK_SERIES`] series.
+struct Chunks {
+    families: Arc<Vec<MetricFamily>>,
+    /// Family and series the next chunk starts at.
+    family: usize,
+    series: usize,
+}
+
+impl Iterator for Chunks {
+    type Item = std::result::Result<Bytes, Infallible>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let family = loop {
+            let family = self.families.get(self.family)?;
+            if self.series < family.get_metric().len() {
+                break family;
+            }
+            self.family += 1;
+            self.series = 0;
+        };
+
+        let metrics = family.get_metric();
+        let end = metrics.len().min(self.series + CHUNK_SERIES);
+        let mut chunk = MetricFamily::default();
+        chunk.set_name(family.get_name().to_string());
+        chunk.set_help(family.get_help().to_string());
+        chunk.set_field_type(family.get_field_type());
+        chunk.set_metric(metrics[self.series..end].to_vec().into());
+
+        let mut buffer = Vec::<u8>::new();
+        TextEncoder::new()
+            .encode(&[chunk], &mut buffer)
+            .expect("Encoding error");
+        let mut buffer = Bytes::from(buffer);
+        // Only the first chunk of a family describes it
+        if self.series > 0 {
+            buffer = buffer.slice(header_len(&buffer)..);
+        }
+
+        self.series = end;
+        Some(Ok(buffer))
+    }
+}
+
+/// Length of the `# HELP` and `# TYPE` lines an encoded family starts with.
+fn header_len(encoded: &[u8]) -> usize {
+    let mut len = 0;
+    while encoded[len..].starts_with(b"# ") {
+        match encoded[len..].iter().position(|&byte| byte == b'\n') {
+            Some(end) => len += end + 1,
+            None => return encoded.len(),
+        }
+    }
+    len
+}
+
+fn unauthorized() -> Response<Body> {
+    let mut response = error(StatusCode::UNAUTHORIZED, "unauthorized", "Unauthorized");
+    response
+        .headers_mut()
+        .insert(WWW_AUTHENTICATE, "Bearer".parse().expect("Invalid header"));
+    response
+}
+
+/// The bearer token of `req`, if any.
+fn bearer_token(req: &Request<Body>) -> Option<&str> {
+    req.headers()
+        .get(AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix("Bearer "))
+}
+
+fn plain(status: StatusCode, body: &str) -> Response<Body> {
+    Response::builder()
+        .status(status)
+        .body(Body::from(body.to_string()))
+        .expect("Failed to build response")
+}
+
+/// Body of error responses, so that automation can tell failures apart
+/// without reading the logs of the exporter.
+#[derive(Serialize)]
+struct ErrorBody<'a> {
+    /// Kind of error, e.g. `device_not_found` or `nvml_error`.
+    code: &'a str,
+    message: &'a str,
+    /// UUID of the device the request failed for, if it concerned one.
+    device: Option<String>,
+    /// Description of the error NVML failed with, if any.
+    nvml_error: Option<String>,
+}
+
+fn json_error(status: StatusCode, body: &ErrorBody) -> Response<Body> {
+    let body = serde_json::to_vec(body).expect("Failed to serialize error");
+    Response::builder()
+        .status(status)
+        .header(CONTENT_TYPE, "application/json")
+        .body(Body::from(body))
+        .expect("Failed to build error response")
+}
+
+/// An error response that neither concerns a device nor NVML.
+fn error(status: StatusCode, code: &str, message: &str) -> Response<Body> {
+    json_error(
+        status,
+        &ErrorBody {
+            code,
+            message,
+            device: None,
+            nvml_error: None,
+        },
+    )
+}
+
+/// The response to a scrape whose gather panicked.
+fn gather_failed() -> Response<Body> {
+    error(
+        StatusCode::INTERNAL_SERVER_ERROR,
+        "internal_error",
+        "Collection failed",
+    )
+}
+
+fn not_found() -> Response<Body> {
+    error(StatusCode::NOT_FOUND, "not_found", "Not found")
+}
+
+/// The error response to a request that failed with `err`, for the device
+/// with the UUID `device`, if any.
+fn failed(err: &CollectingError, device: Option<String>) -> Response<Body> {
+    let (status, code) = match err {
+        CollectingError::NotFound => (StatusCode::NOT_FOUND, "device_not_found"),
+        CollectingError::NotSupported => (StatusCode::NOT_IMPLEMENTED, "not_supported"),
+        CollectingError::Unhealthy => (StatusCode::SERVICE_UNAVAILABLE, "device_unhealthy"),
+        // The device may come back, e.g. after it briefly fell off the bus
+        CollectingError::Nvml(NvmlError::GpuLost) => (StatusCode::SERVICE_UNAVAILABLE, "gpu_lost"),
+        CollectingError::Nvml(_) if err.is_transient() => {
+            (StatusCode::SERVICE_UNAVAILABLE, "nvml_error")
+        }
+        CollectingError::Nvml(_) => (StatusCode::INTERNAL_SERVER_ERROR, "nvml_error"),
+        CollectingError::Prometheus(_) | CollectingError::Io(_) => {
+            (StatusCode::INTERNAL_SERVER_ERROR, "internal_error")
+        }
+        CollectingError::Template(_) => (StatusCode::INTERNAL_SERVER_ERROR, "template_error"),
+    };
+    let message = match err {
+        CollectingError::NotFound => "No such device".to_string(),
+        CollectingError::NotSupported => "Not supported by the device".to_string(),
+        err => err.to_string(),
+    };
+
+    json_error(
+        status,
+        &ErrorBody {
+            code,
+            message: &message,
+            device,
+            nvml_error: nvml_error(err),
+        },
+    )
+}
+
+/// The error response to requests while NVML failed to initialize with
+/// `err`.
+fn unavailable(err: &CollectingError) -> Response<Body> {
+    json_error(
+        StatusCode::INTERNAL_SERVER_ERROR,
+        &ErrorBody {
+            code: "nvml_unavailable",
+            message: "Could not get access to NVML",
+            device: None,
+            nvml_error: nvml_error(err),
+        },
+    )
+}
+
+fn nvml_error(err: &CollectingError) -> Option<String> {
+    match err {
+        CollectingError::Nvml(e) => Some(e.to_string()),
+        _ => None,
+    }
+}
+
+/// Parses the query parameter `name` of `req`.
+fn query_param<T: std::str::FromStr>(req: &Request<Body>, name: &str) -> Option<T> {
+    req.uri()
+        .query()?
+        .split('&')
+        .filter_map(|pair| {
+            let mut parts = pair.splitn(2, '=');
+            Some((parts.next()?, parts.next()?))
+        })
+        .find(|(key, _)| *key == name)
+        .and_then(|(_, value)| value.parse().ok())
+}
+
+/// All values of the repeatable query parameter `name` of `req`, which may be
+/// percent-encoded like `collect%5B%5D`.
+fn query_params(req: &Request<Body>, name: &str) -> Vec<String> {
+    let encoded = name.replace('[', "%5B").replace(']', "%5D");
+    req.uri()
+        .query()
+        .unwrap_or_default()
+        .split('&')
+        .filter_map(|pair| {
+            let mut parts = pair.splitn(2, '=');
+            let key = parts.next()?;
+            let matches = key == name || key.eq_ignore_ascii_case(&encoded);
+            Some(parts.next()?.to_string()).filter(|_| matches)
+        })
+        .collect()
+}
+
+/// Parses the mandatory query parameter `name`, answering with a bad request
+/// if it is missing or malformed.
+fn required<T: std::str::FromStr>(
+    req: &Request<Body>,
+    name: &str,
+) -> std::result::Result<T, Response<Body>> {
+    query_param(req, name).ok_or_else(|| {
+        error(
+            StatusCode::BAD_REQUEST,
+            "bad_request",
+            &format!("Expected the {} query parameter", name),
+        )
+    })
+}
+
+/// Compares `token` to `expected` in time independent of where they differ.
+fn token_matches(token: &str, expected: &str) -> bool {
+    token.len() == expected.len()
+        && token
+            .bytes()
+            .zip(expected.bytes())
+            .fold(0, |acc, (a, b)| acc | (a ^ b))
+            == 0
+}
+
+fn readiness(ready: bool) -> Response<Body> {
+    let (status, body) = if ready {
+        (StatusCode::OK, "OK")
+    } else {
+        (StatusCode::SERVICE_UNAVAILABLE, "Not ready")
+    };
+
+    Response::builder()
+        .status(status)
+        .body(Body::from(body))
+        .expect("Failed to build readiness response")
+}
+
+/// Which endpoints a listener serves.
+#[derive(Clone, Copy)]
+enum Endpoints {
+    All,
+    Metrics,
+    Admin,
+}
+
+/// Health, readiness, lifecycle, admin and debug endpoints, which can be
+/// served on a separate listener.
+fn is_admin(path: &str) -> bool {
+    matches!(
+        path,
+        "/healthz" | "/readyz" | "/-/quit" | "/-/refresh-devices" | "/debug/devices"
+    ) || path.starts_with("/admin/")
+}
+
+/// Everything a connection needs to answer requests.
+struct State<B> {
+    exporter: Result<Exporter<B>>,
+    web: WebConfig,
+    proxy: Option<Proxy>,
+    shutdown: Notify,
+}
+
+impl<B: GpuBackend + 'static> State<B> {
+    /// Whether `req` carries the configured admin token.
+    fn authorized(&self, req: &Request<Body>) -> bool {
+        let expected = match &self.web.admin_token {
+            Some(token) => token,
+            None => return false,
+        };
+
+        bearer_token(req).map_or(false, |token| token_matches(token, expected))
+    }
+
+    /// The shard served at `path`, if any.
+    fn shard(&self, path: &str) -> Option<&WebShard> {
+        self.web.shards.iter().find(|shard| shard.path == path)
+    }
+
+    /// Answers `req` to the path of `shard`.
+    fn shard_metrics(&self, shard: &WebShard, req: &Request<Body>) -> Response<Body> {
+        if *req.method() != Method::GET {
+            return not_found();
+        }
+        if !bearer_token(req).map_or(false, |token| token_matches(token, &shard.token)) {
+            return unauthorized();
+        }
+
+        match &self.exporter {
+            Ok(exporter) => exporter.shard_metrics(shard),
+            Err(e) => unavailable(e),
+        }
+    }
+
+    /// The user whose processes `req` may see, `None` for all users, or the
+    /// response to a request without a valid user token.
+    fn viewer(&self, req: &Request<Body>) -> std::result::Result<Option<&str>, Response<Body>> {
+        if self.web.users.is_empty() {
+            return Ok(None);
+        }
+
+        let user = bearer_token(req).and_then(|token| {
+            self.web
+                .users
+                .iter()
+                .find(|user| token_matches(token, &user.token))
+        });
+        match user {
+            Some(user) if user.all_processes => Ok(None),
+            Some(user) => Ok(Some(&user.name)),
+            None => Err(unauthorized()),
+        }
+    }
+
+    async fn handle(&self, endpoints: Endpoints, req: &Request<Body>) -> Response<Body> {
+        let served = match endpoints {
+            Endpoints::All => true,
+            Endpoints::Metrics => !is_admin(req.uri().path()),
+            Endpoints::Admin => is_admin(req.uri().path()),
+        };
+        if !served {
+            return not_found();
+        }
+
+        // Shards are authenticated by their own token rather than a user's
+        if let Some(shard) = self.shard(req.uri().path()) {
+            return self.shard_metrics(shard, req);
+        }
+
+        match (req.method(), req.uri().path()) {
+            // Liveness does not depend on NVML
+            (&Method::GET, "/healthz") => Response::builder()
+                .status(StatusCode::OK)
+                .body(Body::from("OK"))
+                .expect("Failed to build health response"),
+            (&Method::POST, "/-/quit") if self.web.enable_lifecycle => {
+                self.shutdown.notify();
+                Response::builder()
+                    .status(StatusCode::OK)
+                    .body(Body::from("Shutting down"))
+                    .expect("Failed to build quit response")
+            }
+            (&Method::POST, path) if path.starts_with("/admin/") && self.web.enable_admin_api => {
+                if !self.authorized(req) {
+                    return unauthorized();
+                }
+
+                match &self.exporter {
+                    Ok(exporter) => exporter.admin(req),
+                    Err(e) => unavailable(e),
+                }
+            }
+            (&Method::POST, "/-/refresh-devices") if self.web.enable_admin_api => {
+                if !self.authorized(req) {
+                    return unauthorized();
+                }
+
+                match &self.exporter {
+                    Ok(exporter) => exporter.refresh_devices(),
+                    Err(e) => unavailable(e),
+                }
+            }
+            (&Method::GET, "/debug/devices") if self.web.enable_admin_api => {
+                if !self.authorized(req) {
+                    return unauthorized();
+                }
+
+                match &self.exporter {
+                    Ok(exporter) => exporter.debug_devices(),
+                    Err(e) => unavailable(e),
+                }
+            }
+            (_, path) => match &self.exporter {
+                Ok(exporter) if path == "/readyz" => exporter.handle(req, None).await,
+                Ok(exporter) => match self.viewer(req) {
+                    Ok(user) => exporter.handle(req, user).await,
+                    Err(response) => response,
+                },
+                Err(_) if path == "/readyz" => readiness(false),
+                Err(e) => unavailable(e),
+            },
+        }
+    }
+
+    /// Like [`handle`](State::handle), but also answers requests to
+    /// `/proxy/metrics`, which have to wait for other exporters.
+    async fn respond(&self, endpoints: Endpoints, req: Request<Body>) -> Response<Body> {
+        let proxied = !matches!(endpoints, Endpoints::Admin)
+            && *req.method() == Method::GET
+            && req.uri().path() == "/proxy/metrics";
+
+        match &self.proxy {
+            Some(proxy) if proxied => {
+                // The proxied metrics cannot be filtered per user
+                match self.viewer(&req) {
+                    Ok(None) => {}
+                    Ok(Some(_)) => return error(StatusCode::FORBIDDEN, "forbidden", "Forbidden"),
+                    Err(response) => return response,
+                }
+
+                let local = match &self.exporter {
+                    Ok(exporter) => exporter.metrics().await.map(|metrics| {
+                        let node = gethostname::gethostname().to_string_lossy().into_owned();
+                        (metrics, node)
+                    }),
+                    Err(_) => None,
+                };
+
+                Response::builder()
+                    .status(200)
+                    .header(CONTENT_TYPE, TextEncoder::new().format_type())
+                    .body(Body::from(proxy.metrics(local).await))
+                    .expect("Failed to build proxy response")
+            }
+            _ => self.handle(endpoints, &req).await,
+        }
+    }
+
+    /// Resolves once shutdown was requested.
+    async fn shutdown(&self) {
+        self.shutdown.notified().await;
+        // Pass the notification on to the other listener, if any
+        self.shutdown.notify();
+    }
+}
+
+fn serve<B: GpuBackend + 'static>(
+    state: Arc<State<B>>,
+    addr: &SocketAddr,
+    endpoints: Endpoints,
+) -> (SocketAddr, impl Future<Output = hyper::Result<()>>) {
+    let make_service = {
+        let state = state.clone();
+
+        make_service_fn(move |_| {
+            let state = state.clone();
+
+            async move {
+                Ok::<_, Error>(service_fn(move |req| {
+                    let state = state.clone();
+                    let span = tracing::info_span!(
+                        "request",
+                        method = %req.method(),
+                        path = req.uri().path()
+                    );
+                    async move { Ok::<_, Error>(state.respond(endpoints, req).await) }
+                        .instrument(span)
+                }))
+            }
+        })
+    };
+
+    let server = Server::bind(addr).serve(make_service);
+    let addr = server.local_addr();
+    let server = server.with_graceful_shutdown(async move { state.shutdown().await });
+    (addr, server)
+}
+
+fn shared_state<B>(exporter: Result<Exporter<B>>, web: &WebConfig) -> Arc<State<B>> {
+    let proxy = if web.proxy_targets.is_empty() {
+        None
+    } else {
+        Some(Proxy::new(web.proxy_targets.clone()))
+    };
+
+    Arc::new(State {
+        exporter,
+        web: web.clone(),
+        proxy,
+        shutdown: Notify::new(),
+    })
+}
+
+/// Binds the HTTP server to `addr`, returning the bound address and the future
+/// running the server.
+///
+/// `/healthz` always answers with 200 while the server is running. If
+/// `exporter` failed to initialize, `/readyz` reports unavailability and every
+/// other request is answered with an internal server error. If lifecycle
+/// endpoints are enabled, a POST to `/-/quit` shuts the server down gracefully,
+/// which completes the returned future. If proxy targets are configured,
+/// `/proxy/metrics` serves their metrics together with the local ones, each
+/// labeled with the `node` they come from.
+pub fn bind<B: GpuBackend + 'static>(
+    addr: &SocketAddr,
+    exporter: Result<Exporter<B>>,
+    web: &WebConfig,
+) -> (SocketAddr, impl Future<Output = hyper::Result<()>>) {
+    serve(shared_state(exporter, web), addr, Endpoints::All)
+}
+
+/// Like [`bind`], but serves `/healthz`, `/readyz` and `/-/quit` only on
+/// `admin_addr` and everything else only on `addr`. Returns both bound
+/// addresses and a future running both listeners, which completes once both
+/// have shut down.
+pub fn bind_with_admin<B: GpuBackend + 'static>(
+    addr: &SocketAddr,
+    admin_addr: &SocketAddr,
+    exporter: Result<Exporter<B>>,
+    web: &WebConfig,
+) -> (
+    SocketAddr,
+    SocketAddr,
+    impl Future<Output = hyper::Result<()>>,
+) {
+    let state = shared_state(exporter, web);
+    let (addr, metrics) = serve(state.clone(), addr, Endpoints::Metrics);
+    let (admin_addr, admin) = serve(state, admin_addr, Endpoints::Admin);
+
+    let server = async move {
+        let (metrics, admin) = tokio::join!(metrics, admin);
+        metrics.and(admin)
+    };
+    (addr, admin_addr, server)
+}
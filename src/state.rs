@@ -0,0 +1,40 @@
+//! Counters the exporter derives itself, persisted across restarts so that
+//! `increase()` over them stays accurate over the lifetime of a node.
+
+use std::collections::BTreeMap;
+use std::fs;
+use std::io;
+use std::path::Path;
+
+use serde::{Deserialize, Serialize};
+
+/// Content of the state file.
+#[derive(Debug, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct State {
+    /// Number of XID errors by XID and device UUID.
+    pub xid_errors: BTreeMap<String, BTreeMap<u64, u64>>,
+    /// Seconds the clocks were throttled by reason and device UUID.
+    pub throttled_seconds: BTreeMap<String, BTreeMap<String, f64>>,
+}
+
+impl State {
+    /// Reads the state file at `path`, or returns `None` if there is none
+    /// yet.
+    pub fn read(path: &Path) -> io::Result<Option<State>> {
+        match fs::read(path) {
+            Ok(content) => Ok(Some(serde_json::from_slice(&content)?)),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Replaces the state file at `path`. The file is written next to it
+    /// first and then renamed, so that a crash leaves the previous state.
+    pub fn write(&self, path: &Path) -> io::Result<()> {
+        let mut temporary = path.as_os_str().to_owned();
+        temporary.push(".tmp");
+        fs::write(&temporary, serde_json::to_vec(self)?)?;
+        fs::rename(&temporary, path)
+    }
+}
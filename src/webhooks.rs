@@ -0,0 +1,101 @@
+//! Delivery of alert notifications to webhooks such as Slack or Discord.
+
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+
+use hyper::client::HttpConnector;
+use hyper::header::CONTENT_TYPE;
+use hyper::{Body, Client, Request};
+use hyper_tls::HttpsConnector;
+use serde_json::json;
+use tokio::sync::mpsc::UnboundedReceiver;
+
+use crate::alerts::Notification;
+use crate::config::{WebhookConfig, WebhookFormat};
+
+const DEFAULT_TEMPLATE: &str = "[{status}] {alert} on {name} ({uuid}): {value}";
+
+/// Time after which a webhook that did not answer is given up on, so that it
+/// does not hold back the notifications after it.
+const TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Renders `template`, replacing `{alert}`, `{status}`, `{value}` and
+/// `{<label>}` with the values of `notification`.
+pub fn render(template: &str, notification: &Notification) -> String {
+    let alert = &notification.alert;
+    let status = if notification.resolved {
+        "resolved"
+    } else {
+        "firing"
+    };
+
+    let mut message = template
+        .replace("{alert}", &alert.name)
+        .replace("{status}", status)
+        .replace("{value}", &alert.value.to_string());
+    for (name, value) in &alert.labels {
+        message = message.replace(&format!("{{{}}}", name), value);
+    }
+    message
+}
+
+/// Body of the request to `webhook`.
+pub fn payload(webhook: &WebhookConfig, notification: &Notification) -> serde_json::Value {
+    let template = webhook.template.as_deref().unwrap_or(DEFAULT_TEMPLATE);
+    let message = render(template, notification);
+
+    match webhook.format {
+        WebhookFormat::Slack => json!({ "text": message }),
+        WebhookFormat::Discord => json!({ "content": message }),
+        WebhookFormat::Generic => json!({
+            "text": message,
+            "status": if notification.resolved { "resolved" } else { "firing" },
+            "alert": notification.alert,
+        }),
+    }
+}
+
+/// Posts every received notification to all webhooks, skipping notifications
+/// about an alert, series and status already sent within the webhook's
+/// `min_interval`. Completes once `notifications` is closed.
+pub async fn run(webhooks: Vec<WebhookConfig>, mut notifications: UnboundedReceiver<Notification>) {
+    let client: Client<HttpsConnector<HttpConnector>> =
+        Client::builder().build(HttpsConnector::new());
+    let mut last_sent = HashMap::new();
+
+    while let Some(notification) = notifications.recv().await {
+        let now = Instant::now();
+
+        for (i, webhook) in webhooks.iter().enumerate() {
+            let key = (
+                i,
+                notification.alert.name.clone(),
+                notification.alert.labels.clone(),
+                notification.resolved,
+            );
+            if let Some(sent) = last_sent.get(&key) {
+                if now.duration_since(*sent) < webhook.min_interval {
+                    continue;
+                }
+            }
+            last_sent.insert(key, now);
+
+            let body = payload(webhook, &notification).to_string();
+            let request = Request::post(webhook.url.as_str())
+                .header(CONTENT_TYPE, "application/json")
+                .body(Body::from(body))
+                .expect("Failed to build webhook request");
+
+            match tokio::time::timeout(TIMEOUT, client.request(request)).await {
+                Ok(Ok(response)) if response.status().is_success() => {}
+                Ok(Ok(response)) => eprintln!(
+                    "Webhook {} answered with {}",
+                    webhook.url,
+                    response.status()
+                ),
+                Ok(Err(e)) => eprintln!("Could not notify webhook {}: {}", webhook.url, e),
+                Err(_) => eprintln!("Could not notify webhook {}: timed out", webhook.url),
+            }
+        }
+    }
+}
@@ -0,0 +1,111 @@
+//! Critical XID errors reported by the driver while the exporter is running,
+//! counted by the XID watcher, and the health of the watcher itself.
+
+use std::collections::{BTreeMap, HashMap};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// Number of errors by XID and device UUID, and the events seen by the watcher.
+#[derive(Default)]
+pub struct Xids {
+    /// Whether the watcher is waiting for errors, without which no errors
+    /// are counted.
+    watching: AtomicBool,
+    errors: Mutex<HashMap<String, BTreeMap<u64, u64>>>,
+    /// Events the watcher received.
+    received: AtomicU64,
+    /// Events that could not be attributed to a device.
+    dropped: AtomicU64,
+    /// When the watcher last returned from waiting for events, `None` unless
+    /// it is running.
+    last_wait: Mutex<Option<Instant>>,
+}
+
+impl Xids {
+    pub fn set_watching(&self, watching: bool) {
+        self.watching.store(watching, Ordering::SeqCst);
+    }
+
+    pub fn is_watching(&self) -> bool {
+        self.watching.load(Ordering::SeqCst)
+    }
+
+    /// Counts an error `xid` of the device `uuid`.
+    pub fn record(&self, uuid: &str, xid: u64) {
+        let mut errors = self.errors.lock().expect("XID errors poisoned");
+        *errors
+            .entry(uuid.to_string())
+            .or_default()
+            .entry(xid)
+            .or_default() += 1;
+    }
+
+    /// Number of errors by XID and device UUID.
+    pub fn all_errors(&self) -> BTreeMap<String, BTreeMap<u64, u64>> {
+        self.errors
+            .lock()
+            .expect("XID errors poisoned")
+            .iter()
+            .map(|(uuid, errors)| (uuid.clone(), errors.clone()))
+            .collect()
+    }
+
+    /// Adds `errors` by XID and device UUID, e.g. those counted before a
+    /// restart.
+    pub fn restore(&self, errors: BTreeMap<String, BTreeMap<u64, u64>>) {
+        let mut all = self.errors.lock().expect("XID errors poisoned");
+        for (uuid, errors) in errors {
+            let device = all.entry(uuid).or_default();
+            for (xid, count) in errors {
+                *device.entry(xid).or_default() += count;
+            }
+        }
+    }
+
+    /// Counts an event received by the watcher, which was `dropped` if it
+    /// could not be attributed to a device.
+    pub fn record_event(&self, dropped: bool) {
+        self.received.fetch_add(1, Ordering::SeqCst);
+        if dropped {
+            self.dropped.fetch_add(1, Ordering::SeqCst);
+        }
+    }
+
+    /// Number of events received and dropped by the watcher.
+    pub fn events(&self) -> (u64, u64) {
+        (
+            self.received.load(Ordering::SeqCst),
+            self.dropped.load(Ordering::SeqCst),
+        )
+    }
+
+    /// Records that the watcher started, or returned from waiting for events.
+    pub fn record_wait(&self) {
+        *self.last_wait.lock().expect("XID watcher poisoned") = Some(Instant::now());
+    }
+
+    /// Records that the watcher stopped for good.
+    pub fn record_stop(&self) {
+        *self.last_wait.lock().expect("XID watcher poisoned") = None;
+    }
+
+    /// Time since the watcher last returned from waiting for events, or
+    /// `None` unless it is running.
+    pub fn wait_lag(&self) -> Option<Duration> {
+        self.last_wait
+            .lock()
+            .expect("XID watcher poisoned")
+            .map(|at| at.elapsed())
+    }
+
+    /// Number of errors of the device `uuid` by XID.
+    pub fn errors(&self, uuid: &str) -> BTreeMap<u64, u64> {
+        self.errors
+            .lock()
+            .expect("XID errors poisoned")
+            .get(uuid)
+            .cloned()
+            .unwrap_or_default()
+    }
+}
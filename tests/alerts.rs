@@ -0,0 +1,140 @@
+use prometheus::core::Collector;
+use prometheus::{Encoder, TextEncoder};
+
+use prometheus_nvidia_gpu::alerts::AlertState;
+use prometheus_nvidia_gpu::backend::{MockBackend, MockDevice};
+use prometheus_nvidia_gpu::{webhooks, Config, GpuCollector};
+use serde_json::json;
+
+fn backend() -> MockBackend {
+    let mut hot = MockDevice::new(0, "Tesla V100-SXM2-16GB");
+    hot.temperature = Some(91);
+    let mut cool = MockDevice::new(1, "Tesla T4");
+    cool.temperature = Some(40);
+    MockBackend::new(vec![hot, cool])
+}
+
+fn collector(rules: &str) -> GpuCollector<MockBackend> {
+    let config: Config = toml::from_str(rules).unwrap();
+    GpuCollector::with_config(backend(), &config).unwrap()
+}
+
+fn render(collector: &GpuCollector<MockBackend>) -> String {
+    let mut buffer = Vec::new();
+    TextEncoder::new()
+        .encode(&collector.collect(), &mut buffer)
+        .unwrap();
+    String::from_utf8(buffer).unwrap()
+}
+
+#[test]
+fn alert_fires_for_offending_device() {
+    let collector = collector(
+        r#"
+        [[alerting.rules]]
+        name = "GpuTooHot"
+        metric = "nvidia_gpu_temperature_celsius"
+        op = ">"
+        threshold = 85.0
+        "#,
+    );
+
+    let output = render(&collector);
+    let alerts = collector.alerts();
+
+    assert_eq!(alerts.len(), 1);
+    assert_eq!(alerts[0].name, "GpuTooHot");
+    assert_eq!(alerts[0].state, AlertState::Firing);
+    assert_eq!(alerts[0].value, 91.0);
+    assert_eq!(alerts[0].labels["name"], "Tesla V100-SXM2-16GB");
+    assert_eq!(output.matches("nvidia_gpu_alert_firing{").count(), 1);
+    assert!(output.contains("nvidia_gpu_alert_firing{alert=\"GpuTooHot\","));
+}
+
+#[test]
+fn alert_is_pending_until_duration_elapsed() {
+    let collector = collector(
+        r#"
+        [[alerting.rules]]
+        name = "GpuTooHot"
+        metric = "nvidia_gpu_temperature_celsius"
+        op = ">"
+        threshold = 85.0
+        for = "1h"
+        "#,
+    );
+
+    let output = render(&collector);
+    let alerts = collector.alerts();
+
+    assert_eq!(alerts.len(), 1);
+    assert_eq!(alerts[0].state, AlertState::Pending);
+    assert!(!output.contains("nvidia_gpu_alert_firing{"));
+}
+
+#[test]
+fn subscribers_are_notified_once_when_alert_fires() {
+    let collector = collector(
+        r#"
+        [[alerting.rules]]
+        name = "GpuTooHot"
+        metric = "nvidia_gpu_temperature_celsius"
+        op = ">"
+        threshold = 85.0
+        "#,
+    );
+    let mut notifications = collector.subscribe_alerts();
+
+    render(&collector);
+    render(&collector);
+
+    let notification = notifications.try_recv().unwrap();
+    assert_eq!(notification.alert.name, "GpuTooHot");
+    assert!(!notification.resolved);
+    assert!(notifications.try_recv().is_err());
+}
+
+#[test]
+fn webhook_messages_are_templated() {
+    let config: Config = toml::from_str(
+        r#"
+        [[alerting.rules]]
+        name = "GpuTooHot"
+        metric = "nvidia_gpu_temperature_celsius"
+        op = ">"
+        threshold = 85.0
+
+        [[alerting.webhooks]]
+        url = "https://hooks.slack.com/services/T000/B000/XXXX"
+        format = "slack"
+        template = "{alert} is {status} on {name} ({value})"
+        "#,
+    )
+    .unwrap();
+    let collector = GpuCollector::with_config(backend(), &config).unwrap();
+    let mut notifications = collector.subscribe_alerts();
+
+    render(&collector);
+    let notification = notifications.try_recv().unwrap();
+    let payload = webhooks::payload(&config.alerting.webhooks[0], &notification);
+
+    assert_eq!(
+        payload,
+        json!({ "text": "GpuTooHot is firing on Tesla V100-SXM2-16GB (91)" })
+    );
+}
+
+#[test]
+fn unknown_comparison_is_rejected() {
+    let config = toml::from_str::<Config>(
+        r#"
+        [[alerting.rules]]
+        name = "GpuTooHot"
+        metric = "nvidia_gpu_temperature_celsius"
+        op = "=>"
+        threshold = 85.0
+        "#,
+    );
+
+    assert!(config.is_err());
+}
@@ -0,0 +1,1602 @@
+use std::fs;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use nvml_wrapper::error::NvmlError;
+use prometheus::core::Collector;
+use prometheus::{Encoder, Registry, TextEncoder};
+
+use prometheus_nvidia_gpu::backend::{
+    DeviceInfo, FakeBackend, Feature, Field, GpmMetrics, GpuBackend, GridLicense, MemoryInfo,
+    MigDevice, MockBackend, MockDevice, NvmlBackend, OperationMode, PcieErrors, ProcessInfo,
+    ProcessType, ProcessUtilization, ThrottleReason, Utilization,
+};
+use prometheus_nvidia_gpu::config::WatchdogConfig;
+use prometheus_nvidia_gpu::kubernetes::Allocation;
+use prometheus_nvidia_gpu::{CollectingError, Config, GpuCollector, Result};
+
+fn backend() -> MockBackend {
+    let mut device = MockDevice::new(0, "Tesla T4");
+    device.utilization = Some(Utilization {
+        gpu: 3,
+        memory: Some(1),
+    });
+    device.memory_info = Some(MemoryInfo {
+        total: 100,
+        free: 60,
+        used: 40,
+    });
+    MockBackend::new(vec![device])
+}
+
+fn render<B: GpuBackend + 'static>(collector: GpuCollector<B>) -> String {
+    let registry = Registry::new();
+    registry.register(Box::new(collector)).unwrap();
+
+    let mut buffer = Vec::new();
+    TextEncoder::new()
+        .encode(&registry.gather(), &mut buffer)
+        .unwrap();
+    String::from_utf8(buffer).unwrap()
+}
+
+#[test]
+fn disabled_collectors_are_not_run() {
+    let config: Config = toml::from_str("[collectors.memory]\nenabled = false\n").unwrap();
+    let collector = GpuCollector::with_config(backend(), &config).unwrap();
+
+    let output = render(collector);
+
+    assert!(output.contains("nvidia_gpu_gpu_utilization{"));
+    assert!(!output.contains("nvidia_gpu_memory_used_bytes"));
+    assert!(output.contains("nvidia_gpu_exporter_collector_success{collector=\"utilization\"} 1\n"));
+    assert!(!output.contains("collector=\"memory\""));
+}
+
+#[test]
+fn start_time_and_uptime_are_exported() {
+    let collector = GpuCollector::with_backend(backend()).unwrap();
+    thread::sleep(Duration::from_millis(10));
+
+    let output = render(collector);
+
+    let value = |name: &str| -> f64 {
+        let line = output
+            .lines()
+            .find(|line| line.starts_with(name))
+            .unwrap_or_else(|| panic!("{} missing", name));
+        line[name.len() + 1..].parse().unwrap()
+    };
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap()
+        .as_secs_f64();
+    let start_time = value("nvidia_gpu_exporter_start_time_seconds");
+    assert!(start_time > now - 60.0 && start_time <= now);
+    assert!(value("nvidia_gpu_exporter_uptime_seconds") >= 0.01);
+}
+
+#[test]
+fn unknown_collectors_are_rejected() {
+    let config: Config = toml::from_str("[collectors.dcgm]\nenabled = true\n").unwrap();
+
+    assert!(config.validate().is_err());
+}
+
+#[test]
+fn host_labels_clashing_with_identity_labels_are_rejected() {
+    let config: Config = toml::from_str(
+        "[labels]\nidentity = [\"uuid\", \"hostname\"]\nhost_label = \"hostname\"\n",
+    )
+    .unwrap();
+    assert!(config.validate().is_err());
+
+    let config: Config = toml::from_str("[labels]\nhost_label = \"node name\"\n").unwrap();
+    assert!(config.validate().is_err());
+
+    let config: Config = toml::from_str("[labels]\nhost_label = \"node\"\n").unwrap();
+    assert!(config.validate().is_ok());
+}
+
+#[test]
+fn invalid_alert_metric_names_are_rejected() {
+    let config: Config = toml::from_str(
+        "[[alerting.rules]]\nname = \"Hot\"\nmetric = \"nvidia-gpu temperature\"\nop = \">\"\nthreshold = 1.0\n",
+    )
+    .unwrap();
+
+    assert!(config.validate().is_err());
+}
+
+#[test]
+fn admin_api_requires_token() {
+    let config: Config = toml::from_str("[web]\nenable_admin_api = true\n").unwrap();
+
+    assert!(config.validate().is_err());
+}
+
+#[test]
+fn uuid_labels_can_be_shortened() {
+    let config: Config =
+        toml::from_str("[labels]\nstrip_uuid_prefix = true\nshort_uuid = true\n").unwrap();
+    let collector = GpuCollector::with_config(backend(), &config).unwrap();
+    assert!(render(collector).contains("uuid=\"00000000\""));
+
+    let config: Config = toml::from_str("[labels]\nshort_uuid = true\n").unwrap();
+    let collector = GpuCollector::with_config(backend(), &config).unwrap();
+    assert!(render(collector).contains("uuid=\"GPU-00000000\""));
+}
+
+#[test]
+fn identity_labels_are_configurable() {
+    let config: Config =
+        toml::from_str("[labels]\nidentity = [\"index\", \"pci_bus_id\"]\n").unwrap();
+    let collector = GpuCollector::with_config(backend(), &config).unwrap();
+
+    let output = render(collector);
+
+    assert!(output
+        .contains("nvidia_gpu_gpu_utilization{index=\"0\",pci_bus_id=\"00000000:01:00.0\"} 3\n"));
+    assert!(!output.contains("uuid="));
+}
+
+#[test]
+fn devices_are_indexed_by_pci_bus_id() {
+    let devices = ["00000000:3B:00.0", "00000000:1A:00.0", "00000000:D8:00.0"]
+        .iter()
+        .enumerate()
+        .map(|(index, bus_id)| {
+            let mut device = MockDevice::new(index as u32, "Tesla T4");
+            device.info.pci_bus_id = Some(bus_id.to_string());
+            device.temperature = Some(40);
+            device
+        })
+        .collect();
+    let config: Config =
+        toml::from_str("[labels]\nidentity = [\"index\", \"index_by_busid\"]\n").unwrap();
+    assert!(config.validate().is_ok());
+    let collector = GpuCollector::with_config(MockBackend::new(devices), &config).unwrap();
+
+    let output = render(collector);
+
+    for (index, index_by_busid) in &[(0, 1), (1, 0), (2, 2)] {
+        assert!(output.contains(&format!(
+            "nvidia_gpu_temperature_celsius{{index=\"{}\",index_by_busid=\"{}\"}} 40\n",
+            index, index_by_busid
+        )));
+    }
+}
+
+#[test]
+fn unknown_identity_labels_are_rejected() {
+    let config: Config = toml::from_str("[labels]\nidentity = [\"rack\"]\n").unwrap();
+
+    assert!(config.validate().is_err());
+}
+
+#[test]
+fn mig_instances_are_exported_as_configured() {
+    let mut device = MockDevice::new(0, "A100-SXM4-40GB");
+    device.memory_info = Some(MemoryInfo {
+        total: 100,
+        free: 60,
+        used: 40,
+    });
+    device.mig_devices = Some(vec![
+        MigDevice {
+            uuid: "MIG-00000000-0000-0000-0000-00000000000a".to_string(),
+            memory_info: MemoryInfo {
+                total: 50,
+                free: 20,
+                used: 30,
+            },
+        },
+        MigDevice {
+            uuid: "MIG-00000000-0000-0000-0000-00000000000b".to_string(),
+            memory_info: MemoryInfo {
+                total: 50,
+                free: 40,
+                used: 10,
+            },
+        },
+    ]);
+    let backend = MockBackend::new(vec![device, MockDevice::new(1, "Tesla T4")]);
+    let parent = "minor_number=\"0\",name=\"A100-SXM4-40GB\",uuid=\"GPU-00000000-0000-0000-0000-000000000000\"";
+    let instance = "minor_number=\"0\",name=\"A100-SXM4-40GB\",uuid=\"MIG-00000000-0000-0000-0000-00000000000a\"";
+
+    let output = render(GpuCollector::with_backend(backend.clone()).unwrap());
+    assert!(output.contains(&format!("nvidia_gpu_memory_used_bytes{{{}}} 40\n", parent)));
+    assert!(!output.contains("MIG-"));
+
+    let config: Config = toml::from_str("[labels]\nmig = \"instances\"\n").unwrap();
+    let output = render(GpuCollector::with_config(backend.clone(), &config).unwrap());
+    assert!(output.contains(&format!(
+        "nvidia_gpu_memory_used_bytes{{{}}} 30\n",
+        instance
+    )));
+    assert!(output.contains("uuid=\"MIG-00000000-0000-0000-0000-00000000000b\"} 10\n"));
+    assert!(!output.contains(&format!("nvidia_gpu_memory_used_bytes{{{}}}", parent)));
+
+    let config: Config = toml::from_str("[labels]\nmig = \"both\"\n").unwrap();
+    let output = render(GpuCollector::with_config(backend, &config).unwrap());
+    assert!(output.contains(
+        "nvidia_gpu_memory_used_bytes{minor_number=\"0\",name=\"A100-SXM4-40GB\",parent_uuid=\"\",uuid=\"GPU-00000000-0000-0000-0000-000000000000\"} 40\n"
+    ));
+    assert!(output.contains(
+        "nvidia_gpu_memory_used_bytes{minor_number=\"0\",name=\"A100-SXM4-40GB\",parent_uuid=\"GPU-00000000-0000-0000-0000-000000000000\",uuid=\"MIG-00000000-0000-0000-0000-00000000000a\"} 30\n"
+    ));
+
+    let config: Config =
+        toml::from_str("[labels]\nidentity = [\"index\"]\nmig = \"both\"\n").unwrap();
+    assert!(config.validate().is_err());
+}
+
+#[test]
+fn units_are_configurable() {
+    let mut device = MockDevice::new(0, "Tesla T4");
+    device.power_usage = Some(70500);
+    device.graphics_clock = Some(1590);
+    let backend = MockBackend::new(vec![device]);
+
+    let output = render(GpuCollector::with_backend(backend.clone()).unwrap());
+    assert!(output.contains("nvidia_gpu_power_usage_milliwatts{"));
+    assert!(output.contains("} 70500\n"));
+    assert!(output.contains("nvidia_gpu_clock_speed_graphics_hertz{"));
+    assert!(output.contains("} 1590000000\n"));
+
+    let config: Config =
+        toml::from_str("[units]\npower = \"watts\"\nclocks = \"megahertz\"\n").unwrap();
+    let output = render(GpuCollector::with_config(backend, &config).unwrap());
+    assert!(output.contains("nvidia_gpu_power_usage_watts{"));
+    assert!(output.contains("} 70.5\n"));
+    assert!(output.contains("nvidia_gpu_clock_speed_graphics_megahertz{"));
+    assert!(!output.contains("_milliwatts"));
+    assert!(!output.contains("_hertz"));
+}
+
+#[test]
+fn power_usage_is_exported_by_scope_where_reported() {
+    let mut scoped = MockDevice::new(0, "GH200 480GB");
+    scoped.power_usage = Some(300_000);
+    scoped.module_power_usage = Some(450_000);
+    scoped.memory_power_usage = Some(40_000);
+    let mut legacy = MockDevice::new(1, "Tesla T4");
+    legacy.power_usage = Some(70_000);
+    let backend = MockBackend::new(vec![scoped, legacy]);
+
+    let output = render(GpuCollector::with_backend(backend).unwrap());
+
+    for (scope, value) in &[("gpu", 300000), ("module", 450000), ("memory", 40000)] {
+        assert!(output.contains(&format!(
+            "nvidia_gpu_power_usage_milliwatts{{minor_number=\"0\",name=\"GH200 480GB\",scope=\"{}\",uuid=\"GPU-00000000-0000-0000-0000-000000000000\"}} {}\n",
+            scope, value
+        )));
+    }
+    assert!(output.contains(
+        "nvidia_gpu_power_usage_milliwatts{minor_number=\"1\",name=\"Tesla T4\",scope=\"\",uuid=\"GPU-00000000-0000-0000-0000-000000000001\"} 70000\n"
+    ));
+}
+
+#[test]
+fn fan_speed_is_exported_in_rpm_where_supported() {
+    let mut device = MockDevice::new(0, "GeForce RTX 2080");
+    device.fan_speed = Some(40);
+    device.fan_speed_rpm = Some(1800);
+    device.target_fan_speed = Some(45);
+    let backend = MockBackend::new(vec![device, MockDevice::new(1, "Tesla T4")]);
+
+    let output = render(GpuCollector::with_backend(backend).unwrap());
+
+    assert!(output.contains("nvidia_gpu_fan_speed_rpm{minor_number=\"0\""));
+    assert!(output.contains("} 1800\n"));
+    assert!(output.contains("nvidia_gpu_fan_speed_target_percent{minor_number=\"0\""));
+    assert!(!output.contains("nvidia_gpu_fan_speed_rpm{minor_number=\"1\""));
+}
+
+#[test]
+fn renamed_metrics_are_exported_under_their_old_names_unless_excluded() {
+    let mut device = MockDevice::new(0, "GeForce RTX 2080");
+    device.fan_speed = Some(40);
+    device.fan_speed_rpm = Some(1800);
+    let backend = MockBackend::new(vec![device, MockDevice::new(1, "Tesla T4")]);
+    let labels =
+        "minor_number=\"0\",name=\"GeForce RTX 2080\",uuid=\"GPU-00000000-0000-0000-0000-000000000000\"";
+
+    let output = render(GpuCollector::with_backend(backend.clone()).unwrap());
+    assert!(output.contains(&format!("nvidia_gpu_fan_speed_percent{{{}}} 40\n", labels)));
+    assert!(output.contains(&format!("nvidia_gpu_fanspeed_percent{{{}}} 40\n", labels)));
+    assert!(output.contains("# HELP nvidia_gpu_fanspeed_percent Fan speed of the GPU device as a percent of its maximum (deprecated, renamed to nvidia_gpu_fan_speed_percent)\n"));
+    // Only released under its current name
+    assert!(!output.contains("nvidia_gpu_fanspeed_rpm"));
+
+    let config: Config = toml::from_str(
+        "deprecated_metrics = \"include\"\n[collectors.fan]\nunsupported = \"supported_gauge\"\n",
+    )
+    .unwrap();
+    let output = render(GpuCollector::with_config(backend.clone(), &config).unwrap());
+    assert!(output.contains(&format!(
+        "nvidia_gpu_fanspeed_percent_supported{{{}}} 1\n",
+        labels
+    )));
+
+    let config: Config = toml::from_str("deprecated_metrics = \"exclude\"\n").unwrap();
+    let output = render(GpuCollector::with_config(backend, &config).unwrap());
+    assert!(output.contains(&format!("nvidia_gpu_fan_speed_percent{{{}}} 40\n", labels)));
+    assert!(!output.contains("nvidia_gpu_fanspeed"));
+}
+
+#[test]
+fn unsupported_readings_are_exported_as_configured() {
+    let mut device = MockDevice::new(0, "GeForce RTX 2080");
+    device.fan_speed = Some(0);
+    let backend = MockBackend::new(vec![device, MockDevice::new(1, "Tesla T4")]);
+    let passive =
+        "minor_number=\"1\",name=\"Tesla T4\",uuid=\"GPU-00000000-0000-0000-0000-000000000001\"";
+
+    let output = render(GpuCollector::with_backend(backend.clone()).unwrap());
+    assert!(!output.contains(&format!("nvidia_gpu_fan_speed_percent{{{}}}", passive)));
+
+    let config: Config = toml::from_str("[collectors.fan]\nunsupported = \"nan\"\n").unwrap();
+    let output = render(GpuCollector::with_config(backend.clone(), &config).unwrap());
+    assert!(output.contains("nvidia_gpu_fan_speed_percent{minor_number=\"0\""));
+    assert!(output.contains(&format!(
+        "nvidia_gpu_fan_speed_percent{{{}}} NaN\n",
+        passive
+    )));
+    assert!(!output.contains("_supported"));
+
+    let config: Config =
+        toml::from_str("[collectors.fan]\nunsupported = \"supported_gauge\"\n").unwrap();
+    let output = render(GpuCollector::with_config(backend, &config).unwrap());
+    assert!(!output.contains(&format!("nvidia_gpu_fan_speed_percent{{{}}}", passive)));
+    assert!(output.contains("nvidia_gpu_fan_speed_percent_supported{minor_number=\"0\""));
+    assert!(output.contains(&format!(
+        "nvidia_gpu_fan_speed_percent_supported{{{}}} 0\n",
+        passive
+    )));
+    assert!(output.contains(&format!(
+        "nvidia_gpu_fan_speed_rpm_supported{{{}}} 0\n",
+        passive
+    )));
+}
+
+#[test]
+fn operation_mode_is_exported_per_mode() {
+    let mut device = MockDevice::new(0, "Tesla K80");
+    device.operation_mode = Some(OperationMode::Compute);
+    let backend = MockBackend::new(vec![device]);
+
+    let output = render(GpuCollector::with_backend(backend).unwrap());
+
+    assert!(output.contains("nvidia_gpu_operation_mode{minor_number=\"0\",mode=\"compute\""));
+    assert!(output.contains("mode=\"compute\",name=\"Tesla K80\",uuid=\"GPU-00000000-0000-0000-0000-000000000000\"} 1\n"));
+    assert!(output.contains(
+        "mode=\"all_on\",name=\"Tesla K80\",uuid=\"GPU-00000000-0000-0000-0000-000000000000\"} 0\n"
+    ));
+}
+
+#[test]
+fn numa_node_and_cpu_affinity_are_exported_as_labels() {
+    let mut device = MockDevice::new(0, "Tesla T4");
+    device.numa_node = Some(1);
+    device.cpu_affinity = Some(vec![16, 17, 18, 19, 48, 50, 51]);
+    let backend = MockBackend::new(vec![device, MockDevice::new(1, "Tesla T4")]);
+
+    let output = render(GpuCollector::with_backend(backend).unwrap());
+
+    assert!(output.contains(
+        "nvidia_gpu_affinity_info{cpus=\"16-19,48,50-51\",minor_number=\"0\",name=\"Tesla T4\",numa_node=\"1\","
+    ));
+    assert!(!output.contains("nvidia_gpu_affinity_info{cpus=\"\",minor_number=\"1\""));
+}
+
+#[test]
+fn physical_slot_is_exported_as_label() {
+    let mut device = MockDevice::new(0, "Tesla T4");
+    device.physical_slot = Some("PCIe Slot 3".to_string());
+    let backend = MockBackend::new(vec![device, MockDevice::new(1, "Tesla T4")]);
+
+    let output = render(GpuCollector::with_backend(backend).unwrap());
+
+    assert!(output.contains(
+        "nvidia_gpu_slot_info{minor_number=\"0\",name=\"Tesla T4\",slot=\"PCIe Slot 3\","
+    ));
+    assert!(!output.contains("nvidia_gpu_slot_info{minor_number=\"1\""));
+}
+
+#[test]
+fn compute_capability_is_exported_as_labels() {
+    let mut device = MockDevice::new(0, "Tesla T4");
+    device.compute_capability = Some((7, 5));
+    let backend = MockBackend::new(vec![device]);
+
+    let output = render(GpuCollector::with_backend(backend).unwrap());
+
+    assert!(
+        output.contains("nvidia_gpu_compute_capability{major=\"7\",minor=\"5\",minor_number=\"0\"")
+    );
+}
+
+#[test]
+fn pcie_errors_are_exported_per_severity() {
+    let mut device = MockDevice::new(0, "Tesla V100-SXM2-16GB");
+    device.pcie_errors = Some(PcieErrors {
+        replays: Some(12),
+        correctable: Some(3),
+        non_fatal: Some(0),
+        fatal: None,
+    });
+    let backend = MockBackend::new(vec![device]);
+
+    let output = render(GpuCollector::with_backend(backend).unwrap());
+
+    assert!(output.contains("nvidia_gpu_pcie_replays_total{"));
+    assert!(output.contains("} 12\n"));
+    assert!(output.contains(
+        "severity=\"correctable\",uuid=\"GPU-00000000-0000-0000-0000-000000000000\"} 3\n"
+    ));
+    assert!(output.contains("severity=\"non_fatal\""));
+    assert!(!output.contains("severity=\"fatal\""));
+}
+
+#[test]
+fn pcie_errors_are_read_from_the_fields_of_nvml_h() {
+    assert_eq!(NvmlBackend::field_id(Field::PcieReplays), (94, 0));
+    // NVML_FI_DEV_PCIE_COUNT_CORRECTABLE_ERRORS, ..._NON_FATAL_ERROR and
+    // ..._FATAL_ERROR, not the BAD_TLP, LCRC_ERROR and LANE_ERROR next to them
+    assert_eq!(
+        NvmlBackend::field_id(Field::PcieCorrectableErrors),
+        (173, 0)
+    );
+    assert_eq!(NvmlBackend::field_id(Field::PcieNonFatalErrors), (179, 0));
+    assert_eq!(NvmlBackend::field_id(Field::PcieFatalErrors), (180, 0));
+}
+
+#[test]
+fn grid_license_state_is_exported_per_feature() {
+    let mut device = MockDevice::new(0, "Tesla T4");
+    device.grid_licenses = Some(vec![GridLicense {
+        feature: "vgpu".to_string(),
+        product: "NVIDIA Virtual PC".to_string(),
+        licensed: false,
+        enabled: true,
+    }]);
+    let backend = MockBackend::new(vec![device]);
+
+    let output = render(GpuCollector::with_backend(backend).unwrap());
+
+    assert!(output.contains("nvidia_gpu_grid_licensed{feature=\"vgpu\",minor_number=\"0\",name=\"Tesla T4\",product=\"NVIDIA Virtual PC\""));
+    assert!(output.contains("nvidia_gpu_grid_feature_enabled{feature=\"vgpu\""));
+}
+
+#[test]
+fn persistence_mode_is_enabled_on_selected_devices() {
+    let mut devices = vec![
+        MockDevice::new(0, "Tesla T4"),
+        MockDevice::new(1, "Tesla T4"),
+    ];
+    for device in &mut devices {
+        device.persistence_mode = Some(false);
+    }
+    let backend = MockBackend::new(devices);
+    let collector = GpuCollector::with_backend(backend.clone()).unwrap();
+
+    let results = collector
+        .enable_persistence_mode(&["1".to_string()])
+        .unwrap();
+
+    assert_eq!(results.len(), 1);
+    assert!(results[0].1.is_ok());
+    assert!(!backend.persistence_mode(0).unwrap());
+    assert!(backend.persistence_mode(1).unwrap());
+    assert!(render(collector).contains("nvidia_gpu_persistence_mode{"));
+}
+
+/// Backend whose device enumeration fails until it is reinitialized.
+#[derive(Default)]
+struct CrashedBackend {
+    reinits: AtomicUsize,
+}
+
+impl GpuBackend for CrashedBackend {
+    fn device_count(&self) -> Result<u32> {
+        if self.reinits.load(Ordering::SeqCst) == 0 {
+            Err(CollectingError::NotFound)
+        } else {
+            Ok(0)
+        }
+    }
+
+    fn device_info(&self, _index: u32) -> Result<DeviceInfo> {
+        Err(CollectingError::NotFound)
+    }
+
+    fn utilization(&self, _index: u32) -> Result<Utilization> {
+        Err(CollectingError::NotFound)
+    }
+
+    fn memory_info(&self, _index: u32) -> Result<MemoryInfo> {
+        Err(CollectingError::NotFound)
+    }
+
+    fn processes(&self, _index: u32) -> Result<Vec<ProcessInfo>> {
+        Err(CollectingError::NotFound)
+    }
+
+    fn reinit(&self) -> Result<()> {
+        self.reinits.fetch_add(1, Ordering::SeqCst);
+        Ok(())
+    }
+}
+
+#[test]
+fn watchdog_reinitializes_after_repeated_failures() {
+    let collector = GpuCollector::with_backend(CrashedBackend::default()).unwrap();
+    let config = WatchdogConfig::default();
+
+    collector.collect();
+    collector.collect();
+    assert!(!collector.check_watchdog(&config));
+
+    collector.collect();
+    assert!(collector.check_watchdog(&config));
+    assert!(!collector.check_watchdog(&config));
+
+    collector.collect();
+    assert_eq!(collector.last_collection_succeeded(), Some(true));
+    assert!(render(collector).contains("nvidia_gpu_nvml_reinits_total 1\n"));
+}
+
+#[test]
+fn unsupported_readings_are_not_queried_again() {
+    // The device does not report a fan speed
+    let collector = GpuCollector::with_backend(backend()).unwrap();
+
+    collector.collect();
+    collector.collect();
+    let output = render(collector);
+
+    assert!(!output.contains("nvidia_gpu_fan_speed_percent"));
+    assert!(output.contains("nvidia_gpu_nvml_call_duration_seconds_count{call=\"fan_speed\"} 1\n"));
+}
+
+#[test]
+fn utilization_averages_are_exported_once_sampled() {
+    let collector = GpuCollector::with_backend(backend()).unwrap();
+    assert!(!render(collector.clone()).contains("nvidia_gpu_gpu_utilization_avg_1m"));
+
+    collector.sample().unwrap();
+    collector.sample().unwrap();
+    let output = render(collector);
+
+    for metric in &[
+        "nvidia_gpu_gpu_utilization_avg_1m{",
+        "nvidia_gpu_gpu_utilization_avg_5m{",
+    ] {
+        assert!(output
+            .lines()
+            .any(|line| line.starts_with(metric) && line.ends_with("} 3")));
+    }
+}
+
+#[test]
+fn throttled_time_is_accumulated_by_the_sampler() {
+    let mut device = MockDevice::new(0, "Tesla T4");
+    device.throttle_reasons = Some(vec![ThrottleReason::SwPowerCap]);
+    let collector = GpuCollector::with_backend(MockBackend::new(vec![device])).unwrap();
+    assert!(!render(collector.clone()).contains("nvidia_gpu_throttle_reason_seconds_total"));
+
+    collector.sample().unwrap();
+    thread::sleep(Duration::from_millis(20));
+    collector.sample().unwrap();
+    let output = render(collector);
+
+    let value = |reason: &str| -> f64 {
+        let prefix = format!(
+            "nvidia_gpu_throttle_reason_seconds_total{{minor_number=\"0\",name=\"Tesla T4\",reason=\"{}\",",
+            reason
+        );
+        let line = output.lines().find(|l| l.starts_with(&prefix)).unwrap();
+        line.rsplit(' ').next().unwrap().parse().unwrap()
+    };
+    assert!(value("sw_power_cap") >= 0.02);
+    assert_eq!(value("hw_slowdown"), 0.0);
+}
+
+#[test]
+fn performance_state_residency_is_accumulated_by_the_sampler() {
+    let mut device = MockDevice::new(0, "Tesla T4");
+    device.performance_state = Some(8);
+    let collector = GpuCollector::with_backend(MockBackend::new(vec![device])).unwrap();
+    assert!(!render(collector.clone()).contains("nvidia_gpu_performance_state_seconds_total"));
+
+    collector.sample().unwrap();
+    thread::sleep(Duration::from_millis(20));
+    collector.sample().unwrap();
+    let output = render(collector);
+
+    let prefix =
+        "nvidia_gpu_performance_state_seconds_total{minor_number=\"0\",name=\"Tesla T4\",pstate=\"P8\",";
+    let line = output.lines().find(|l| l.starts_with(prefix)).unwrap();
+    let value: f64 = line.rsplit(' ').next().unwrap().parse().unwrap();
+    assert!(value >= 0.02);
+    assert!(!output.contains("pstate=\"P0\""));
+}
+
+#[test]
+fn process_memory_peaks_between_scrapes_are_exported() {
+    let mut device = MockDevice::new(0, "Tesla T4");
+    device.utilization = Some(Utilization {
+        gpu: 50,
+        memory: Some(10),
+    });
+    device.processes = vec![ProcessInfo {
+        pid: 1,
+        used_memory: Some(1024),
+        process_type: ProcessType::Compute,
+    }];
+    let backend = MockBackend::new(vec![device]);
+    let collector = GpuCollector::with_backend(backend.clone()).unwrap();
+    let set_memory = |used_memory| {
+        backend.update(0, |device| {
+            device.processes[0].used_memory = Some(used_memory)
+        })
+    };
+    let value = |output: &str, name: &str| {
+        output
+            .lines()
+            .find(|l| l.starts_with(&format!("{}{{", name)))
+            .map(|l| l.rsplit(' ').next().unwrap().to_string())
+    };
+    let max = |output: &str| value(output, "nvidia_gpu_process_memory_max_bytes");
+
+    assert_eq!(max(&render(collector.clone())).as_deref(), Some("1024"));
+
+    // The spike is only seen by the sampler
+    set_memory(4096);
+    collector.sample().unwrap();
+    set_memory(2048);
+    let output = render(collector.clone());
+    let used = value(&output, "nvidia_gpu_process_memory_used_bytes");
+    assert_eq!(used.as_deref(), Some("2048"));
+    assert_eq!(max(&output).as_deref(), Some("4096"));
+
+    // A new process reusing the PID starts over
+    backend.update(0, |device| device.processes.clear());
+    assert_eq!(max(&render(collector.clone())), None);
+    backend.update(0, |device| {
+        device.processes.push(ProcessInfo {
+            pid: 1,
+            used_memory: Some(512),
+            process_type: ProcessType::Compute,
+        })
+    });
+    assert_eq!(max(&render(collector)).as_deref(), Some("512"));
+}
+
+#[test]
+fn idle_time_is_tracked_by_the_sampler() {
+    let mut idle = MockDevice::new(0, "Tesla T4");
+    idle.utilization = Some(Utilization {
+        gpu: 0,
+        memory: Some(0),
+    });
+    let mut busy = MockDevice::new(1, "Tesla T4");
+    busy.utilization = idle.utilization;
+    busy.processes = vec![ProcessInfo {
+        pid: 1,
+        used_memory: Some(1024),
+        process_type: ProcessType::Compute,
+    }];
+    let collector = GpuCollector::with_backend(MockBackend::new(vec![idle, busy])).unwrap();
+    assert!(!render(collector.clone()).contains("nvidia_gpu_gpu_idle_seconds"));
+
+    collector.sample().unwrap();
+    thread::sleep(Duration::from_millis(20));
+    collector.sample().unwrap();
+    let output = render(collector);
+
+    let value = |minor_number: &str| -> f64 {
+        let prefix = format!(
+            "nvidia_gpu_gpu_idle_seconds{{minor_number=\"{}\",",
+            minor_number
+        );
+        let line = output.lines().find(|l| l.starts_with(&prefix)).unwrap();
+        line.rsplit(' ').next().unwrap().parse().unwrap()
+    };
+    assert!(value("0") >= 0.02);
+    assert_eq!(value("1"), 0.0);
+}
+
+#[test]
+fn xid_errors_and_resets_are_counted_by_the_watcher() {
+    let backend = MockBackend::new(vec![
+        MockDevice::new(0, "Tesla T4"),
+        MockDevice::new(1, "Tesla T4"),
+    ]);
+    let collector = GpuCollector::with_backend(backend.clone()).unwrap();
+    assert!(!render(collector.clone()).contains("nvidia_gpu_gpu_resets_total"));
+
+    backend.report_xid_error(1, 79);
+    backend.report_xid_error(1, 13);
+    backend.report_xid_error(1, 13);
+    collector.spawn_xid_watcher();
+
+    // The errors are counted in the order they were reported
+    let device =
+        "minor_number=\"1\",name=\"Tesla T4\",uuid=\"GPU-00000000-0000-0000-0000-000000000001\"";
+    let xid_13 = format!("nvidia_gpu_xid_errors_total{{{},xid=\"13\"}} 2\n", device);
+    let mut output = String::new();
+    for _ in 0..100 {
+        output = render(collector.clone());
+        if output.contains(&xid_13) {
+            break;
+        }
+        thread::sleep(Duration::from_millis(10));
+    }
+
+    assert!(output.contains(&xid_13));
+    assert!(output.contains(
+        "nvidia_gpu_gpu_resets_total{minor_number=\"1\",name=\"Tesla T4\",reason=\"fallen_off_bus\",uuid=\"GPU-00000000-0000-0000-0000-000000000001\"} 1\n"
+    ));
+    assert!(output.contains(
+        "nvidia_gpu_gpu_resets_total{minor_number=\"0\",name=\"Tesla T4\",reason=\"fallen_off_bus\",uuid=\"GPU-00000000-0000-0000-0000-000000000000\"} 0\n"
+    ));
+}
+
+#[test]
+fn xid_watcher_health_is_exported_while_it_runs() {
+    let backend = MockBackend::new(vec![MockDevice::new(0, "Tesla T4")]);
+    let collector = GpuCollector::with_backend(backend.clone()).unwrap();
+    assert!(!render(collector.clone()).contains("nvidia_gpu_exporter_xid_"));
+
+    // The second error is reported for a device that does not exist
+    backend.report_xid_error(0, 79);
+    backend.report_xid_error(5, 79);
+    collector.spawn_xid_watcher();
+
+    let dropped = "nvidia_gpu_exporter_xid_events_dropped_total 1\n";
+    let mut output = String::new();
+    for _ in 0..100 {
+        output = render(collector.clone());
+        if output.contains(dropped) {
+            break;
+        }
+        thread::sleep(Duration::from_millis(10));
+    }
+
+    assert!(output.contains(dropped));
+    assert!(output.contains("nvidia_gpu_exporter_xid_events_received_total 2\n"));
+    let line = output
+        .lines()
+        .find(|l| l.starts_with("nvidia_gpu_exporter_xid_watcher_lag_seconds "))
+        .unwrap();
+    let lag: f64 = line.rsplit(' ').next().unwrap().parse().unwrap();
+    assert!(lag < 1.5);
+}
+
+#[test]
+fn derived_counters_are_restored_after_a_restart() {
+    let path = std::env::temp_dir().join(format!("gpu-state-{}.json", std::process::id()));
+    let _ = fs::remove_file(&path);
+    let mut device = MockDevice::new(0, "Tesla T4");
+    device.throttle_reasons = Some(vec![ThrottleReason::SwPowerCap]);
+    let labels =
+        "minor_number=\"0\",name=\"Tesla T4\",uuid=\"GPU-00000000-0000-0000-0000-000000000000\"";
+    let render_until = |collector: &GpuCollector<MockBackend>, expected: &str| -> String {
+        let mut output = String::new();
+        for _ in 0..100 {
+            output = render(collector.clone());
+            if output.contains(expected) {
+                break;
+            }
+            thread::sleep(Duration::from_millis(10));
+        }
+        output
+    };
+
+    let backend = MockBackend::new(vec![device.clone()]);
+    let collector = GpuCollector::with_backend(backend.clone()).unwrap();
+    // Nothing to restore on the first start
+    collector.restore_state(&path).unwrap();
+    collector.sample().unwrap();
+    thread::sleep(Duration::from_millis(20));
+    collector.sample().unwrap();
+    backend.report_xid_error(0, 79);
+    collector.spawn_xid_watcher();
+    let xid = format!("nvidia_gpu_xid_errors_total{{{},xid=\"79\"}} 1\n", labels);
+    assert!(render_until(&collector, &xid).contains(&xid));
+    collector.save_state(&path).unwrap();
+
+    // Another instance counting one more error
+    let backend = MockBackend::new(vec![device]);
+    let restarted = GpuCollector::with_backend(backend.clone()).unwrap();
+    restarted.restore_state(&path).unwrap();
+    backend.report_xid_error(0, 79);
+    restarted.spawn_xid_watcher();
+    let xid = format!("nvidia_gpu_xid_errors_total{{{},xid=\"79\"}} 2\n", labels);
+    let output = render_until(&restarted, &xid);
+    fs::remove_file(&path).unwrap();
+
+    assert!(output.contains(&xid));
+    let prefix = "nvidia_gpu_throttle_reason_seconds_total{minor_number=\"0\",name=\"Tesla T4\",reason=\"sw_power_cap\",";
+    let line = output.lines().find(|l| l.starts_with(prefix)).unwrap();
+    let throttled: f64 = line.rsplit(' ').next().unwrap().parse().unwrap();
+    assert!(throttled >= 0.02);
+}
+
+#[test]
+fn extremes_since_previous_scrape_are_exported() {
+    let collector = GpuCollector::with_backend(backend()).unwrap();
+
+    collector.sample().unwrap();
+    let output = render(collector.clone());
+    assert!(output.contains("nvidia_gpu_gpu_utilization_min{"));
+    assert!(output.contains("nvidia_gpu_gpu_utilization_max{"));
+
+    // Extremes are reset by every scrape
+    assert!(!render(collector).contains("nvidia_gpu_gpu_utilization_max{"));
+}
+
+#[test]
+fn alerts_are_evaluated_against_the_last_scrape() {
+    let config: Config = toml::from_str(
+        "[[alerting.rules]]\nname = \"Busy\"\nmetric = \"nvidia_gpu_gpu_utilization\"\n\
+         op = \">\"\nthreshold = 1.0\n",
+    )
+    .unwrap();
+
+    // Nobody scraped, so the alerts are collected for
+    let idle = GpuCollector::with_config(backend(), &config).unwrap();
+    idle.spawn_alert_evaluation(Duration::from_secs(3600));
+    thread::sleep(Duration::from_millis(100));
+    assert_eq!(idle.alerts().len(), 1);
+
+    let collector = GpuCollector::with_config(backend(), &config).unwrap();
+    let scraped = call_count(&render(collector.clone()), "device_count");
+    collector.spawn_alert_evaluation(Duration::from_secs(3600));
+    thread::sleep(Duration::from_millis(100));
+    let output = render(collector.clone());
+
+    assert_eq!(call_count(&output, "device_count"), 2 * scraped);
+    assert_eq!(collector.alerts().len(), 1);
+}
+
+#[test]
+fn extremes_are_only_reset_by_scrapes_of_all_metrics() {
+    let config: Config = toml::from_str(
+        "[[alerting.rules]]\nname = \"Busy\"\nmetric = \"nvidia_gpu_gpu_utilization\"\n\
+         op = \">\"\nthreshold = 90.0\n",
+    )
+    .unwrap();
+    let collector = GpuCollector::with_config(backend(), &config).unwrap();
+
+    collector.sample().unwrap();
+    collector.spawn_alert_evaluation(Duration::from_millis(10));
+    thread::sleep(Duration::from_millis(100));
+    let device = collector.collect_device("0").unwrap();
+    assert!(device
+        .iter()
+        .any(|family| family.get_name() == "nvidia_gpu_gpu_utilization_max"));
+    collector
+        .collect_only(&["utilization".to_string()])
+        .unwrap();
+
+    let output = render(collector.clone());
+    assert!(output.contains("nvidia_gpu_gpu_utilization_max{"));
+    assert!(!render(collector).contains("nvidia_gpu_gpu_utilization_max{"));
+}
+
+#[test]
+fn kubernetes_allocations_are_exported_per_device() {
+    let backend = MockBackend::new(vec![
+        MockDevice::new(0, "Tesla T4"),
+        MockDevice::new(1, "Tesla T4"),
+    ]);
+    let collector = GpuCollector::with_backend(backend).unwrap();
+
+    let output = render(collector.clone());
+    assert!(!output.contains("nvidia_gpu_gpu_allocated"));
+
+    collector.set_allocations(vec![Allocation {
+        device_id: "GPU-00000000-0000-0000-0000-000000000001".to_string(),
+        pod: "train-0".to_string(),
+        namespace: "ml".to_string(),
+        container: "trainer".to_string(),
+    }]);
+    let output = render(collector);
+
+    assert!(output.contains(
+        "nvidia_gpu_gpu_allocated{minor_number=\"0\",name=\"Tesla T4\",namespace=\"\",pod=\"\""
+    ));
+    assert!(output.contains(
+        "nvidia_gpu_gpu_allocated{minor_number=\"1\",name=\"Tesla T4\",namespace=\"ml\",pod=\"train-0\",uuid=\"GPU-00000000-0000-0000-0000-000000000001\"} 1"
+    ));
+}
+
+#[test]
+fn gpu_utilization_is_attributed_to_pods() {
+    let utilization = vec![
+        ProcessUtilization {
+            pid: u32::max_value() - 1,
+            gpu: 30,
+            memory: 10,
+        },
+        ProcessUtilization {
+            pid: u32::max_value() - 2,
+            gpu: 20,
+            memory: 5,
+        },
+    ];
+    let mut exclusive = MockDevice::new(0, "Tesla T4");
+    exclusive.process_utilization = Some(utilization.clone());
+    let mut shared = MockDevice::new(1, "Tesla T4");
+    shared.process_utilization = Some(utilization);
+    let collector = GpuCollector::with_backend(MockBackend::new(vec![exclusive, shared])).unwrap();
+
+    let allocation = |device_id: &str, pod: &str| Allocation {
+        device_id: device_id.to_string(),
+        pod: pod.to_string(),
+        namespace: "ml".to_string(),
+        container: "trainer".to_string(),
+    };
+    collector.set_allocations(vec![
+        allocation("GPU-00000000-0000-0000-0000-000000000000", "train-0"),
+        // Replicas of a time-sliced device
+        allocation("GPU-00000000-0000-0000-0000-000000000001::0", "infer-0"),
+        allocation("GPU-00000000-0000-0000-0000-000000000001::1", "infer-1"),
+    ]);
+    let output = render(collector);
+
+    assert!(output.contains(
+        "nvidia_gpu_pod_gpu_utilization{minor_number=\"0\",name=\"Tesla T4\",namespace=\"ml\",pod=\"train-0\",uuid=\"GPU-00000000-0000-0000-0000-000000000000\"} 50\n"
+    ));
+    assert!(output.contains(
+        "nvidia_gpu_gpu_allocated{minor_number=\"1\",name=\"Tesla T4\",namespace=\"ml\",pod=\"infer-1\",uuid=\"GPU-00000000-0000-0000-0000-000000000001\"} 1\n"
+    ));
+    // Neither process exists, so they cannot be attributed on a shared device
+    assert!(output.contains(
+        "nvidia_gpu_pod_gpu_utilization{minor_number=\"1\",name=\"Tesla T4\",namespace=\"ml\",pod=\"infer-0\",uuid=\"GPU-00000000-0000-0000-0000-000000000001\"} 0\n"
+    ));
+    assert!(output.contains(
+        "nvidia_gpu_pod_gpu_utilization{minor_number=\"1\",name=\"Tesla T4\",namespace=\"\",pod=\"\",uuid=\"GPU-00000000-0000-0000-0000-000000000001\"} 50\n"
+    ));
+}
+
+#[test]
+fn process_memory_is_summed_up_per_cgroup() {
+    // Neither process exists, so both end up in the unknown cgroup
+    let mut device = MockDevice::new(0, "Tesla T4");
+    device.processes = vec![
+        ProcessInfo {
+            pid: u32::max_value() - 1,
+            used_memory: Some(100),
+            process_type: ProcessType::Compute,
+        },
+        ProcessInfo {
+            pid: u32::max_value() - 2,
+            used_memory: Some(50),
+            process_type: ProcessType::Compute,
+        },
+    ];
+    let backend = MockBackend::new(vec![device]);
+
+    let output = render(GpuCollector::with_backend(backend).unwrap());
+
+    assert!(output.contains(
+        "nvidia_gpu_cgroup_memory_used_bytes{cgroup=\"\",minor_number=\"0\",name=\"Tesla T4\",uuid=\"GPU-00000000-0000-0000-0000-000000000000\"} 150\n"
+    ));
+}
+
+#[test]
+fn process_memory_is_bucketed_per_device() {
+    let mut device = MockDevice::new(0, "Tesla T4");
+    device.processes = vec![
+        ProcessInfo {
+            pid: u32::max_value() - 1,
+            used_memory: Some(512 * 1024 * 1024),
+            process_type: ProcessType::Compute,
+        },
+        ProcessInfo {
+            pid: u32::max_value() - 2,
+            used_memory: Some(12 * 1024 * 1024 * 1024),
+            process_type: ProcessType::Compute,
+        },
+    ];
+    let backend = MockBackend::new(vec![device]);
+
+    let output = render(GpuCollector::with_backend(backend).unwrap());
+
+    let labels =
+        "minor_number=\"0\",name=\"Tesla T4\",uuid=\"GPU-00000000-0000-0000-0000-000000000000\"";
+    assert!(output.contains(&format!(
+        "nvidia_gpu_process_memory_bytes_bucket{{{},le=\"1073741824\"}} 1\n",
+        labels
+    )));
+    assert!(output.contains(&format!(
+        "nvidia_gpu_process_memory_bytes_bucket{{{},le=\"17179869184\"}} 2\n",
+        labels
+    )));
+    assert!(output.contains(&format!(
+        "nvidia_gpu_process_memory_bytes_count{{{}}} 2\n",
+        labels
+    )));
+}
+
+#[test]
+fn processes_are_labeled_with_their_type() {
+    let mut device = MockDevice::new(0, "Tesla T4");
+    device.processes = vec![ProcessInfo {
+        pid: u32::max_value() - 1,
+        used_memory: Some(100),
+        process_type: ProcessType::Graphics,
+    }];
+    let backend = MockBackend::new(vec![device]);
+
+    let output = render(GpuCollector::with_backend(backend).unwrap());
+
+    assert!(output.contains(&format!(
+        "nvidia_gpu_process_memory_used_bytes{{command=\"\",minor_number=\"0\",name=\"Tesla T4\",pid=\"{}\",type=\"graphics\",user=\"\",uuid=\"GPU-00000000-0000-0000-0000-000000000000\"}} 100\n",
+        u32::max_value() - 1
+    )));
+}
+
+#[test]
+fn process_owners_and_commands_are_pseudonymized_if_enabled() {
+    let mut device = MockDevice::new(0, "Tesla T4");
+    device.processes = vec![ProcessInfo {
+        pid: std::process::id(),
+        used_memory: Some(100),
+        process_type: ProcessType::Compute,
+    }];
+    let backend = MockBackend::new(vec![device]);
+    let config: Config = toml::from_str("[privacy]\nenabled = true\nsalt = \"secret\"\n").unwrap();
+    let user_label = |output: &str| -> String {
+        let line = output
+            .lines()
+            .find(|l| l.starts_with("nvidia_gpu_process_memory_used_bytes{"))
+            .unwrap();
+        let start = line.find("user=\"").unwrap() + "user=\"".len();
+        line[start..].split('"').next().unwrap().to_string()
+    };
+
+    let output = render(GpuCollector::with_config(backend.clone(), &config).unwrap());
+
+    // The test binary runs as the only process of the device
+    let executable = std::env::args().next().unwrap();
+    let executable = std::path::Path::new(&executable)
+        .file_name()
+        .unwrap()
+        .to_string_lossy()
+        .into_owned();
+    assert!(output.contains(&format!("command=\"{}\"", executable)));
+    let user = user_label(&output);
+    assert!(user.starts_with("u-"));
+    assert_eq!(user.len(), "u-".len() + 16);
+
+    // Stable for the same salt only
+    let again = render(GpuCollector::with_config(backend.clone(), &config).unwrap());
+    assert_eq!(user_label(&again), user);
+    let config: Config = toml::from_str("[privacy]\nenabled = true\nsalt = \"other\"\n").unwrap();
+    let salted = render(GpuCollector::with_config(backend, &config).unwrap());
+    assert_ne!(user_label(&salted), user);
+
+    let config: Config = toml::from_str("[privacy]\nenabled = true\n").unwrap();
+    assert!(config.validate().is_err());
+}
+
+#[test]
+fn pods_are_pseudonymized_if_enabled() {
+    let mut device = MockDevice::new(0, "Tesla T4");
+    device.process_utilization = Some(vec![ProcessUtilization {
+        pid: u32::max_value() - 1,
+        gpu: 30,
+        memory: 10,
+    }]);
+    let config: Config = toml::from_str("[privacy]\nenabled = true\nsalt = \"secret\"\n").unwrap();
+    let collector = GpuCollector::with_config(MockBackend::new(vec![device]), &config).unwrap();
+    collector.set_allocations(vec![Allocation {
+        device_id: "GPU-00000000-0000-0000-0000-000000000000".to_string(),
+        pod: "train-alice-0".to_string(),
+        namespace: "ml".to_string(),
+        container: "trainer".to_string(),
+    }]);
+
+    let output = render(collector);
+
+    assert!(!output.contains("alice"));
+    for family in &[
+        "nvidia_gpu_gpu_allocated{",
+        "nvidia_gpu_pod_gpu_utilization{",
+    ] {
+        let line = output.lines().find(|l| l.starts_with(family)).unwrap();
+        assert!(line.contains("namespace=\"ml\",pod=\"p-"));
+    }
+}
+
+#[test]
+fn cgroups_are_pseudonymized_if_enabled() {
+    let mut device = MockDevice::new(0, "Tesla T4");
+    device.processes = vec![ProcessInfo {
+        pid: std::process::id(),
+        used_memory: Some(100),
+        process_type: ProcessType::Compute,
+    }];
+    let config: Config = toml::from_str("[privacy]\nenabled = true\nsalt = \"secret\"\n").unwrap();
+    let collector = GpuCollector::with_config(MockBackend::new(vec![device]), &config).unwrap();
+
+    let output = render(collector);
+
+    let line = output
+        .lines()
+        .find(|l| l.starts_with("nvidia_gpu_cgroup_memory_used_bytes{"))
+        .unwrap();
+    let start = line.find("cgroup=\"").unwrap() + "cgroup=\"".len();
+    let cgroup = line[start..].split('"').next().unwrap();
+    // The test runs in a user slice on desktops, but not necessarily in CI
+    let status = std::fs::read_to_string("/proc/self/status").unwrap_or_default();
+    if let Some(uid) = status
+        .lines()
+        .find(|l| l.starts_with("Uid:"))
+        .and_then(|l| l.split_whitespace().nth(1))
+    {
+        assert!(!cgroup.contains(&format!("user-{}.slice", uid)));
+        assert!(!cgroup.contains(&format!("user@{}.service", uid)));
+        assert!(!cgroup.contains(&format!("uid_{}", uid)));
+    }
+    let raw = std::fs::read_to_string("/proc/self/cgroup").unwrap_or_default();
+    if raw.contains(".slice/user-") {
+        assert!(cgroup.contains("/user-u-"));
+    }
+}
+
+#[test]
+fn process_memory_is_exported_as_a_fraction_of_the_device() {
+    let mut device = MockDevice::new(0, "Tesla T4");
+    device.processes = vec![ProcessInfo {
+        pid: u32::max_value() - 1,
+        used_memory: Some(100),
+        process_type: ProcessType::Compute,
+    }];
+    let backend = MockBackend::new(vec![device.clone()]);
+
+    let output = render(GpuCollector::with_backend(backend).unwrap());
+    assert!(!output.contains("nvidia_gpu_process_memory_used_ratio"));
+
+    device.memory_info = Some(MemoryInfo {
+        total: 400,
+        free: 300,
+        used: 100,
+    });
+    let backend = MockBackend::new(vec![device]);
+
+    let output = render(GpuCollector::with_backend(backend).unwrap());
+    assert!(output.contains(&format!(
+        "nvidia_gpu_process_memory_used_ratio{{command=\"\",minor_number=\"0\",name=\"Tesla T4\",pid=\"{}\",type=\"compute\",user=\"\",uuid=\"GPU-00000000-0000-0000-0000-000000000000\"}} 0.25\n",
+        u32::max_value() - 1
+    )));
+}
+
+#[test]
+fn mps_clients_are_exported_as_processes() {
+    let mut device = MockDevice::new(0, "Tesla T4");
+    device.mps_processes = Some(vec![ProcessInfo {
+        pid: u32::max_value() - 1,
+        used_memory: Some(100),
+        process_type: ProcessType::Compute,
+    }]);
+    let backend = MockBackend::new(vec![device, MockDevice::new(1, "Tesla T4")]);
+
+    let output = render(GpuCollector::with_backend(backend).unwrap());
+
+    assert!(output.contains(
+        "nvidia_gpu_mps_enabled{minor_number=\"0\",name=\"Tesla T4\",uuid=\"GPU-00000000-0000-0000-0000-000000000000\"} 1\n"
+    ));
+    assert!(output.contains(
+        "nvidia_gpu_mps_enabled{minor_number=\"1\",name=\"Tesla T4\",uuid=\"GPU-00000000-0000-0000-0000-000000000001\"} 0\n"
+    ));
+    assert!(output.contains(&format!(
+        "nvidia_gpu_process_memory_used_bytes{{command=\"\",minor_number=\"0\",name=\"Tesla T4\",pid=\"{}\",type=\"compute\",user=\"\",uuid=\"GPU-00000000-0000-0000-0000-000000000000\"}} 100\n",
+        u32::max_value() - 1
+    )));
+}
+
+#[test]
+fn effective_configuration_is_exported() {
+    let config: Config = toml::from_str(
+        "[collectors.fan]\nenabled = false\n[collectors.processes]\nenabled = false\n\
+         [units]\npower = \"watts\"\n[sampling]\nenabled = true\ninterval = \"500ms\"\n",
+    )
+    .unwrap();
+
+    let output = render(GpuCollector::with_config(backend(), &config).unwrap());
+
+    assert!(output.contains(
+        "nvidia_gpu_exporter_config_info{clock_unit=\"hertz\",collectors_enabled=\"utilization,memory,power,clocks,temperature,operation_mode,persistence,info,pcie,grid,kubernetes,gpm,features,xid\",identity_labels=\"minor_number,uuid,name\",kubernetes=\"false\",power_unit=\"watts\",sampling_interval=\"0.5s\",watchdog=\"true\"} 1\n"
+    ));
+}
+
+#[test]
+fn memory_temperature_is_exported_where_reported() {
+    let mut hbm = MockDevice::new(0, "NVIDIA A100-SXM4-40GB");
+    hbm.temperature = Some(60);
+    hbm.memory_temperature = Some(72);
+    let mut gddr = MockDevice::new(1, "Tesla T4");
+    gddr.temperature = Some(50);
+    let backend = MockBackend::new(vec![hbm, gddr]);
+
+    let output = render(GpuCollector::with_backend(backend).unwrap());
+
+    assert!(output.contains(
+        "nvidia_gpu_memory_temperature_celsius{minor_number=\"0\",name=\"NVIDIA A100-SXM4-40GB\",uuid=\"GPU-00000000-0000-0000-0000-000000000000\"} 72\n"
+    ));
+    assert!(!output.contains("nvidia_gpu_memory_temperature_celsius{minor_number=\"1\""));
+}
+
+#[test]
+fn field_values_are_read_once_per_device_and_collection() {
+    let mut device = MockDevice::new(0, "Tesla V100-SXM2-16GB");
+    device.power_usage = Some(70500);
+    device.pcie_errors = Some(PcieErrors {
+        replays: Some(12),
+        correctable: None,
+        non_fatal: None,
+        fatal: None,
+    });
+    let backend = MockBackend::new(vec![device]);
+
+    let output = render(GpuCollector::with_backend(backend).unwrap());
+
+    // Shared by the power and the pcie collector
+    assert!(output.contains("nvidia_gpu_power_usage_milliwatts{"));
+    assert!(output.contains("nvidia_gpu_pcie_replays_total{"));
+    assert!(
+        output.contains("nvidia_gpu_nvml_call_duration_seconds_count{call=\"field_values\"} 1\n")
+    );
+}
+
+#[test]
+fn devices_are_identified_once_while_the_count_is_unchanged() {
+    let collector = GpuCollector::with_backend(backend()).unwrap();
+
+    collector.collect();
+    collector.collect();
+    let output = render(collector);
+
+    assert!(
+        output.contains("nvidia_gpu_nvml_call_duration_seconds_count{call=\"device_count\"} 3\n")
+    );
+    assert!(output.contains("nvidia_gpu_nvml_call_duration_seconds_count{call=\"identity\"} 1\n"));
+}
+
+#[test]
+fn static_attributes_are_read_again_after_refresh() {
+    let mut device = MockDevice::new(0, "Tesla T4");
+    device.vbios_version = Some("90.04.96.00.01".to_string());
+    let collector = GpuCollector::with_backend(MockBackend::new(vec![device])).unwrap();
+
+    collector.collect();
+    collector.collect();
+    let output = render(collector.clone());
+    assert_eq!(call_count(&output, "vbios_version"), 1);
+    assert!(output.contains(
+        "nvidia_gpu_vbios_info{minor_number=\"0\",name=\"Tesla T4\",uuid=\"GPU-00000000-0000-0000-0000-000000000000\",version=\"90.04.96.00.01\"} 1\n"
+    ));
+
+    collector.refresh_devices();
+    let output = render(collector);
+    assert_eq!(call_count(&output, "identity"), 2);
+    assert_eq!(call_count(&output, "vbios_version"), 2);
+}
+
+#[test]
+fn fake_gpus_serve_plausible_readings() {
+    let backend = FakeBackend::new(3);
+
+    for _ in 0..100 {
+        let utilization = backend.utilization(1).unwrap();
+        assert!(utilization.gpu <= 100);
+        let temperature = backend.temperature(1).unwrap();
+        assert!(temperature >= 32 && temperature <= 78);
+    }
+    let memory_info = backend.memory_info(2).unwrap();
+    assert_eq!(memory_info.used + memory_info.free, memory_info.total);
+    assert!(backend.utilization(3).is_err());
+
+    let output = render(GpuCollector::with_backend(backend).unwrap());
+    assert!(output.contains("nvidia_gpu_num_devices 3\n"));
+    assert!(output.contains("nvidia_gpu_process_memory_used_bytes{"));
+}
+
+#[test]
+fn gpm_metrics_are_exported_when_reported() {
+    let mut device = MockDevice::new(0, "NVIDIA H100 80GB HBM3");
+    device.gpm_metrics = Some(GpmMetrics {
+        sm_activity: Some(87.5),
+        sm_occupancy: Some(42.0),
+        tensor_activity: Some(61.25),
+        dram_bandwidth: None,
+    });
+    let backend = MockBackend::new(vec![device]);
+
+    let output = render(GpuCollector::with_backend(backend).unwrap());
+
+    assert!(output.contains(
+        "nvidia_gpu_sm_activity_percent{minor_number=\"0\",name=\"NVIDIA H100 80GB HBM3\",uuid=\"GPU-00000000-0000-0000-0000-000000000000\"} 87.5\n"
+    ));
+    assert!(output.contains("nvidia_gpu_tensor_activity_percent{"));
+    assert!(!output.contains("nvidia_gpu_dram_bandwidth_utilization_percent{"));
+}
+
+#[test]
+fn supported_features_are_exported() {
+    let mut device = MockDevice::new(0, "Tesla V100-SXM2-16GB");
+    device.power_usage = Some(70500);
+    device.features = vec![Feature::Ecc, Feature::Nvlink];
+    let backend = MockBackend::new(vec![device]);
+
+    let output = render(GpuCollector::with_backend(backend).unwrap());
+
+    for &(feature, supported) in &[
+        ("ecc", 1),
+        ("nvlink", 1),
+        ("mig", 0),
+        ("fan", 0),
+        ("power_readings", 1),
+    ] {
+        assert!(output.contains(&format!(
+            "nvidia_gpu_feature_supported{{feature=\"{}\",minor_number=\"0\",name=\"Tesla V100-SXM2-16GB\",uuid=\"GPU-00000000-0000-0000-0000-000000000000\"}} {}\n",
+            feature, supported
+        )));
+    }
+}
+
+/// Backend whose utilization readings fail as if the GPU fell off the bus.
+struct LostBackend {
+    inner: MockBackend,
+    calls: Arc<AtomicUsize>,
+}
+
+impl GpuBackend for LostBackend {
+    fn device_count(&self) -> Result<u32> {
+        self.inner.device_count()
+    }
+
+    fn device_info(&self, index: u32) -> Result<DeviceInfo> {
+        self.inner.device_info(index)
+    }
+
+    fn utilization(&self, _index: u32) -> Result<Utilization> {
+        self.calls.fetch_add(1, Ordering::SeqCst);
+        Err(CollectingError::Nvml(NvmlError::GpuLost))
+    }
+
+    fn memory_info(&self, index: u32) -> Result<MemoryInfo> {
+        self.inner.memory_info(index)
+    }
+
+    fn processes(&self, index: u32) -> Result<Vec<ProcessInfo>> {
+        self.inner.processes(index)
+    }
+}
+
+#[test]
+fn devices_failing_repeatedly_are_skipped() {
+    let config: Config = toml::from_str(
+        "[nvml]\nretries = 1\nretry_backoff = \"1ms\"\ncircuit_breaker_failures = 2\n",
+    )
+    .unwrap();
+    let calls = Arc::new(AtomicUsize::new(0));
+    let backend = LostBackend {
+        inner: backend(),
+        calls: calls.clone(),
+    };
+    let collector = GpuCollector::with_config(backend, &config).unwrap();
+
+    collector.collect();
+    collector.collect();
+    let output = render(collector);
+
+    // Retried once in each of the first two collections, then skipped
+    assert_eq!(calls.load(Ordering::SeqCst), 4);
+    assert!(output.contains(
+        "nvidia_gpu_device_healthy{minor_number=\"0\",name=\"Tesla T4\",uuid=\"GPU-00000000-0000-0000-0000-000000000000\"} 0\n"
+    ));
+}
+
+/// Number of calls of `call` recorded in `output`.
+fn call_count(output: &str, call: &str) -> u64 {
+    let prefix = format!(
+        "nvidia_gpu_nvml_call_duration_seconds_count{{call=\"{}\"}} ",
+        call
+    );
+    output
+        .lines()
+        .find(|line| line.starts_with(&prefix))
+        .map_or(0, |line| line[prefix.len()..].parse().unwrap())
+}
+
+#[test]
+fn background_collectors_run_at_their_own_interval() {
+    let config: Config = toml::from_str(
+        "[sampling]\nenabled = false\nbackground_collection = true\n\
+         [collectors.utilization]\ninterval = \"10ms\"\n",
+    )
+    .unwrap();
+    let collector = GpuCollector::with_config(backend(), &config).unwrap();
+
+    collector.spawn_collectors(Duration::from_secs(3600));
+    thread::sleep(Duration::from_millis(200));
+    let output = render(collector);
+
+    // The scrape is served the results of the background threads
+    assert!(call_count(&output, "utilization") >= 3);
+    assert_eq!(call_count(&output, "memory"), 1);
+}
+
+#[test]
+fn adaptive_collectors_run_less_often_while_busy() {
+    let config = |threshold: u32| -> Config {
+        toml::from_str(&format!(
+            "[sampling]\nenabled = true\n\
+             [adaptive]\nenabled = true\nutilization_threshold = {}\n\
+             interval = \"1h\"\ncollectors = [\"memory\"]\n",
+            threshold
+        ))
+        .unwrap()
+    };
+
+    // The utilization of 3% is above the threshold
+    let collector = GpuCollector::with_config(backend(), &config(2)).unwrap();
+    collector.sample().unwrap();
+    render(collector.clone());
+    let output = render(collector);
+    assert_eq!(call_count(&output, "memory"), 1);
+    assert!(call_count(&output, "utilization") >= 3);
+    assert!(output.contains("nvidia_gpu_exporter_collectors_throttled 1\n"));
+
+    let collector = GpuCollector::with_config(backend(), &config(50)).unwrap();
+    collector.sample().unwrap();
+    render(collector.clone());
+    let output = render(collector);
+    assert_eq!(call_count(&output, "memory"), 2);
+    assert!(output.contains("nvidia_gpu_exporter_collectors_throttled 0\n"));
+}
+
+#[test]
+fn average_power_is_computed_from_energy() {
+    let mut device = MockDevice::new(0, "Tesla V100-SXM2-16GB");
+    device.total_energy = Some(1_000_000);
+    let backend = MockBackend::new(vec![device]);
+    let collector = GpuCollector::with_backend(backend.clone()).unwrap();
+
+    let families = collector.collect();
+    assert!(!families
+        .iter()
+        .any(|family| family.get_name() == "nvidia_gpu_power_usage_avg_milliwatts"));
+
+    // 30 joules in between 100 milliseconds and a few seconds
+    thread::sleep(Duration::from_millis(100));
+    backend.update(0, |device| device.total_energy = Some(1_030_000));
+    let families = collector.collect();
+    let average = families
+        .iter()
+        .find(|family| family.get_name() == "nvidia_gpu_power_usage_avg_milliwatts")
+        .unwrap()
+        .get_metric()[0]
+        .get_gauge()
+        .get_value();
+    assert!(average > 3_000.0 && average <= 300_000.0);
+}
+
+#[test]
+fn gpustat_is_taken_from_the_previous_collection() {
+    let mut device = MockDevice::new(0, "Tesla T4");
+    device.temperature = Some(35);
+    device.utilization = Some(Utilization {
+        gpu: 7,
+        memory: Some(1),
+    });
+    device.memory_info = Some(MemoryInfo {
+        total: 16 << 30,
+        free: 12 << 30,
+        used: 4 << 30,
+    });
+    device.processes = vec![ProcessInfo {
+        pid: std::process::id(),
+        used_memory: Some(1 << 30),
+        process_type: ProcessType::Compute,
+    }];
+    let backend = MockBackend::new(vec![device]);
+    let calls = "nvidia_gpu_nvml_call_duration_seconds_count{call=\"temperature\"}";
+
+    let collector = GpuCollector::with_backend(backend.clone()).unwrap();
+    collector.collect();
+    let summary = collector.process().unwrap();
+    assert!(summary.starts_with(
+        "[0] Tesla T4|GPU-00000000-0000-0000-0000-000000000000| 35°C   7%|   4096 / 16384  MiB | "
+    ));
+    assert!(summary.contains(&format!("/{}(1024 MiB)", std::process::id())));
+    assert!(render(collector).contains(&format!("{} 2\n", calls)));
+
+    // Too old to be reused
+    let config: Config = toml::from_str("[gpustat]\nmax_age = \"10ms\"\n").unwrap();
+    let collector = GpuCollector::with_config(backend, &config).unwrap();
+    collector.collect();
+    thread::sleep(Duration::from_millis(20));
+    collector.process().unwrap();
+    assert!(render(collector).contains(&format!("{} 3\n", calls)));
+}
+
+#[test]
+fn gpustat_can_be_rendered_with_a_template() {
+    let mut device = MockDevice::new(0, "Tesla T4");
+    device.temperature = Some(35);
+    device.processes = vec![ProcessInfo {
+        pid: std::process::id(),
+        used_memory: Some(1 << 30),
+        process_type: ProcessType::Compute,
+    }];
+    let backend = MockBackend::new(vec![device]);
+
+    let template = "{{#each devices}}{{index}} {{name}} {{temperature}}\n\
+                    {{#each processes}}{{pid}} {{used_memory_mib}}\n{{/each}}{{/each}}";
+    let mut config = Config::default();
+    config.gpustat.template = Some(template.to_string());
+    assert!(config.validate().is_ok());
+
+    let collector = GpuCollector::with_config(backend, &config).unwrap();
+    assert_eq!(
+        collector.process().unwrap(),
+        format!("0 Tesla T4 35\n{} 1024\n", std::process::id())
+    );
+
+    config.gpustat.template = Some("{{#each devices}}".to_string());
+    assert!(config.validate().is_err());
+}
+
+#[test]
+fn snapshot_consistency_reads_all_devices_at_every_collection() {
+    let devices = (0..3)
+        .map(|index| {
+            let mut device = MockDevice::new(index, "Tesla T4");
+            device.temperature = Some(40 + index);
+            device
+        })
+        .collect();
+    let config: Config =
+        toml::from_str("consistency = \"snapshot\"\n[collectors.temperature]\ninterval = \"1h\"\n")
+            .unwrap();
+    assert!(config.validate().is_ok());
+    let collector = GpuCollector::with_config(MockBackend::new(devices), &config).unwrap();
+
+    render(collector.clone());
+    let output = render(collector);
+    assert!(output.contains("nvidia_gpu_exporter_snapshot_timestamp_seconds "));
+    assert_eq!(
+        output
+            .lines()
+            .filter(|l| l.starts_with("# TYPE nvidia_gpu_temperature_celsius "))
+            .count(),
+        1
+    );
+    for index in 0..3 {
+        assert!(output.contains(&format!(
+            "minor_number=\"{}\",name=\"Tesla T4\",uuid=\"GPU-00000000-0000-0000-0000-00000000000{}\"}} {}\n",
+            index,
+            index,
+            40 + index
+        )));
+    }
+    // The interval is ignored, as the readings would be of another time
+    assert!(
+        output.contains("nvidia_gpu_nvml_call_duration_seconds_count{call=\"temperature\"} 6\n")
+    );
+
+    let config: Config =
+        toml::from_str("consistency = \"snapshot\"\n[sampling]\nbackground_collection = true\n")
+            .unwrap();
+    assert!(config.validate().is_err());
+}
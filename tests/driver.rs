@@ -0,0 +1,55 @@
+use prometheus_nvidia_gpu::driver::{
+    parse_kernel_module_version, parse_library_version, parse_parameters,
+};
+
+#[test]
+fn kernel_module_version_is_parsed() {
+    let proprietary = "NVRM version: NVIDIA UNIX x86_64 Kernel Module  535.104.05  Sat Aug 19 01:15:15 UTC 2023\n\
+                       GCC version:  gcc version 12.3.0 (Ubuntu 12.3.0-1ubuntu1~22.04)\n";
+    assert_eq!(
+        parse_kernel_module_version(proprietary).as_deref(),
+        Some("535.104.05")
+    );
+
+    let open = "NVRM version: NVIDIA UNIX Open Kernel Module for x86_64  550.54.14  Release Build  (dvs-builder@U16-I3-B03-4-3)  Thu Feb 22 01:25:25 UTC 2024\n";
+    assert_eq!(
+        parse_kernel_module_version(open).as_deref(),
+        Some("550.54.14")
+    );
+
+    assert_eq!(parse_kernel_module_version(""), None);
+}
+
+#[test]
+fn library_version_is_parsed_from_mappings() {
+    let maps = "7f2c1a000000-7f2c1a021000 r--p 00000000 08:01 1835 /usr/lib/x86_64-linux-gnu/libc.so.6\n\
+                7f2c1b000000-7f2c1b0f0000 r-xp 00000000 08:01 2048 /usr/lib/x86_64-linux-gnu/libnvidia-ml.so.535.113.01\n\
+                7ffd5c000000-7ffd5c021000 rw-p 00000000 00:00 0 [stack]\n";
+    assert_eq!(parse_library_version(maps).as_deref(), Some("535.113.01"));
+
+    assert_eq!(
+        parse_library_version("7ffd5c000000-7ffd5c021000 rw-p 00000000 00:00 0 [stack]\n"),
+        None
+    );
+}
+
+#[test]
+fn selected_parameters_are_parsed() {
+    let params = "ResmanDebugLevel: 4294967295\n\
+                  RmLogonRC: 1\n\
+                  ModifyDeviceFiles: 1\n\
+                  EnableMSI: 1\n\
+                  RegistryDwords: \"RMUseSwI2c=0x01\"\n\
+                  EnableGpuFirmware: 18\n";
+    assert_eq!(
+        parse_parameters(params),
+        vec![
+            ("NVreg_EnableMSI".to_string(), "1".to_string()),
+            (
+                "NVreg_RegistryDwords".to_string(),
+                "RMUseSwI2c=0x01".to_string()
+            ),
+            ("NVreg_EnableGpuFirmware".to_string(), "18".to_string()),
+        ]
+    );
+}
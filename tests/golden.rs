@@ -0,0 +1,163 @@
+//! Golden tests of the exposition format. What alert rules and dashboards
+//! depend on, the names, types, help texts and label names of all metrics,
+//! is rendered from fake devices and compared to the files in
+//! `tests/golden`. See the stability policy in the README before accepting
+//! a change with `UPDATE_GOLDEN=1 cargo test --test golden`.
+
+// The golden files use the Linux identity labels
+#![cfg(target_os = "linux")]
+
+use std::collections::BTreeSet;
+use std::env;
+use std::fmt::Write;
+use std::fs;
+use std::path::Path;
+
+use prometheus::proto::MetricType;
+use prometheus::Registry;
+
+use prometheus_nvidia_gpu::backend::FakeBackend;
+use prometheus_nvidia_gpu::{Config, GpuCollector};
+
+/// Families that depend on the machine running the tests rather than on the
+/// backend.
+const HOST_DEPENDENT: [&str; 3] = [
+    "nvidia_gpu_driver_parameter_info",
+    "nvidia_gpu_driver_version_mismatch",
+    "nvidia_gpu_persistenced_running",
+];
+
+fn type_name(metric_type: MetricType) -> &'static str {
+    match metric_type {
+        MetricType::COUNTER => "counter",
+        MetricType::GAUGE => "gauge",
+        MetricType::SUMMARY => "summary",
+        MetricType::UNTYPED => "untyped",
+        MetricType::HISTOGRAM => "histogram",
+    }
+}
+
+/// Renders the exposition of a collection of fake devices down to one line
+/// per family and set of label names, after its `# HELP` and `# TYPE` lines.
+fn shape(config: &Config) -> String {
+    let collector = GpuCollector::with_config(FakeBackend::new(3), config).unwrap();
+    let registry = Registry::new();
+    registry.register(Box::new(collector)).unwrap();
+
+    let mut shape = String::new();
+    for family in registry.gather() {
+        let name = family.get_name();
+        if family.get_metric().is_empty() || HOST_DEPENDENT.contains(&name) {
+            continue;
+        }
+
+        writeln!(shape, "# HELP {} {}", name, family.get_help()).unwrap();
+        writeln!(
+            shape,
+            "# TYPE {} {}",
+            name,
+            type_name(family.get_field_type())
+        )
+        .unwrap();
+        let label_names: BTreeSet<Vec<&str>> = family
+            .get_metric()
+            .iter()
+            .map(|metric| {
+                let mut names: Vec<&str> =
+                    metric.get_label().iter().map(|l| l.get_name()).collect();
+                names.sort();
+                names
+            })
+            .collect();
+        for names in label_names {
+            if names.is_empty() {
+                writeln!(shape, "{}", name).unwrap();
+            } else {
+                writeln!(shape, "{}{{{}}}", name, names.join(",")).unwrap();
+            }
+        }
+    }
+    shape
+}
+
+/// Compares `actual` to the golden file `name`, or overwrites the file with
+/// it if `UPDATE_GOLDEN` is set.
+fn assert_golden(name: &str, actual: &str) {
+    let path = Path::new(env!("CARGO_MANIFEST_DIR"))
+        .join("tests/golden")
+        .join(name);
+    if env::var_os("UPDATE_GOLDEN").is_some() {
+        fs::write(&path, actual).unwrap();
+        return;
+    }
+
+    let expected = fs::read_to_string(&path)
+        .unwrap_or_else(|e| panic!("Could not read {}: {}", path.display(), e));
+    if actual != expected {
+        let expected_lines: BTreeSet<&str> = expected.lines().collect();
+        let actual_lines: BTreeSet<&str> = actual.lines().collect();
+        let mut diff = String::new();
+        for line in expected_lines.difference(&actual_lines) {
+            writeln!(diff, "- {}", line).unwrap();
+        }
+        for line in actual_lines.difference(&expected_lines) {
+            writeln!(diff, "+ {}", line).unwrap();
+        }
+        panic!(
+            "Exposition differs from {}, see the stability policy in the README:\n{}",
+            path.display(),
+            diff
+        );
+    }
+}
+
+#[test]
+fn default_exposition_matches_golden_file() {
+    assert_golden("default.txt", &shape(&Config::default()));
+}
+
+/// Configuration changing the names and labels of metrics.
+fn configured() -> Config {
+    toml::from_str(
+        "[labels]\nidentity = [\"index\", \"pci_bus_id\", \"uuid\"]\n\n\
+         [units]\npower = \"watts\"\nclocks = \"megahertz\"\n",
+    )
+    .unwrap()
+}
+
+#[test]
+fn configured_exposition_matches_golden_file() {
+    assert_golden("configured.txt", &shape(&configured()));
+}
+
+#[test]
+fn metric_names_follow_the_conventions() {
+    for config in &[Config::default(), configured()] {
+        let shape = shape(config);
+
+        for line in shape.lines().filter(|l| l.starts_with("# TYPE ")) {
+            let mut parts = line["# TYPE ".len()..].split(' ');
+            let name = parts.next().unwrap();
+            let metric_type = parts.next().unwrap();
+
+            assert!(
+                name.starts_with("nvidia_gpu_"),
+                "{} lacks the namespace",
+                name
+            );
+            assert!(
+                name.chars()
+                    .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '_'),
+                "{} is not snake case",
+                name
+            );
+            assert_eq!(
+                metric_type == "counter",
+                name.ends_with("_total"),
+                "{} is a {}, but only counters end in _total",
+                name,
+                metric_type
+            );
+        }
+    }
+}
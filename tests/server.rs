@@ -0,0 +1,845 @@
+// The expected expositions use the Linux identity labels
+#![cfg(target_os = "linux")]
+
+use std::net::SocketAddr;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::thread;
+use std::time::Duration;
+
+use hyper::{Body, Client, Request, StatusCode};
+
+use prometheus_nvidia_gpu::backend::{
+    DeviceInfo, GpuBackend, MemoryInfo, MockBackend, MockDevice, ProcessInfo, ProcessType,
+    Utilization,
+};
+use prometheus_nvidia_gpu::config::{ProxyTarget, WebConfig, WebShard, WebUser};
+use prometheus_nvidia_gpu::server::{self, Exporter};
+use prometheus_nvidia_gpu::{CollectingError, Config, GpuCollector};
+
+const GIB: u64 = 1024 * 1024 * 1024;
+
+fn fake_backend() -> MockBackend {
+    let mut first = MockDevice::new(0, "Tesla V100-SXM2-16GB");
+    first.utilization = Some(Utilization {
+        gpu: 42,
+        memory: Some(7),
+    });
+    first.memory_info = Some(MemoryInfo {
+        total: 16 * GIB,
+        free: 12 * GIB,
+        used: 4 * GIB,
+    });
+    first.power_usage = Some(123_456);
+    first.power_limit = Some(300_000);
+    first.temperature = Some(61);
+    first.processes = vec![ProcessInfo {
+        pid: 4242,
+        used_memory: Some(4 * GIB),
+        process_type: ProcessType::Compute,
+    }];
+
+    // A passively cooled card without power readings
+    let mut second = MockDevice::new(1, "Tesla T4");
+    second.utilization = Some(Utilization {
+        gpu: 0,
+        memory: Some(0),
+    });
+    second.memory_info = Some(MemoryInfo {
+        total: 15 * GIB,
+        free: 15 * GIB,
+        used: 0,
+    });
+    second.temperature = Some(35);
+
+    MockBackend::new(vec![first, second])
+}
+
+/// Backend that counts device enumerations, each taking a while.
+struct SlowBackend {
+    inner: MockBackend,
+    sweeps: Arc<AtomicUsize>,
+}
+
+impl GpuBackend for SlowBackend {
+    fn device_count(&self) -> prometheus_nvidia_gpu::Result<u32> {
+        self.sweeps.fetch_add(1, Ordering::SeqCst);
+        thread::sleep(Duration::from_millis(200));
+        self.inner.device_count()
+    }
+
+    fn device_info(&self, index: u32) -> prometheus_nvidia_gpu::Result<DeviceInfo> {
+        self.inner.device_info(index)
+    }
+
+    fn utilization(&self, index: u32) -> prometheus_nvidia_gpu::Result<Utilization> {
+        self.inner.utilization(index)
+    }
+
+    fn memory_info(&self, index: u32) -> prometheus_nvidia_gpu::Result<MemoryInfo> {
+        self.inner.memory_info(index)
+    }
+
+    fn processes(&self, index: u32) -> prometheus_nvidia_gpu::Result<Vec<ProcessInfo>> {
+        self.inner.processes(index)
+    }
+}
+
+/// Backend panicking on the first enumeration of its devices.
+struct PanickingBackend {
+    inner: MockBackend,
+    panicked: AtomicBool,
+}
+
+impl GpuBackend for PanickingBackend {
+    fn device_count(&self) -> prometheus_nvidia_gpu::Result<u32> {
+        if !self.panicked.swap(true, Ordering::SeqCst) {
+            panic!("device count");
+        }
+        self.inner.device_count()
+    }
+
+    fn device_info(&self, index: u32) -> prometheus_nvidia_gpu::Result<DeviceInfo> {
+        self.inner.device_info(index)
+    }
+}
+
+async fn spawn_server(
+    exporter: prometheus_nvidia_gpu::Result<Exporter<MockBackend>>,
+) -> SocketAddr {
+    let (addr, server) = server::bind(&([127, 0, 0, 1], 0).into(), exporter, &WebConfig::default());
+    tokio::spawn(server);
+    addr
+}
+
+async fn get(addr: SocketAddr, path: &str) -> (StatusCode, String) {
+    let uri = format!("http://{}{}", addr, path).parse().unwrap();
+    let response = Client::new().get(uri).await.unwrap();
+    let status = response.status();
+    let body = hyper::body::to_bytes(response.into_body()).await.unwrap();
+    (status, String::from_utf8(body.to_vec()).unwrap())
+}
+
+#[tokio::test]
+async fn metrics_contain_device_readings() {
+    let collector = GpuCollector::with_backend(fake_backend()).unwrap();
+    let addr = spawn_server(Exporter::new(collector)).await;
+
+    let (status, body) = get(addr, "/metrics").await;
+
+    assert_eq!(status, StatusCode::OK);
+    assert!(body.contains("nvidia_gpu_num_devices 2\n"));
+    assert!(body.contains(
+        "nvidia_gpu_gpu_utilization{minor_number=\"0\",name=\"Tesla V100-SXM2-16GB\",uuid=\"GPU-00000000-0000-0000-0000-000000000000\"} 42\n"
+    ));
+    assert!(body.contains(
+        "nvidia_gpu_memory_used_bytes{minor_number=\"1\",name=\"Tesla T4\",uuid=\"GPU-00000000-0000-0000-0000-000000000001\"} 0\n"
+    ));
+    assert!(body.contains(
+        "nvidia_gpu_temperature_celsius{minor_number=\"1\",name=\"Tesla T4\",uuid=\"GPU-00000000-0000-0000-0000-000000000001\"} 35\n"
+    ));
+    assert!(body.contains("# TYPE nvidia_gpu_nvml_call_duration_seconds histogram\n"));
+}
+
+#[tokio::test]
+async fn service_discovery_lists_one_target_per_device() {
+    let collector = GpuCollector::with_backend(fake_backend()).unwrap();
+    let addr = spawn_server(Exporter::new(collector)).await;
+
+    let (status, body) = get(addr, "/sd").await;
+    assert_eq!(status, StatusCode::OK);
+
+    let targets: serde_json::Value = serde_json::from_str(&body).unwrap();
+    assert_eq!(targets.as_array().unwrap().len(), 2);
+    assert_eq!(targets[1]["targets"][0], addr.to_string());
+    assert_eq!(targets[1]["labels"]["name"], "Tesla T4");
+    assert_eq!(
+        targets[1]["labels"]["__metrics_path__"],
+        "/metrics/gpu/GPU-00000000-0000-0000-0000-000000000001"
+    );
+}
+
+#[tokio::test]
+async fn metrics_can_be_scraped_per_device() {
+    let collector = GpuCollector::with_backend(fake_backend()).unwrap();
+    let addr = spawn_server(Exporter::new(collector)).await;
+
+    let (status, body) = get(addr, "/metrics/gpu/1").await;
+    assert_eq!(status, StatusCode::OK);
+    assert!(body.contains("uuid=\"GPU-00000000-0000-0000-0000-000000000001\"} 35\n"));
+    assert!(!body.contains("GPU-00000000-0000-0000-0000-000000000000"));
+    assert!(!body.contains("nvidia_gpu_num_devices"));
+
+    let (status, body) = get(
+        addr,
+        "/metrics?device=GPU-00000000-0000-0000-0000-000000000000",
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+    assert!(body.contains("name=\"Tesla V100-SXM2-16GB\""));
+    assert!(!body.contains("Tesla T4"));
+
+    let (status, _) = get(addr, "/metrics/gpu/2").await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+}
+
+#[tokio::test]
+async fn shards_serve_their_devices_with_their_own_token() {
+    let collector = GpuCollector::with_backend(fake_backend()).unwrap();
+    let web = WebConfig {
+        shards: vec![
+            WebShard {
+                path: "/metrics/team-a".to_string(),
+                devices: vec!["0".to_string()],
+                token: "team-a-secret".to_string(),
+            },
+            WebShard {
+                path: "/metrics/team-b".to_string(),
+                devices: vec!["GPU-00000000-0000-0000-0000-000000000001".to_string()],
+                token: "team-b-secret".to_string(),
+            },
+        ],
+        ..WebConfig::default()
+    };
+    let (addr, server) = server::bind(&([127, 0, 0, 1], 0).into(), Exporter::new(collector), &web);
+    tokio::spawn(server);
+
+    let (status, body) = get_as(addr, "/metrics/team-a", Some("team-a-secret")).await;
+    assert_eq!(status, StatusCode::OK);
+    assert!(body.contains("name=\"Tesla V100-SXM2-16GB\""));
+    assert!(!body.contains("Tesla T4"));
+
+    let (status, body) = get_as(addr, "/metrics/team-b", Some("team-b-secret")).await;
+    assert_eq!(status, StatusCode::OK);
+    assert!(body.contains("name=\"Tesla T4\""));
+    assert!(!body.contains("Tesla V100"));
+
+    let (status, _) = get_as(addr, "/metrics/team-b", Some("team-a-secret")).await;
+    assert_eq!(status, StatusCode::UNAUTHORIZED);
+    let (status, _) = get_as(addr, "/metrics/team-a", None).await;
+    assert_eq!(status, StatusCode::UNAUTHORIZED);
+}
+
+#[tokio::test]
+async fn host_label_is_attached_to_every_series() {
+    let config: Config = toml::from_str("[labels]\nhost_label = \"node\"\n").unwrap();
+    let collector = GpuCollector::with_config(fake_backend(), &config).unwrap();
+    let addr = spawn_server(Exporter::new(collector)).await;
+    let node = format!("node=\"{}\"", gethostname::gethostname().to_string_lossy());
+
+    for path in &["/metrics", "/metrics/gpu/0", "/metrics?collect[]=memory"] {
+        let (status, body) = get(addr, path).await;
+        assert_eq!(status, StatusCode::OK);
+        assert!(body.contains("nvidia_gpu_memory_used_bytes{"));
+        for line in body.lines().filter(|line| !line.starts_with('#')) {
+            assert!(line.contains(&node), "{} lacks the host label", line);
+        }
+    }
+}
+
+#[tokio::test]
+async fn collect_parameters_select_collectors() {
+    let collector = GpuCollector::with_backend(fake_backend()).unwrap();
+    let addr = spawn_server(Exporter::new(collector)).await;
+
+    let (status, body) = get(addr, "/metrics?collect[]=utilization&collect%5B%5D=memory").await;
+    assert_eq!(status, StatusCode::OK);
+    assert!(body.contains("nvidia_gpu_gpu_utilization{"));
+    assert!(body.contains("nvidia_gpu_memory_used_bytes{"));
+    assert!(!body.contains("nvidia_gpu_temperature_celsius"));
+    assert!(body.contains("nvidia_gpu_num_devices 2\n"));
+
+    let (status, _) = get(addr, "/metrics?collect[]=voltage").await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+}
+
+#[tokio::test(threaded_scheduler)]
+async fn concurrent_scrapes_share_one_collection() {
+    let sweeps = Arc::new(AtomicUsize::new(0));
+    let backend = SlowBackend {
+        inner: fake_backend(),
+        sweeps: sweeps.clone(),
+    };
+    let collector = GpuCollector::with_backend(backend).unwrap();
+    let (addr, server) = server::bind(
+        &([127, 0, 0, 1], 0).into(),
+        Exporter::new(collector),
+        &WebConfig::default(),
+    );
+    tokio::spawn(server);
+
+    let ((first_status, first), (second_status, second)) =
+        tokio::join!(get(addr, "/metrics"), get(addr, "/metrics"));
+
+    assert_eq!(first_status, StatusCode::OK);
+    assert_eq!(second_status, StatusCode::OK);
+    assert_eq!(first, second);
+    assert_eq!(sweeps.load(Ordering::SeqCst), 1);
+}
+
+#[tokio::test(threaded_scheduler)]
+async fn scrapes_recover_from_a_panicking_collection() {
+    let backend = PanickingBackend {
+        inner: fake_backend(),
+        panicked: AtomicBool::new(false),
+    };
+    let collector = GpuCollector::with_backend(backend).unwrap();
+    let (addr, server) = server::bind(
+        &([127, 0, 0, 1], 0).into(),
+        Exporter::new(collector),
+        &WebConfig::default(),
+    );
+    tokio::spawn(server);
+
+    let (status, body) = get(addr, "/metrics").await;
+    assert_eq!(status, StatusCode::INTERNAL_SERVER_ERROR);
+    assert!(body.contains("\"code\":\"internal_error\""));
+
+    let (status, body) = get(addr, "/metrics").await;
+    assert_eq!(status, StatusCode::OK);
+    assert!(body.contains("nvidia_gpu_num_devices 2\n"));
+}
+
+#[tokio::test]
+async fn unsupported_readings_are_omitted() {
+    let collector = GpuCollector::with_backend(fake_backend()).unwrap();
+    let addr = spawn_server(Exporter::new(collector)).await;
+
+    let (_, body) = get(addr, "/metrics").await;
+
+    assert!(body.contains("nvidia_gpu_power_usage_milliwatts{minor_number=\"0\""));
+    assert!(!body.contains("nvidia_gpu_power_usage_milliwatts{minor_number=\"1\""));
+    assert!(!body.contains("nvidia_gpu_fan_speed_percent"));
+}
+
+#[tokio::test]
+async fn unknown_path_is_not_found() {
+    let collector = GpuCollector::with_backend(fake_backend()).unwrap();
+    let addr = spawn_server(Exporter::new(collector)).await;
+
+    let (status, _) = get(addr, "/nope").await;
+
+    assert_eq!(status, StatusCode::NOT_FOUND);
+}
+
+#[tokio::test]
+async fn failed_initialization_is_an_internal_server_error() {
+    let addr = spawn_server(Err(CollectingError::NotFound)).await;
+
+    let (status, body) = get(addr, "/metrics").await;
+
+    assert_eq!(status, StatusCode::INTERNAL_SERVER_ERROR);
+    let error: serde_json::Value = serde_json::from_str(&body).unwrap();
+    assert_eq!(error["code"], "nvml_unavailable");
+    assert_eq!(error["message"], "Could not get access to NVML");
+}
+
+#[tokio::test]
+async fn ready_after_successful_collection() {
+    let collector = GpuCollector::with_backend(fake_backend()).unwrap();
+    let addr = spawn_server(Exporter::new(collector)).await;
+
+    assert_eq!(get(addr, "/healthz").await.0, StatusCode::OK);
+    assert_eq!(get(addr, "/readyz").await.0, StatusCode::OK);
+}
+
+#[tokio::test]
+async fn not_ready_while_expected_devices_are_missing() {
+    let collector = GpuCollector::with_backend(fake_backend()).unwrap();
+    collector.expect_devices(3);
+    let addr = spawn_server(Exporter::new(collector)).await;
+
+    assert_eq!(
+        get(addr, "/readyz").await.0,
+        StatusCode::SERVICE_UNAVAILABLE
+    );
+    assert!(get(addr, "/metrics")
+        .await
+        .1
+        .contains("nvidia_gpu_devices_missing 1\n"));
+
+    let collector = GpuCollector::with_backend(fake_backend()).unwrap();
+    collector.expect_devices(2);
+    let addr = spawn_server(Exporter::new(collector)).await;
+
+    assert_eq!(get(addr, "/readyz").await.0, StatusCode::OK);
+    assert!(get(addr, "/metrics")
+        .await
+        .1
+        .contains("nvidia_gpu_devices_missing 0\n"));
+}
+
+#[tokio::test]
+async fn live_but_not_ready_without_nvml() {
+    let addr = spawn_server(Err(CollectingError::NotFound)).await;
+
+    assert_eq!(get(addr, "/healthz").await.0, StatusCode::OK);
+    assert_eq!(
+        get(addr, "/readyz").await.0,
+        StatusCode::SERVICE_UNAVAILABLE
+    );
+}
+
+#[tokio::test]
+async fn quit_shuts_down_when_enabled() {
+    let collector = GpuCollector::with_backend(fake_backend()).unwrap();
+    let web = WebConfig {
+        enable_lifecycle: true,
+        ..WebConfig::default()
+    };
+    let (addr, server) = server::bind(&([127, 0, 0, 1], 0).into(), Exporter::new(collector), &web);
+    let server = tokio::spawn(server);
+
+    let request = Request::post(format!("http://{}/-/quit", addr))
+        .body(Body::empty())
+        .unwrap();
+    let response = Client::new().request(request).await.unwrap();
+
+    assert_eq!(response.status(), StatusCode::OK);
+    server.await.unwrap().unwrap();
+}
+
+#[tokio::test]
+async fn quit_is_not_found_when_disabled() {
+    let collector = GpuCollector::with_backend(fake_backend()).unwrap();
+    let addr = spawn_server(Exporter::new(collector)).await;
+
+    let request = Request::post(format!("http://{}/-/quit", addr))
+        .body(Body::empty())
+        .unwrap();
+    let response = Client::new().request(request).await.unwrap();
+
+    assert_eq!(response.status(), StatusCode::NOT_FOUND);
+}
+
+#[tokio::test]
+async fn admin_endpoints_on_separate_listener() {
+    let collector = GpuCollector::with_backend(fake_backend()).unwrap();
+    let (addr, admin_addr, server) = server::bind_with_admin(
+        &([127, 0, 0, 1], 0).into(),
+        &([127, 0, 0, 1], 0).into(),
+        Exporter::new(collector),
+        &WebConfig::default(),
+    );
+    tokio::spawn(server);
+
+    assert_eq!(get(addr, "/metrics").await.0, StatusCode::OK);
+    assert_eq!(get(addr, "/healthz").await.0, StatusCode::NOT_FOUND);
+    assert_eq!(get(admin_addr, "/healthz").await.0, StatusCode::OK);
+    assert_eq!(get(admin_addr, "/readyz").await.0, StatusCode::OK);
+    assert_eq!(get(admin_addr, "/metrics").await.0, StatusCode::NOT_FOUND);
+}
+
+fn admin_web() -> WebConfig {
+    WebConfig {
+        enable_admin_api: true,
+        admin_token: Some("secret".to_string()),
+        ..WebConfig::default()
+    }
+}
+
+async fn post(addr: SocketAddr, path: &str, token: Option<&str>) -> StatusCode {
+    let mut request = Request::post(format!("http://{}{}", addr, path));
+    if let Some(token) = token {
+        request = request.header("Authorization", format!("Bearer {}", token));
+    }
+    let request = request.body(Body::empty()).unwrap();
+    Client::new().request(request).await.unwrap().status()
+}
+
+#[tokio::test]
+async fn admin_api_sets_power_limit() {
+    let backend = fake_backend();
+    let collector = GpuCollector::with_backend(backend.clone()).unwrap();
+    let (addr, server) = server::bind(
+        &([127, 0, 0, 1], 0).into(),
+        Exporter::new(collector),
+        &admin_web(),
+    );
+    tokio::spawn(server);
+
+    let path = "/admin/power_limit?index=0&milliwatts=250000";
+    assert_eq!(post(addr, path, Some("secret")).await, StatusCode::OK);
+    assert_eq!(backend.power_limit(0).unwrap(), 250_000);
+
+    // The second device does not report a power limit
+    let path = "/admin/power_limit?index=1&milliwatts=250000";
+    assert_eq!(
+        post(addr, path, Some("secret")).await,
+        StatusCode::NOT_IMPLEMENTED
+    );
+    let path = "/admin/power_limit?index=0";
+    assert_eq!(
+        post(addr, path, Some("secret")).await,
+        StatusCode::BAD_REQUEST
+    );
+}
+
+#[tokio::test]
+async fn errors_are_described_as_json() {
+    let collector = GpuCollector::with_backend(fake_backend()).unwrap();
+    let (addr, server) = server::bind(
+        &([127, 0, 0, 1], 0).into(),
+        Exporter::new(collector),
+        &admin_web(),
+    );
+    tokio::spawn(server);
+
+    let (status, body) = get(addr, "/metrics/gpu/2").await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+    let error: serde_json::Value = serde_json::from_str(&body).unwrap();
+    assert_eq!(error["code"], "device_not_found");
+    assert_eq!(error["device"], serde_json::Value::Null);
+
+    // The second device does not report a power limit
+    let request = Request::post(format!(
+        "http://{}/admin/power_limit?index=1&milliwatts=250000",
+        addr
+    ))
+    .header("Authorization", "Bearer secret")
+    .body(Body::empty())
+    .unwrap();
+    let response = Client::new().request(request).await.unwrap();
+    assert_eq!(response.status(), StatusCode::NOT_IMPLEMENTED);
+    assert_eq!(response.headers()["content-type"], "application/json");
+    let body = hyper::body::to_bytes(response.into_body()).await.unwrap();
+    let error: serde_json::Value = serde_json::from_slice(&body).unwrap();
+    assert_eq!(error["code"], "not_supported");
+    assert_eq!(error["device"], "GPU-00000000-0000-0000-0000-000000000001");
+    assert_eq!(error["nvml_error"], serde_json::Value::Null);
+}
+
+#[tokio::test]
+async fn admin_api_locks_clocks() {
+    let mut device = MockDevice::new(0, "Tesla T4");
+    device.graphics_clock = Some(585);
+    let collector = GpuCollector::with_backend(MockBackend::new(vec![device])).unwrap();
+    let (addr, server) = server::bind(
+        &([127, 0, 0, 1], 0).into(),
+        Exporter::new(collector),
+        &admin_web(),
+    );
+    tokio::spawn(server);
+
+    let path = "/admin/locked_clocks?index=0&min_mhz=1350&max_mhz=1350";
+    assert_eq!(post(addr, path, Some("secret")).await, StatusCode::OK);
+    let (_, body) = get(addr, "/metrics").await;
+    assert!(body.contains(
+        "nvidia_gpu_clock_speed_graphics_hertz{minor_number=\"0\",name=\"Tesla T4\",uuid=\"GPU-00000000-0000-0000-0000-000000000000\"} 1350000000\n"
+    ));
+
+    let path = "/admin/reset_locked_clocks?index=0";
+    assert_eq!(post(addr, path, Some("secret")).await, StatusCode::OK);
+}
+
+#[tokio::test]
+async fn admin_api_requires_token() {
+    let backend = fake_backend();
+    let collector = GpuCollector::with_backend(backend.clone()).unwrap();
+    let (addr, server) = server::bind(
+        &([127, 0, 0, 1], 0).into(),
+        Exporter::new(collector),
+        &admin_web(),
+    );
+    tokio::spawn(server);
+
+    let path = "/admin/power_limit?index=0&milliwatts=100000";
+    assert_eq!(post(addr, path, None).await, StatusCode::UNAUTHORIZED);
+    assert_eq!(
+        post(addr, path, Some("wrong")).await,
+        StatusCode::UNAUTHORIZED
+    );
+    assert_eq!(backend.power_limit(0).unwrap(), 300_000);
+}
+
+#[tokio::test]
+async fn debug_endpoint_dumps_readings_and_errors() {
+    let collector = GpuCollector::with_backend(fake_backend()).unwrap();
+    let (addr, server) = server::bind(
+        &([127, 0, 0, 1], 0).into(),
+        Exporter::new(collector),
+        &admin_web(),
+    );
+    tokio::spawn(server);
+
+    assert_eq!(
+        get_as(addr, "/debug/devices", None).await.0,
+        StatusCode::UNAUTHORIZED
+    );
+    let (status, body) = get_as(addr, "/debug/devices", Some("secret")).await;
+    assert_eq!(status, StatusCode::OK);
+
+    let dump: serde_json::Value = serde_json::from_str(&body).unwrap();
+    assert_eq!(dump["device_count"], 2);
+    assert_eq!(dump["devices"][0]["utilization"]["value"]["gpu"], 42);
+    // A passively cooled card
+    assert_eq!(
+        dump["devices"][1]["fan_speed_percent"]["error"],
+        "Not supported"
+    );
+}
+
+#[tokio::test]
+async fn devices_are_refreshed_on_request() {
+    let collector = GpuCollector::with_backend(fake_backend()).unwrap();
+    let (addr, server) = server::bind(
+        &([127, 0, 0, 1], 0).into(),
+        Exporter::new(collector),
+        &admin_web(),
+    );
+    tokio::spawn(server);
+
+    assert_eq!(
+        post(addr, "/-/refresh-devices", None).await,
+        StatusCode::UNAUTHORIZED
+    );
+    assert_eq!(
+        post(addr, "/-/refresh-devices", Some("secret")).await,
+        StatusCode::OK
+    );
+}
+
+#[tokio::test]
+async fn openmetrics_carry_exemplars_of_the_top_process() {
+    let config: Config = toml::from_str("[web]\nopenmetrics = true\n").unwrap();
+    let collector = GpuCollector::with_config(fake_backend(), &config).unwrap();
+    let addr = spawn_server(Exporter::new(collector)).await;
+
+    let request = Request::get(format!("http://{}/metrics", addr))
+        .header(
+            "Accept",
+            "application/openmetrics-text;version=1.0.0,text/plain;version=0.0.4;q=0.5",
+        )
+        .body(Body::empty())
+        .unwrap();
+    let response = Client::new().request(request).await.unwrap();
+    assert_eq!(
+        response.headers()["Content-Type"],
+        "application/openmetrics-text; version=1.0.0; charset=utf-8"
+    );
+    let body = hyper::body::to_bytes(response.into_body()).await.unwrap();
+    let body = String::from_utf8(body.to_vec()).unwrap();
+
+    assert!(body.contains(
+        "nvidia_gpu_memory_used_bytes{minor_number=\"0\",name=\"Tesla V100-SXM2-16GB\",uuid=\"GPU-00000000-0000-0000-0000-000000000000\"} 4294967296 # {pid=\"4242\",user=\""
+    ));
+    // Without processes, there is no exemplar
+    assert!(body.contains(
+        "nvidia_gpu_memory_used_bytes{minor_number=\"1\",name=\"Tesla T4\",uuid=\"GPU-00000000-0000-0000-0000-000000000001\"} 0\n"
+    ));
+    assert!(body.ends_with("# EOF\n"));
+
+    // Other scrapers still get the text format
+    let (_, body) = get(addr, "/metrics").await;
+    assert!(!body.contains("# EOF"));
+}
+
+#[tokio::test]
+async fn admin_api_is_not_found_when_disabled() {
+    let collector = GpuCollector::with_backend(fake_backend()).unwrap();
+    let addr = spawn_server(Exporter::new(collector)).await;
+
+    let path = "/admin/power_limit?index=0&milliwatts=100000";
+    assert_eq!(
+        post(addr, path, Some("secret")).await,
+        StatusCode::NOT_FOUND
+    );
+}
+
+#[tokio::test]
+async fn dashboard_contains_exported_metrics() {
+    let collector = GpuCollector::with_backend(fake_backend()).unwrap();
+    let addr = spawn_server(Exporter::new(collector)).await;
+
+    let (status, body) = get(addr, "/dashboard.json").await;
+    let dashboard: serde_json::Value = serde_json::from_str(&body).unwrap();
+    let exprs: Vec<&str> = dashboard["panels"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|panel| panel["targets"][0]["expr"].as_str().unwrap())
+        .collect();
+
+    assert_eq!(status, StatusCode::OK);
+    assert!(exprs.contains(&"nvidia_gpu_temperature_celsius"));
+    assert!(exprs.contains(
+        &"histogram_quantile(0.99, sum by (le) (rate(nvidia_gpu_nvml_call_duration_seconds_bucket[5m])))"
+    ));
+    assert!(!exprs.contains(&"nvidia_gpu_fan_speed_percent"));
+}
+
+#[tokio::test]
+async fn proxy_serves_metrics_of_other_exporters() {
+    let collector = GpuCollector::with_backend(fake_backend()).unwrap();
+    let target = spawn_server(Exporter::new(collector)).await;
+
+    let web = WebConfig {
+        proxy_targets: vec![
+            ProxyTarget {
+                url: format!("http://{}/metrics", target),
+                node: Some("lab-1".to_string()),
+            },
+            ProxyTarget {
+                url: "http://127.0.0.1:1/metrics".to_string(),
+                node: Some("lab-2".to_string()),
+            },
+        ],
+        ..WebConfig::default()
+    };
+    let no_gpus: prometheus_nvidia_gpu::Result<Exporter<MockBackend>> =
+        Err(CollectingError::NotFound);
+    let (addr, server) = server::bind(&([127, 0, 0, 1], 0).into(), no_gpus, &web);
+    tokio::spawn(server);
+
+    let (status, body) = get(addr, "/proxy/metrics").await;
+
+    assert_eq!(status, StatusCode::OK);
+    assert!(body.contains(
+        "nvidia_gpu_gpu_utilization{node=\"lab-1\",minor_number=\"0\",name=\"Tesla V100-SXM2-16GB\",uuid=\"GPU-00000000-0000-0000-0000-000000000000\"} 42\n"
+    ));
+    assert_eq!(
+        body.matches("# TYPE nvidia_gpu_gpu_utilization gauge")
+            .count(),
+        1
+    );
+    assert!(body.contains("nvidia_gpu_exporter_proxy_target_up{node=\"lab-1\"} 1\n"));
+    assert!(body.contains("nvidia_gpu_exporter_proxy_target_up{node=\"lab-2\"} 0\n"));
+}
+
+async fn get_as(addr: SocketAddr, path: &str, token: Option<&str>) -> (StatusCode, String) {
+    let mut request = Request::get(format!("http://{}{}", addr, path));
+    if let Some(token) = token {
+        request = request.header("Authorization", format!("Bearer {}", token));
+    }
+    let response = Client::new()
+        .request(request.body(Body::empty()).unwrap())
+        .await
+        .unwrap();
+    let status = response.status();
+    let body = hyper::body::to_bytes(response.into_body()).await.unwrap();
+    (status, String::from_utf8(body.to_vec()).unwrap())
+}
+
+#[tokio::test]
+async fn users_only_see_their_own_processes() {
+    // A process of whoever runs the tests, which is not alice
+    let mut device = MockDevice::new(0, "Tesla T4");
+    device.processes = vec![ProcessInfo {
+        pid: std::process::id(),
+        used_memory: Some(GIB),
+        process_type: ProcessType::Compute,
+    }];
+    device.memory_info = Some(MemoryInfo {
+        total: 16 * GIB,
+        free: 15 * GIB,
+        used: GIB,
+    });
+    let collector = GpuCollector::with_backend(MockBackend::new(vec![device])).unwrap();
+    let web = WebConfig {
+        users: vec![
+            WebUser {
+                name: "alice".to_string(),
+                token: "alice-secret".to_string(),
+                all_processes: false,
+            },
+            WebUser {
+                name: "prometheus".to_string(),
+                token: "prometheus-secret".to_string(),
+                all_processes: true,
+            },
+        ],
+        ..WebConfig::default()
+    };
+    let (addr, server) = server::bind(&([127, 0, 0, 1], 0).into(), Exporter::new(collector), &web);
+    tokio::spawn(server);
+
+    let (status, _) = get_as(addr, "/metrics", None).await;
+    assert_eq!(status, StatusCode::UNAUTHORIZED);
+
+    let (status, body) = get_as(addr, "/metrics", Some("alice-secret")).await;
+    assert_eq!(status, StatusCode::OK);
+    assert!(body.contains("nvidia_gpu_num_devices 1\n"));
+    assert!(!body.contains("nvidia_gpu_process_memory_used_bytes"));
+    assert!(!body.contains("nvidia_gpu_process_memory_used_ratio"));
+
+    let (status, body) = get_as(addr, "/metrics", Some("prometheus-secret")).await;
+    assert_eq!(status, StatusCode::OK);
+    assert!(body.contains("nvidia_gpu_process_memory_used_bytes{"));
+    assert!(body.contains("nvidia_gpu_process_memory_used_ratio{"));
+
+    // Health checks stay unauthenticated
+    let (status, _) = get_as(addr, "/healthz", None).await;
+    assert_eq!(status, StatusCode::OK);
+}
+
+#[tokio::test]
+async fn users_do_not_see_the_cgroups_of_other_users() {
+    // A process of whoever runs the tests, which is not alice
+    let mut device = MockDevice::new(0, "Tesla T4");
+    device.processes = vec![ProcessInfo {
+        pid: std::process::id(),
+        used_memory: Some(GIB),
+        process_type: ProcessType::Compute,
+    }];
+    let collector = GpuCollector::with_backend(MockBackend::new(vec![device])).unwrap();
+    let web = WebConfig {
+        users: vec![
+            WebUser {
+                name: "alice".to_string(),
+                token: "alice-secret".to_string(),
+                all_processes: false,
+            },
+            WebUser {
+                name: "prometheus".to_string(),
+                token: "prometheus-secret".to_string(),
+                all_processes: true,
+            },
+        ],
+        ..WebConfig::default()
+    };
+    let (addr, server) = server::bind(&([127, 0, 0, 1], 0).into(), Exporter::new(collector), &web);
+    tokio::spawn(server);
+
+    for path in &["/metrics", "/metrics/gpu/0", "/metrics?collect[]=processes"] {
+        let (status, body) = get_as(addr, path, Some("alice-secret")).await;
+        assert_eq!(status, StatusCode::OK);
+        assert!(!body.contains("nvidia_gpu_cgroup_memory_used_bytes"));
+        assert!(!body.contains("nvidia_gpu_process_memory_bytes"));
+    }
+
+    let (_, body) = get_as(addr, "/metrics", Some("prometheus-secret")).await;
+    assert!(body.contains("nvidia_gpu_cgroup_memory_used_bytes{"));
+    assert!(body.contains("nvidia_gpu_process_memory_bytes_bucket{"));
+}
+
+#[tokio::test]
+async fn large_expositions_are_streamed_in_chunks() {
+    let mut device = MockDevice::new(0, "Tesla T4");
+    device.processes = (0..1200)
+        .map(|pid| ProcessInfo {
+            pid: 100_000 + pid,
+            used_memory: Some(GIB),
+            process_type: ProcessType::Compute,
+        })
+        .collect();
+    let collector = GpuCollector::with_backend(MockBackend::new(vec![device])).unwrap();
+    let addr = spawn_server(Exporter::new(collector)).await;
+
+    let uri = format!("http://{}/metrics", addr).parse().unwrap();
+    let response = Client::new().get(uri).await.unwrap();
+    assert!(response.headers().get("content-length").is_none());
+    let body = hyper::body::to_bytes(response.into_body()).await.unwrap();
+    let body = String::from_utf8(body.to_vec()).unwrap();
+
+    let described = body
+        .lines()
+        .filter(|line| line.starts_with("# TYPE nvidia_gpu_process_memory_used_bytes "))
+        .count();
+    let series = body
+        .lines()
+        .filter(|line| line.starts_with("nvidia_gpu_process_memory_used_bytes{"))
+        .count();
+    assert_eq!(described, 1);
+    assert_eq!(series, 1200);
+    assert!(body.contains("nvidia_gpu_num_devices 1\n"));
+}
@@ -0,0 +1,78 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use prometheus_nvidia_gpu::backend::{ClockType, GpuBackend, TegraBackend};
+
+/// Builds a fake Jetson filesystem in a fresh temporary directory.
+fn fake_root(name: &str) -> PathBuf {
+    let root = std::env::temp_dir().join(format!("tegra-{}-{}", name, std::process::id()));
+    let _ = fs::remove_dir_all(&root);
+
+    let write = |path: &str, content: &str| {
+        let path = root.join(path);
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(path, content).unwrap();
+    };
+    write("sys/devices/gpu.0/load", "503\n");
+    write(
+        "sys/devices/gpu.0/devfreq/17000000.gv11b/cur_freq",
+        "1109250000\n",
+    );
+    write(
+        "sys/devices/virtual/thermal/thermal_zone0/type",
+        "CPU-therm\n",
+    );
+    write("sys/devices/virtual/thermal/thermal_zone0/temp", "45000\n");
+    write(
+        "sys/devices/virtual/thermal/thermal_zone1/type",
+        "GPU-therm\n",
+    );
+    write("sys/devices/virtual/thermal/thermal_zone1/temp", "43500\n");
+    write(
+        "sys/bus/i2c/drivers/ina3221x/1-0040/iio:device0/rail_name_0",
+        "VDD_IN\n",
+    );
+    write(
+        "sys/bus/i2c/drivers/ina3221x/1-0040/iio:device0/rail_name_1",
+        "VDD_CPU_GPU_CV\n",
+    );
+    write(
+        "sys/bus/i2c/drivers/ina3221x/1-0040/iio:device0/in_power1_input",
+        "1234\n",
+    );
+    write("proc/device-tree/model", "NVIDIA Jetson Xavier NX\0");
+    write(
+        "proc/meminfo",
+        "MemTotal:        7999812 kB\nMemFree:          123456 kB\nMemAvailable:    5999812 kB\n",
+    );
+
+    root
+}
+
+#[test]
+fn reads_sysfs() {
+    let root = fake_root("reads");
+    let backend = TegraBackend::with_root(&root).unwrap();
+
+    assert_eq!(backend.device_count().unwrap(), 1);
+    assert_eq!(
+        backend.device_info(0).unwrap().name,
+        "NVIDIA Jetson Xavier NX"
+    );
+    assert_eq!(backend.utilization(0).unwrap().gpu, 50);
+    assert_eq!(backend.utilization(0).unwrap().memory, None);
+    assert_eq!(backend.clock(0, ClockType::Graphics).unwrap(), 1109);
+    assert_eq!(backend.temperature(0).unwrap(), 43);
+    assert_eq!(backend.power_usage(0).unwrap(), 1234);
+
+    let memory = backend.memory_info(0).unwrap();
+    assert_eq!(memory.total, 7999812 * 1024);
+    assert_eq!(memory.used, 2000000 * 1024);
+
+    fs::remove_dir_all(root).unwrap();
+}
+
+#[test]
+fn is_not_detected_without_gpu() {
+    assert!(TegraBackend::with_root(Path::new("/nonexistent")).is_none());
+}